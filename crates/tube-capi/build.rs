@@ -0,0 +1,11 @@
+// generates `include/tube.h` from the exported functions and types
+fn main() {
+    let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", dir)).unwrap();
+    cbindgen::generate_with_config(&dir, config)
+        .expect("couldn't generate the header")
+        .write_to_file(format!("{}/include/tube.h", dir));
+}
@@ -0,0 +1,102 @@
+#ifndef TUBE_H
+#define TUBE_H
+
+/* generated by cbindgen from src/lib.rs, don't edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define TUBE_ACCESS 1
+
+#define TUBE_MODIFY 2
+
+#define TUBE_ATTRIB 4
+
+#define TUBE_CLOSE_WRITE 8
+
+#define TUBE_CLOSE_NOWRITE 16
+
+#define TUBE_OPEN 32
+
+#define TUBE_MOVED_FROM 64
+
+#define TUBE_MOVED_TO 128
+
+#define TUBE_CREATE 256
+
+#define TUBE_DELETE 512
+
+#define TUBE_DELETE_SELF 1024
+
+#define TUBE_MOVE_SELF 2048
+
+#define TUBE_ALL_EVENTS 4095
+
+#define TUBE_UNMOUNT 8192
+
+#define TUBE_OVERFLOW 16384
+
+#define TUBE_IGNORED 32768
+
+/**
+ * an inotify instance and the events read from it but not returned yet
+ */
+typedef struct tube tube;
+
+/**
+ * an event returned by `tube_next_event`
+ */
+typedef struct {
+  /**
+   * the full path, valid until the next call to `tube_next_event` or `tube_free`
+   */
+  const char *path;
+  /**
+   * one of the `TUBE_*` events
+   */
+  uint32_t mask;
+  /**
+   * the same for the `TUBE_MOVED_FROM` and `TUBE_MOVED_TO` of a rename
+   */
+  uint32_t cookie;
+  bool is_dir;
+} tube_event;
+
+/**
+ * creates an instance, `NULL` on failure
+ */
+tube *tube_new(void);
+
+/**
+ * watches the path for the events in the mask, with `recursive` directories
+ * under it are watched too, including the ones created later. returns the watch
+ * descriptor of the path
+ */
+int tube_watch(tube *tube, const char *path, uint32_t mask, bool recursive);
+
+/**
+ * removes a watch returned by `tube_watch`
+ */
+int tube_unwatch(tube *tube, int wd);
+
+/**
+ * the inotify descriptor, readable when events are ready, so the instance
+ * can be added to an existing poll/epoll loop
+ */
+int tube_fd(const tube *tube);
+
+/**
+ * waits up to `timeout_ms` for the next event (-1 waits forever, 0 doesn't wait),
+ * returns 1 and fills `event` when there is one, 0 when the timeout expired
+ */
+int tube_next_event(tube *tube, tube_event *event, int timeout_ms);
+
+/**
+ * closes the instance and its watches
+ */
+void tube_free(tube *tube);
+
+#endif  /* TUBE_H */
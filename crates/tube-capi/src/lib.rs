@@ -0,0 +1,203 @@
+//! the C API of tube, `include/tube.h` is generated from this file.
+//!
+//! functions that fail return -1 (or `NULL`) and set `errno`
+#![allow(clippy::missing_safety_doc)]
+
+use std::collections::VecDeque;
+use std::ffi::{c_char, c_int, CStr, CString, OsStr};
+use std::os::fd::AsRawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use tube_inotify::{Depth, Errno, Event, Flag, Inotify};
+
+pub const TUBE_ACCESS: u32 = 0x00000001;
+pub const TUBE_MODIFY: u32 = 0x00000002;
+pub const TUBE_ATTRIB: u32 = 0x00000004;
+pub const TUBE_CLOSE_WRITE: u32 = 0x00000008;
+pub const TUBE_CLOSE_NOWRITE: u32 = 0x00000010;
+pub const TUBE_OPEN: u32 = 0x00000020;
+pub const TUBE_MOVED_FROM: u32 = 0x00000040;
+pub const TUBE_MOVED_TO: u32 = 0x00000080;
+pub const TUBE_CREATE: u32 = 0x00000100;
+pub const TUBE_DELETE: u32 = 0x00000200;
+pub const TUBE_DELETE_SELF: u32 = 0x00000400;
+pub const TUBE_MOVE_SELF: u32 = 0x00000800;
+pub const TUBE_ALL_EVENTS: u32 = 0x00000fff;
+pub const TUBE_UNMOUNT: u32 = 0x00002000;
+pub const TUBE_OVERFLOW: u32 = 0x00004000;
+pub const TUBE_IGNORED: u32 = 0x00008000;
+
+const EINVAL: c_int = 22;
+
+/// an inotify instance and the events read from it but not returned yet
+pub struct Tube {
+    inotify: Inotify,
+    events: VecDeque<Event>,
+    // the path of the last returned event, `tube_event.path` points into it
+    path: CString,
+}
+
+/// an event returned by `tube_next_event`
+#[repr(C)]
+pub struct TubeEvent {
+    /// the full path, valid until the next call to `tube_next_event` or `tube_free`
+    pub path: *const c_char,
+    /// one of the `TUBE_*` events
+    pub mask: u32,
+    /// the same for the `TUBE_MOVED_FROM` and `TUBE_MOVED_TO` of a rename
+    pub cookie: u32,
+    pub is_dir: bool,
+}
+
+extern "C" {
+    fn __errno_location() -> *mut c_int;
+    fn poll(fds: *mut pollfd, nfds: u64, timeout: c_int) -> c_int;
+}
+
+#[repr(C)]
+struct pollfd {
+    fd: c_int,
+    events: i16,
+    revents: i16,
+}
+
+fn set_errno(e: impl Into<Errno>) {
+    unsafe { *__errno_location() = e.into().raw() };
+}
+
+/// creates an instance, `NULL` on failure
+#[no_mangle]
+pub extern "C" fn tube_new() -> *mut Tube {
+    match Inotify::with_flags(Flag::NONBLOCKING) {
+        Ok(inotify) => Box::into_raw(Box::new(Tube {
+            inotify,
+            events: VecDeque::new(),
+            path: CString::default(),
+        })),
+        Err(e) => {
+            set_errno(e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// watches the path for the events in the mask, with `recursive` directories
+/// under it are watched too, including the ones created later. returns the watch
+/// descriptor of the path
+#[no_mangle]
+pub unsafe extern "C" fn tube_watch(
+    tube: *mut Tube,
+    path: *const c_char,
+    mask: u32,
+    recursive: bool,
+) -> c_int {
+    let (Some(tube), false) = (tube.as_mut(), path.is_null()) else {
+        set_errno(Errno::from(EINVAL));
+        return -1;
+    };
+    let path = PathBuf::from(OsStr::from_bytes(CStr::from_ptr(path).to_bytes()));
+    let result = match recursive && path.is_dir() {
+        true => tube
+            .inotify
+            .add_recursive(path.clone(), mask, Depth::any())
+            .map(|_| {
+                tube.inotify
+                    .watches()
+                    .find(|(_, watched)| *watched == path)
+                    .map_or(-1, |(wd, _)| wd)
+            }),
+        false => tube.inotify.add_watch(path, mask),
+    };
+    match result {
+        Ok(wd) => wd,
+        Err(e) => {
+            set_errno(e);
+            -1
+        }
+    }
+}
+
+/// removes a watch returned by `tube_watch`
+#[no_mangle]
+pub unsafe extern "C" fn tube_unwatch(tube: *mut Tube, wd: c_int) -> c_int {
+    let Some(tube) = tube.as_mut() else {
+        set_errno(Errno::from(EINVAL));
+        return -1;
+    };
+    match tube.inotify.unwatch(wd) {
+        Ok(()) => 0,
+        Err(e) => {
+            set_errno(e);
+            -1
+        }
+    }
+}
+
+/// the inotify descriptor, readable when events are ready, so the instance
+/// can be added to an existing poll/epoll loop
+#[no_mangle]
+pub unsafe extern "C" fn tube_fd(tube: *const Tube) -> c_int {
+    match tube.as_ref() {
+        Some(tube) => tube.inotify.as_raw_fd(),
+        None => -1,
+    }
+}
+
+/// waits up to `timeout_ms` for the next event (-1 waits forever, 0 doesn't wait),
+/// returns 1 and fills `event` when there is one, 0 when the timeout expired
+#[no_mangle]
+pub unsafe extern "C" fn tube_next_event(
+    tube: *mut Tube,
+    event: *mut TubeEvent,
+    timeout_ms: c_int,
+) -> c_int {
+    let (Some(tube), Some(out)) = (tube.as_mut(), event.as_mut()) else {
+        set_errno(Errno::from(EINVAL));
+        return -1;
+    };
+    if tube.events.is_empty() {
+        let mut fds = [pollfd {
+            fd: tube.inotify.as_raw_fd(),
+            events: 0x001,
+            revents: 0,
+        }];
+        match poll(fds.as_mut_ptr(), 1, timeout_ms) {
+            -1 => return -1,
+            0 => return 0,
+            _ => {}
+        }
+        match tube.inotify.try_read() {
+            Ok(Some(batch)) => {
+                let inotify = &tube.inotify;
+                tube.events
+                    .extend(batch.filter_map(|event| inotify.resolve(&event)));
+            }
+            Ok(None) => return 0,
+            Err(e) => {
+                set_errno(e);
+                return -1;
+            }
+        }
+    }
+
+    let Some(event) = tube.events.pop_front() else {
+        return 0;
+    };
+    // paths can't have nul bytes in them
+    tube.path = CString::new(event.path.into_os_string().into_encoded_bytes()).unwrap_or_default();
+    *out = TubeEvent {
+        path: tube.path.as_ptr(),
+        mask: event.kind.mask(),
+        cookie: event.cookie,
+        is_dir: event.is_dir,
+    };
+    1
+}
+
+/// closes the instance and its watches
+#[no_mangle]
+pub unsafe extern "C" fn tube_free(tube: *mut Tube) {
+    if !tube.is_null() {
+        drop(Box::from_raw(tube));
+    }
+}
@@ -0,0 +1,104 @@
+use std::io;
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::raw::{c_int, c_short, c_ulong};
+use std::task::{Context, Poll};
+
+use crate::reactor::AsyncFd;
+
+pub const POLLIN: c_short = 0x001;
+pub const POLLPRI: c_short = 0x002;
+pub const POLLERR: c_short = 0x008;
+
+#[allow(non_camel_case_types)]
+#[repr(C)]
+struct pollfd {
+    fd: c_int,
+    events: c_short,
+    revents: c_short,
+}
+
+extern "C" {
+    fn poll(fds: *mut pollfd, nfds: c_ulong, timeout: c_int) -> c_int;
+}
+
+/// a source backed by a file descriptor that becomes ready when it has
+/// something to read (timerfd, signalfd, pidfd, netlink sockets...)
+pub trait FdSource: AsRawFd {
+    type Item;
+
+    /// `true` when polling the descriptor clears the events it reports, like the
+    /// mount table does. the reactor polls it when it is registered, so the source
+    /// is read once more right after, or the events cleared then would be missed
+    const POLL_CLEARS: bool = false;
+
+    /// reads the next item once the descriptor is ready, `Ok(None)` if there
+    /// was nothing to read after all, e.g. another reader was faster. the
+    /// descriptor is non blocking, the read may come without it being ready
+    fn read_ready(&mut self) -> io::Result<Option<Self::Item>>;
+
+    /// an item read along with an earlier one and not yielded yet, returned
+    /// without waiting on the descriptor
+    fn pending(&mut self) -> Option<Self::Item> {
+        None
+    }
+
+    /// `true` once the source won't produce items anymore, ending its stream
+    fn is_done(&self) -> bool {
+        false
+    }
+
+    /// the descriptor registered with the reactor, with the events it is ready on
+    fn io(&mut self) -> &mut AsyncFd;
+}
+
+/// the body of `Stream::poll_next` for fd sources, returns `Poll::Pending` until
+/// the descriptor is ready and the task is woken once it is
+pub fn poll_source<S: FdSource>(
+    source: &mut S,
+    cx: &mut Context<'_>,
+) -> Poll<Option<io::Result<S::Item>>> {
+    loop {
+        if let Some(item) = source.pending() {
+            return Poll::Ready(Some(Ok(item)));
+        }
+        if source.is_done() {
+            return Poll::Ready(None);
+        }
+        match source.io().poll_ready(cx) {
+            Poll::Ready(Ok(_)) => {}
+            Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e))),
+            Poll::Pending if S::POLL_CLEARS => {
+                return match source.read_ready() {
+                    Ok(Some(item)) => Poll::Ready(Some(Ok(item))),
+                    Ok(None) => Poll::Pending,
+                    Err(e) => Poll::Ready(Some(Err(e))),
+                }
+            }
+            Poll::Pending => return Poll::Pending,
+        }
+        match source.read_ready() {
+            Ok(Some(item)) => return Poll::Ready(Some(Ok(item))),
+            Ok(None) => continue,
+            Err(e) => return Poll::Ready(Some(Err(e))),
+        }
+    }
+}
+
+/// the events the descriptor has, waiting for one of them up to `timeout`
+/// milliseconds, `0` if it has none by then. interrupted waits are retried
+pub(crate) fn ready(fd: RawFd, events: c_short, timeout: c_int) -> io::Result<c_short> {
+    let mut fds = [pollfd {
+        fd,
+        events,
+        revents: 0,
+    }];
+    loop {
+        match unsafe { poll(fds.as_mut_ptr(), 1, timeout) } {
+            -1 => match io::Error::last_os_error() {
+                e if e.kind() == io::ErrorKind::Interrupted => continue,
+                e => return Err(e),
+            },
+            _ => return Ok(fds[0].revents),
+        }
+    }
+}
@@ -0,0 +1,7 @@
+mod fd;
+mod merged;
+mod reactor;
+
+pub use fd::*;
+pub use merged::*;
+pub use reactor::AsyncFd;
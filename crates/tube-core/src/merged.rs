@@ -0,0 +1,142 @@
+use futures::channel::mpsc;
+use futures::stream::{BoxStream, Stream, StreamExt};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// merges event sources of any kind into a single stream, every item is
+/// yielded with the tag of the source it came from. sources are polled
+/// in turns, so a busy source can't starve the others, and sources that
+/// ended are dropped, the merged stream ends once all of them did
+///
+/// sources must return `Poll::Pending` when they have nothing to yield,
+/// like `Inotify` does, sources that block in `poll_next` should be added
+/// with `push_blocking`
+pub struct MergedEvents<K, T> {
+    sources: Vec<Source<K, T>>,
+    // the source polled first on the next poll
+    next: usize,
+}
+
+struct Source<K, T> {
+    tag: K,
+    stream: BoxStream<'static, T>,
+}
+
+impl<K, T> MergedEvents<K, T>
+where
+    K: Clone,
+    T: Send + 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            sources: Vec::new(),
+            next: 0,
+        }
+    }
+
+    /// adds a source, builder style
+    pub fn with<S>(mut self, tag: K, stream: S) -> Self
+    where
+        S: Stream<Item = T> + Send + 'static,
+    {
+        self.push(tag, stream);
+        self
+    }
+
+    pub fn push<S>(&mut self, tag: K, stream: S)
+    where
+        S: Stream<Item = T> + Send + 'static,
+    {
+        self.sources.push(Source {
+            tag,
+            stream: stream.boxed(),
+        });
+    }
+
+    /// adds a source that blocks until it has items, it is driven
+    /// on its own thread and its items are forwarded to the merged stream
+    pub fn push_blocking<S>(&mut self, tag: K, stream: S)
+    where
+        S: Stream<Item = T> + Send + 'static,
+    {
+        self.push(tag, threaded(stream));
+    }
+
+    /// the number of sources that didn't end yet
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+}
+
+impl<K, T> Default for MergedEvents<K, T>
+where
+    K: Clone,
+    T: Send + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, T> Stream for MergedEvents<K, T>
+where
+    K: Clone + Unpin,
+{
+    type Item = (K, T);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let mut polled = 0;
+        while polled < this.sources.len() {
+            let i = (this.next + polled) % this.sources.len();
+            match this.sources[i].stream.poll_next_unpin(cx) {
+                Poll::Ready(Some(item)) => {
+                    // the source after this one goes first next time
+                    this.next = (i + 1) % this.sources.len();
+                    return Poll::Ready(Some((this.sources[i].tag.clone(), item)));
+                }
+                Poll::Ready(None) => {
+                    this.sources.remove(i);
+                    if i < this.next {
+                        this.next -= 1;
+                    }
+                    if this.sources.is_empty() {
+                        return Poll::Ready(None);
+                    }
+                    this.next %= this.sources.len();
+                }
+                Poll::Pending => polled += 1,
+            }
+        }
+        match this.sources.is_empty() {
+            true => Poll::Ready(None),
+            false => Poll::Pending,
+        }
+    }
+}
+
+/// drives a stream that blocks in `poll_next` on its own thread, the returned
+/// stream yields its items and ends when it does, the thread stops once the
+/// returned stream is dropped and the source yields its next item
+pub fn threaded<S>(stream: S) -> impl Stream<Item = S::Item> + Send + 'static
+where
+    S: Stream + Send + 'static,
+    S::Item: Send + 'static,
+{
+    let (tx, rx) = mpsc::unbounded();
+    std::thread::spawn(move || {
+        futures::executor::block_on(async move {
+            let mut stream = std::pin::pin!(stream);
+            while let Some(item) = stream.next().await {
+                if tx.unbounded_send(item).is_err() {
+                    break;
+                }
+            }
+        })
+    });
+    rx
+}
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::io;
+use std::os::fd::RawFd;
+use std::os::raw::{c_int, c_short};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Waker};
+
+use crate::fd::ready;
+
+const EPOLL_CLOEXEC: c_int = 0o2000000;
+const EPOLL_CTL_ADD: c_int = 1;
+const EPOLL_CTL_DEL: c_int = 2;
+const EPOLL_CTL_MOD: c_int = 3;
+const EPOLLONESHOT: u32 = 1 << 30;
+const SIG_BLOCK: c_int = 0;
+
+#[allow(non_camel_case_types)]
+#[repr(C)]
+#[cfg_attr(target_arch = "x86_64", repr(packed))]
+#[derive(Clone, Copy)]
+struct epoll_event {
+    events: u32,
+    data: u64,
+}
+
+// big enough for the `sigset_t` of every libc
+#[allow(non_camel_case_types)]
+#[repr(C)]
+struct sigset_t([u64; 16]);
+
+extern "C" {
+    fn sigfillset(set: *mut sigset_t) -> c_int;
+    fn pthread_sigmask(how: c_int, set: *const sigset_t, old: *mut sigset_t) -> c_int;
+    fn epoll_create1(flags: c_int) -> c_int;
+    fn epoll_ctl(epfd: c_int, op: c_int, fd: c_int, event: *mut epoll_event) -> c_int;
+    fn epoll_wait(epfd: c_int, events: *mut epoll_event, max: c_int, timeout: c_int) -> c_int;
+}
+
+/// a thread waiting on the descriptors of the streams that returned `Poll::Pending`,
+/// it wakes their task once the descriptor is ready
+struct Reactor {
+    epfd: RawFd,
+    // by the token of the descriptor
+    wakers: Mutex<HashMap<u64, Registration>>,
+}
+
+/// the task waiting on a descriptor, and where the events the reactor saw are left for it
+struct Registration {
+    waker: Waker,
+    fired: Arc<AtomicU32>,
+}
+
+// the tokens of the descriptors, never reused
+static TOKENS: AtomicU64 = AtomicU64::new(0);
+
+/// the reactor, started by the first stream that waits on its descriptor
+fn reactor() -> io::Result<&'static Reactor> {
+    static REACTOR: OnceLock<Result<Reactor, i32>> = OnceLock::new();
+    let reactor = REACTOR.get_or_init(|| {
+        let epfd = unsafe { epoll_create1(EPOLL_CLOEXEC) };
+        if epfd == -1 {
+            return Err(io::Error::last_os_error().raw_os_error().unwrap_or(0));
+        }
+        Ok(Reactor {
+            epfd,
+            wakers: Mutex::new(HashMap::new()),
+        })
+    });
+    match reactor {
+        Ok(reactor) => {
+            static STARTED: OnceLock<()> = OnceLock::new();
+            STARTED.get_or_init(|| {
+                std::thread::Builder::new()
+                    .name("tube-reactor".to_string())
+                    .spawn(move || reactor.run())
+                    .expect("couldn't start the reactor thread");
+            });
+            Ok(reactor)
+        }
+        Err(code) => Err(io::Error::from_raw_os_error(*code)),
+    }
+}
+
+impl Reactor {
+    fn run(&self) {
+        // signals are left to the threads that handle them, e.g. a `signalfd`
+        // created after the reactor started only blocks them in its own thread
+        unsafe {
+            let mut set = sigset_t([0; 16]);
+            sigfillset(&mut set);
+            pthread_sigmask(SIG_BLOCK, &set, std::ptr::null_mut());
+        }
+        let mut events = [epoll_event { events: 0, data: 0 }; 64];
+        loop {
+            let n = unsafe { epoll_wait(self.epfd, events.as_mut_ptr(), 64, -1) };
+            if n == -1 {
+                match io::Error::last_os_error() {
+                    e if e.kind() == io::ErrorKind::Interrupted => continue,
+                    e => panic!("the reactor couldn't wait on the descriptors: {}", e),
+                }
+            }
+            let woken: Vec<(Registration, u32)> = {
+                let mut wakers = self.wakers.lock().unwrap();
+                events[..n as usize]
+                    .iter()
+                    .filter_map(|event| Some((wakers.remove(&{ event.data })?, event.events)))
+                    .collect()
+            };
+            // the descriptors are one shot, the streams arm them again
+            // the next time they have nothing to read
+            for (registration, events) in woken {
+                registration.fired.fetch_or(events, Ordering::AcqRel);
+                registration.waker.wake();
+            }
+        }
+    }
+}
+
+/// a descriptor the stream reading it waits on without blocking the thread, polling
+/// it returns `Poll::Pending` until the descriptor has one of the events, and the
+/// task is woken once it does. the descriptor stays owned by the stream
+#[derive(Debug)]
+pub struct AsyncFd {
+    fd: RawFd,
+    events: c_short,
+    token: u64,
+    // added to the reactor, the first time the stream waited
+    added: bool,
+    // the events the reactor saw, polling some descriptors clears them,
+    // e.g. the mount table, so they aren't polled for again
+    fired: Arc<AtomicU32>,
+}
+
+impl AsyncFd {
+    /// `events` are `poll` events, e.g. `POLLIN`
+    pub fn new(fd: RawFd, events: c_short) -> Self {
+        Self {
+            fd,
+            events,
+            token: TOKENS.fetch_add(1, Ordering::Relaxed),
+            added: false,
+            fired: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    /// `Poll::Ready` with the events the descriptor has, or `Poll::Pending`
+    /// with the task woken once it has one of the events
+    pub fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<c_short>> {
+        match self.fired.swap(0, Ordering::AcqRel) {
+            0 => {}
+            events => return Poll::Ready(Ok(events as u16 as c_short)),
+        }
+        match ready(self.fd, self.events, 0) {
+            Ok(0) => {}
+            revents => return Poll::Ready(revents),
+        }
+        let reactor = match reactor() {
+            Ok(reactor) => reactor,
+            Err(e) => return Poll::Ready(Err(e)),
+        };
+        reactor.wakers.lock().unwrap().insert(
+            self.token,
+            Registration {
+                waker: cx.waker().clone(),
+                fired: self.fired.clone(),
+            },
+        );
+        // level triggered, armed on a descriptor that became ready
+        // meanwhile it is reported right away
+        let mut event = epoll_event {
+            events: self.events as u16 as u32 | EPOLLONESHOT,
+            data: self.token,
+        };
+        let op = match self.added {
+            true => EPOLL_CTL_MOD,
+            false => EPOLL_CTL_ADD,
+        };
+        if unsafe { epoll_ctl(reactor.epfd, op, self.fd, &mut event) } == -1 {
+            let e = io::Error::last_os_error();
+            reactor.wakers.lock().unwrap().remove(&self.token);
+            return Poll::Ready(Err(e));
+        }
+        self.added = true;
+        Poll::Pending
+    }
+}
+
+impl Drop for AsyncFd {
+    fn drop(&mut self) {
+        if !self.added {
+            return;
+        }
+        if let Ok(reactor) = reactor() {
+            // fails when the stream closed the descriptor already, it
+            // was removed from the reactor with it then
+            let mut event = epoll_event { events: 0, data: 0 };
+            unsafe { epoll_ctl(reactor.epfd, EPOLL_CTL_DEL, self.fd, &mut event) };
+            reactor.wakers.lock().unwrap().remove(&self.token);
+        }
+    }
+}
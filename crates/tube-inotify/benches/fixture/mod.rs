@@ -0,0 +1,51 @@
+// shared by the benches, each uses only some of it
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+/// a directory for the bench on tmpfs when there is one, removed on drop
+pub struct Fixture {
+    pub dir: PathBuf,
+}
+
+impl Fixture {
+    pub fn new(name: &str) -> Self {
+        let base = match Path::new("/dev/shm").is_dir() {
+            true => PathBuf::from("/dev/shm"),
+            false => std::env::temp_dir(),
+        };
+        let dir = base.join(format!("tube-bench-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        Self { dir }
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// an event in the format the kernel writes it, the name is padded
+/// with zeros to a multiple of the header size like the kernel does
+pub fn event_bytes(wd: i32, mask: u32, cookie: u32, name: &str) -> Vec<u8> {
+    let len = match name.len() {
+        0 => 0,
+        n => (n + 1).div_ceil(16) * 16,
+    };
+    let mut bytes = Vec::with_capacity(16 + len);
+    bytes.extend_from_slice(&wd.to_ne_bytes());
+    bytes.extend_from_slice(&mask.to_ne_bytes());
+    bytes.extend_from_slice(&cookie.to_ne_bytes());
+    bytes.extend_from_slice(&(len as u32).to_ne_bytes());
+    bytes.extend_from_slice(name.as_bytes());
+    bytes.resize(16 + len, 0);
+    bytes
+}
+
+/// `count` `CREATE` events of files named after their index
+pub fn batch_bytes(wd: i32, count: usize) -> Vec<u8> {
+    (0..count)
+        .flat_map(|i| event_bytes(wd, 0x100, 0, &format!("file-{}.txt", i)))
+        .collect()
+}
@@ -0,0 +1,85 @@
+mod fixture;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use futures::StreamExt;
+use std::fs::File;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tube_inotify::{Depth, Inotify, Mask};
+
+use fixture::Fixture;
+
+/// creates and removes files in the directory at about `rate` events per second
+/// until stopped, the load the probe events are measured under
+fn generate(dir: std::path::PathBuf, rate: u64, stop: Arc<AtomicBool>) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let interval = Duration::from_secs(1) / rate.max(1) as u32;
+        let mut next = Instant::now();
+        let mut i = 0u64;
+        while !stop.load(Ordering::Relaxed) {
+            let path = dir.join((i % 1000).to_string());
+            let _ = std::fs::remove_file(&path);
+            let _ = File::create(&path);
+            i += 1;
+            next += interval;
+            if let Some(wait) = next.checked_duration_since(Instant::now()) {
+                std::thread::sleep(wait);
+            }
+        }
+    })
+}
+
+/// the time from creating a file until its event is read, with other events
+/// generated in the same watched tree at the given rate
+fn latency(c: &mut Criterion) {
+    let mut group = c.benchmark_group("latency");
+    for rate in [0, 1_000, 10_000] {
+        let fixture = Fixture::new(&format!("latency-{}", rate));
+        let churn = fixture.dir.join("churn");
+        std::fs::create_dir(&churn).unwrap();
+
+        let mut events = Inotify::new()
+            .unwrap()
+            .watch_recursive(fixture.dir.clone(), Mask::CREATE, Depth::any())
+            .unwrap()
+            .resolved();
+        let stop = Arc::new(AtomicBool::new(false));
+        let generator = (rate > 0).then(|| generate(churn, rate, stop.clone()));
+
+        let probe = fixture.dir.join("probe");
+        group.bench_function(BenchmarkId::from_parameter(rate), |b| {
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    let _ = std::fs::remove_file(&probe);
+                    let start = Instant::now();
+                    File::create(&probe).unwrap();
+                    futures::executor::block_on(async {
+                        while let Some(event) = events.next().await {
+                            if event.unwrap().path == probe {
+                                break;
+                            }
+                        }
+                    });
+                    elapsed += start.elapsed();
+                }
+                elapsed
+            })
+        });
+
+        stop.store(true, Ordering::Relaxed);
+        if let Some(generator) = generator {
+            generator.join().unwrap();
+        }
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20);
+    targets = latency
+}
+criterion_main!(benches);
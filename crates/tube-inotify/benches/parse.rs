@@ -0,0 +1,79 @@
+mod fixture;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tube_inotify::{Inotify, InotifyEvent, InotifyEventBatch, Mask};
+
+use fixture::{batch_bytes, event_bytes, Fixture};
+
+fn from_buffer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("from_buffer");
+    for (name, event) in [
+        ("no_name", event_bytes(1, 0x100, 0, "")),
+        ("short_name", event_bytes(1, 0x100, 0, "foo.txt")),
+        ("long_name", event_bytes(1, 0x100, 0, &"x".repeat(255))),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter(|| InotifyEvent::from_buffer(black_box(&event)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batch");
+    for count in [1, 64, 1024] {
+        let bytes = batch_bytes(1, count);
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &bytes, |b, bytes| {
+            b.iter(|| InotifyEventBatch::<4096>::from_bytes(bytes.clone()).count())
+        });
+    }
+    group.finish();
+}
+
+/// the names copied out of the batch against borrowed from it
+fn batch_refs(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batch_refs");
+    for count in [64, 1024] {
+        let batch = InotifyEventBatch::<4096>::from_bytes(batch_bytes(1, count));
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::new("owned", count), &batch, |b, batch| {
+            b.iter(|| batch.refs().map(|event| black_box(event.to_owned())).last())
+        });
+        group.bench_with_input(BenchmarkId::new("borrowed", count), &batch, |b, batch| {
+            b.iter(|| batch.refs().map(|event| black_box(event.name())).last())
+        });
+    }
+    group.finish();
+}
+
+fn resolve(c: &mut Criterion) {
+    let fixture = Fixture::new("resolve");
+    let mut inotify = Inotify::new().unwrap();
+    let wd = inotify
+        .add_watch(fixture.dir.clone(), Mask::CREATE)
+        .unwrap();
+    // other watches, so the lookup isn't in a map of one
+    for i in 0..1000 {
+        let dir = fixture.dir.join(i.to_string());
+        std::fs::create_dir(&dir).unwrap();
+        inotify.add_watch(dir, Mask::CREATE).unwrap();
+    }
+
+    let events: Vec<InotifyEvent> =
+        InotifyEventBatch::<4096>::from_bytes(batch_bytes(wd, 1024)).collect();
+    let mut group = c.benchmark_group("resolve");
+    group.throughput(Throughput::Elements(events.len() as u64));
+    group.bench_function("1024", |b| {
+        b.iter(|| {
+            events
+                .iter()
+                .filter_map(|event| inotify.resolve(event))
+                .count()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, from_buffer, batch, batch_refs, resolve);
+criterion_main!(benches);
@@ -0,0 +1,81 @@
+mod fixture;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::fs::File;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tube_inotify::{Flag, Inotify, Mask, ReadStrategy};
+
+use fixture::Fixture;
+
+/// events queued before every read, under the default kernel queue limit
+const EVENTS: usize = 4000;
+
+/// queues `EVENTS` creations, the files are removed before so the names are new
+fn churn(dir: &Path) {
+    for i in 0..EVENTS {
+        let _ = std::fs::remove_file(dir.join(i.to_string()));
+    }
+    for i in 0..EVENTS {
+        File::create(dir.join(i.to_string())).unwrap();
+    }
+}
+
+/// reads until all the queued events were read, returns the number of reads
+fn drain(inotify: &mut Inotify) -> usize {
+    let (mut events, mut wakeups) = (0, 0);
+    while events < EVENTS {
+        if let Some(batch) = inotify.try_read().unwrap() {
+            events += batch.count();
+            wakeups += 1;
+        }
+    }
+    wakeups
+}
+
+fn read(c: &mut Criterion) {
+    let fixture = Fixture::new("read");
+    let dir = &fixture.dir;
+    let mut group = c.benchmark_group("read");
+    let drained = ReadStrategy::Drain { max_bytes: 1 << 20 };
+    for (name, strategy, buffers) in [
+        ("single", ReadStrategy::Single, 1),
+        ("drain", drained, 1),
+        ("drain_readv", drained, 16),
+    ] {
+        let mut inotify = Inotify::with_flags(Flag::NONBLOCKING)
+            .unwrap()
+            .read_strategy(strategy)
+            .buffer_pool(4096, buffers)
+            .watch(dir.clone(), Mask::CREATE)
+            .unwrap();
+        churn(dir);
+        println!(
+            "read/{}: {} wakeups for {} events",
+            name,
+            drain(&mut inotify),
+            EVENTS
+        );
+
+        group.bench_function(name, |b| {
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    churn(dir);
+                    let start = Instant::now();
+                    drain(&mut inotify);
+                    elapsed += start.elapsed();
+                }
+                elapsed
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20);
+    targets = read
+}
+criterion_main!(benches);
@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tube_inotify::{InotifyEvent, InotifyEventBatch};
+
+fuzz_target!(|data: &[u8]| {
+    if let Some((size, event)) = InotifyEvent::from_buffer(data) {
+        assert!(size <= data.len());
+        let _ = (event.wd(), event.mask(), event.cookie(), event.name());
+    }
+    for event in InotifyEventBatch::<4096>::from_bytes(data.to_vec()) {
+        let _ = event.name();
+    }
+});
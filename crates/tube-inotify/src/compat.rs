@@ -0,0 +1,188 @@
+use notify::event::{
+    AccessKind, AccessMode, CreateKind, DataChange, Flag as NotifyFlag, MetadataKind, ModifyKind,
+    RemoveKind, RenameMode,
+};
+use notify::{Config, EventHandler, RecursiveMode, WatcherKind};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::errno::WatchError;
+use crate::event::{Event, EventKind};
+use crate::ffi;
+use crate::inotify::{Depth, Flag, Inotify, Mask};
+
+/// how long the reading thread waits for events before checking if the watcher was dropped
+const POLL_TIMEOUT_MS: i32 = 100;
+
+/// the events `notify`'s own inotify backend watches for
+const MASK: u32 = Mask::ALL_EVENTS & !Mask::ACCESS & !Mask::CLOSE_NOWRITE & !Mask::OPEN;
+
+/// implements `notify::Watcher` on top of `Inotify`, code written against
+/// `notify` can switch to it without changing how it handles the events
+///
+/// ```no_run
+/// # use notify::{RecursiveMode, Watcher};
+/// # use tube_inotify::compat::TubeWatcher;
+/// # fn main() -> notify::Result<()> {
+/// let mut watcher = TubeWatcher::new(
+///     |event: notify::Result<notify::Event>| println!("{:?}", event),
+///     notify::Config::default(),
+/// )?;
+/// watcher.watch("/tmp".as_ref(), RecursiveMode::Recursive)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TubeWatcher {
+    inotify: Arc<Mutex<Inotify>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl TubeWatcher {
+    fn spawn<F: EventHandler>(&self, mut handler: F) {
+        let inotify = self.inotify.clone();
+        let stopped = self.stopped.clone();
+        let fd = inotify.lock().unwrap().as_raw_fd();
+        std::thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                // waits without the lock, so watches can be added meanwhile
+                let mut fds = [ffi::pollfd {
+                    fd,
+                    events: ffi::POLLIN,
+                    revents: 0,
+                }];
+                if unsafe { ffi::poll(fds.as_mut_ptr(), 1, POLL_TIMEOUT_MS) } <= 0 {
+                    continue;
+                }
+
+                let mut inotify = inotify.lock().unwrap();
+                let batch = match inotify.try_read() {
+                    Ok(Some(batch)) => batch,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        handler.handle_event(Err(error(e)));
+                        continue;
+                    }
+                };
+                let events: Vec<_> = batch.filter_map(|event| inotify.resolve(&event)).collect();
+                // the handler may take a while, watches shouldn't wait for it
+                drop(inotify);
+                for event in events {
+                    if event.kind != EventKind::Ignored {
+                        handler.handle_event(Ok(event.into()));
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl notify::Watcher for TubeWatcher {
+    fn new<F: EventHandler>(event_handler: F, _config: Config) -> notify::Result<Self> {
+        let inotify = Inotify::with_flags(Flag::NONBLOCKING).map_err(error)?;
+        let watcher = Self {
+            inotify: Arc::new(Mutex::new(inotify.include_hidden(true))),
+            stopped: Arc::new(AtomicBool::new(false)),
+        };
+        watcher.spawn(event_handler);
+        Ok(watcher)
+    }
+
+    fn watch(&mut self, path: &Path, recursive_mode: RecursiveMode) -> notify::Result<()> {
+        let path = path.canonicalize().map_err(notify::Error::io)?;
+        let mut inotify = self.inotify.lock().unwrap();
+        let result = match recursive_mode {
+            RecursiveMode::Recursive if path.is_dir() => {
+                inotify.add_recursive(path.clone(), MASK, Depth::any())
+            }
+            _ => inotify.add_watch(path.clone(), MASK).map(|_| ()),
+        };
+        result.map_err(error)
+    }
+
+    /// removes the watches of the path and of the directories under it
+    fn unwatch(&mut self, path: &Path) -> notify::Result<()> {
+        let path = path.canonicalize().map_err(notify::Error::io)?;
+        let mut inotify = self.inotify.lock().unwrap();
+        let wds: Vec<_> = inotify
+            .watches()
+            .filter(|(_, watched)| watched.starts_with(&path))
+            .map(|(wd, _)| wd)
+            .collect();
+        if wds.is_empty() {
+            return Err(notify::Error::watch_not_found().add_path(path));
+        }
+        for wd in wds {
+            inotify.unwatch(wd).map_err(error)?;
+        }
+        Ok(())
+    }
+
+    fn kind() -> WatcherKind {
+        WatcherKind::Inotify
+    }
+}
+
+impl Drop for TubeWatcher {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+/// the path that failed is added to the error like `notify` does
+fn error(e: WatchError) -> notify::Error {
+    let io = std::io::Error::from_raw_os_error(e.errno.raw());
+    match e.path {
+        Some(path) => notify::Error::io(io).add_path(path),
+        None => notify::Error::io(io),
+    }
+}
+
+/// the same mapping `notify`'s inotify backend makes, so handlers
+/// written against it see the events they expect
+impl From<Event> for notify::Event {
+    fn from(event: Event) -> Self {
+        let create = match event.is_dir {
+            true => CreateKind::Folder,
+            false => CreateKind::File,
+        };
+        let remove = match event.is_dir {
+            true => RemoveKind::Folder,
+            false => RemoveKind::File,
+        };
+        let kind = match event.kind {
+            EventKind::Access => notify::EventKind::Access(AccessKind::Read),
+            EventKind::Modify => notify::EventKind::Modify(ModifyKind::Data(DataChange::Any)),
+            EventKind::Attrib => notify::EventKind::Modify(ModifyKind::Metadata(MetadataKind::Any)),
+            EventKind::CloseWrite => {
+                notify::EventKind::Access(AccessKind::Close(AccessMode::Write))
+            }
+            EventKind::CloseNoWrite => {
+                notify::EventKind::Access(AccessKind::Close(AccessMode::Read))
+            }
+            EventKind::Open => notify::EventKind::Access(AccessKind::Open(AccessMode::Any)),
+            EventKind::MovedFrom => notify::EventKind::Modify(ModifyKind::Name(RenameMode::From)),
+            EventKind::MovedTo => notify::EventKind::Modify(ModifyKind::Name(RenameMode::To)),
+            EventKind::Create => notify::EventKind::Create(create),
+            EventKind::FileReady => notify::EventKind::Access(AccessKind::Close(AccessMode::Write)),
+            EventKind::Delete | EventKind::DeleteSelf => notify::EventKind::Remove(remove),
+            EventKind::MoveSelf => notify::EventKind::Modify(ModifyKind::Name(RenameMode::From)),
+            EventKind::Unmount | EventKind::Overflow | EventKind::Ignored | EventKind::Other(_) => {
+                notify::EventKind::Other
+            }
+        };
+
+        let mut converted = notify::Event::new(kind);
+        if !event.path.as_os_str().is_empty() {
+            converted = converted.add_path(event.path);
+        }
+        match event.kind {
+            EventKind::MovedFrom | EventKind::MovedTo => {
+                converted.set_tracker(event.cookie as usize)
+            }
+            EventKind::Overflow => converted.set_flag(NotifyFlag::Rescan),
+            _ => converted,
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use futures::stream::Stream;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use crate::errno::WatchError;
+use crate::event::{Event, EventKind};
+use crate::source::EventSource;
+
+/// how many events a channel queues when no capacity was given
+const DEFAULT_CAPACITY: usize = 1024;
+
+/// the channel of the events no route matches
+pub const DEFAULT_CHANNEL: &str = "default";
+
+/// routes the events of a source to named channels by the path they happened on, every
+/// channel has a bounded queue and a priority, the events of the channels with a higher
+/// priority are received first, so a flood of events in a busy directory doesn't hold
+/// back the ones of a few files that matter more
+///
+/// ```no_run
+/// # use futures::StreamExt;
+/// # use tube_inotify::{Depth, Dispatcher, Inotify, Mask};
+/// # fn main() -> Result<(), tube_inotify::WatchError> {
+/// let inotify = Inotify::new()?
+///     .watch("/etc/app.toml".into(), Mask::CLOSE_WRITE)?
+///     .watch_recursive("/srv/data".into(), Mask::CREATE, Depth::any())?;
+/// let mut events = Dispatcher::new()
+///     .channel("config", 10, 16)
+///     .route("/etc/app.toml", "config")
+///     .route("/srv/data", "bulk")
+///     .spawn(inotify);
+/// # futures::executor::block_on(async {
+/// while let Some(routed) = events.next().await {
+///     let routed = routed?;
+///     println!("{}: {}", routed.channel, routed.event);
+/// }
+/// # Ok(())
+/// # })
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Dispatcher {
+    channels: Vec<Channel>,
+    routes: Vec<(PathBuf, usize)>,
+}
+
+#[derive(Debug, Clone)]
+struct Channel {
+    name: Arc<str>,
+    priority: u8,
+    capacity: usize,
+}
+
+/// an event with the channel it was routed to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Routed {
+    pub channel: Arc<str>,
+    pub event: Event,
+}
+
+impl Default for Dispatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dispatcher {
+    /// a dispatcher with only the default channel, of priority 0
+    pub fn new() -> Self {
+        Self {
+            channels: vec![Channel::new(DEFAULT_CHANNEL)],
+            routes: Vec::new(),
+        }
+    }
+
+    /// sets the priority (higher is received first, channels of the same priority in
+    /// the order they were added) and the most events queued for the channel. when a
+    /// channel is full its new events are dropped until the queued ones were received,
+    /// followed by an `OVERFLOW` event
+    pub fn channel(mut self, name: &str, priority: u8, capacity: usize) -> Self {
+        let channel = self.channel_index(name);
+        self.channels[channel].priority = priority;
+        self.channels[channel].capacity = capacity.max(1);
+        self
+    }
+
+    /// the events on the path and under it go to the channel, when routes overlap
+    /// the deepest path wins. channels that weren't set up are of priority 0
+    pub fn route<P: Into<PathBuf>>(mut self, path: P, channel: &str) -> Self {
+        let channel = self.channel_index(channel);
+        self.routes.push((path.into(), channel));
+        self
+    }
+
+    fn channel_index(&mut self, name: &str) -> usize {
+        match self.channels.iter().position(|c| &*c.name == name) {
+            Some(i) => i,
+            None => {
+                self.channels.push(Channel::new(name));
+                self.channels.len() - 1
+            }
+        }
+    }
+
+    /// the channel of an event, overflows are on every path
+    fn route_of(&self, event: &Event) -> usize {
+        self.routes
+            .iter()
+            .filter(|(path, _)| event.path.starts_with(path))
+            .max_by_key(|(path, _)| path.components().count())
+            .map_or(0, |(_, channel)| *channel)
+    }
+
+    /// reads the source on a thread of its own and routes its events, the thread
+    /// ends with the source, or on the batch read after the stream was dropped
+    pub fn spawn<S>(self, mut source: S) -> Dispatched
+    where
+        S: EventSource + Send + 'static,
+    {
+        let queues: Vec<Queue> = self
+            .channels
+            .iter()
+            .map(|channel| Queue {
+                channel: channel.clone(),
+                events: VecDeque::new(),
+                lost: false,
+            })
+            .collect();
+        let mut order: Vec<usize> = (0..queues.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(queues[i].channel.priority));
+
+        let shared = Arc::new(Mutex::new(Shared {
+            queues,
+            order,
+            errors: VecDeque::new(),
+            ended: false,
+            closed: false,
+            waker: None,
+        }));
+        let reader = shared.clone();
+        std::thread::spawn(move || {
+            futures::executor::block_on(async {
+                loop {
+                    let next = source.next_events().await;
+                    let mut shared = reader.lock().unwrap();
+                    if shared.closed {
+                        return;
+                    }
+                    match next {
+                        Some(Ok(events)) => {
+                            for event in events {
+                                let channel = match event.kind {
+                                    EventKind::Overflow => None,
+                                    _ => Some(self.route_of(&event)),
+                                };
+                                shared.push(channel, event);
+                            }
+                        }
+                        Some(Err(e)) => shared.errors.push_back(e),
+                        None => shared.ended = true,
+                    }
+                    if let Some(waker) = shared.waker.take() {
+                        waker.wake();
+                    }
+                    if shared.ended {
+                        return;
+                    }
+                }
+            })
+        });
+        Dispatched { shared }
+    }
+}
+
+impl Channel {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.into(),
+            priority: 0,
+            capacity: DEFAULT_CAPACITY,
+        }
+    }
+}
+
+/// the events routed by `Dispatcher::spawn`, the channels are drained by priority
+pub struct Dispatched {
+    shared: Arc<Mutex<Shared>>,
+}
+
+struct Shared {
+    queues: Vec<Queue>,
+    // the queues by priority
+    order: Vec<usize>,
+    // errors of the source, received before any event
+    errors: VecDeque<WatchError>,
+    ended: bool,
+    // the stream was dropped
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+struct Queue {
+    channel: Channel,
+    events: VecDeque<Event>,
+    // events were dropped since the last one received
+    lost: bool,
+}
+
+impl Shared {
+    /// `None` for the events of all channels
+    fn push(&mut self, channel: Option<usize>, event: Event) {
+        let queues = match channel {
+            Some(channel) => channel..channel + 1,
+            None => 0..self.queues.len(),
+        };
+        for queue in &mut self.queues[queues] {
+            // the overflow comes after the events still queued, nothing is
+            // queued after it until it was received
+            if queue.lost || queue.events.len() >= queue.channel.capacity {
+                queue.lost = true;
+            } else {
+                queue.events.push_back(event.clone());
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Option<Routed> {
+        let i = *self.order.iter().find(|&&i| {
+            let queue = &self.queues[i];
+            !queue.events.is_empty() || queue.lost
+        })?;
+        let queue = &mut self.queues[i];
+        let event = match queue.events.pop_front() {
+            Some(event) => event,
+            None => {
+                queue.lost = false;
+                overflow()
+            }
+        };
+        Some(Routed {
+            channel: queue.channel.name.clone(),
+            event,
+        })
+    }
+}
+
+impl Dispatched {
+    /// the events waiting in the queue of the channel
+    pub fn queued(&self, channel: &str) -> usize {
+        let shared = self.shared.lock().unwrap();
+        shared
+            .queues
+            .iter()
+            .find(|queue| &*queue.channel.name == channel)
+            .map_or(0, |queue| queue.events.len())
+    }
+}
+
+impl Stream for Dispatched {
+    type Item = Result<Routed, WatchError>;
+
+    /// errors first, then the event of the channel with the highest priority that
+    /// has one, `None` once the source ended and every queue is drained
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut shared = self.shared.lock().unwrap();
+        if let Some(e) = shared.errors.pop_front() {
+            return Poll::Ready(Some(Err(e)));
+        }
+        if let Some(routed) = shared.pop() {
+            return Poll::Ready(Some(Ok(routed)));
+        }
+        if shared.ended {
+            return Poll::Ready(None);
+        }
+        shared.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for Dispatched {
+    fn drop(&mut self) {
+        self.shared.lock().unwrap().closed = true;
+    }
+}
+
+/// received on a channel after its events were dropped
+fn overflow() -> Event {
+    Event {
+        path: PathBuf::new(),
+        kind: EventKind::Overflow,
+        cookie: 0,
+        is_dir: false,
+        process: None,
+        changes: Vec::new(),
+        inode: None,
+        renamed_from: None,
+    }
+}
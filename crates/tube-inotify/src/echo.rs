@@ -0,0 +1,76 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::event::Event;
+
+/// the writes a program expects to make itself, so the events they cause can
+/// be told apart from the changes of others. handles are cheap to clone and share
+/// the same expectations, the writing side keeps one and the instance reading the
+/// events another, see `Inotify::suppress_echoes`
+#[derive(Debug, Clone, Default)]
+pub struct Echoes {
+    inner: Arc<Mutex<Expected>>,
+}
+
+#[derive(Debug, Default)]
+struct Expected {
+    // events on the path are echoes until the deadline
+    paths: HashMap<PathBuf, Instant>,
+    // device and inode of the files being written
+    writers: HashSet<(u64, u64)>,
+}
+
+impl Echoes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the events on the path in the next `window` are echoes, should be called
+    /// before writing, the window should cover the time until the events are read
+    pub fn expect(&self, path: &Path, window: Duration) {
+        let mut expected = self.inner.lock().unwrap();
+        let now = Instant::now();
+        expected.paths.retain(|_, deadline| *deadline > now);
+        expected.paths.insert(path.to_path_buf(), now + window);
+    }
+
+    /// the events on the file are echoes as long as it is marked, whatever
+    /// name it has, until `release_writer` is called with it
+    pub fn mark_writer(&self, file: &File) {
+        if let Some(inode) = file_inode(file) {
+            self.inner.lock().unwrap().writers.insert(inode);
+        }
+    }
+
+    pub fn release_writer(&self, file: &File) {
+        if let Some(inode) = file_inode(file) {
+            self.inner.lock().unwrap().writers.remove(&inode);
+        }
+    }
+
+    /// checks if the event was caused by an expected write
+    pub fn is_echo(&self, event: &Event) -> bool {
+        let expected = self.inner.lock().unwrap();
+        if expected
+            .paths
+            .get(&event.path)
+            .is_some_and(|deadline| *deadline > Instant::now())
+        {
+            return true;
+        }
+        if expected.writers.is_empty() {
+            return false;
+        }
+        std::fs::symlink_metadata(&event.path)
+            .is_ok_and(|metadata| expected.writers.contains(&(metadata.dev(), metadata.ino())))
+    }
+}
+
+fn file_inode(file: &File) -> Option<(u64, u64)> {
+    let metadata = file.metadata().ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
@@ -1,10 +1,14 @@
 use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::ffi;
 
 #[derive(Debug)]
 pub struct Errno(i32);
 
 /// contains all errno values that can be found
 /// in C, represent them as rust enum
+#[non_exhaustive]
 pub enum ErrnoKind {
     EPERM,
     ENOENT,
@@ -54,11 +58,21 @@ impl Errno {
     pub fn kind(&self) -> ErrnoKind {
         ErrnoKind::from(self)
     }
+
+    /// returns the raw errno code
+    pub fn raw(&self) -> i32 {
+        self.0
+    }
 }
 
 impl fmt::Display for Errno {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}: {}", self.kind(), "")
+        write!(
+            f,
+            "{}: {}",
+            self.kind(),
+            std::io::Error::from_raw_os_error(self.0)
+        )
     }
 }
 
@@ -165,3 +179,89 @@ impl From<i32> for ErrnoKind {
         }
     }
 }
+
+/// what the instance was doing when an error happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Op {
+    /// creating the instance with `inotify_init1`
+    Init,
+    /// adding a watch with `inotify_add_watch`
+    Watch,
+    /// removing a watch with `inotify_rm_watch`
+    Unwatch,
+    /// listing a directory while watching a tree
+    Walk,
+    /// waiting for events with `poll`
+    Poll,
+    /// reading events from the descriptor
+    Read,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Init => write!(f, "couldn't create the inotify instance"),
+            Self::Watch => write!(f, "couldn't watch"),
+            Self::Unwatch => write!(f, "couldn't unwatch"),
+            Self::Walk => write!(f, "couldn't list"),
+            Self::Poll => write!(f, "couldn't poll for events"),
+            Self::Read => write!(f, "couldn't read events"),
+        }
+    }
+}
+
+/// the errors of the instance, with what it was doing and the path
+/// it was doing it on, when there is one
+#[derive(Debug)]
+pub struct WatchError {
+    pub op: Op,
+    pub path: Option<PathBuf>,
+    pub errno: Errno,
+}
+
+impl WatchError {
+    pub fn new(op: Op, path: Option<PathBuf>, errno: Errno) -> Self {
+        Self { op, path, errno }
+    }
+
+    /// the error of the last syscall
+    pub(crate) fn last(op: Op, path: Option<&Path>) -> Self {
+        Self::new(op, path.map(Path::to_path_buf), Errno::last())
+    }
+
+    /// checks if the path is gone, e.g. removed while a tree was walked
+    pub fn is_not_found(&self) -> bool {
+        self.errno.raw() == ffi::ENOENT
+    }
+}
+
+impl fmt::Display for WatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{} `{}`: {}", self.op, path.display(), self.errno),
+            None => write!(f, "{}: {}", self.op, self.errno),
+        }
+    }
+}
+
+impl std::error::Error for WatchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.errno)
+    }
+}
+
+impl From<WatchError> for Errno {
+    fn from(value: WatchError) -> Self {
+        value.errno
+    }
+}
+
+impl From<WatchError> for std::io::Error {
+    fn from(value: WatchError) -> Self {
+        std::io::Error::new(
+            std::io::Error::from_raw_os_error(value.errno.raw()).kind(),
+            value,
+        )
+    }
+}
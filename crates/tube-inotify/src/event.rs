@@ -0,0 +1,200 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::ffi;
+use crate::meta::MetaChange;
+use crate::process::Process;
+
+/// the kind of a single inotify event, every event the kernel reports
+/// carries exactly one of those bits in its mask. kinds can be added, and
+/// bits newer kernels report are kept in `Other`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum EventKind {
+    Access,
+    Modify,
+    Attrib,
+    CloseWrite,
+    CloseNoWrite,
+    Open,
+    MovedFrom,
+    MovedTo,
+    Create,
+    Delete,
+    DeleteSelf,
+    MoveSelf,
+    Unmount,
+    Overflow,
+    Ignored,
+    /// a file was written and closed, then renamed to its final name or left
+    /// alone long enough, reported by `Uploads` in place of the events of the
+    /// upload, never by the kernel
+    FileReady,
+    /// bits of the mask that are not known to the crate
+    Other(u32),
+}
+
+impl EventKind {
+    /// returns the `EventKind` for the given raw inotify mask, flag bits like
+    /// `IN_ISDIR` are ignored, unknown bits are returned as `Other`, `None` is
+    /// returned if no bit is left
+    pub fn from_mask(mask: u32) -> Option<Self> {
+        let kind = match mask {
+            m if m & ffi::IN_ACCESS != 0 => Self::Access,
+            m if m & ffi::IN_MODIFY != 0 => Self::Modify,
+            m if m & ffi::IN_ATTRIB != 0 => Self::Attrib,
+            m if m & ffi::IN_CLOSE_WRITE != 0 => Self::CloseWrite,
+            m if m & ffi::IN_CLOSE_NOWRITE != 0 => Self::CloseNoWrite,
+            m if m & ffi::IN_OPEN != 0 => Self::Open,
+            m if m & ffi::IN_MOVED_FROM != 0 => Self::MovedFrom,
+            m if m & ffi::IN_MOVED_TO != 0 => Self::MovedTo,
+            m if m & ffi::IN_CREATE != 0 => Self::Create,
+            m if m & ffi::IN_DELETE != 0 => Self::Delete,
+            m if m & ffi::IN_DELETE_SELF != 0 => Self::DeleteSelf,
+            m if m & ffi::IN_MOVE_SELF != 0 => Self::MoveSelf,
+            m if m & ffi::IN_UNMOUNT != 0 => Self::Unmount,
+            m if m & ffi::IN_Q_OVERFLOW != 0 => Self::Overflow,
+            m if m & ffi::IN_IGNORED != 0 => Self::Ignored,
+            m if m & !ffi::IN_ISDIR != 0 => Self::Other(m & !ffi::IN_ISDIR),
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    /// returns the raw inotify mask bit for the kind
+    pub fn mask(&self) -> u32 {
+        match self {
+            Self::Access => ffi::IN_ACCESS,
+            Self::Modify => ffi::IN_MODIFY,
+            Self::Attrib => ffi::IN_ATTRIB,
+            Self::CloseWrite => ffi::IN_CLOSE_WRITE,
+            Self::CloseNoWrite => ffi::IN_CLOSE_NOWRITE,
+            Self::Open => ffi::IN_OPEN,
+            Self::MovedFrom => ffi::IN_MOVED_FROM,
+            Self::MovedTo => ffi::IN_MOVED_TO,
+            Self::Create => ffi::IN_CREATE,
+            Self::Delete => ffi::IN_DELETE,
+            Self::DeleteSelf => ffi::IN_DELETE_SELF,
+            Self::MoveSelf => ffi::IN_MOVE_SELF,
+            Self::Unmount => ffi::IN_UNMOUNT,
+            Self::Overflow => ffi::IN_Q_OVERFLOW,
+            Self::Ignored => ffi::IN_IGNORED,
+            // made of several kernel events, no single bit stands for it
+            Self::FileReady => 0,
+            Self::Other(bits) => *bits,
+        }
+    }
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Access => write!(f, "ACCESS"),
+            Self::Modify => write!(f, "MODIFY"),
+            Self::Attrib => write!(f, "ATTRIB"),
+            Self::CloseWrite => write!(f, "CLOSE_WRITE"),
+            Self::CloseNoWrite => write!(f, "CLOSE_NOWRITE"),
+            Self::Open => write!(f, "OPEN"),
+            Self::MovedFrom => write!(f, "MOVED_FROM"),
+            Self::MovedTo => write!(f, "MOVED_TO"),
+            Self::Create => write!(f, "CREATE"),
+            Self::Delete => write!(f, "DELETE"),
+            Self::DeleteSelf => write!(f, "DELETE_SELF"),
+            Self::MoveSelf => write!(f, "MOVE_SELF"),
+            Self::Unmount => write!(f, "UNMOUNT"),
+            Self::Overflow => write!(f, "OVERFLOW"),
+            Self::Ignored => write!(f, "IGNORED"),
+            Self::FileReady => write!(f, "FILE_READY"),
+            Self::Other(bits) => write!(f, "{:#x}", bits),
+        }
+    }
+}
+
+/// parses the names printed by `Display`, so kinds can be read back from
+/// anything that was written with them (logs, recordings)
+impl FromStr for EventKind {
+    type Err = UnknownEventKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let kind = match s {
+            "ACCESS" => Self::Access,
+            "MODIFY" => Self::Modify,
+            "ATTRIB" => Self::Attrib,
+            "CLOSE_WRITE" => Self::CloseWrite,
+            "CLOSE_NOWRITE" => Self::CloseNoWrite,
+            "OPEN" => Self::Open,
+            "MOVED_FROM" => Self::MovedFrom,
+            "MOVED_TO" => Self::MovedTo,
+            "CREATE" => Self::Create,
+            "DELETE" => Self::Delete,
+            "DELETE_SELF" => Self::DeleteSelf,
+            "MOVE_SELF" => Self::MoveSelf,
+            "UNMOUNT" => Self::Unmount,
+            "OVERFLOW" => Self::Overflow,
+            "IGNORED" => Self::Ignored,
+            "FILE_READY" => Self::FileReady,
+            _ => return Self::from_bits(s).ok_or_else(|| UnknownEventKind(s.to_string())),
+        };
+        Ok(kind)
+    }
+}
+
+impl EventKind {
+    /// the hex mask `Other` kinds are printed as
+    fn from_bits(s: &str) -> Option<Self> {
+        let bits = u32::from_str_radix(s.strip_prefix("0x")?, 16).ok()?;
+        Self::from_mask(bits)
+    }
+}
+
+/// returned when parsing a name that is not a known `EventKind`
+#[derive(Debug)]
+pub struct UnknownEventKind(String);
+
+impl fmt::Display for UnknownEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown event kind `{}`", self.0)
+    }
+}
+
+impl std::error::Error for UnknownEventKind {}
+
+/// a resolved event, unlike `InotifyEvent` which only carries the watch
+/// descriptor, `Event` holds the full path the event happened on
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Event {
+    pub path: PathBuf,
+    pub kind: EventKind,
+    pub cookie: u32,
+    pub is_dir: bool,
+    /// who caused the event, only set by an `Attributor`
+    pub process: Option<Process>,
+    /// what metadata changed on `ATTRIB` events, only set by a `MetaCache`
+    pub changes: Vec<MetaChange>,
+    /// the inode number of the file, hard links share it, only set by an `InodeTracker`
+    pub inode: Option<u64>,
+    /// the path a `MOVED_TO` was renamed from, only set by an `InodeTracker`
+    pub renamed_from: Option<PathBuf>,
+}
+
+/// `CREATE file foo.txt in /watch/dir`, overflows are not on any path
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.as_os_str().is_empty() {
+            return write!(f, "{}", self.kind);
+        }
+        let subject = if self.is_dir { "dir" } else { "file" };
+        match (self.path.parent(), self.path.file_name()) {
+            (Some(parent), Some(name)) => write!(
+                f,
+                "{} {} {} in {}",
+                self.kind,
+                subject,
+                name.to_string_lossy(),
+                parent.display()
+            ),
+            _ => write!(f, "{} {} {}", self.kind, subject, self.path.display()),
+        }
+    }
+}
@@ -1,14 +1,55 @@
-use std::os::raw::{c_char, c_int, c_short, c_ulong};
+#![allow(non_camel_case_types, dead_code)]
+
+use std::os::raw::{c_char, c_int, c_short, c_uint, c_ulong};
 
 pub const POLLIN: c_short = 0x001;
+pub const POLLERR: c_short = 0x008;
+pub const POLLNVAL: c_short = 0x020;
+
+pub const ENOENT: c_int = 2;
+pub const EBADF: c_int = 9;
+pub const EAGAIN: c_int = 11;
+pub const EINVAL: c_int = 22;
 
 pub const IN_NONBLOCK: c_int = 2048;
+pub const EFD_NONBLOCK: c_int = 0o4000;
+pub const EFD_CLOEXEC: c_int = 0o2000000;
+pub const FIONREAD: c_ulong = 0x541B;
+pub const IN_ACCESS: u32 = 0x00000001;
+pub const IN_MODIFY: u32 = 0x00000002;
+pub const IN_ATTRIB: u32 = 0x00000004;
 pub const IN_CLOSE_WRITE: u32 = 0x00000008;
 pub const IN_CLOSE_NOWRITE: u32 = 0x00000010;
 pub const IN_OPEN: u32 = 0x00000020;
+pub const IN_MOVED_FROM: u32 = 0x00000040;
+pub const IN_MOVED_TO: u32 = 0x00000080;
 pub const IN_CLOSE: u32 = IN_CLOSE_WRITE | IN_CLOSE_NOWRITE;
+pub const IN_MOVE: u32 = IN_MOVED_FROM | IN_MOVED_TO;
 pub const IN_CREATE: u32 = 0x00000100;
 pub const IN_DELETE: u32 = 0x00000200;
+pub const IN_DELETE_SELF: u32 = 0x00000400;
+pub const IN_MOVE_SELF: u32 = 0x00000800;
+pub const IN_ALL_EVENTS: u32 = 0x00000fff;
+
+pub const IN_UNMOUNT: u32 = 0x00002000;
+pub const IN_Q_OVERFLOW: u32 = 0x00004000;
+pub const IN_IGNORED: u32 = 0x00008000;
+pub const IN_DONT_FOLLOW: u32 = 0x02000000;
+pub const IN_MASK_ADD: u32 = 0x20000000;
+pub const IN_ISDIR: u32 = 0x40000000;
+
+pub const AT_FDCWD: c_int = -100;
+pub const O_RDONLY: c_uint = 0;
+pub const O_LARGEFILE: c_uint = 0o100000;
+
+pub const FAN_CLOEXEC: c_uint = 0x01;
+pub const FAN_NONBLOCK: c_uint = 0x02;
+pub const FAN_CLASS_NOTIF: c_uint = 0x00;
+pub const FAN_MARK_ADD: c_uint = 0x01;
+pub const FAN_MARK_MOUNT: c_uint = 0x10;
+pub const FAN_MODIFY: u64 = 0x02;
+pub const FAN_CLOSE_WRITE: u64 = 0x08;
+pub const FAN_NOFD: c_int = -1;
 
 pub type nfds_t = c_ulong;
 
@@ -20,6 +61,24 @@ pub struct inotify_event {
     pub len: u32,
 }
 
+#[repr(C)]
+pub struct fanotify_event_metadata {
+    pub event_len: u32,
+    pub vers: u8,
+    pub reserved: u8,
+    pub metadata_len: u16,
+    pub mask: u64,
+    pub fd: c_int,
+    pub pid: c_int,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct iovec {
+    pub iov_base: *mut u8,
+    pub iov_len: usize,
+}
+
 #[repr(C)]
 pub struct pollfd {
     pub fd: c_int,
@@ -33,7 +92,26 @@ extern "C" {
     pub(crate) fn inotify_add_watch(fd: c_int, pathname: *const c_char, mask: u32) -> c_int;
     pub(crate) fn inotify_rm_watch(fd: c_int, wd: c_int) -> c_int;
     pub(crate) fn read(fd: c_int, buf: *mut u8, count: usize) -> isize;
+    pub(crate) fn readv(fd: c_int, iov: *const iovec, iovcnt: c_int) -> isize;
+    pub(crate) fn write(fd: c_int, buf: *const u8, count: usize) -> isize;
     pub(crate) fn close(fd: c_int) -> c_int;
+    pub(crate) fn eventfd(initval: c_uint, flags: c_int) -> c_int;
+    pub(crate) fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
     pub(crate) fn poll(fds: *mut pollfd, nfds: nfds_t, timeout: c_int) -> c_int;
     pub(crate) fn __errno_location() -> *mut c_int;
+    pub(crate) fn llistxattr(path: *const c_char, list: *mut c_char, size: usize) -> isize;
+    pub(crate) fn lgetxattr(
+        path: *const c_char,
+        name: *const c_char,
+        value: *mut u8,
+        size: usize,
+    ) -> isize;
+    pub(crate) fn fanotify_init(flags: c_uint, event_f_flags: c_uint) -> c_int;
+    pub(crate) fn fanotify_mark(
+        fd: c_int,
+        flags: c_uint,
+        mask: u64,
+        dirfd: c_int,
+        pathname: *const c_char,
+    ) -> c_int;
 }
@@ -0,0 +1,169 @@
+use std::collections::{BTreeSet, HashMap};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::event::{Event, EventKind};
+
+/// how long a `MOVED_FROM` waits for the `MOVED_TO` of the same inode
+const MOVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// a file is identified by its inode and the device it lives on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Inode {
+    dev: u64,
+    ino: u64,
+}
+
+impl Inode {
+    /// without following symlinks, `None` if the path is gone
+    fn read(path: &Path) -> Option<Self> {
+        let metadata = path.symlink_metadata().ok()?;
+        Some(Self {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+        })
+    }
+}
+
+/// keeps the inode of every path seen in events, so events on hard links
+/// of the same file can be grouped by `Event::inode`, and a `MOVED_TO` is
+/// matched with the `MOVED_FROM` of the same inode even when their cookies
+/// were read in different batches. files that existed before can be added
+/// with `remember`
+#[derive(Debug, Default)]
+pub struct InodeTracker {
+    paths: HashMap<PathBuf, Inode>,
+    links: HashMap<Inode, BTreeSet<PathBuf>>,
+    // moved away, waiting for the name they were moved to
+    moved: HashMap<Inode, (PathBuf, Instant)>,
+}
+
+impl InodeTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// reads the current inode of the path
+    pub fn remember(&mut self, path: &Path) {
+        if let Some(inode) = Inode::read(path) {
+            self.insert(path.to_path_buf(), inode);
+        }
+    }
+
+    /// the other known names of the file, its hard links
+    pub fn links(&self, path: &Path) -> Vec<&Path> {
+        let Some(inode) = self.paths.get(path) else {
+            return Vec::new();
+        };
+        self.links[inode]
+            .iter()
+            .map(PathBuf::as_path)
+            .filter(|&link| link != path)
+            .collect()
+    }
+
+    /// fills the inode of the event, and the path a `MOVED_TO` came from
+    /// when the same inode was moved away before
+    pub fn update(&mut self, event: &mut Event) {
+        let now = Instant::now();
+        let expired: Vec<Inode> = self
+            .moved
+            .iter()
+            .filter(|(_, (_, at))| now.duration_since(*at) >= MOVE_TIMEOUT)
+            .map(|(&inode, _)| inode)
+            .collect();
+        for inode in expired {
+            // moved out of the watched paths, along with what was under it
+            if let Some((from, _)) = self.moved.remove(&inode) {
+                self.forget_children(&from);
+            }
+        }
+
+        let inode = match event.kind {
+            EventKind::Delete | EventKind::DeleteSelf => self.remove(&event.path),
+            EventKind::MovedFrom => {
+                let inode = self.remove(&event.path);
+                if let Some(inode) = inode {
+                    self.moved.insert(inode, (event.path.clone(), now));
+                }
+                inode
+            }
+            EventKind::MovedTo => {
+                let inode = Inode::read(&event.path);
+                if let Some((from, _)) = inode.and_then(|inode| self.moved.remove(&inode)) {
+                    if event.is_dir {
+                        self.rename_children(&from, &event.path);
+                    }
+                    event.renamed_from = Some(from);
+                }
+                inode
+            }
+            EventKind::Overflow | EventKind::Ignored | EventKind::Unmount => None,
+            // the name may point to another file since it was last seen
+            _ => Inode::read(&event.path).or_else(|| self.paths.get(&event.path).copied()),
+        };
+        if let Some(inode) = inode {
+            if !matches!(
+                event.kind,
+                EventKind::Delete | EventKind::DeleteSelf | EventKind::MovedFrom
+            ) {
+                self.insert(event.path.clone(), inode);
+            }
+            event.inode = Some(inode.ino);
+        }
+    }
+
+    fn insert(&mut self, path: PathBuf, inode: Inode) {
+        // the name may have been reused by another file
+        if let Some(old) = self.paths.insert(path.clone(), inode) {
+            if old != inode {
+                self.unlink(&path, old);
+            }
+        }
+        self.links.entry(inode).or_default().insert(path);
+    }
+
+    fn remove(&mut self, path: &Path) -> Option<Inode> {
+        let inode = self.paths.remove(path)?;
+        self.unlink(path, inode);
+        Some(inode)
+    }
+
+    fn unlink(&mut self, path: &Path, inode: Inode) {
+        if let Some(links) = self.links.get_mut(&inode) {
+            links.remove(path);
+            if links.is_empty() {
+                self.links.remove(&inode);
+            }
+        }
+    }
+
+    fn forget_children(&mut self, dir: &Path) {
+        let children: Vec<PathBuf> = self
+            .paths
+            .keys()
+            .filter(|path| path.starts_with(dir))
+            .cloned()
+            .collect();
+        for path in children {
+            self.remove(&path);
+        }
+    }
+
+    /// the paths under a renamed directory keep their inodes under the new name
+    fn rename_children(&mut self, from: &Path, to: &Path) {
+        let children: Vec<(PathBuf, Inode)> = self
+            .paths
+            .iter()
+            .filter(|(path, _)| path.starts_with(from))
+            .map(|(path, &inode)| (path.clone(), inode))
+            .collect();
+        for (path, inode) in children {
+            self.remove(&path);
+            if let Ok(rest) = path.strip_prefix(from) {
+                self.insert(to.join(rest), inode);
+            }
+        }
+    }
+}
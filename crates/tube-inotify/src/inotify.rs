@@ -1,40 +1,236 @@
-use futures::stream::Stream;
-use std::collections::HashMap;
-use std::ffi::{OsStr, OsString};
+use futures::stream::{Stream, StreamExt};
+use std::collections::{HashMap, HashSet};
+use std::ffi::{CString, OsStr, OsString};
 use std::fmt;
 use std::os::fd::{AsRawFd, RawFd};
 use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
-use std::task::{Context, Poll};
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use tube_core::AsyncFd;
 
-use crate::errno::Errno;
+use crate::echo::Echoes;
+use crate::errno::{Errno, Op, WatchError};
+use crate::event::{Event, EventKind};
 use crate::ffi;
+use crate::masks::PathMasks;
+use crate::pool::{Pool, MAX_BUFFERS};
+use crate::rescan::Rescan;
+use crate::shutdown::ShutdownToken;
+use crate::state::{SavedTree, SavedWatch, WatchState};
+use crate::stats::{Lag, Stats};
+use crate::stream::{Resolved, ResolvedMoves};
 
 pub const SYSCALL_ERROR: i32 = -1;
 
+/// the most a single `read` of the descriptor returns
+const READ_SIZE: usize = 4096;
+
 /// a opaque struct that defines consts that can be used
 /// as flags with bitwise operations
 pub struct Mask;
 
 impl Mask {
+    pub const ACCESS: u32 = ffi::IN_ACCESS;
+    pub const MODIFY: u32 = ffi::IN_MODIFY;
+    pub const ATTRIB: u32 = ffi::IN_ATTRIB;
     pub const CREATE: u32 = ffi::IN_CREATE;
     pub const DELETE: u32 = ffi::IN_DELETE;
+    pub const DELETE_SELF: u32 = ffi::IN_DELETE_SELF;
     pub const OPEN: u32 = ffi::IN_OPEN;
     pub const CLOSE: u32 = ffi::IN_CLOSE;
     pub const CLOSE_WRITE: u32 = ffi::IN_CLOSE_WRITE;
     pub const CLOSE_NOWRITE: u32 = ffi::IN_CLOSE_NOWRITE;
+    pub const MOVED_FROM: u32 = ffi::IN_MOVED_FROM;
+    pub const MOVED_TO: u32 = ffi::IN_MOVED_TO;
+    pub const MOVE: u32 = ffi::IN_MOVE;
+    pub const MOVE_SELF: u32 = ffi::IN_MOVE_SELF;
+    pub const ALL_EVENTS: u32 = ffi::IN_ALL_EVENTS;
+
+    /// doesn't follow the path if it's a symlink, see `Symlinks::DontFollow`
+    pub const DONT_FOLLOW: u32 = ffi::IN_DONT_FOLLOW;
+
+    /// adds the events to the ones of the watch the file already has instead of
+    /// replacing them, see `Inotify::try_add_watch`
+    pub const MASK_ADD: u32 = ffi::IN_MASK_ADD;
+
+    /// set by the kernel on events that happened on a directory
+    pub const ISDIR: u32 = ffi::IN_ISDIR;
+
+    /// formats the names of the bits set in the mask as `CREATE|ISDIR`,
+    /// bits without a name are printed in hex
+    pub fn names(mask: u32) -> MaskNames {
+        MaskNames(mask)
+    }
+}
+
+/// the bits `MaskNames` knows the name of, the names are the ones `EventKind` prints
+const MASK_NAMES: [(u32, &str); 17] = [
+    (ffi::IN_ACCESS, "ACCESS"),
+    (ffi::IN_MODIFY, "MODIFY"),
+    (ffi::IN_ATTRIB, "ATTRIB"),
+    (ffi::IN_CLOSE_WRITE, "CLOSE_WRITE"),
+    (ffi::IN_CLOSE_NOWRITE, "CLOSE_NOWRITE"),
+    (ffi::IN_OPEN, "OPEN"),
+    (ffi::IN_MOVED_FROM, "MOVED_FROM"),
+    (ffi::IN_MOVED_TO, "MOVED_TO"),
+    (ffi::IN_CREATE, "CREATE"),
+    (ffi::IN_DELETE, "DELETE"),
+    (ffi::IN_DELETE_SELF, "DELETE_SELF"),
+    (ffi::IN_MOVE_SELF, "MOVE_SELF"),
+    (ffi::IN_UNMOUNT, "UNMOUNT"),
+    (ffi::IN_Q_OVERFLOW, "OVERFLOW"),
+    (ffi::IN_IGNORED, "IGNORED"),
+    (ffi::IN_DONT_FOLLOW, "DONT_FOLLOW"),
+    (ffi::IN_ISDIR, "ISDIR"),
+];
+
+/// a mask printed by the names of its bits, returned by `Mask::names`
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct MaskNames(pub u32);
+
+impl fmt::Display for MaskNames {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == 0 {
+            return write!(f, "0");
+        }
+        let mut left = self.0;
+        let mut first = true;
+        for (bit, name) in MASK_NAMES {
+            if left & bit != 0 {
+                if !first {
+                    write!(f, "|")?;
+                }
+                write!(f, "{}", name)?;
+                left &= !bit;
+                first = false;
+            }
+        }
+        match (left, first) {
+            (0, _) => Ok(()),
+            (left, true) => write!(f, "{:#x}", left),
+            (left, false) => write!(f, "|{:#x}", left),
+        }
+    }
+}
+
+impl fmt::Debug for MaskNames {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
 }
 
 pub struct Flag;
 
+/// how symlinks are watched, set with `Inotify::symlinks` and kept by every
+/// watch added after, so watches added in between can differ
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Symlinks {
+    /// the link target is watched and events are reported under the link path,
+    /// `watch_recursive` descends into linked directories
+    #[default]
+    Follow,
+    /// the link itself is watched, `watch_recursive` doesn't descend into linked directories
+    DontFollow,
+    /// the link target is watched and events are reported under the target path,
+    /// `watch_recursive` descends into linked directories
+    ResolveTarget,
+}
+
+/// how deep `watch_recursive` goes, counted the way `find` counts, the watched
+/// directory is at depth 0 and its entries at depth 1. events on paths outside
+/// of `min..=max` are not reported, the directories needed to reach `max` are
+/// watched even when they are above `min`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Depth {
+    pub min: usize,
+    /// `None` for no limit
+    pub max: Option<usize>,
+}
+
+impl Depth {
+    /// every level, same as `Depth::default()`
+    pub fn any() -> Self {
+        Self::default()
+    }
+
+    /// `0` reports only the events on the watched directory itself, `1` the
+    /// events of its entries too, without watching the directories under it
+    pub fn max_depth(mut self, max: usize) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    /// `1` leaves out the events on the watched directory itself
+    pub fn min_depth(mut self, min: usize) -> Self {
+        self.min = min;
+        self
+    }
+
+    /// checks if the events of paths at the depth are reported
+    pub fn contains(&self, depth: usize) -> bool {
+        depth >= self.min && self.max.is_none_or(|max| depth <= max)
+    }
+}
+
+/// what `Inotify::try_add_watch` did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Added {
+    /// the file wasn't watched yet
+    Watch(RawFd),
+    /// the file was already watched, maybe under another path which is kept,
+    /// the watch now reports the events of the added mask, along with the ones
+    /// of its mask if `Mask::MASK_ADD` was given
+    AlreadyWatched {
+        wd: RawFd,
+        path: PathBuf,
+        existing_mask: u32,
+    },
+}
+
+impl Added {
+    /// the watch descriptor of the file
+    pub fn wd(&self) -> RawFd {
+        match self {
+            Self::Watch(wd) | Self::AlreadyWatched { wd, .. } => *wd,
+        }
+    }
+}
+
+/// how `Inotify::pause` stops the events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pause {
+    /// stops reading, the kernel keeps queueing the events (up to its queue
+    /// limit, then reports an overflow) and they are read after `resume`
+    Buffer,
+    /// removes the watches, nothing is queued while paused, events that were
+    /// queued and not read yet are lost. `resume` adds the watches again and walks
+    /// the recursive ones for directories created in between
+    Detach,
+}
+
+/// how many reads `Inotify` makes every time the descriptor is ready
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReadStrategy {
+    /// a single read of up to 4096 bytes per batch
+    #[default]
+    Single,
+    /// reads until nothing is left (or `max_bytes` were read) and yields all
+    /// of it as one batch, so a busy queue is emptied with a single wakeup
+    Drain { max_bytes: usize },
+}
+
 impl Flag {
     pub const NONBLOCKING: i32 = ffi::IN_NONBLOCK;
 }
 
 /// a single InotifyEvent, those events are returned by `InotifyEventBatch`
-/// when iterating over it
-#[derive(Debug)]
+/// when iterating over it, events compare by their raw fields, names
+/// byte for byte
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct InotifyEvent {
     wd: RawFd,
     mask: u32,
@@ -46,17 +242,86 @@ impl InotifyEvent {
     /// returns `InotifyEvent` from given silice, because the `name` field can be dynamic
     /// function also returns the size in bytes of the event, in case the original buffer
     /// contains multiple events and the caller to `from_buffer` need to know the size in buffer
-    /// of the returned event
-    fn from_buffer(buffer: &[u8]) -> (usize, Self) {
+    /// of the returned event. the slice has to start with an event as the kernel writes it,
+    /// `None` is returned if the event is cut short
+    pub fn from_buffer(buffer: &[u8]) -> Option<(usize, Self)> {
+        InotifyEventRef::from_buffer(buffer).map(|(size, event)| (size, event.to_owned()))
+    }
+
+    /// returns the watch descriptor the event was reported for
+    pub fn wd(&self) -> RawFd {
+        self.wd
+    }
+
+    /// returns the raw mask of the event, containing the event
+    /// bit and flags like `Mask::ISDIR`
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    /// returns the cookie, used to pair `MOVED_FROM` and `MOVED_TO` events
+    pub fn cookie(&self) -> u32 {
+        self.cookie
+    }
+
+    /// returns the name of the file inside the watched directory, `None`
+    /// when the event is on the watched path itself
+    pub fn name(&self) -> Option<&OsStr> {
+        self.name.as_deref().filter(|name| !name.is_empty())
+    }
+
+    /// returns `true` if the event subject is a directory
+    pub fn is_dir(&self) -> bool {
+        self.mask & ffi::IN_ISDIR != 0
+    }
+}
+
+impl fmt::Debug for InotifyEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InotifyEvent")
+            .field("wd", &self.wd)
+            .field("mask", &MaskNames(self.mask))
+            .field("cookie", &self.cookie)
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+/// `CREATE file foo.txt in watch 1`, only the watch descriptor is known
+/// before the event is resolved, see `Inotify::resolve`
+impl fmt::Display for InotifyEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&InotifyEventRef::from(self), f)
+    }
+}
+
+/// same as `InotifyEvent`, with the name borrowed from the buffer the event was
+/// read into, so reading it doesn't allocate. returned by `InotifyEventBatch::refs`,
+/// `to_owned` copies it out for keeping it after the batch is dropped
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InotifyEventRef<'buf> {
+    wd: RawFd,
+    mask: u32,
+    cookie: u32,
+    name: Option<&'buf OsStr>,
+}
+
+impl<'buf> InotifyEventRef<'buf> {
+    /// same as `InotifyEvent::from_buffer`
+    pub fn from_buffer(buffer: &'buf [u8]) -> Option<(usize, Self)> {
         let event_size = std::mem::size_of::<ffi::inotify_event>();
+        if buffer.len() < event_size {
+            return None;
+        }
         let ptr = buffer.as_ptr() as *const ffi::inotify_event;
-        assert!(buffer.len() >= event_size);
-
-        let ffi_event = unsafe { ptr.read() };
+        let ffi_event = unsafe { ptr.read_unaligned() };
 
         // index to the last byte in the buffer, the `ffi_event.len` defines
         // the length of `name` field, which is dynamic size and part of the event
-        let event_end = event_size + ffi_event.len as usize;
+        let event_end = event_size.checked_add(ffi_event.len as usize)?;
+        if buffer.len() < event_end {
+            return None;
+        }
 
         // the name is an optional field that is defined at the end of the event buffer,
         // the `ffi_event.len` defines the length of the name string, so we
@@ -64,7 +329,7 @@ impl InotifyEvent {
         // which should be the end of name string
         let name = buffer[event_size..event_end]
             .splitn(2, |c| c == &0u8)
-            .map(|s| OsStr::from_bytes(s).to_os_string())
+            .map(OsStr::from_bytes)
             .next();
 
         let event = Self {
@@ -73,41 +338,177 @@ impl InotifyEvent {
             cookie: ffi_event.cookie,
             name,
         };
-        (event_end, event)
+        Some((event_end, event))
+    }
+
+    /// returns the watch descriptor the event was reported for
+    pub fn wd(&self) -> RawFd {
+        self.wd
+    }
+
+    /// returns the raw mask of the event, containing the event
+    /// bit and flags like `Mask::ISDIR`
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    /// returns the cookie, used to pair `MOVED_FROM` and `MOVED_TO` events
+    pub fn cookie(&self) -> u32 {
+        self.cookie
+    }
+
+    /// returns the name of the file inside the watched directory, `None`
+    /// when the event is on the watched path itself
+    pub fn name(&self) -> Option<&'buf OsStr> {
+        self.name.filter(|name| !name.is_empty())
+    }
+
+    /// returns `true` if the event subject is a directory
+    pub fn is_dir(&self) -> bool {
+        self.mask & ffi::IN_ISDIR != 0
+    }
+
+    /// copies the event out of the buffer
+    pub fn to_owned(&self) -> InotifyEvent {
+        InotifyEvent {
+            wd: self.wd,
+            mask: self.mask,
+            cookie: self.cookie,
+            name: self.name.map(OsStr::to_os_string),
+        }
+    }
+}
+
+impl<'a> From<&'a InotifyEvent> for InotifyEventRef<'a> {
+    fn from(event: &'a InotifyEvent) -> Self {
+        Self {
+            wd: event.wd,
+            mask: event.mask,
+            cookie: event.cookie,
+            name: event.name.as_deref(),
+        }
+    }
+}
+
+impl fmt::Debug for InotifyEventRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InotifyEventRef")
+            .field("wd", &self.wd)
+            .field("mask", &MaskNames(self.mask))
+            .field("cookie", &self.cookie)
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+/// same as the `Display` of `InotifyEvent`
+impl fmt::Display for InotifyEventRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let subject = if self.is_dir() { "dir" } else { "file" };
+        write!(f, "{}", MaskNames(self.mask & !ffi::IN_ISDIR))?;
+        match self.name() {
+            Some(name) => write!(
+                f,
+                " {} {} in watch {}",
+                subject,
+                name.to_string_lossy(),
+                self.wd
+            ),
+            None => write!(f, " on watch {}", self.wd),
+        }
     }
 }
 
-/// a struct that holds a buffer that should contain `InotifyEvent`'s, the buffer should be
-/// filled by syscall `read` when reading from the inotify descriptor
+/// a struct that holds buffers that should contain `InotifyEvent`'s, the buffers are
+/// filled by syscall `readv` when reading from the inotify descriptor, every buffer holds
+/// whole events. the buffers come from the pool of the `Inotify` instance and are given
+/// back to it when the batch is dropped, `N` is the default size of a single read
 #[derive(Debug)]
 pub struct InotifyEventBatch<const N: usize> {
-    buffer: [u8; N],
-    num_bytes: usize,
+    buffers: Vec<Vec<u8>>,
+    // the buffer and the position in it of the next event
+    index: usize,
     pos: usize,
+    pool: Pool,
 }
 
 impl<const N: usize> InotifyEventBatch<N> {
-    fn new(buffer: [u8; N], num_bytes: usize) -> Self {
+    fn new(buffers: Vec<Vec<u8>>, pool: Pool) -> Self {
         Self {
-            buffer,
-            num_bytes,
+            buffers,
+            index: 0,
+            pos: 0,
+            pool,
+        }
+    }
+
+    /// a batch of the events in the buffer, which holds whole events in the
+    /// format the kernel writes them, e.g. events that were read earlier
+    pub fn from_bytes(buffer: Vec<u8>) -> Self {
+        let pool = Pool::new(buffer.len(), 1, 0);
+        Self::new(vec![buffer], pool)
+    }
+
+    /// borrows every event of the batch without copying the names, whatever
+    /// was already taken by iterating over the batch itself
+    pub fn refs(&self) -> InotifyEventRefs<'_> {
+        InotifyEventRefs {
+            buffers: &self.buffers,
+            index: 0,
             pos: 0,
         }
     }
 }
 
-/// iterates over the events found in the given buffer returned by syscall `read`
+/// iterates over the events found in the given buffers returned by syscall `readv`
 impl<const N: usize> Iterator for InotifyEventBatch<N> {
     type Item = InotifyEvent;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.pos >= self.num_bytes {
-            return None;
+        let event = next_event(&self.buffers, &mut self.index, &mut self.pos)?;
+        Some(event.to_owned())
+    }
+}
+
+/// iterator returned by `InotifyEventBatch::refs`
+#[derive(Debug)]
+pub struct InotifyEventRefs<'buf> {
+    buffers: &'buf [Vec<u8>],
+    index: usize,
+    pos: usize,
+}
+
+impl<'buf> Iterator for InotifyEventRefs<'buf> {
+    type Item = InotifyEventRef<'buf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        next_event(self.buffers, &mut self.index, &mut self.pos)
+    }
+}
+
+/// reads the event at `pos` in the buffer at `index` and moves past it
+fn next_event<'buf>(
+    buffers: &'buf [Vec<u8>],
+    index: &mut usize,
+    pos: &mut usize,
+) -> Option<InotifyEventRef<'buf>> {
+    loop {
+        let buffer = buffers.get(*index)?;
+        if *pos < buffer.len() {
+            if let Some((size, event)) = InotifyEventRef::from_buffer(&buffer[*pos..]) {
+                *pos += size;
+                return Some(event);
+            }
+            // the rest of the buffer can't be read, the next one may be
         }
+        *index += 1;
+        *pos = 0;
+    }
+}
 
-        let (size, event) = InotifyEvent::from_buffer(&self.buffer[self.pos..]);
-        self.pos += size;
-        Some(event)
+impl<const N: usize> Drop for InotifyEventBatch<N> {
+    fn drop(&mut self) {
+        self.pool.give_back_chain(std::mem::take(&mut self.buffers));
     }
 }
 
@@ -117,107 +518,1026 @@ impl<const N: usize> Iterator for InotifyEventBatch<N> {
 pub struct Inotify {
     fd: RawFd,
     watchers: HashMap<RawFd, PathBuf>,
+    masks: HashMap<RawFd, u32>,
+    recursive: HashMap<RawFd, RecursiveWatch>,
+    // device and inode of the directories `watch_recursive` watches, so a
+    // directory reached again through a symlink isn't walked twice
+    inodes: HashMap<(u64, u64), RawFd>,
+    hidden: bool,
+    symlinks: Symlinks,
+    dir_filter: Option<DirFilter>,
+    echoes: Option<Echoes>,
+    read: ReadStrategy,
+    pool: Pool,
+    // the sizes `pool` was created with
+    buffer_size: usize,
+    buffers: usize,
+    nonblocking: bool,
+    paused: Option<Paused>,
+    // woken on `resume`, set when the stream was polled while paused
+    waker: Option<Waker>,
+    scan_new: bool,
+    // the synthetic events of the directories scanned while reading a batch
+    scanned: Option<Vec<u8>>,
+    shutdown: Option<ShutdownToken>,
+    lag: Lag,
+    // the descriptor registered with the reactor, waited on by `poll_next`
+    io: AsyncFd,
+}
+
+/// the watches removed by `Pause::Detach`, added again on resume
+enum Paused {
+    Buffer,
+    Detach(Vec<(PathBuf, u32, Option<RecursiveWatch>)>),
+}
+
+/// predicate deciding which directories `watch_recursive` descends into
+type DirFilter = Box<dyn Fn(&Path) -> bool + Send>;
+
+/// information kept for directories that were added by `watch_recursive`, used
+/// to extend the watch when new directories are created under them
+#[derive(Debug, Clone)]
+struct RecursiveWatch {
+    // the masks of the whole tree, and the one the directory was asked with
+    masks: Arc<PathMasks>,
+    mask: u32,
+    // the depth of the directory in the tree, and the depths reported
+    level: usize,
+    depth: Depth,
+    symlinks: Symlinks,
+}
+
+impl RecursiveWatch {
+    /// checks if the directories in this one are watched, the entries
+    /// of directories at the max depth would be too deep
+    fn descends(&self) -> bool {
+        self.depth.max.is_none_or(|max| self.level + 1 < max)
+    }
 }
 
 impl Inotify {
-    pub fn new(self) -> Result<Self, Errno> {
+    pub fn new() -> Result<Self, WatchError> {
         Self::with_flags(0)
     }
 
     /// returns new `Inotify` with `inotify_init1` syscall and passing
     /// the `flags` to the syscall, if the syscall returned any error, an
-    /// `Err(Errno)` will be returned
-    pub fn with_flags(flags: i32) -> Result<Self, Errno> {
+    /// `Err(WatchError)` will be returned
+    pub fn with_flags(flags: i32) -> Result<Self, WatchError> {
         match unsafe { ffi::inotify_init1(flags) } {
-            SYSCALL_ERROR => Err(Errno::last()),
+            SYSCALL_ERROR => Err(WatchError::last(Op::Init, None)),
             fd => Ok(Self {
                 fd,
                 watchers: HashMap::new(),
+                masks: HashMap::new(),
+                recursive: HashMap::new(),
+                inodes: HashMap::new(),
+                hidden: false,
+                symlinks: Symlinks::default(),
+                dir_filter: None,
+                echoes: None,
+                read: ReadStrategy::default(),
+                pool: Pool::new(READ_SIZE, 1, READ_SIZE),
+                buffer_size: READ_SIZE,
+                buffers: 1,
+                nonblocking: flags & Flag::NONBLOCKING != 0,
+                paused: None,
+                waker: None,
+                scan_new: false,
+                scanned: None,
+                shutdown: None,
+                lag: Lag::default(),
+                io: AsyncFd::new(fd, ffi::POLLIN),
             }),
         }
     }
 
+    /// defines if hidden directories (starting with `.`) are watched
+    /// by `watch_recursive`, they are skipped by default
+    pub fn include_hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    /// how the watches added after deal with symlinks
+    pub fn symlinks(mut self, symlinks: Symlinks) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// same as `symlinks` but doesn't consume the instance
+    pub fn set_symlinks(&mut self, symlinks: Symlinks) {
+        self.symlinks = symlinks;
+    }
+
+    /// sets a filter that is called with the path of every directory `watch_recursive`
+    /// finds (including directories created later), directories the filter returns `false`
+    /// for are not watched and not descended into
+    pub fn filter_dirs<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&Path) -> bool + Send + 'static,
+    {
+        self.dir_filter = Some(Box::new(filter));
+        self
+    }
+
+    /// sets how much is read every time events are ready
+    pub fn read_strategy(mut self, read: ReadStrategy) -> Self {
+        self.read = read;
+        self.pool = self.new_pool();
+        self
+    }
+
+    /// sets the size of the buffers events are read into (4096 bytes by default, at least
+    /// the size of the largest event) and how many of them every read fills (1 by default,
+    /// at most 16). buffers are reused from batch to batch, so once enough were allocated
+    /// reading doesn't allocate anymore
+    pub fn buffer_pool(mut self, buffer_size: usize, buffers: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self.buffers = buffers;
+        self.pool = self.new_pool();
+        self
+    }
+
+    fn new_pool(&self) -> Pool {
+        let max_bytes = match self.read {
+            ReadStrategy::Single => 0,
+            ReadStrategy::Drain { max_bytes } => max_bytes,
+        };
+        Pool::new(self.buffer_size, self.buffers, max_bytes)
+    }
+
+    /// directories created under recursive watches can be filled before their watch is
+    /// added, when set what they hold once watched is reported with `CREATE` events made
+    /// up after the one of the directory. something created right as the watch is added
+    /// may be reported twice
+    pub fn scan_new_directories(mut self, scan: bool) -> Self {
+        self.set_scan_new_directories(scan);
+        self
+    }
+
+    /// same as `scan_new_directories` but doesn't consume the instance
+    pub fn set_scan_new_directories(&mut self, scan: bool) {
+        self.scan_new = scan;
+    }
+
+    /// ends the stream once the token is cancelled, the stream returns `None` right
+    /// away even when it waits for events or is paused
+    pub fn with_shutdown(mut self, token: ShutdownToken) -> Self {
+        self.set_shutdown(token);
+        self
+    }
+
+    /// same as `with_shutdown` but doesn't consume the instance
+    pub fn set_shutdown(&mut self, token: ShutdownToken) {
+        self.shutdown = Some(token);
+    }
+
+    fn is_shut_down(&self) -> bool {
+        self.shutdown
+            .as_ref()
+            .is_some_and(ShutdownToken::is_cancelled)
+    }
+
+    /// drops the events caused by the writes expected in `echoes` in `resolve`
+    pub fn suppress_echoes(mut self, echoes: Echoes) -> Self {
+        self.echoes = Some(echoes);
+        self
+    }
+
     /// addes a path to the inotify watch event via `inotify_add_watch`
-    pub fn watch(mut self, pathname: PathBuf, mask: u32) -> Result<Self, Errno> {
-        let wd = unsafe {
-            ffi::inotify_add_watch(
-                self.fd,
-                pathname.to_str().unwrap().as_ptr() as *const i8,
-                mask,
-            )
+    pub fn watch(mut self, pathname: PathBuf, mask: u32) -> Result<Self, WatchError> {
+        self.add_watch(pathname, mask)?;
+        Ok(self)
+    }
+
+    /// watches the given directory and the directories under it as deep as `depth` goes,
+    /// directories that are created later in the tree are added to the watch when their
+    /// creation event is read from the stream.
+    ///
+    /// `Mask::CREATE` and `Mask::MOVED_TO` are always added to the mask, because they are
+    /// required to follow new directories
+    pub fn watch_recursive(
+        mut self,
+        pathname: PathBuf,
+        mask: u32,
+        depth: Depth,
+    ) -> Result<Self, WatchError> {
+        self.add_recursive(pathname, mask, depth)?;
+        Ok(self)
+    }
+
+    /// same as `watch_recursive`, the directories are watched with the mask of the
+    /// rule matching them, see `PathMasks`
+    pub fn watch_recursive_masks(
+        mut self,
+        pathname: PathBuf,
+        masks: PathMasks,
+        depth: Depth,
+    ) -> Result<Self, WatchError> {
+        self.add_recursive_masks(pathname, masks, depth)?;
+        Ok(self)
+    }
+
+    /// watches all the given paths or none of them, if a path can't be watched the
+    /// watches added for the paths before it are removed again and the failing path is
+    /// reported in the error. paths that were already watched stay watched
+    pub fn watch_all<I>(mut self, watches: I) -> Result<Self, WatchError>
+    where
+        I: IntoIterator<Item = (PathBuf, u32)>,
+    {
+        self.add_all(watches)?;
+        Ok(self)
+    }
+
+    /// same as `watch_all` but doesn't consume the instance, returns the
+    /// watch descriptors of the paths in the same order
+    pub fn add_all<I>(&mut self, watches: I) -> Result<Vec<RawFd>, WatchError>
+    where
+        I: IntoIterator<Item = (PathBuf, u32)>,
+    {
+        let before = self.watched();
+        let mut wds = Vec::new();
+        for (path, mask) in watches {
+            match self.add_watch(path, mask) {
+                Ok(wd) => wds.push(wd),
+                Err(e) => {
+                    self.rollback(&before);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(wds)
+    }
+
+    /// same as `watch` but doesn't consume the instance, returns the
+    /// watch descriptor for the added path
+    pub fn add_watch(&mut self, pathname: PathBuf, mask: u32) -> Result<RawFd, WatchError> {
+        self.try_add_watch(pathname, mask).map(|added| added.wd())
+    }
+
+    /// same as `add_watch`, tells if the file was already watched. the path is made
+    /// absolute first, a link is still watched as a link, and a file already watched
+    /// under another path (`./foo` and `/abs/path/foo`, hard links) keeps its watch and
+    /// path. like `inotify_add_watch` the mask replaces the one of the watch, unless
+    /// it has `Mask::MASK_ADD`, then the watch reports the events of both masks
+    pub fn try_add_watch(&mut self, pathname: PathBuf, mask: u32) -> Result<Added, WatchError> {
+        self.add_watch_with(canonical(&pathname), mask, self.symlinks)
+    }
+
+    fn add_watch_with(
+        &mut self,
+        pathname: PathBuf,
+        mask: u32,
+        symlinks: Symlinks,
+    ) -> Result<Added, WatchError> {
+        let (pathname, mask) = match symlinks {
+            Symlinks::Follow => (pathname, mask),
+            Symlinks::DontFollow => (pathname, mask | ffi::IN_DONT_FOLLOW),
+            Symlinks::ResolveTarget if pathname.is_symlink() => match pathname.canonicalize() {
+                Ok(target) => (target, mask),
+                Err(e) => return Err(io_error(Op::Watch, pathname, e)),
+            },
+            Symlinks::ResolveTarget => (pathname, mask),
         };
-        match wd {
-            SYSCALL_ERROR => Err(Errno::last()),
-            _ => {
+        let Ok(cpath) = CString::new(pathname.as_os_str().as_bytes()) else {
+            let errno = Errno::from(ffi::EINVAL);
+            return Err(WatchError::new(Op::Watch, Some(pathname), errno));
+        };
+        let wd = unsafe { ffi::inotify_add_watch(self.fd, cpath.as_ptr(), mask) };
+        if wd == SYSCALL_ERROR {
+            return Err(WatchError::last(Op::Watch, Some(&pathname)));
+        }
+        let (merge, mask) = (mask & ffi::IN_MASK_ADD != 0, mask & !ffi::IN_MASK_ADD);
+        match self.watchers.get(&wd) {
+            Some(path) => {
+                let existing_mask = self.masks.get(&wd).copied().unwrap_or_default();
+                let mask = match merge {
+                    true => existing_mask | mask,
+                    false => mask,
+                };
+                self.masks.insert(wd, mask);
+                Ok(Added::AlreadyWatched {
+                    wd,
+                    path: path.clone(),
+                    existing_mask,
+                })
+            }
+            None => {
                 self.watchers.insert(wd, pathname);
-                Ok(self)
+                self.masks.insert(wd, mask);
+                Ok(Added::Watch(wd))
+            }
+        }
+    }
+
+    /// removes the watch via `inotify_rm_watch`, directories that were added by
+    /// `watch_recursive` under it are not removed and should be unwatched on their own
+    pub fn unwatch(&mut self, wd: RawFd) -> Result<(), WatchError> {
+        let path = self.watchers.remove(&wd);
+        self.masks.remove(&wd);
+        self.recursive.remove(&wd);
+        self.inodes.retain(|_, watched| *watched != wd);
+        match unsafe { ffi::inotify_rm_watch(self.fd, wd) } {
+            SYSCALL_ERROR => Err(WatchError::last(Op::Unwatch, path.as_deref())),
+            _ => Ok(()),
+        }
+    }
+
+    /// what was read so far and how many events are left in the kernel queue, a
+    /// growing lag or queue means the events are read slower than they happen
+    pub fn stats(&self) -> Stats {
+        self.lag.stats()
+    }
+
+    /// returns the watch descriptors and the paths they watch
+    pub fn watches(&self) -> impl Iterator<Item = (RawFd, &Path)> {
+        self.watchers.iter().map(|(wd, path)| (*wd, path.as_path()))
+    }
+
+    /// same as `watch_recursive` but doesn't consume the instance, walks the
+    /// directory tree and adds a watch for every directory found, the tree root is always watched.
+    /// if a directory can't be watched (e.g. `ENOSPC` when out of watches) the directories
+    /// watched so far are unwatched again, so the tree is never left half watched
+    pub fn add_recursive(
+        &mut self,
+        pathname: PathBuf,
+        mask: u32,
+        depth: Depth,
+    ) -> Result<(), WatchError> {
+        self.add_recursive_masks(pathname, PathMasks::new(mask), depth)
+    }
+
+    /// same as `watch_recursive_masks` but doesn't consume the instance
+    pub fn add_recursive_masks(
+        &mut self,
+        pathname: PathBuf,
+        masks: PathMasks,
+        depth: Depth,
+    ) -> Result<(), WatchError> {
+        let pathname = canonical(&pathname);
+        let masks = Arc::new(masks.under(&pathname));
+        let before = self.watched();
+        self.add_tree(pathname, &masks, depth, 0, self.symlinks)
+            .inspect_err(|_| self.rollback(&before))
+    }
+
+    fn watched(&self) -> HashSet<RawFd> {
+        self.watchers.keys().copied().collect()
+    }
+
+    /// removes the watches that were added since `before`
+    fn rollback(&mut self, before: &HashSet<RawFd>) {
+        let added: Vec<RawFd> = self
+            .watchers
+            .keys()
+            .filter(|wd| !before.contains(wd))
+            .copied()
+            .collect();
+        for wd in added {
+            let _ = self.unwatch(wd);
+        }
+    }
+
+    fn add_tree(
+        &mut self,
+        pathname: PathBuf,
+        masks: &Arc<PathMasks>,
+        depth: Depth,
+        level: usize,
+        symlinks: Symlinks,
+    ) -> Result<(), WatchError> {
+        let mask = masks.mask_for(&pathname);
+        // creations are needed to watch the new directories, a directory
+        // also watched on its own keeps the events it was asked for
+        let wd = self
+            .add_watch_with(
+                pathname.clone(),
+                mask | ffi::IN_CREATE | ffi::IN_MOVED_TO | ffi::IN_MASK_ADD,
+                symlinks,
+            )?
+            .wd();
+        let watch = RecursiveWatch {
+            masks: masks.clone(),
+            mask,
+            level,
+            depth,
+            symlinks,
+        };
+        let descends = watch.descends();
+        self.recursive.insert(wd, watch);
+        if let Some(inode) = inode(&pathname) {
+            self.inodes.insert(inode, wd);
+        }
+        // the path events are reported under, the target for resolved links
+        let pathname = self.watchers[&wd].clone();
+
+        if !descends && self.scanned.is_none() {
+            return Ok(());
+        }
+        let entries = match std::fs::read_dir(&pathname) {
+            Ok(entries) => entries,
+            Err(e) => return Err(io_error(Op::Walk, pathname, e)),
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let file_type = entry.file_type().ok();
+            if let Some(scanned) = &mut self.scanned {
+                // like the kernel, links to directories are created without `IN_ISDIR`
+                let mask = match file_type {
+                    Some(t) if t.is_dir() => ffi::IN_CREATE | ffi::IN_ISDIR,
+                    _ => ffi::IN_CREATE,
+                };
+                scanned.extend(event_bytes(wd, mask, &entry.file_name()));
+            }
+            let is_dir = is_dir(&path, file_type, symlinks);
+            if !descends || !is_dir || !self.should_descend(&path) {
+                continue;
+            }
+            // a symlink back up the tree, or to a directory that is already watched
+            if inode(&path).is_some_and(|inode| self.inodes.contains_key(&inode)) {
+                continue;
+            }
+
+            match self.add_tree(path, masks, depth, level + 1, symlinks) {
+                // the directory could have been removed while we walked the tree
+                Err(e) if e.is_not_found() => continue,
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+
+    /// stops the stream until `resume` is called, the watches are kept, see `Pause`
+    /// for what happens to the events in between. pausing a paused instance does nothing
+    pub fn pause(&mut self, mode: Pause) -> Result<(), WatchError> {
+        if self.paused.is_some() {
+            return Ok(());
+        }
+        let paused = match mode {
+            Pause::Buffer => Paused::Buffer,
+            Pause::Detach => {
+                let mut watches: Vec<(RawFd, PathBuf)> = self.watchers.drain().collect();
+                // parents are walked again before their children on resume
+                watches.sort_by(|a, b| a.1.cmp(&b.1));
+                let detached = watches
+                    .into_iter()
+                    .map(|(wd, path)| {
+                        unsafe { ffi::inotify_rm_watch(self.fd, wd) };
+                        let mask = self.masks.remove(&wd).unwrap_or_default();
+                        (path, mask, self.recursive.remove(&wd))
+                    })
+                    .collect();
+                self.inodes.clear();
+                Paused::Detach(detached)
+            }
+        };
+        self.paused = Some(paused);
+        Ok(())
+    }
+
+    /// continues the stream stopped by `pause`, watches removed by `Pause::Detach` are
+    /// added again, paths that are gone by now are not watched anymore
+    pub fn resume(&mut self) -> Result<(), WatchError> {
+        let Some(paused) = self.paused.take() else {
+            return Ok(());
+        };
+        if let Paused::Detach(detached) = paused {
+            for (path, mask, recursive) in detached {
+                let result = match recursive {
+                    // already watched by the walk of a parent
+                    Some(_) if inode(&path).is_some_and(|i| self.inodes.contains_key(&i)) => {
+                        continue
+                    }
+                    Some(watch) => {
+                        self.add_tree(path, &watch.masks, watch.depth, watch.level, watch.symlinks)
+                    }
+                    None => self
+                        .add_watch_with(path, mask, Symlinks::Follow)
+                        .map(|_| ()),
+                };
+                match result {
+                    Err(e) if e.is_not_found() => continue,
+                    result => result?,
+                }
+            }
+        }
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    /// checks if the stream is stopped by `pause`
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_some()
+    }
+
+    /// the paths watched, with their masks, and what the watched directories hold
+    /// right now, to watch the same paths again with `restore_state`. directories
+    /// under recursive watches are not kept, they are walked again
+    pub fn export_state(&self) -> WatchState {
+        let mut watches: Vec<SavedWatch> = self
+            .watchers
+            .iter()
+            .filter_map(|(wd, path)| {
+                let tree = match self.recursive.get(wd) {
+                    Some(watch) if watch.level > 0 => return None,
+                    Some(watch) => Some(SavedTree {
+                        masks: (*watch.masks).clone(),
+                        depth: watch.depth,
+                        symlinks: watch.symlinks,
+                    }),
+                    None => None,
+                };
+                Some(SavedWatch {
+                    path: path.clone(),
+                    mask: self.masks.get(wd).copied().unwrap_or_default(),
+                    tree,
+                })
+            })
+            .collect();
+        watches.sort_by(|a, b| a.path.cmp(&b.path));
+        WatchState {
+            watches,
+            snapshot: Rescan::new(self),
+        }
+    }
+
+    /// watches the paths of the state again and returns what changed since it was
+    /// exported as `CREATE`, `MODIFY` and `DELETE` events like `Rescan::recover`.
+    /// paths that are gone by now are not watched anymore
+    pub fn restore_state(&mut self, state: WatchState) -> Result<Vec<Event>, WatchError> {
+        for watch in state.watches {
+            let result = match watch.tree {
+                // already watched by the walk of a parent
+                Some(_) if inode(&watch.path).is_some_and(|i| self.inodes.contains_key(&i)) => {
+                    continue
+                }
+                Some(tree) => {
+                    let masks = Arc::new(tree.masks);
+                    self.add_tree(watch.path, &masks, tree.depth, 0, tree.symlinks)
+                }
+                None => self
+                    .add_watch_with(watch.path, watch.mask, Symlinks::Follow)
+                    .map(|_| ()),
+            };
+            match result {
+                Err(e) if e.is_not_found() => continue,
+                result => result?,
+            }
+        }
+        let mut snapshot = state.snapshot;
+        Ok(snapshot.recover(self))
+    }
+
+    /// points the watches of the directory, and the directories under it, to its new
+    /// path after it was renamed, so their events are reported under the new path
+    pub(crate) fn rename_watches(&mut self, from: &Path, to: &Path) {
+        for path in self.watchers.values_mut() {
+            if let Ok(rest) = path.strip_prefix(from) {
+                *path = to.join(rest);
+            }
+        }
+    }
+
+    /// removes the watches of the directory and the directories under it,
+    /// for directories that were moved out of the watched paths
+    pub(crate) fn unwatch_tree(&mut self, dir: &Path) {
+        let wds: Vec<RawFd> = self
+            .watchers
+            .iter()
+            .filter(|(_, path)| path.starts_with(dir))
+            .map(|(&wd, _)| wd)
+            .collect();
+        for wd in wds {
+            // the kernel may have removed the watch already
+            let _ = self.unwatch(wd);
+        }
+    }
+
+    /// brings the recursive watches up to date after events were lost, the watches
+    /// of directories that are gone are removed and the directories created since
+    /// are watched
+    pub(crate) fn watch_missed_directories(&mut self) {
+        let gone: Vec<RawFd> = self
+            .watchers
+            .iter()
+            .filter(|(_, path)| !path.exists())
+            .map(|(&wd, _)| wd)
+            .collect();
+        for wd in gone {
+            let _ = self.unwatch(wd);
+        }
+
+        let mut recursive: Vec<(PathBuf, RecursiveWatch)> = self
+            .recursive
+            .iter()
+            .map(|(wd, watch)| (self.watchers[wd].clone(), watch.clone()))
+            .collect();
+        recursive.sort_by(|a, b| a.0.cmp(&b.0));
+        for (dir, watch) in recursive {
+            if !watch.descends() {
+                continue;
+            }
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if !is_dir(&path, entry.file_type().ok(), watch.symlinks)
+                    || !self.should_descend(&path)
+                    || inode(&path).is_some_and(|inode| self.inodes.contains_key(&inode))
+                {
+                    continue;
+                }
+                let _ = self.add_tree(
+                    path,
+                    &watch.masks,
+                    watch.depth,
+                    watch.level + 1,
+                    watch.symlinks,
+                );
             }
         }
     }
 
+    /// checks if `watch_recursive` should watch the given directory
+    fn should_descend(&self, path: &Path) -> bool {
+        if !self.hidden && path.file_name().is_some_and(is_hidden) {
+            return false;
+        }
+        self.dir_filter.as_ref().is_none_or(|filter| filter(path))
+    }
+
     /// returns the defined path for given watch descriptor
     pub fn path_for_watch(&self, wd: RawFd) -> Option<&Path> {
-        self.watchers.get(&wd).and_then(|p| Some(p.as_path()))
+        self.watchers.get(&wd).map(|p| p.as_path())
+    }
+
+    /// resolves the given `InotifyEvent` into an `Event` with the full path the
+    /// event happened on, returns `None` if the event watch descriptor is unknown,
+    /// its mask contains no known event or it is an echo of an expected write
+    pub fn resolve(&self, event: &InotifyEvent) -> Option<Event> {
+        self.resolve_ref(event.into())
+    }
+
+    /// flattens the batches into single events with their full path, events of
+    /// unknown watch descriptors are dropped. the watches are resolved by the
+    /// instance itself, the adapters of `TubeStreamExt` follow it
+    pub fn resolved(self) -> Resolved {
+        Resolved::new(self)
+    }
+
+    /// same as `resolved`, with the moves joined by a `MoveResolver`, a `MOVED_FROM`
+    /// is a move out of the watched paths when nothing is left to read after it
+    pub fn resolved_moves(self) -> ResolvedMoves {
+        ResolvedMoves::new(self)
+    }
+
+    /// same as `resolve`, for the events borrowed with `InotifyEventBatch::refs`
+    pub fn resolve_ref(&self, event: InotifyEventRef<'_>) -> Option<Event> {
+        let kind = EventKind::from_mask(event.mask)?;
+        if let Some(watch) = self.recursive.get(&event.wd) {
+            // recursive watches get creations whether they were asked for or not
+            if kind.mask() & (ffi::IN_CREATE | ffi::IN_MOVED_TO) != 0
+                && watch.mask & kind.mask() == 0
+            {
+                return None;
+            }
+            let depth = watch.level + usize::from(event.name().is_some());
+            if !watch.depth.contains(depth) {
+                return None;
+            }
+        }
+        let path = match kind {
+            // overflow events are not related to any watch
+            EventKind::Overflow => PathBuf::new(),
+            _ => {
+                let dir = self.path_for_watch(event.wd)?;
+                match event.name() {
+                    Some(name) => dir.join(name),
+                    None => dir.to_path_buf(),
+                }
+            }
+        };
+        let event = Event {
+            path,
+            kind,
+            cookie: event.cookie,
+            is_dir: event.is_dir(),
+            process: None,
+            changes: Vec::new(),
+            inode: None,
+            renamed_from: None,
+        };
+        match &self.echoes {
+            Some(echoes) if echoes.is_echo(&event) => None,
+            _ => Some(event),
+        }
+    }
+
+    /// reads events until one that satisfies the predicate arrives and
+    /// returns it, events that don't match are discarded. `None` is returned
+    /// if the stream was shut down first
+    pub async fn wait_for<F>(&mut self, mut predicate: F) -> Result<Option<Event>, WatchError>
+    where
+        F: FnMut(&Event) -> bool,
+    {
+        while let Some(events) = self.next().await {
+            for event in events? {
+                match self.resolve(&event) {
+                    Some(event) if predicate(&event) => return Ok(Some(event)),
+                    _ => continue,
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// goes over the events in the buffer and adds a watch for directories that
+    /// were created (or moved) under directories watched by `watch_recursive`, and
+    /// forgets the inodes of the watched directories that were removed
+    fn watch_new_directories(&mut self, buffer: &[u8]) {
+        let mut pos = 0;
+        while pos < buffer.len() {
+            let Some((size, event)) = InotifyEventRef::from_buffer(&buffer[pos..]) else {
+                break;
+            };
+            pos += size;
+
+            // the directory is gone, a directory created later may get its inode
+            if event.mask & ffi::IN_IGNORED != 0 {
+                self.inodes.retain(|_, watched| *watched != event.wd);
+                continue;
+            }
+            if event.mask & (ffi::IN_CREATE | ffi::IN_MOVED_TO) == 0 {
+                continue;
+            }
+            let (Some(parent), Some(name)) = (self.recursive.get(&event.wd), event.name()) else {
+                continue;
+            };
+            // links to directories are created without `IN_ISDIR`
+            if !event.is_dir() && parent.symlinks == Symlinks::DontFollow {
+                continue;
+            }
+            if !parent.descends() {
+                continue;
+            }
+            let parent = parent.clone();
+            let path = self.watchers[&event.wd].join(name);
+            let is_dir = event.is_dir() || (path.is_symlink() && path.is_dir());
+            if !is_dir {
+                continue;
+            }
+            if !self.should_descend(&path)
+                || inode(&path).is_some_and(|inode| self.inodes.contains_key(&inode))
+            {
+                continue;
+            }
+
+            // the directory may already be gone, nothing to watch then
+            let _ = self.add_tree(
+                path,
+                &parent.masks,
+                parent.depth,
+                parent.level + 1,
+                parent.symlinks,
+            );
+        }
+    }
+
+    /// reads the events that are ready without waiting for them, for instances created
+    /// with `Flag::NONBLOCKING` that are polled by an outside event loop, returns `None`
+    /// if there was nothing to read or the instance is paused
+    pub fn try_read(&mut self) -> Result<Option<InotifyEventBatch<4096>>, WatchError> {
+        if self.paused.is_some() {
+            return Ok(None);
+        }
+        let read_size = self.pool.read_size();
+        let max_bytes = match self.read {
+            ReadStrategy::Single => read_size,
+            ReadStrategy::Drain { max_bytes } => max_bytes.max(read_size),
+        };
+        let mut buffers = self.pool.chain();
+        let mut total = 0;
+        loop {
+            let start = buffers.len();
+            buffers.extend((0..self.pool.buffers()).map(|_| self.pool.buffer()));
+            let mut iov = [ffi::iovec {
+                iov_base: std::ptr::null_mut(),
+                iov_len: 0,
+            }; MAX_BUFFERS];
+            for (iov, buffer) in iov.iter_mut().zip(&mut buffers[start..]) {
+                let spare = buffer.spare_capacity_mut();
+                iov.iov_base = spare.as_mut_ptr().cast();
+                iov.iov_len = spare.len();
+            }
+            let count = buffers.len() - start;
+            let bytes_read = unsafe { ffi::readv(self.fd, iov.as_ptr(), count as i32) };
+            if bytes_read == SYSCALL_ERROR as isize {
+                let e = Errno::last();
+                for buffer in buffers.drain(start..) {
+                    self.pool.give_back(buffer);
+                }
+                match e {
+                    e if e.raw() == ffi::EAGAIN && total == 0 => {
+                        self.pool.give_back_chain(buffers);
+                        return Ok(None);
+                    }
+                    e if total == 0 => {
+                        self.pool.give_back_chain(buffers);
+                        return Err(WatchError::new(Op::Read, None, e));
+                    }
+                    // what was read so far is still returned
+                    _ => break,
+                }
+            }
+
+            // the kernel reads every buffer on its own and stops at the first
+            // one it didn't fill, so the bytes fill the buffers in order
+            let mut left = bytes_read as usize;
+            for buffer in &mut buffers[start..] {
+                let len = left.min(buffer.capacity());
+                unsafe { buffer.set_len(len) };
+                left -= len;
+            }
+            while buffers.last().is_some_and(Vec::is_empty) {
+                self.pool.give_back(buffers.pop().unwrap());
+            }
+            total += bytes_read as usize;
+            // a blocking descriptor is only read again if it wouldn't block
+            if total + read_size > max_bytes || (!self.nonblocking && !self.has_pending()) {
+                break;
+            }
+        }
+
+        self.lag.reading();
+        self.lag.read(self.fd, &buffers);
+        if !self.recursive.is_empty() {
+            self.scanned = self.scan_new.then(Vec::new);
+            for buffer in &buffers {
+                self.watch_new_directories(buffer);
+            }
+            // read along with the events of the batch
+            if let Some(scanned) = self.scanned.take().filter(|scanned| !scanned.is_empty()) {
+                let mut buffer = self.pool.buffer();
+                buffer.extend(scanned);
+                buffers.push(buffer);
+            }
+        }
+        Ok(Some(InotifyEventBatch::new(buffers, self.pool.clone())))
+    }
+
+    /// checks if events can be read right now, without waiting for them
+    pub(crate) fn has_pending(&self) -> bool {
+        self.events_ready(0).unwrap_or(false)
     }
 
     /// checks if event is ready on the inotify descriptor by using the
-    /// `poll` syscall, if `poll` returned any error, `Err(Errno)` will be returned
-    fn events_ready(&self) -> Result<bool, Errno> {
-        let mut fds = [ffi::pollfd {
-            fd: self.fd,
-            events: ffi::POLLIN,
-            revents: 0,
-        }; 1];
-        match unsafe { ffi::poll(fds.as_mut_ptr(), 1, -1) } {
-            SYSCALL_ERROR => Err(Errno::last()),
+    /// `poll` syscall, waiting up to `timeout` milliseconds (`-1` for no limit),
+    /// if `poll` returned any error, `Err(WatchError)` will be returned. the wait
+    /// ends early when the shutdown token is cancelled
+    fn events_ready(&self, timeout: i32) -> Result<bool, WatchError> {
+        let shutdown = self.shutdown.as_ref().map_or(-1, ShutdownToken::fd);
+        let mut fds = [
+            ffi::pollfd {
+                fd: self.fd,
+                events: ffi::POLLIN,
+                revents: 0,
+            },
+            // negative descriptors are ignored
+            ffi::pollfd {
+                fd: shutdown,
+                events: ffi::POLLIN,
+                revents: 0,
+            },
+        ];
+        match unsafe { ffi::poll(fds.as_mut_ptr(), 2, timeout) } {
+            SYSCALL_ERROR => Err(WatchError::last(Op::Poll, None)),
             ret if ret < 0 => {
                 panic!(
                     "poll file descriptor returned unexpected status code `{}`",
                     ret
                 )
             }
-            ret => Ok(ret != 0 && fds[0].revents & (ffi::POLLIN as i16) != 0),
+            ret => Ok(ret != 0 && fds[0].revents & ffi::POLLIN != 0),
         }
     }
 }
 
 impl Stream for Inotify {
-    type Item = Result<InotifyEventBatch<4096>, Errno>;
+    type Item = Result<InotifyEventBatch<4096>, WatchError>;
 
-    /// pull next never returns `None`, will always return some event (if ready), the check
-    /// for event is made via syscall `poll` to check the current inotify descriptor, when
-    /// `poll` returns that there are events ready, the events are pulled to a buffer with fixed
-    /// size of 4096 bytes.
+    /// pull next only returns `None` once the shutdown token is cancelled, it returns
+    /// `Poll::Pending` while the inotify descriptor has no events and the task is woken
+    /// once it has, so the stream never blocks the executor. the ready events are
+    /// pulled to a buffer with fixed size of 4096 bytes.
     ///
     /// the InotifyEventBatch will be responsible for reading the events from the given
     /// buffer.
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let events_ready = self.events_ready();
-
-        if events_ready.is_err() {
-            return Poll::Ready(Some(Err(unsafe { events_ready.unwrap_err_unchecked() })));
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.is_shut_down() {
+            return Poll::Ready(None);
         }
-
-        // if the `event_ready` was not returned when we cheked if its an error
-        // then its a `Ok(bool)`, and we check if the returned value is `false` (there are no
-        // events) we mark the `poll_next` as still pending
-        if !unsafe { events_ready.unwrap_unchecked() } {
+        if self.paused.is_some() {
+            self.waker = Some(cx.waker().clone());
+            if let Some(shutdown) = &self.shutdown {
+                shutdown.register(cx.waker());
+                // cancelled before the waker was registered
+                if shutdown.is_cancelled() {
+                    return Poll::Ready(None);
+                }
+            }
             return Poll::Pending;
         }
+        let this = self.get_mut();
+        loop {
+            match this.io.poll_ready(cx) {
+                Poll::Ready(Ok(_)) => {}
+                Poll::Ready(Err(e)) => {
+                    let errno = Errno::from(e.raw_os_error().unwrap_or(0));
+                    return Poll::Ready(Some(Err(WatchError::new(Op::Poll, None, errno))));
+                }
+                Poll::Pending => {
+                    if let Some(shutdown) = &this.shutdown {
+                        shutdown.register(cx.waker());
+                        // cancelled before the waker was registered
+                        if shutdown.is_cancelled() {
+                            return Poll::Ready(None);
+                        }
+                    }
+                    return Poll::Pending;
+                }
+            }
+            // `None` when another reader was faster, the descriptor is waited on again
+            match this.try_read() {
+                Ok(Some(batch)) => return Poll::Ready(Some(Ok(batch))),
+                Ok(None) => continue,
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+    }
+}
 
-        // create local buffer with fixed size 4096 and read
-        // all that can fit into the buffer with the `read` syscall
-        let mut buffer = [0u8; 4096];
-        let bytes_read = unsafe { ffi::read(self.fd, buffer.as_mut_ptr(), buffer.len()) };
+/// the path made absolute without `.`, `..` or links in its parent, the last
+/// component is kept as is, so a link is still watched as the link
+pub(crate) fn canonical(path: &Path) -> PathBuf {
+    let canonical = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if parent.as_os_str().is_empty() => {
+            std::env::current_dir().map(|dir| dir.join(name))
+        }
+        (Some(parent), Some(name)) => parent.canonicalize().map(|parent| parent.join(name)),
+        _ => path.canonicalize(),
+    };
+    // the kernel reports the path missing
+    canonical.unwrap_or_else(|_| path.to_path_buf())
+}
 
-        cx.waker().wake_by_ref();
-        Poll::Ready(Some(Ok(InotifyEventBatch::new(
-            buffer,
-            bytes_read as usize,
-        ))))
+/// an event in the format the kernel writes it, the name is padded with
+/// zeros to a multiple of the header size like the kernel does
+fn event_bytes(wd: RawFd, mask: u32, name: &OsStr) -> Vec<u8> {
+    let header = std::mem::size_of::<ffi::inotify_event>();
+    let len = (name.len() + 1).div_ceil(header) * header;
+    let mut bytes = Vec::with_capacity(header + len);
+    bytes.extend_from_slice(&wd.to_ne_bytes());
+    bytes.extend_from_slice(&mask.to_ne_bytes());
+    bytes.extend_from_slice(&0u32.to_ne_bytes());
+    bytes.extend_from_slice(&(len as u32).to_ne_bytes());
+    bytes.extend_from_slice(name.as_bytes());
+    bytes.resize(header + len, 0);
+    bytes
+}
+
+/// `true` for directories, and links to directories when the links are followed
+fn is_dir(path: &Path, file_type: Option<std::fs::FileType>, symlinks: Symlinks) -> bool {
+    match file_type {
+        Some(file_type) if file_type.is_dir() => true,
+        Some(file_type) if file_type.is_symlink() => {
+            symlinks != Symlinks::DontFollow && path.is_dir()
+        }
+        _ => false,
     }
 }
 
+/// the device and inode of the path, following symlinks
+fn inode(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+fn io_error(op: Op, path: PathBuf, e: std::io::Error) -> WatchError {
+    let errno = Errno::from(e.raw_os_error().unwrap_or(ffi::EINVAL));
+    WatchError::new(op, Some(path), errno)
+}
+
+/// checks if the given file name is hidden (starts with `.`)
+fn is_hidden(name: &OsStr) -> bool {
+    name.as_bytes().first() == Some(&b'.')
+}
+
 impl AsRawFd for Inotify {
     fn as_raw_fd(&self) -> RawFd {
         self.fd
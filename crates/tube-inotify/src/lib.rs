@@ -1,6 +1,45 @@
+#[cfg(feature = "notify")]
+pub mod compat;
+mod dispatch;
+mod echo;
 mod errno;
+mod event;
 mod ffi;
+mod inode;
 mod inotify;
+mod masks;
+mod meta;
+#[cfg(feature = "test-util")]
+mod mock;
+mod moves;
+mod pool;
+mod process;
+mod rescan;
+mod shard;
+mod shutdown;
+mod source;
+mod state;
+mod stats;
+mod stream;
+mod upload;
 
+pub use dispatch::*;
+pub use echo::*;
 pub use errno::*;
+pub use event::*;
+pub use inode::*;
 pub use inotify::*;
+pub use masks::*;
+pub use meta::*;
+#[cfg(feature = "test-util")]
+pub use mock::*;
+pub use moves::*;
+pub use process::*;
+pub use rescan::*;
+pub use shard::*;
+pub use shutdown::*;
+pub use source::*;
+pub use state::*;
+pub use stats::*;
+pub use stream::*;
+pub use upload::*;
@@ -0,0 +1,60 @@
+use std::path::{Path, PathBuf};
+
+/// the masks the directories of a recursive watch are watched with, rules give the
+/// subtrees under a path a mask of their own, e.g. only `CLOSE_WRITE` for `uploads/`.
+/// the rules are evaluated every time a directory is added, see `Inotify::watch_recursive_masks`
+///
+/// ```no_run
+/// # use tube_inotify::{Depth, Inotify, Mask, PathMasks};
+/// # fn main() -> Result<(), tube_inotify::WatchError> {
+/// let masks = PathMasks::new(Mask::CREATE | Mask::DELETE)
+///     .rule("uploads", Mask::CLOSE_WRITE)
+///     .rule("uploads/tmp", 0);
+/// let inotify = Inotify::new()?.watch_recursive_masks("/srv".into(), masks, Depth::any())?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PathMasks {
+    mask: u32,
+    rules: Vec<(PathBuf, u32)>,
+}
+
+impl PathMasks {
+    /// `mask` is for the directories no rule matches
+    pub fn new(mask: u32) -> Self {
+        Self {
+            mask,
+            rules: Vec::new(),
+        }
+    }
+
+    /// the directory and the directories under it are watched with `mask`, relative
+    /// paths are under the watched path. when rules overlap the deepest path wins
+    pub fn rule<P: Into<PathBuf>>(mut self, path: P, mask: u32) -> Self {
+        self.rules.push((path.into(), mask));
+        self
+    }
+
+    /// the mask the directory is watched with
+    pub fn mask_for(&self, dir: &Path) -> u32 {
+        self.rules
+            .iter()
+            .filter(|(path, _)| dir.starts_with(path))
+            .max_by_key(|(path, _)| path.components().count())
+            .map_or(self.mask, |(_, mask)| *mask)
+    }
+
+    /// the rules with their relative paths put under the root
+    pub(crate) fn under(&self, root: &Path) -> Self {
+        Self {
+            mask: self.mask,
+            rules: self
+                .rules
+                .iter()
+                .map(|(path, mask)| (root.join(path), *mask))
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{CString, OsStr, OsString};
+use std::fmt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::event::{Event, EventKind};
+use crate::ffi;
+use crate::inotify::SYSCALL_ERROR;
+
+/// a piece of metadata that changed, reported on `ATTRIB` events
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MetaChange {
+    /// the permission bits
+    Mode {
+        from: u32,
+        to: u32,
+    },
+    Owner {
+        from: u32,
+        to: u32,
+    },
+    Group {
+        from: u32,
+        to: u32,
+    },
+    Mtime {
+        from: SystemTime,
+        to: SystemTime,
+    },
+    Atime {
+        from: SystemTime,
+        to: SystemTime,
+    },
+    XattrAdded(OsString),
+    XattrRemoved(OsString),
+    XattrChanged(OsString),
+}
+
+impl fmt::Display for MetaChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mode { from, to } => write!(f, "mode {:04o} -> {:04o}", from, to),
+            Self::Owner { from, to } => write!(f, "uid {} -> {}", from, to),
+            Self::Group { from, to } => write!(f, "gid {} -> {}", from, to),
+            Self::Mtime { .. } => write!(f, "mtime"),
+            Self::Atime { .. } => write!(f, "atime"),
+            Self::XattrAdded(name) => write!(f, "xattr {} added", name.to_string_lossy()),
+            Self::XattrRemoved(name) => write!(f, "xattr {} removed", name.to_string_lossy()),
+            Self::XattrChanged(name) => write!(f, "xattr {} changed", name.to_string_lossy()),
+        }
+    }
+}
+
+/// the metadata of a file that `ATTRIB` events can change
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Meta {
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    mtime: SystemTime,
+    atime: SystemTime,
+    xattrs: BTreeMap<OsString, Vec<u8>>,
+}
+
+impl Meta {
+    /// without following symlinks, `None` if the path is gone
+    fn read(path: &Path) -> Option<Self> {
+        let metadata = path.symlink_metadata().ok()?;
+        Some(Self {
+            mode: metadata.mode() & 0o7777,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            mtime: time(metadata.mtime(), metadata.mtime_nsec()),
+            atime: time(metadata.atime(), metadata.atime_nsec()),
+            xattrs: xattrs(path),
+        })
+    }
+
+    fn changes(&self, new: &Meta) -> Vec<MetaChange> {
+        let mut changes = Vec::new();
+        if self.mode != new.mode {
+            changes.push(MetaChange::Mode {
+                from: self.mode,
+                to: new.mode,
+            });
+        }
+        if self.uid != new.uid {
+            changes.push(MetaChange::Owner {
+                from: self.uid,
+                to: new.uid,
+            });
+        }
+        if self.gid != new.gid {
+            changes.push(MetaChange::Group {
+                from: self.gid,
+                to: new.gid,
+            });
+        }
+        if self.mtime != new.mtime {
+            changes.push(MetaChange::Mtime {
+                from: self.mtime,
+                to: new.mtime,
+            });
+        }
+        if self.atime != new.atime {
+            changes.push(MetaChange::Atime {
+                from: self.atime,
+                to: new.atime,
+            });
+        }
+        for (name, value) in &new.xattrs {
+            match self.xattrs.get(name) {
+                None => changes.push(MetaChange::XattrAdded(name.clone())),
+                Some(old) if old != value => changes.push(MetaChange::XattrChanged(name.clone())),
+                Some(_) => {}
+            }
+        }
+        for name in self.xattrs.keys() {
+            if !new.xattrs.contains_key(name) {
+                changes.push(MetaChange::XattrRemoved(name.clone()));
+            }
+        }
+        changes
+    }
+}
+
+fn time(secs: i64, nsecs: i64) -> SystemTime {
+    match secs {
+        secs if secs >= 0 => SystemTime::UNIX_EPOCH + Duration::new(secs as u64, nsecs as u32),
+        secs => SystemTime::UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()),
+    }
+}
+
+/// the extended attributes of the path and their values, empty if they can't be read
+fn xattrs(path: &Path) -> BTreeMap<OsString, Vec<u8>> {
+    let Ok(cpath) = CString::new(path.as_os_str().as_bytes()) else {
+        return BTreeMap::new();
+    };
+    let Some(names) =
+        read_xattr(|buf, size| unsafe { ffi::llistxattr(cpath.as_ptr(), buf.cast(), size) })
+    else {
+        return BTreeMap::new();
+    };
+    names
+        .split(|&b| b == 0)
+        .filter(|name| !name.is_empty())
+        .filter_map(|name| {
+            let cname = CString::new(name).ok()?;
+            let value = read_xattr(|buf, size| unsafe {
+                ffi::lgetxattr(cpath.as_ptr(), cname.as_ptr(), buf, size)
+            })?;
+            Some((OsStr::from_bytes(name).to_os_string(), value))
+        })
+        .collect()
+}
+
+/// asks for the size first and reads the value after, `None` if the call failed
+fn read_xattr(call: impl Fn(*mut u8, usize) -> isize) -> Option<Vec<u8>> {
+    // the value may grow in between, then the read is retried
+    for _ in 0..3 {
+        let size = call(std::ptr::null_mut(), 0);
+        if size == SYSCALL_ERROR as isize {
+            return None;
+        }
+        let mut buf = vec![0u8; size as usize];
+        match call(buf.as_mut_ptr(), buf.len()) {
+            n if n == SYSCALL_ERROR as isize => continue,
+            n => {
+                buf.truncate(n as usize);
+                return Some(buf);
+            }
+        }
+    }
+    None
+}
+
+/// remembers the metadata of the files seen in events, so `ATTRIB` events
+/// can say what changed. a file has to be known before its metadata changes,
+/// files that existed before can be added with `remember`
+#[derive(Debug, Default)]
+pub struct MetaCache {
+    files: HashMap<PathBuf, Meta>,
+}
+
+impl MetaCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// reads the current metadata of the file, the baseline of its next `ATTRIB`
+    pub fn remember(&mut self, path: &Path) {
+        if let Some(meta) = Meta::read(path) {
+            self.files.insert(path.to_path_buf(), meta);
+        }
+    }
+
+    /// fills the changes of `ATTRIB` events and keeps the metadata of the other events
+    /// up to date, changes are left empty when the file wasn't known before
+    pub fn update(&mut self, event: &mut Event) {
+        match event.kind {
+            EventKind::Delete | EventKind::DeleteSelf | EventKind::MovedFrom => {
+                self.files.remove(&event.path);
+            }
+            EventKind::Attrib => {
+                let Some(meta) = Meta::read(&event.path) else {
+                    self.files.remove(&event.path);
+                    return;
+                };
+                if let Some(old) = self.files.insert(event.path.clone(), meta) {
+                    event.changes = old.changes(&self.files[&event.path]);
+                }
+            }
+            EventKind::Create | EventKind::MovedTo | EventKind::Modify | EventKind::CloseWrite => {
+                self.remember(&event.path);
+            }
+            _ => {}
+        }
+    }
+}
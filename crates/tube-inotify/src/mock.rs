@@ -0,0 +1,204 @@
+use futures::future::FutureExt;
+use futures_timer::Delay;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use crate::errno::WatchError;
+use crate::event::{Event, EventKind};
+use crate::source::EventSource;
+
+/// an `EventSource` playing a script instead of watching the filesystem, for
+/// testing the code handling the events. the steps are played in order, batches
+/// of events, waits between them and errors, the source ends after the last step
+/// unless a `MockHandle` is still around to add more
+///
+/// ```
+/// # use tube_inotify::{Errno, EventKind, EventSource, MockSource, Op, WatchError};
+/// # use std::time::Duration;
+/// let mut source = MockSource::new()
+///     .event("/tmp/a", EventKind::Create)
+///     .wait(Duration::from_millis(10))
+///     .event("/tmp/a", EventKind::CloseWrite)
+///     .error(WatchError::new(Op::Read, None, Errno::from(5)));
+///
+/// futures::executor::block_on(async {
+///     let events = source.next_events().await.unwrap().unwrap();
+///     assert_eq!(events[0].kind, EventKind::Create);
+///     let events = source.next_events().await.unwrap().unwrap();
+///     assert_eq!(events[0].kind, EventKind::CloseWrite);
+///     assert!(source.next_events().await.unwrap().is_err());
+///     assert!(source.next_events().await.is_none());
+/// });
+/// ```
+#[derive(Debug, Default)]
+pub struct MockSource {
+    script: Arc<Mutex<Script>>,
+    // the current `Step::Wait`
+    delay: Option<Delay>,
+}
+
+/// adds steps to the script of a `MockSource` while it is read, e.g. from the test
+/// after checking what the previous events did, the source waits for more steps
+/// while any handle is alive
+#[derive(Debug)]
+pub struct MockHandle {
+    script: Arc<Mutex<Script>>,
+}
+
+#[derive(Debug, Default)]
+struct Script {
+    steps: VecDeque<Step>,
+    handles: usize,
+    // the source waiting for more steps
+    waker: Option<Waker>,
+}
+
+#[derive(Debug)]
+enum Step {
+    Events(Vec<Event>),
+    Wait(Duration),
+    Error(WatchError),
+}
+
+impl Script {
+    fn push(&mut self, step: Step) {
+        self.steps.push_back(step);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl MockSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// yields the events as a single batch
+    pub fn events<I: IntoIterator<Item = Event>>(self, events: I) -> Self {
+        self.push(Step::Events(events.into_iter().collect()));
+        self
+    }
+
+    /// yields a batch with a single event on a file
+    pub fn event<P: Into<PathBuf>>(self, path: P, kind: EventKind) -> Self {
+        self.events([mock_event(path.into(), kind)])
+    }
+
+    /// waits before playing the next step, the source is pending meanwhile
+    pub fn wait(self, duration: Duration) -> Self {
+        self.push(Step::Wait(duration));
+        self
+    }
+
+    /// yields the error, the source goes on with the next step when polled again
+    pub fn error(self, error: WatchError) -> Self {
+        self.push(Step::Error(error));
+        self
+    }
+
+    /// returns a handle adding steps after the script already given
+    pub fn handle(&self) -> MockHandle {
+        self.script.lock().unwrap().handles += 1;
+        MockHandle {
+            script: self.script.clone(),
+        }
+    }
+
+    fn push(&self, step: Step) {
+        self.script.lock().unwrap().push(step);
+    }
+}
+
+impl EventSource for MockSource {
+    fn poll_events(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Vec<Event>, WatchError>>> {
+        loop {
+            if let Some(delay) = &mut self.delay {
+                if delay.poll_unpin(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                self.delay = None;
+            }
+            let mut script = self.script.lock().unwrap();
+            match script.steps.pop_front() {
+                Some(Step::Events(events)) => return Poll::Ready(Some(Ok(events))),
+                Some(Step::Error(e)) => return Poll::Ready(Some(Err(e))),
+                Some(Step::Wait(duration)) => self.delay = Some(Delay::new(duration)),
+                None if script.handles > 0 => {
+                    script.waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+impl MockHandle {
+    /// same as `MockSource::events`
+    pub fn events<I: IntoIterator<Item = Event>>(&self, events: I) {
+        self.push(Step::Events(events.into_iter().collect()));
+    }
+
+    /// same as `MockSource::event`
+    pub fn event<P: Into<PathBuf>>(&self, path: P, kind: EventKind) {
+        self.events([mock_event(path.into(), kind)]);
+    }
+
+    /// same as `MockSource::wait`
+    pub fn wait(&self, duration: Duration) {
+        self.push(Step::Wait(duration));
+    }
+
+    /// same as `MockSource::error`
+    pub fn error(&self, error: WatchError) {
+        self.push(Step::Error(error));
+    }
+
+    /// drops the handle, the source ends after the last step once no handle is left
+    pub fn close(self) {}
+
+    fn push(&self, step: Step) {
+        self.script.lock().unwrap().push(step);
+    }
+}
+
+impl Clone for MockHandle {
+    fn clone(&self) -> Self {
+        self.script.lock().unwrap().handles += 1;
+        Self {
+            script: self.script.clone(),
+        }
+    }
+}
+
+impl Drop for MockHandle {
+    fn drop(&mut self) {
+        let mut script = self.script.lock().unwrap();
+        script.handles -= 1;
+        if script.handles == 0 {
+            if let Some(waker) = script.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+fn mock_event(path: PathBuf, kind: EventKind) -> Event {
+    Event {
+        path,
+        kind,
+        cookie: 0,
+        is_dir: false,
+        process: None,
+        changes: Vec::new(),
+        inode: None,
+        renamed_from: None,
+    }
+}
@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+
+use crate::event::{Event, EventKind};
+use crate::inotify::Inotify;
+
+/// an event, or a move made of two events, returned by `MoveResolver`
+#[derive(Debug)]
+pub enum MoveEvent {
+    Event(Event),
+    /// moved inside the watched paths, both paths are absolute
+    Moved {
+        from: PathBuf,
+        to: PathBuf,
+        is_dir: bool,
+    },
+}
+
+/// turns the `MOVED_FROM` and `MOVED_TO` pairs of an `Inotify` instance into
+/// `MoveEvent::Moved` with the absolute old and new paths, even when the two
+/// halves were reported by different watches. moves out of the watched paths
+/// are reported as `DELETE` and moves into them as `CREATE`.
+///
+/// the watches of moved directories are kept up to date, so the events under a
+/// renamed directory are reported under its new path, and directories moved out
+/// are not watched anymore
+#[derive(Debug, Default)]
+pub struct MoveResolver {
+    // waiting for the `MOVED_TO` with the same cookie
+    moved_from: Option<Event>,
+}
+
+impl MoveResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// resolves the next event read from the instance, events are returned in the
+    /// order they happened. a `MOVED_FROM` is held until the event after it shows
+    /// if it has a counterpart, `flush` gives it back when nothing else is coming
+    pub fn push(&mut self, inotify: &mut Inotify, event: Event) -> Vec<MoveEvent> {
+        let mut resolved = Vec::new();
+        if let Some(from) = self.moved_from.take() {
+            if event.kind == EventKind::MovedTo && event.cookie == from.cookie {
+                if event.is_dir {
+                    inotify.rename_watches(&from.path, &event.path);
+                }
+                resolved.push(MoveEvent::Moved {
+                    from: from.path,
+                    to: event.path,
+                    is_dir: event.is_dir,
+                });
+                return resolved;
+            }
+            resolved.push(moved_out(inotify, from));
+        }
+        match event.kind {
+            EventKind::MovedFrom => self.moved_from = Some(event),
+            EventKind::MovedTo => resolved.push(MoveEvent::Event(Event {
+                kind: EventKind::Create,
+                cookie: 0,
+                ..event
+            })),
+            _ => resolved.push(MoveEvent::Event(event)),
+        }
+        resolved
+    }
+
+    /// returns the held `MOVED_FROM` as a move out of the watched paths,
+    /// to be called once there are no more events to read
+    pub fn flush(&mut self, inotify: &mut Inotify) -> Option<MoveEvent> {
+        self.moved_from.take().map(|from| moved_out(inotify, from))
+    }
+}
+
+fn moved_out(inotify: &mut Inotify, from: Event) -> MoveEvent {
+    if from.is_dir {
+        inotify.unwatch_tree(&from.path);
+    }
+    MoveEvent::Event(Event {
+        kind: EventKind::Delete,
+        cookie: 0,
+        ..from
+    })
+}
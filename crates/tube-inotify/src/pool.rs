@@ -0,0 +1,84 @@
+use std::sync::{Arc, Mutex};
+
+/// the most buffers filled by a single `readv`
+pub(crate) const MAX_BUFFERS: usize = 16;
+
+/// room for the largest event, its header and a name of `NAME_MAX` bytes
+const MIN_BUFFER_SIZE: usize = 16 + 256;
+
+/// recycles the buffers events are read into, batches give their buffers back
+/// when dropped, so reading doesn't allocate once the pool is warm
+#[derive(Debug, Clone)]
+pub(crate) struct Pool {
+    free: Arc<Mutex<Free>>,
+    buffer_size: usize,
+    buffers: usize,
+    // the most buffers kept for the next reads
+    keep: usize,
+}
+
+#[derive(Debug, Default)]
+struct Free {
+    buffers: Vec<Vec<u8>>,
+    // the lists batches keep their buffers in
+    chains: Vec<Vec<Vec<u8>>>,
+}
+
+impl Pool {
+    /// `max_bytes` is the most a batch holds, enough buffers are kept to fill one
+    pub(crate) fn new(buffer_size: usize, buffers: usize, max_bytes: usize) -> Self {
+        let buffer_size = buffer_size.max(MIN_BUFFER_SIZE);
+        let buffers = buffers.clamp(1, MAX_BUFFERS);
+        Self {
+            free: Arc::default(),
+            buffer_size,
+            buffers,
+            keep: (max_bytes.div_ceil(buffer_size) + buffers) * 2,
+        }
+    }
+
+    /// the number of buffers filled by every read
+    pub(crate) fn buffers(&self) -> usize {
+        self.buffers
+    }
+
+    /// the most a single read returns
+    pub(crate) fn read_size(&self) -> usize {
+        self.buffer_size * self.buffers
+    }
+
+    /// an empty list for the buffers of a batch
+    pub(crate) fn chain(&self) -> Vec<Vec<u8>> {
+        let chain = self.free.lock().unwrap().chains.pop();
+        chain.unwrap_or_else(|| Vec::with_capacity(self.buffers))
+    }
+
+    /// an empty buffer with room for `buffer_size` bytes
+    pub(crate) fn buffer(&self) -> Vec<u8> {
+        let buffer = self.free.lock().unwrap().buffers.pop();
+        buffer.unwrap_or_else(|| Vec::with_capacity(self.buffer_size))
+    }
+
+    pub(crate) fn give_back(&self, buffer: Vec<u8>) {
+        let mut free = self.free.lock().unwrap();
+        self.keep(&mut free, buffer);
+    }
+
+    /// keeps the buffers for the next reads, as many as two full batches use
+    pub(crate) fn give_back_chain(&self, mut chain: Vec<Vec<u8>>) {
+        let mut free = self.free.lock().unwrap();
+        for buffer in chain.drain(..) {
+            self.keep(&mut free, buffer);
+        }
+        if free.chains.len() < self.keep {
+            free.chains.push(chain);
+        }
+    }
+
+    fn keep(&self, free: &mut Free, mut buffer: Vec<u8>) {
+        if free.buffers.len() < self.keep {
+            buffer.clear();
+            free.buffers.push(buffer);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::event::{Event, EventKind};
+use crate::ffi;
+use crate::inotify::SYSCALL_ERROR;
+
+/// how long a change seen by fanotify is kept around for the inotify event matching it
+const RECENT: Duration = Duration::from_secs(5);
+
+/// the process behind an event
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Process {
+    pub pid: u32,
+    /// `None` when the process exited before it was looked up
+    pub uid: Option<u32>,
+    pub exe: Option<PathBuf>,
+}
+
+impl Process {
+    /// looks the process up in `/proc`, what can't be read is left out
+    pub fn from_pid(pid: u32) -> Self {
+        let uid = fs::read_to_string(format!("/proc/{}/status", pid))
+            .ok()
+            .and_then(|status| {
+                let line = status.lines().find(|line| line.starts_with("Uid:"))?;
+                line.split_whitespace().nth(1)?.parse().ok()
+            });
+        Self {
+            pid,
+            uid,
+            exe: fs::read_link(format!("/proc/{}/exe", pid)).ok(),
+        }
+    }
+}
+
+/// finds the processes that changed the files. with `CAP_SYS_ADMIN` the mounts of
+/// the watched paths are marked with fanotify, which reports the pid of every write.
+/// without it, the processes having the file open when the event is attributed
+/// are looked for in `/proc`, which misses the ones that were quick to close it
+pub struct Attributor {
+    fanotify: Option<i32>,
+    // the last process that wrote to each path, according to fanotify
+    recent: HashMap<PathBuf, (Process, Instant)>,
+}
+
+impl Attributor {
+    pub fn new<'a>(paths: impl IntoIterator<Item = &'a Path>) -> Self {
+        let flags = ffi::FAN_CLASS_NOTIF | ffi::FAN_CLOEXEC | ffi::FAN_NONBLOCK;
+        let fanotify = match unsafe { ffi::fanotify_init(flags, ffi::O_RDONLY | ffi::O_LARGEFILE) }
+        {
+            SYSCALL_ERROR => None,
+            fd => Some(fd),
+        };
+        let mut attributor = Self {
+            fanotify,
+            recent: HashMap::new(),
+        };
+        if let Some(fd) = attributor.fanotify {
+            for path in paths {
+                let Ok(cpath) = CString::new(path.as_os_str().as_bytes()) else {
+                    continue;
+                };
+                let result = unsafe {
+                    ffi::fanotify_mark(
+                        fd,
+                        ffi::FAN_MARK_ADD | ffi::FAN_MARK_MOUNT,
+                        ffi::FAN_MODIFY | ffi::FAN_CLOSE_WRITE,
+                        ffi::AT_FDCWD,
+                        cpath.as_ptr(),
+                    )
+                };
+                if result == SYSCALL_ERROR {
+                    unsafe { ffi::close(fd) };
+                    attributor.fanotify = None;
+                    break;
+                }
+            }
+        }
+        attributor
+    }
+
+    /// `true` when fanotify is used, `/proc` is scanned otherwise
+    pub fn is_privileged(&self) -> bool {
+        self.fanotify.is_some()
+    }
+
+    /// sets the process of the event, if it could be found
+    pub fn attribute(&mut self, event: &mut Event) {
+        if event.process.is_some() || event.is_dir {
+            return;
+        }
+        match self.fanotify {
+            Some(fd) => {
+                self.read_fanotify(fd);
+                self.recent.retain(|_, (_, seen)| seen.elapsed() < RECENT);
+                event.process = self
+                    .recent
+                    .get(&event.path)
+                    .map(|(process, _)| process.clone());
+            }
+            // the file is only still open while it's being written to
+            None if matches!(event.kind, EventKind::Modify | EventKind::Open) => {
+                event.process = holder(&event.path).map(Process::from_pid);
+            }
+            None => {}
+        }
+    }
+
+    /// moves the pending fanotify events into `recent`
+    fn read_fanotify(&mut self, fd: i32) {
+        let mut buffer = [0u8; 4096];
+        loop {
+            let n = unsafe { ffi::read(fd, buffer.as_mut_ptr(), buffer.len()) };
+            if n <= 0 {
+                return;
+            }
+            let size = std::mem::size_of::<ffi::fanotify_event_metadata>();
+            let mut pos = 0;
+            while pos + size <= n as usize {
+                let metadata = unsafe {
+                    (buffer[pos..].as_ptr() as *const ffi::fanotify_event_metadata).read_unaligned()
+                };
+                pos += metadata.event_len.max(size as u32) as usize;
+                if metadata.fd == ffi::FAN_NOFD {
+                    continue;
+                }
+                // the event comes with a descriptor of the file, its path is its link in /proc
+                let path = fs::read_link(format!("/proc/self/fd/{}", metadata.fd));
+                unsafe { ffi::close(metadata.fd) };
+                if let Ok(path) = path {
+                    let process = Process::from_pid(metadata.pid as u32);
+                    self.recent.insert(path, (process, Instant::now()));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Attributor {
+    fn drop(&mut self) {
+        if let Some(fd) = self.fanotify {
+            unsafe { ffi::close(fd) };
+        }
+    }
+}
+
+/// a process other than this one with the file open
+fn holder(path: &Path) -> Option<u32> {
+    let own = std::process::id();
+    fs::read_dir("/proc")
+        .ok()?
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|&pid| pid != own)
+        .find(|pid| {
+            fs::read_dir(format!("/proc/{}/fd", pid))
+                .into_iter()
+                .flatten()
+                .flatten()
+                .any(|fd| fs::read_link(fd.path()).is_ok_and(|target| target == path))
+        })
+}
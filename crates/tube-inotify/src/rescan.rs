@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::event::{Event, EventKind};
+use crate::inotify::Inotify;
+
+/// what is known about an entry of the watched directories
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Entry {
+    is_dir: bool,
+    len: u64,
+    mtime: Option<SystemTime>,
+}
+
+impl Entry {
+    /// without following symlinks, `None` if the path is gone
+    fn read(path: &Path) -> Option<Self> {
+        let metadata = path.symlink_metadata().ok()?;
+        Some(Self {
+            is_dir: metadata.is_dir(),
+            len: metadata.len(),
+            mtime: metadata.modified().ok(),
+        })
+    }
+}
+
+/// a snapshot of the watched directories, kept up to date with the events, so
+/// what happened while the kernel queue overflowed (`OVERFLOW` events) can be
+/// found by scanning the directories again and comparing
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rescan {
+    entries: BTreeMap<PathBuf, Entry>,
+}
+
+impl Rescan {
+    /// scans the paths the instance watches
+    pub fn new(inotify: &Inotify) -> Self {
+        Self {
+            entries: scan(inotify),
+        }
+    }
+
+    /// applies the event to the snapshot
+    pub fn update(&mut self, event: &Event) {
+        match event.kind {
+            EventKind::Delete | EventKind::MovedFrom => self.remove(&event.path),
+            EventKind::Create
+            | EventKind::MovedTo
+            | EventKind::CloseWrite
+            | EventKind::Modify
+            | EventKind::Attrib => match Entry::read(&event.path) {
+                Some(entry) => {
+                    self.entries.insert(event.path.clone(), entry);
+                }
+                None => self.remove(&event.path),
+            },
+            _ => {}
+        }
+    }
+
+    /// watches the directories that were created while the events were lost and
+    /// returns what changed since the snapshot as `CREATE`, `MODIFY` and `DELETE`
+    /// events, sorted by path
+    pub fn recover(&mut self, inotify: &mut Inotify) -> Vec<Event> {
+        inotify.watch_missed_directories();
+        let entries = scan(inotify);
+
+        let mut events = Vec::new();
+        for (path, entry) in &entries {
+            match self.entries.get(path) {
+                None => events.push(synthetic(path, EventKind::Create, entry.is_dir)),
+                Some(old) if !entry.is_dir && old != entry => {
+                    events.push(synthetic(path, EventKind::Modify, false))
+                }
+                Some(_) => {}
+            }
+        }
+        for (path, old) in &self.entries {
+            if !entries.contains_key(path) {
+                events.push(synthetic(path, EventKind::Delete, old.is_dir));
+            }
+        }
+        events.sort_by(|a, b| a.path.cmp(&b.path));
+        self.entries = entries;
+        events
+    }
+
+    /// removes the path and, for directories, what was under it
+    fn remove(&mut self, path: &Path) {
+        // paths sort right before the paths under them
+        let removed: Vec<PathBuf> = self
+            .entries
+            .range(path.to_path_buf()..)
+            .take_while(|(entry, _)| entry.starts_with(path))
+            .map(|(entry, _)| entry.clone())
+            .collect();
+        for path in removed {
+            self.entries.remove(&path);
+        }
+    }
+}
+
+/// the watched files and the entries of the watched directories
+fn scan(inotify: &Inotify) -> BTreeMap<PathBuf, Entry> {
+    let mut entries = BTreeMap::new();
+    for (_, path) in inotify.watches() {
+        let Some(entry) = Entry::read(path) else {
+            continue;
+        };
+        if !entry.is_dir {
+            entries.insert(path.to_path_buf(), entry);
+            continue;
+        }
+        let Ok(dir) = std::fs::read_dir(path) else {
+            continue;
+        };
+        for child in dir.flatten() {
+            let child = child.path();
+            if let Some(entry) = Entry::read(&child) {
+                entries.insert(child, entry);
+            }
+        }
+    }
+    entries
+}
+
+fn synthetic(path: &Path, kind: EventKind, is_dir: bool) -> Event {
+    Event {
+        path: path.to_path_buf(),
+        kind,
+        cookie: 0,
+        is_dir,
+        process: None,
+        changes: Vec::new(),
+        inode: None,
+        renamed_from: None,
+    }
+}
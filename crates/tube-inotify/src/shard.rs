@@ -0,0 +1,239 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::task::{Context, Poll};
+
+use crate::errno::{Op, WatchError};
+use crate::event::{Event, EventKind};
+use crate::ffi;
+use crate::inotify::{canonical, Depth, Flag, Inotify};
+use crate::masks::PathMasks;
+use crate::source::EventSource;
+
+/// watches spread over several inotify instances by the hash of their path, the
+/// kernel queue of every instance only holds the events of its own watches, so a
+/// busy tree overflows its own queue and not the one of the others. recursive
+/// watches are kept on the instance of their root.
+///
+/// when an instance fails its watches are added again to the others and an
+/// `OVERFLOW` event is reported, as their events in between are lost
+pub struct ShardedInotify {
+    // `None` for instances that failed
+    shards: Vec<Option<Inotify>>,
+    watches: HashMap<PathBuf, Assigned>,
+}
+
+#[derive(Debug, Clone)]
+struct Assigned {
+    shard: usize,
+    watch: Watch,
+}
+
+/// what a path was watched with, to watch it again on another instance
+#[derive(Debug, Clone)]
+enum Watch {
+    Path(u32),
+    Tree(PathMasks, Depth),
+}
+
+impl ShardedInotify {
+    /// creates `shards` instances (at least one), all nonblocking
+    pub fn new(shards: usize) -> Result<Self, WatchError> {
+        let shards = (0..shards.max(1))
+            .map(|_| Inotify::with_flags(Flag::NONBLOCKING).map(Some))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            shards,
+            watches: HashMap::new(),
+        })
+    }
+
+    /// same as `Inotify::include_hidden`, for every instance
+    pub fn include_hidden(mut self, hidden: bool) -> Self {
+        self.shards = self
+            .shards
+            .into_iter()
+            .map(|shard| shard.map(|inotify| inotify.include_hidden(hidden)))
+            .collect();
+        self
+    }
+
+    /// same as `Inotify::add_watch`, on the instance the path hashes to
+    pub fn add_watch(&mut self, pathname: PathBuf, mask: u32) -> Result<(), WatchError> {
+        self.add(canonical(&pathname), Watch::Path(mask))
+    }
+
+    /// same as `Inotify::add_recursive`, the whole tree is watched by the
+    /// instance its root hashes to
+    pub fn add_recursive(
+        &mut self,
+        pathname: PathBuf,
+        mask: u32,
+        depth: Depth,
+    ) -> Result<(), WatchError> {
+        self.add_recursive_masks(pathname, PathMasks::new(mask), depth)
+    }
+
+    /// same as `Inotify::add_recursive_masks`
+    pub fn add_recursive_masks(
+        &mut self,
+        pathname: PathBuf,
+        masks: PathMasks,
+        depth: Depth,
+    ) -> Result<(), WatchError> {
+        self.add(canonical(&pathname), Watch::Tree(masks, depth))
+    }
+
+    /// removes the watches of the path and of the directories under it on its
+    /// instance, returns `false` if the path wasn't watched
+    pub fn unwatch(&mut self, path: &Path) -> bool {
+        let path = canonical(path);
+        let Some(assigned) = self.watches.remove(&path) else {
+            return false;
+        };
+        if let Some(inotify) = &mut self.shards[assigned.shard] {
+            inotify.unwatch_tree(&path);
+        }
+        true
+    }
+
+    /// the paths watched by all the instances
+    pub fn watches(&self) -> impl Iterator<Item = &Path> {
+        self.live()
+            .flat_map(|(_, inotify)| inotify.watches().map(|(_, path)| path))
+    }
+
+    /// the instance a path given to `add_watch` or `add_recursive` is watched by
+    pub fn shard_of(&self, path: &Path) -> Option<usize> {
+        self.watches
+            .get(&canonical(path))
+            .map(|assigned| assigned.shard)
+    }
+
+    /// the number of instances that didn't fail
+    pub fn shards(&self) -> usize {
+        self.live().count()
+    }
+
+    fn live(&self) -> impl Iterator<Item = (usize, &Inotify)> {
+        self.shards
+            .iter()
+            .enumerate()
+            .filter_map(|(i, shard)| shard.as_ref().map(|inotify| (i, inotify)))
+    }
+
+    /// the instance for the path among the ones that didn't fail
+    fn pick(&self, path: &Path) -> Option<usize> {
+        let live: Vec<usize> = self.live().map(|(i, _)| i).collect();
+        let mut hasher = DefaultHasher::new();
+        path.hash(&mut hasher);
+        live.get(hasher.finish() as usize % live.len().max(1))
+            .copied()
+    }
+
+    fn add(&mut self, path: PathBuf, watch: Watch) -> Result<(), WatchError> {
+        let shard = match self.watches.get(&path) {
+            // watched again, on the instance it is already on
+            Some(assigned) => assigned.shard,
+            None => self.pick(&path).ok_or_else(|| closed(&path))?,
+        };
+        let Some(inotify) = &mut self.shards[shard] else {
+            return Err(closed(&path));
+        };
+        match &watch {
+            Watch::Path(mask) => inotify.add_watch(path.clone(), *mask).map(|_| ())?,
+            Watch::Tree(masks, depth) => {
+                inotify.add_recursive_masks(path.clone(), masks.clone(), *depth)?
+            }
+        }
+        self.watches.insert(path, Assigned { shard, watch });
+        Ok(())
+    }
+
+    /// drops the instance and adds its watches to the others, paths that are gone
+    /// by now are not watched anymore
+    fn fail(&mut self, shard: usize) -> Result<(), WatchError> {
+        self.shards[shard] = None;
+        let moved: Vec<(PathBuf, Watch)> = self
+            .watches
+            .iter()
+            .filter(|(_, assigned)| assigned.shard == shard)
+            .map(|(path, assigned)| (path.clone(), assigned.watch.clone()))
+            .collect();
+        let mut result = Ok(());
+        for (path, watch) in moved {
+            self.watches.remove(&path);
+            match self.add(path, watch) {
+                Err(e) if e.is_not_found() => continue,
+                Err(e) if result.is_ok() => result = Err(e),
+                _ => {}
+            }
+        }
+        result
+    }
+}
+
+impl EventSource for ShardedInotify {
+    /// reads the instances that have events, the batch holds their events one
+    /// instance after the other. `Poll::Pending` when none has any, the instances
+    /// are waited on through the reactor like a single `Inotify` is
+    fn poll_events(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Vec<Event>, WatchError>>> {
+        let live: Vec<usize> = self.live().map(|(i, _)| i).collect();
+        if live.is_empty() {
+            return Poll::Ready(None);
+        }
+
+        // the instances that return `Poll::Pending` wake the task once they have events
+        let mut events = None;
+        let mut failed = Vec::new();
+        for shard in live {
+            let Some(inotify) = &mut self.shards[shard] else {
+                continue;
+            };
+            match inotify.poll_events(cx) {
+                Poll::Ready(Some(Ok(batch))) => events.get_or_insert_with(Vec::new).extend(batch),
+                Poll::Ready(Some(Err(e))) => failed.push((shard, Some(e))),
+                Poll::Ready(None) => failed.push((shard, None)),
+                Poll::Pending => {}
+            }
+        }
+
+        if failed.is_empty() {
+            // the batch may be empty when none of the events read could be resolved
+            return match events {
+                Some(events) => Poll::Ready(Some(Ok(events))),
+                None => Poll::Pending,
+            };
+        }
+        let mut events = events.unwrap_or_default();
+        for (shard, error) in failed {
+            if let Err(e) = self.fail(shard) {
+                return Poll::Ready(Some(Err(e)));
+            }
+            // nothing is left to take the watches
+            if self.shards() == 0 {
+                return Poll::Ready(error.map(Err));
+            }
+        }
+        events.push(Event {
+            path: PathBuf::new(),
+            kind: EventKind::Overflow,
+            cookie: 0,
+            is_dir: false,
+            process: None,
+            changes: Vec::new(),
+            inode: None,
+            renamed_from: None,
+        });
+        Poll::Ready(Some(Ok(events)))
+    }
+}
+
+/// every instance failed
+fn closed(path: &Path) -> WatchError {
+    WatchError::new(Op::Watch, Some(path.to_path_buf()), ffi::EBADF.into())
+}
@@ -0,0 +1,92 @@
+use std::future::Future;
+use std::os::fd::RawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::Waker;
+
+use crate::errno::Errno;
+use crate::ffi;
+use crate::inotify::SYSCALL_ERROR;
+
+/// ends the streams of the instances it was given to, see `Inotify::with_shutdown`.
+/// clones share the same state, so one is kept by the code shutting down and the
+/// others are given to the instances
+#[derive(Debug, Clone)]
+pub struct ShutdownToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    // readable once cancelled, so a stream blocked in `poll` wakes up
+    fd: RawFd,
+    cancelled: AtomicBool,
+    // the streams waiting for events or polled while paused
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl ShutdownToken {
+    pub fn new() -> Result<Self, Errno> {
+        let fd = unsafe { ffi::eventfd(0, ffi::EFD_NONBLOCK | ffi::EFD_CLOEXEC) };
+        if fd == SYSCALL_ERROR {
+            return Err(Errno::last());
+        }
+        Ok(Self {
+            inner: Arc::new(Inner {
+                fd,
+                cancelled: AtomicBool::new(false),
+                wakers: Mutex::new(Vec::new()),
+            }),
+        })
+    }
+
+    /// ends the streams, the ones waiting for events return `None` right away
+    pub fn cancel(&self) {
+        if self.inner.cancelled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let one = 1u64.to_ne_bytes();
+        unsafe { ffi::write(self.inner.fd, one.as_ptr(), one.len()) };
+        for waker in self.inner.wakers.lock().unwrap().drain(..) {
+            waker.wake();
+        }
+    }
+
+    /// cancels the token once the future completes, e.g. `cancelled_owned` of a
+    /// `tokio_util` `CancellationToken`. the future is driven on a thread of its own
+    pub fn cancel_on<F>(&self, future: F)
+    where
+        F: Future + Send + 'static,
+    {
+        let token = self.clone();
+        std::thread::spawn(move || {
+            futures::executor::block_on(future);
+            token.cancel();
+        });
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// polled along with the inotify descriptor
+    pub(crate) fn fd(&self) -> RawFd {
+        self.inner.fd
+    }
+
+    /// wakes the stream on `cancel`
+    pub(crate) fn register(&self, waker: &Waker) {
+        let mut wakers = self.inner.wakers.lock().unwrap();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::close(self.fd);
+        }
+    }
+}
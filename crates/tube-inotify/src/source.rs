@@ -0,0 +1,56 @@
+use futures::stream::StreamExt;
+use std::future::Future;
+use std::task::{Context, Poll};
+
+use crate::errno::WatchError;
+use crate::event::Event;
+use crate::inotify::Inotify;
+
+/// where resolved events are read from, implemented by `Inotify`, code handling
+/// the events can take any source so it can be tested with a `MockSource`
+/// (behind the `test-util` feature) instead of a real filesystem
+pub trait EventSource {
+    /// polls for the next batch of events, `None` once the source has no more events,
+    /// a batch may be empty when none of the events read could be resolved
+    fn poll_events(&mut self, cx: &mut Context<'_>)
+        -> Poll<Option<Result<Vec<Event>, WatchError>>>;
+
+    /// waits for the next batch of events
+    fn next_events(&mut self) -> impl Future<Output = Option<Result<Vec<Event>, WatchError>>> + '_
+    where
+        Self: Sized,
+    {
+        futures::future::poll_fn(move |cx| self.poll_events(cx))
+    }
+}
+
+impl<S: EventSource + ?Sized> EventSource for Box<S> {
+    fn poll_events(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Vec<Event>, WatchError>>> {
+        (**self).poll_events(cx)
+    }
+}
+
+impl EventSource for Inotify {
+    /// reads the next batch and resolves its events, events of unknown
+    /// watch descriptors and echoes are left out, see `Inotify::resolve`
+    fn poll_events(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Vec<Event>, WatchError>>> {
+        match self.poll_next_unpin(cx) {
+            Poll::Ready(Some(Ok(batch))) => {
+                let events = batch
+                    .refs()
+                    .filter_map(|event| self.resolve_ref(event))
+                    .collect();
+                Poll::Ready(Some(Ok(events)))
+            }
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
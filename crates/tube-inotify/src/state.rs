@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+
+use crate::inotify::{Depth, Symlinks};
+use crate::masks::PathMasks;
+use crate::rescan::Rescan;
+
+/// the watches of an instance and what the watched directories held when it was
+/// taken, kept across restarts (with the `serde` feature) to watch the same paths
+/// again and find what changed in between, see `Inotify::export_state`
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WatchState {
+    /// sorted by path, so the roots of trees come before what is under them
+    pub watches: Vec<SavedWatch>,
+    pub(crate) snapshot: Rescan,
+}
+
+/// a path given to `add_watch` or `add_recursive`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SavedWatch {
+    pub path: PathBuf,
+    pub mask: u32,
+    /// set for the roots of recursive watches, the directories under them
+    /// are found again when the state is restored
+    pub tree: Option<SavedTree>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SavedTree {
+    pub masks: PathMasks,
+    pub depth: Depth,
+    pub symlinks: Symlinks,
+}
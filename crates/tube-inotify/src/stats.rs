@@ -0,0 +1,125 @@
+use std::os::fd::RawFd;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use crate::ffi;
+
+/// header size of an event, the name follows it
+const HEADER_SIZE: usize = std::mem::size_of::<ffi::inotify_event>();
+
+/// what an instance read and how far behind its reader is, see `Inotify::stats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub batches: u64,
+    pub events: u64,
+    pub bytes: u64,
+    /// how long the last batch waited in the kernel queue to be read, events only wait
+    /// when the reader is still busy with the ones before them
+    pub lag: Duration,
+    pub max_lag: Duration,
+    /// bytes left in the kernel queue after the last read (`FIONREAD`)
+    pub queued_bytes: usize,
+    /// the events left in the kernel queue, estimated from the average size of the
+    /// events read so far
+    pub queued_events: usize,
+    /// `/proc/sys/fs/inotify/max_queued_events` when the instance first read it, the
+    /// queue overflows past it
+    pub queue_limit: Option<usize>,
+}
+
+/// kept by the instance between reads
+#[derive(Debug, Default)]
+pub(crate) struct Lag {
+    stats: Stats,
+    // events were left in the queue by the last read
+    ready_since: Option<Instant>,
+    // read once, not on every batch
+    queue_limit: OnceLock<Option<usize>>,
+}
+
+impl Lag {
+    pub(crate) fn stats(&self) -> Stats {
+        Stats {
+            queue_limit: self.queue_limit(),
+            ..self.stats
+        }
+    }
+
+    fn queue_limit(&self) -> Option<usize> {
+        *self.queue_limit.get_or_init(queue_limit)
+    }
+
+    /// called before reading, the events left by the last read waited until now
+    pub(crate) fn reading(&mut self) {
+        if let Some(since) = self.ready_since.take() {
+            self.stats.lag = since.elapsed();
+            self.stats.max_lag = self.stats.max_lag.max(self.stats.lag);
+        } else {
+            self.stats.lag = Duration::ZERO;
+        }
+    }
+
+    /// called with the buffers of a batch, right after they were read
+    pub(crate) fn read(&mut self, fd: RawFd, buffers: &[Vec<u8>]) {
+        let stats = &mut self.stats;
+        stats.batches += 1;
+        for buffer in buffers {
+            stats.bytes += buffer.len() as u64;
+            stats.events += count_events(buffer);
+        }
+        stats.queued_bytes = queued_bytes(fd);
+        let average = match stats.events {
+            0 => HEADER_SIZE,
+            events => (stats.bytes / events) as usize,
+        };
+        stats.queued_events = stats.queued_bytes.div_ceil(average.max(1));
+        if stats.queued_bytes > 0 {
+            self.ready_since = Some(Instant::now());
+        }
+
+        #[cfg(feature = "tracing")]
+        {
+            tracing::trace!(
+                lag = ?stats.lag,
+                queued_events = stats.queued_events,
+                "inotify batch read"
+            );
+            let queued_events = stats.queued_events;
+            if let Some(limit) = self.queue_limit().filter(|limit| queued_events > limit / 2) {
+                tracing::warn!(
+                    queued_events,
+                    limit,
+                    "the inotify queue is more than half full, events may be lost"
+                );
+            }
+        }
+    }
+}
+
+/// the events in a buffer the kernel filled, without reading their names
+fn count_events(buffer: &[u8]) -> u64 {
+    let mut count = 0;
+    let mut pos = 0;
+    while let Some(len) = buffer.get(pos + 12..pos + HEADER_SIZE) {
+        let len = u32::from_ne_bytes(len.try_into().unwrap()) as usize;
+        pos += HEADER_SIZE + len;
+        count += 1;
+    }
+    count
+}
+
+fn queued_bytes(fd: RawFd) -> usize {
+    let mut bytes: i32 = 0;
+    match unsafe { ffi::ioctl(fd, ffi::FIONREAD, &mut bytes) } {
+        0 => bytes.max(0) as usize,
+        _ => 0,
+    }
+}
+
+fn queue_limit() -> Option<usize> {
+    std::fs::read_to_string("/proc/sys/fs/inotify/max_queued_events")
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
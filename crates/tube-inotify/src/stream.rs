@@ -0,0 +1,497 @@
+use futures::stream::{Stream, StreamExt};
+use futures_timer::Delay;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use crate::errno::WatchError;
+use crate::event::{Event, EventKind};
+use crate::inotify::{Inotify, InotifyEventBatch};
+use crate::moves::{MoveEvent, MoveResolver};
+use crate::rescan::Rescan;
+
+/// adapters for building pipelines on any stream of `Event`s, e.g. the one
+/// `Inotify::resolved` turns the raw batches into, or one built by the caller
+///
+/// ```no_run
+/// # use tube_inotify::{Inotify, Mask, TubeStreamExt};
+/// # use std::time::Duration;
+/// # fn main() -> Result<(), tube_inotify::WatchError> {
+/// let batches = Inotify::new()?
+///     .watch("/tmp".into(), Mask::CLOSE_WRITE | Mask::MOVE)?
+///     .resolved()
+///     .filter_mask(Mask::CLOSE_WRITE | Mask::MOVED_TO)
+///     .debounced(Duration::from_millis(100));
+/// # Ok(())
+/// # }
+/// ```
+pub trait TubeStreamExt: Stream + Sized {
+    /// keeps only the events whose kind is in the mask, errors are kept
+    fn filter_mask(self, mask: u32) -> FilterMask<Self>
+    where
+        Self: Stream<Item = Result<Event, WatchError>> + Unpin,
+    {
+        FilterMask { inner: self, mask }
+    }
+
+    /// joins every `MOVED_FROM` with the `MOVED_TO` of the same cookie that
+    /// follows it into a single `Change::Renamed`, moves in or out of the watched
+    /// paths have no counterpart and are passed on as `Change::Event`
+    fn paired_renames(self) -> PairedRenames<Self>
+    where
+        Self: Stream<Item = Result<Event, WatchError>> + Unpin,
+    {
+        PairedRenames {
+            inner: self,
+            moved_from: None,
+            pending: None,
+        }
+    }
+
+    /// collects the events until none arrived for the whole window and yields them
+    /// as a batch, only the last event of every path is kept
+    fn debounced(self, window: Duration) -> Debounced<Self>
+    where
+        Self: Stream<Item = Result<Event, WatchError>> + Unpin,
+    {
+        Debounced {
+            inner: self,
+            window,
+            delay: None,
+            events: Vec::new(),
+            index: HashMap::new(),
+            done: false,
+        }
+    }
+
+    /// passes the first event of every path and kind on right away and drops the
+    /// same events that follow it, as long as each comes within `window` of the one
+    /// before. once the repeats stop for the whole window the last one is yielded with
+    /// the number of events it stands for, so the final state isn't missed
+    fn dedup(self, window: Duration) -> Dedup<Self>
+    where
+        Self: Stream<Item = Result<Event, WatchError>> + Unpin,
+    {
+        Dedup {
+            inner: self,
+            window,
+            delay: None,
+            seen: HashMap::new(),
+            ready: VecDeque::new(),
+            done: false,
+        }
+    }
+}
+
+impl<S: Stream> TubeStreamExt for S {}
+
+/// stream returned by `Inotify::resolved`
+pub struct Resolved {
+    inotify: Inotify,
+    batch: Option<InotifyEventBatch<4096>>,
+    rescan: Option<Rescan>,
+    // found by the rescan after an overflow, yielded before reading on
+    recovered: VecDeque<Event>,
+}
+
+impl Resolved {
+    pub(crate) fn new(inotify: Inotify) -> Self {
+        Self {
+            inotify,
+            batch: None,
+            rescan: None,
+            recovered: VecDeque::new(),
+        }
+    }
+
+    /// scans the watched directories after every `OVERFLOW` event, which is still
+    /// yielded, and yields what changed while the events were lost as `CREATE`,
+    /// `MODIFY` and `DELETE` events right after it, see `Rescan`
+    pub fn recover_overflow(mut self) -> Self {
+        self.rescan = Some(Rescan::new(&self.inotify));
+        self
+    }
+
+    /// returns the `Inotify` instance, e.g. to add watches
+    pub fn get_mut(&mut self) -> &mut Inotify {
+        &mut self.inotify
+    }
+}
+
+impl Stream for Resolved {
+    type Item = Result<Event, WatchError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(event) = this.recovered.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+            if let Some(batch) = &mut this.batch {
+                match batch.next() {
+                    Some(event) => match this.inotify.resolve(&event) {
+                        Some(event) => {
+                            if let Some(rescan) = &mut this.rescan {
+                                match event.kind {
+                                    EventKind::Overflow => {
+                                        this.recovered.extend(rescan.recover(&mut this.inotify))
+                                    }
+                                    _ => rescan.update(&event),
+                                }
+                            }
+                            return Poll::Ready(Some(Ok(event)));
+                        }
+                        None => continue,
+                    },
+                    None => this.batch = None,
+                }
+            }
+            match this.inotify.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(batch))) => this.batch = Some(batch),
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// stream returned by `Inotify::resolved_moves`
+pub struct ResolvedMoves {
+    inotify: Inotify,
+    batch: Option<InotifyEventBatch<4096>>,
+    resolver: MoveResolver,
+    ready: VecDeque<MoveEvent>,
+}
+
+impl ResolvedMoves {
+    pub(crate) fn new(inotify: Inotify) -> Self {
+        Self {
+            inotify,
+            batch: None,
+            resolver: MoveResolver::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// returns the `Inotify` instance, e.g. to add watches
+    pub fn get_mut(&mut self) -> &mut Inotify {
+        &mut self.inotify
+    }
+}
+
+impl Stream for ResolvedMoves {
+    type Item = Result<MoveEvent, WatchError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(event) = this.ready.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+            if let Some(batch) = &mut this.batch {
+                match batch.next() {
+                    Some(event) => {
+                        if let Some(event) = this.inotify.resolve(&event) {
+                            let resolved = this.resolver.push(&mut this.inotify, event);
+                            this.ready.extend(resolved);
+                        }
+                        continue;
+                    }
+                    None => this.batch = None,
+                }
+                // the kernel queues both halves of a move together, nothing
+                // left to read means the other half is outside the watched paths
+                if !this.inotify.has_pending() {
+                    this.ready.extend(this.resolver.flush(&mut this.inotify));
+                    continue;
+                }
+            }
+            match this.inotify.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(batch))) => this.batch = Some(batch),
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// stream returned by `TubeStreamExt::filter_mask`
+pub struct FilterMask<S> {
+    inner: S,
+    mask: u32,
+}
+
+impl<S> Stream for FilterMask<S>
+where
+    S: Stream<Item = Result<Event, WatchError>> + Unpin,
+{
+    type Item = Result<Event, WatchError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.inner.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(event))) if event.kind.mask() & self.mask == 0 => continue,
+                poll => return poll,
+            }
+        }
+    }
+}
+
+/// an event, or a rename made of two events, yielded by `TubeStreamExt::paired_renames`
+#[derive(Debug)]
+pub enum Change {
+    Event(Event),
+    Renamed {
+        from: PathBuf,
+        to: PathBuf,
+        is_dir: bool,
+    },
+}
+
+/// stream returned by `TubeStreamExt::paired_renames`
+pub struct PairedRenames<S> {
+    inner: S,
+    // waiting for the `MOVED_TO` that may follow it
+    moved_from: Option<Event>,
+    // read after an unpaired `MOVED_FROM`, yielded right after it
+    pending: Option<Result<Event, WatchError>>,
+}
+
+impl<S> Stream for PairedRenames<S>
+where
+    S: Stream<Item = Result<Event, WatchError>> + Unpin,
+{
+    type Item = Result<Change, WatchError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let next = match self.pending.take() {
+                Some(next) => Some(next),
+                None => match self.inner.poll_next_unpin(cx) {
+                    Poll::Ready(next) => next,
+                    // the kernel queues both halves of a rename together, so nothing
+                    // ready means the file was moved out of the watched paths
+                    Poll::Pending => match self.moved_from.take() {
+                        Some(from) => return Poll::Ready(Some(Ok(Change::Event(from)))),
+                        None => return Poll::Pending,
+                    },
+                },
+            };
+
+            let event = match (next, self.moved_from.take()) {
+                (Some(Ok(to)), Some(from))
+                    if to.kind == EventKind::MovedTo && to.cookie == from.cookie =>
+                {
+                    return Poll::Ready(Some(Ok(Change::Renamed {
+                        from: from.path,
+                        to: to.path,
+                        is_dir: to.is_dir,
+                    })));
+                }
+                (next, Some(from)) => {
+                    self.pending = next;
+                    return Poll::Ready(Some(Ok(Change::Event(from))));
+                }
+                (Some(Ok(event)), None) => event,
+                (next, None) => return Poll::Ready(next.map(|next| next.map(Change::Event))),
+            };
+            match event.kind {
+                EventKind::MovedFrom => self.moved_from = Some(event),
+                _ => return Poll::Ready(Some(Ok(Change::Event(event)))),
+            }
+        }
+    }
+}
+
+/// stream returned by `TubeStreamExt::debounced`
+pub struct Debounced<S> {
+    inner: S,
+    window: Duration,
+    delay: Option<Delay>,
+    events: Vec<Event>,
+    // position of the last event of every path in `events`
+    index: HashMap<PathBuf, usize>,
+    done: bool,
+}
+
+impl<S> Debounced<S> {
+    fn push(&mut self, event: Event) {
+        match self.index.get(&event.path) {
+            Some(&i) => self.events[i] = event,
+            None => {
+                self.index.insert(event.path.clone(), self.events.len());
+                self.events.push(event);
+            }
+        }
+        self.delay = Some(Delay::new(self.window));
+    }
+
+    fn flush(&mut self) -> Vec<Event> {
+        self.index.clear();
+        self.delay = None;
+        std::mem::take(&mut self.events)
+    }
+}
+
+impl<S> Stream for Debounced<S>
+where
+    S: Stream<Item = Result<Event, WatchError>> + Unpin,
+{
+    type Item = Result<Vec<Event>, WatchError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        while !self.done {
+            match self.inner.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(event))) => self.push(event),
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => self.done = true,
+                Poll::Pending => break,
+            }
+        }
+
+        if self.events.is_empty() {
+            return match self.done {
+                true => Poll::Ready(None),
+                false => Poll::Pending,
+            };
+        }
+        // what was collected is flushed right away once the stream ended
+        let done = self.done;
+        let elapsed = match &mut self.delay {
+            Some(delay) if !done => Pin::new(delay).poll(cx).is_ready(),
+            _ => true,
+        };
+        match elapsed {
+            true => Poll::Ready(Some(Ok(self.flush()))),
+            false => Poll::Pending,
+        }
+    }
+}
+
+/// an event yielded by `TubeStreamExt::dedup`, `count` is 1 for the first event
+/// of a path and kind and the number of repeats it stands for after them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deduped {
+    pub event: Event,
+    pub count: usize,
+}
+
+/// stream returned by `TubeStreamExt::dedup`
+pub struct Dedup<S> {
+    inner: S,
+    window: Duration,
+    // the timer of the window that ends first, with when it ends
+    delay: Option<(Instant, Delay)>,
+    seen: HashMap<(PathBuf, EventKind), Seen>,
+    ready: VecDeque<Deduped>,
+    done: bool,
+}
+
+/// the repeats of an event that was passed on
+struct Seen {
+    last: Instant,
+    repeats: usize,
+    latest: Option<Event>,
+}
+
+impl Seen {
+    /// the last repeat with their number, if there were any
+    fn summary(self) -> Option<Deduped> {
+        let count = self.repeats;
+        self.latest.map(|event| Deduped { event, count })
+    }
+}
+
+impl<S> Dedup<S> {
+    fn push(&mut self, event: Event, now: Instant) {
+        let key = (event.path.clone(), event.kind);
+        match self.seen.get_mut(&key) {
+            Some(seen) if now - seen.last < self.window => {
+                seen.last = now;
+                seen.repeats += 1;
+                seen.latest = Some(event);
+            }
+            _ => {
+                let seen = Seen {
+                    last: now,
+                    repeats: 0,
+                    latest: None,
+                };
+                // repeats that were not yielded yet come before
+                if let Some(summary) = self.seen.insert(key, seen).and_then(Seen::summary) {
+                    self.ready.push_back(summary);
+                }
+                self.ready.push_back(Deduped { event, count: 1 });
+            }
+        }
+    }
+
+    /// yields the repeats of the events whose window is over, all of them once
+    /// the stream ended, returns when the next window is over
+    fn expire(&mut self, now: Instant) -> Option<Instant> {
+        let window = self.window;
+        let done = self.done;
+        let expired: Vec<(PathBuf, EventKind)> = self
+            .seen
+            .iter()
+            .filter(|(_, seen)| done || now - seen.last >= window)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            if let Some(summary) = self.seen.remove(&key).and_then(Seen::summary) {
+                self.ready.push_back(summary);
+            }
+        }
+        self.seen.values().map(|seen| seen.last + window).min()
+    }
+}
+
+impl<S> Stream for Dedup<S>
+where
+    S: Stream<Item = Result<Event, WatchError>> + Unpin,
+{
+    type Item = Result<Deduped, WatchError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(deduped) = self.ready.pop_front() {
+                return Poll::Ready(Some(Ok(deduped)));
+            }
+            while !self.done {
+                match self.inner.poll_next_unpin(cx) {
+                    Poll::Ready(Some(Ok(event))) => self.push(event, Instant::now()),
+                    Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                    Poll::Ready(None) => self.done = true,
+                    Poll::Pending => break,
+                }
+                if !self.ready.is_empty() {
+                    break;
+                }
+            }
+            let now = Instant::now();
+            let next = self.expire(now);
+            if !self.ready.is_empty() {
+                continue;
+            }
+            let Some(next) = next else {
+                self.delay = None;
+                return match self.done {
+                    true => Poll::Ready(None),
+                    false => Poll::Pending,
+                };
+            };
+            let delay = match &mut self.delay {
+                Some((deadline, delay)) if *deadline == next => delay,
+                delay => &mut delay.insert((next, Delay::new(next - now))).1,
+            };
+            if Pin::new(delay).poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::event::{Event, EventKind};
+use crate::process::Process;
+
+/// recognizes the files being uploaded, written under a temporary name and renamed
+/// once complete (`file.part` then `file`) or written in place, and reports every
+/// one of them as a single `FILE_READY` event on its final path, in place of the
+/// `CREATE`, `MODIFY`, `CLOSE_WRITE` and `MOVED_FROM`/`MOVED_TO` events of the upload.
+///
+/// a file is ready when it is renamed after it was closed, the two halves of the
+/// rename are paired by their cookie, or when it was closed and no other event
+/// happened on it for the settle time. only the files created while watching are
+/// followed, the events of the others are returned as they are
+#[derive(Debug)]
+pub struct Uploads {
+    settle: Duration,
+    // the files created and not ready yet
+    uploads: HashMap<PathBuf, Upload>,
+    // a renamed upload waiting for the `MOVED_TO` with the same cookie
+    moving: Option<(Event, Upload)>,
+}
+
+#[derive(Debug)]
+struct Upload {
+    // closed after the last write
+    closed: bool,
+    // the time of the last event on the file
+    last: Instant,
+    // the latest process known to have written the file
+    process: Option<Process>,
+}
+
+impl Uploads {
+    pub fn new(settle: Duration) -> Self {
+        Self {
+            settle,
+            uploads: HashMap::new(),
+            moving: None,
+        }
+    }
+
+    /// follows the uploads with the next event, returns the event if it isn't part
+    /// of an upload and the `FILE_READY` of the upload it completed
+    pub fn push(&mut self, event: Event) -> Vec<Event> {
+        let mut ready = Vec::new();
+        if let Some((from, mut upload)) = self.moving.take() {
+            if event.kind == EventKind::MovedTo && event.cookie == from.cookie {
+                upload.update(&event);
+                match upload.closed {
+                    true => ready.push(file_ready(event.path, Some(from.path), upload)),
+                    // renamed while still written, followed under its new name
+                    false => {
+                        self.uploads.insert(event.path, upload);
+                    }
+                }
+                return ready;
+            }
+            // moved out of the watched paths, it won't be ready in them
+        }
+        if event.is_dir {
+            ready.push(event);
+            return ready;
+        }
+
+        let Some(upload) = self.uploads.get_mut(&event.path) else {
+            match event.kind {
+                EventKind::Create => {
+                    let mut upload = Upload {
+                        closed: false,
+                        last: Instant::now(),
+                        process: None,
+                    };
+                    upload.update(&event);
+                    self.uploads.insert(event.path, upload);
+                }
+                _ => ready.push(event),
+            }
+            return ready;
+        };
+        upload.update(&event);
+        match event.kind {
+            EventKind::Modify => upload.closed = false,
+            EventKind::CloseWrite => upload.closed = true,
+            EventKind::MovedFrom => {
+                let upload = self.uploads.remove(&event.path).unwrap();
+                self.moving = Some((event, upload));
+            }
+            // given up on, or replaced by another file
+            EventKind::Delete | EventKind::MovedTo => {
+                self.uploads.remove(&event.path);
+                if event.kind == EventKind::MovedTo {
+                    ready.push(event);
+                }
+            }
+            _ => {}
+        }
+        ready
+    }
+
+    /// returns the `FILE_READY` of the uploads that were closed and left alone for
+    /// the settle time, to be called when `deadline` passed
+    pub fn expire(&mut self) -> Vec<Event> {
+        let now = Instant::now();
+        if self
+            .moving
+            .as_ref()
+            .is_some_and(|(_, upload)| now >= upload.last + self.settle)
+        {
+            self.moving = None;
+        }
+        let settled: Vec<PathBuf> = self
+            .uploads
+            .iter()
+            .filter(|(_, upload)| upload.closed && now >= upload.last + self.settle)
+            .map(|(path, _)| path.clone())
+            .collect();
+        settled
+            .into_iter()
+            .map(|path| {
+                let upload = self.uploads.remove(&path).unwrap();
+                file_ready(path, None, upload)
+            })
+            .collect()
+    }
+
+    /// when `expire` should be called next, `None` when no upload can settle
+    /// before another event happens
+    pub fn deadline(&self) -> Option<Instant> {
+        let moving = self.moving.as_ref().map(|(_, upload)| upload);
+        self.uploads
+            .values()
+            .filter(|upload| upload.closed)
+            .chain(moving)
+            .map(|upload| upload.last + self.settle)
+            .min()
+    }
+}
+
+impl Upload {
+    fn update(&mut self, event: &Event) {
+        self.last = Instant::now();
+        if event.process.is_some() {
+            self.process = event.process.clone();
+        }
+    }
+}
+
+fn file_ready(path: PathBuf, renamed_from: Option<PathBuf>, upload: Upload) -> Event {
+    Event {
+        path,
+        kind: EventKind::FileReady,
+        cookie: 0,
+        is_dir: false,
+        process: upload.process,
+        changes: Vec::new(),
+        inode: None,
+        renamed_from,
+    }
+}
@@ -0,0 +1,583 @@
+use futures::future::{self, Either};
+use futures::task::noop_waker;
+use futures::{Stream, StreamExt};
+use futures_timer::Delay;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::task::Context;
+use std::time::{Duration, Instant};
+use tube_inotify::{
+    Added, Change, Depth, Dispatcher, Event, EventKind, EventSource, Flag, Inotify, Mask,
+    PathMasks, ShardedInotify, ShutdownToken, TubeStreamExt, Uploads,
+};
+use tube_testkit::Fixture;
+
+/// the next item of the stream, panics if it took more than a second
+fn next_item<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+    let timeout = Delay::new(Duration::from_secs(1));
+    match futures::executor::block_on(future::select(stream.next(), timeout)) {
+        Either::Left((item, _)) => item,
+        Either::Right(_) => panic!("the stream yielded nothing in a second"),
+    }
+}
+
+#[test]
+fn files() {
+    let mut fixture = Fixture::new();
+    fixture.create("a").write("a", "hello").chmod("a", 0o600);
+    fixture.expect(&[
+        ("a", EventKind::Create),
+        ("a", EventKind::CloseWrite),
+        ("a", EventKind::Attrib),
+    ]);
+    fixture.write("a", "again");
+    fixture.expect(&[("a", EventKind::CloseWrite)]);
+    fixture.delete("a");
+    fixture.expect(&[("a", EventKind::Delete)]);
+    fixture.expect_quiet(Duration::from_millis(50));
+}
+
+#[test]
+fn renames() {
+    let mut fixture = Fixture::new();
+    fixture.create("a").rename("a", "b");
+    fixture.expect(&[
+        ("a", EventKind::Create),
+        ("a", EventKind::CloseWrite),
+        ("a", EventKind::MovedFrom),
+        ("b", EventKind::MovedTo),
+    ]);
+}
+
+#[test]
+fn new_directories_are_watched() {
+    let mut fixture = Fixture::new();
+    fixture.mkdir("dir").mkdir("dir/sub").write("dir/sub/a", "");
+    fixture.expect(&[
+        ("dir", EventKind::Create),
+        ("dir/sub", EventKind::Create),
+        ("dir/sub/a", EventKind::Create),
+        ("dir/sub/a", EventKind::CloseWrite),
+    ]);
+    fixture.rename("dir/sub", "sub");
+    fixture.expect(&[
+        ("dir/sub", EventKind::MovedFrom),
+        ("sub", EventKind::MovedTo),
+    ]);
+}
+
+#[test]
+fn path_masks() {
+    let masks = PathMasks::new(Mask::CREATE | Mask::DELETE)
+        .rule("uploads", Mask::CLOSE_WRITE)
+        .rule("uploads/tmp", 0);
+    let mut fixture = Fixture::with_masks(masks);
+    fixture
+        .mkdir("uploads")
+        .mkdir("uploads/tmp")
+        .mkdir("uploads/a")
+        .write("uploads/a/f", "")
+        .write("uploads/tmp/f", "")
+        .write("f", "")
+        .delete("uploads/a/f");
+    fixture.expect(&[
+        ("uploads", EventKind::Create),
+        ("uploads/a/f", EventKind::CloseWrite),
+        ("f", EventKind::Create),
+    ]);
+    fixture.expect_quiet(Duration::from_millis(50));
+}
+
+#[test]
+fn max_depth_0() {
+    let mut fixture = Fixture::with_depth(Mask::CREATE | Mask::ATTRIB, Depth::any().max_depth(0));
+    fixture.create("a").mkdir("dir").chmod("", 0o700);
+    fixture.expect(&[("", EventKind::Attrib)]);
+    fixture.expect_quiet(Duration::from_millis(50));
+}
+
+#[test]
+fn max_depth_1() {
+    let mut fixture = Fixture::with_depth(Mask::CREATE, Depth::any().max_depth(1));
+    fixture.mkdir("dir").create("dir/a").create("a");
+    fixture.expect(&[("dir", EventKind::Create), ("a", EventKind::Create)]);
+    fixture.expect_quiet(Duration::from_millis(50));
+}
+
+#[test]
+fn no_max_depth() {
+    let mut fixture = Fixture::with_depth(Mask::CREATE, Depth::any());
+    fixture
+        .mkdir("a")
+        .mkdir("a/b")
+        .mkdir("a/b/c")
+        .create("a/b/c/d");
+    fixture.expect(&[
+        ("a", EventKind::Create),
+        ("a/b", EventKind::Create),
+        ("a/b/c", EventKind::Create),
+        ("a/b/c/d", EventKind::Create),
+    ]);
+}
+
+#[test]
+fn min_depth() {
+    let mut fixture = Fixture::with_depth(
+        Mask::CREATE | Mask::ATTRIB,
+        Depth::any().min_depth(2).max_depth(2),
+    );
+    fixture
+        .chmod("", 0o700)
+        .mkdir("dir")
+        .create("a")
+        .create("dir/a")
+        .mkdir("dir/sub")
+        .create("dir/sub/a");
+    fixture.expect(&[("dir/a", EventKind::Create), ("dir/sub", EventKind::Create)]);
+    fixture.expect_quiet(Duration::from_millis(50));
+}
+
+#[test]
+fn scan_new_directories() {
+    let mut fixture = Fixture::with_mask(Mask::CREATE).scan_new_directories();
+    // filled before the fixture reads the creation of `a` and watches it
+    std::fs::create_dir_all(fixture.join("a/b/c")).unwrap();
+    std::fs::write(fixture.join("a/b/c/f"), "").unwrap();
+    fixture.expect(&[
+        ("a", EventKind::Create),
+        ("a/b", EventKind::Create),
+        ("a/b/c", EventKind::Create),
+        ("a/b/c/f", EventKind::Create),
+    ]);
+    fixture.create("a/b/c/g");
+    fixture.expect(&[("a/b/c/g", EventKind::Create)]);
+    fixture.expect_quiet(Duration::from_millis(50));
+}
+
+#[test]
+fn restore_state() {
+    let mut fixture = Fixture::new();
+    fixture.mkdir("dir").write("dir/a", "a").create("b");
+    let state = Inotify::new()
+        .and_then(|inotify| {
+            inotify.watch_recursive(fixture.path().to_path_buf(), Mask::CREATE, Depth::any())
+        })
+        .unwrap()
+        .export_state();
+
+    // while nothing was watching
+    std::fs::write(fixture.join("dir/a"), "changed").unwrap();
+    std::fs::remove_file(fixture.join("b")).unwrap();
+    std::fs::create_dir(fixture.join("new")).unwrap();
+    std::fs::write(fixture.join("new/c"), "").unwrap();
+
+    let mut inotify = Inotify::new().unwrap();
+    let changes: Vec<_> = inotify
+        .restore_state(state)
+        .unwrap()
+        .into_iter()
+        .map(|event| (event.path, event.kind))
+        .collect();
+    assert_eq!(
+        changes,
+        [
+            (fixture.join("b"), EventKind::Delete),
+            (fixture.join("dir/a"), EventKind::Modify),
+            (fixture.join("new"), EventKind::Create),
+            (fixture.join("new/c"), EventKind::Create),
+        ]
+    );
+    assert!(inotify
+        .watches()
+        .any(|(_, path)| path == fixture.join("new")));
+}
+
+#[test]
+fn shutdown() {
+    let fixture = Fixture::new();
+    let token = ShutdownToken::new().unwrap();
+    let mut inotify = Inotify::new()
+        .unwrap()
+        .with_shutdown(token.clone())
+        .watch(fixture.path().to_path_buf(), Mask::CREATE)
+        .unwrap();
+    let cancel = token.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        cancel.cancel();
+    });
+    // nothing is created, the stream only ends because of the token
+    assert!(futures::executor::block_on(inotify.next_events()).is_none());
+    assert!(token.is_cancelled());
+}
+
+#[test]
+fn stats() {
+    let mut fixture = Fixture::new();
+    let mut inotify = Inotify::with_flags(Flag::NONBLOCKING)
+        .unwrap()
+        .watch(fixture.path().to_path_buf(), Mask::CREATE)
+        .unwrap();
+    for i in 0..200 {
+        fixture.create(format!("file-{i}"));
+    }
+    // a batch is a single buffer, the rest stays queued
+    inotify.try_read().unwrap().unwrap();
+    let stats = inotify.stats();
+    assert_eq!(stats.batches, 1);
+    assert!(stats.events > 0 && stats.events < 200);
+    assert!(stats.queued_bytes > 0);
+    assert_eq!(stats.events + stats.queued_events as u64, 200);
+
+    while inotify.try_read().unwrap().is_some() {}
+    let stats = inotify.stats();
+    assert_eq!(stats.events, 200);
+    assert_eq!(stats.queued_bytes, 0);
+    assert!(stats.max_lag > Duration::ZERO);
+}
+
+#[test]
+fn uploads() {
+    let event = |path: &str, kind, cookie| Event {
+        path: path.into(),
+        kind,
+        cookie,
+        is_dir: false,
+        process: None,
+        changes: Vec::new(),
+        inode: None,
+        renamed_from: None,
+    };
+    let mut uploads = Uploads::new(Duration::ZERO);
+    let mut reported = Vec::new();
+    for e in [
+        event("/in/a.part", EventKind::Create, 0),
+        event("/in/a.part", EventKind::Modify, 0),
+        event("/in/a.part", EventKind::CloseWrite, 0),
+        event("/in/a.part", EventKind::MovedFrom, 7),
+        event("/in/a", EventKind::MovedTo, 7),
+        // not created while watching
+        event("/in/old", EventKind::Modify, 0),
+        // given up on
+        event("/in/b.part", EventKind::Create, 0),
+        event("/in/b.part", EventKind::Delete, 0),
+        event("/in/c", EventKind::Create, 0),
+        event("/in/c", EventKind::CloseWrite, 0),
+    ] {
+        reported.extend(uploads.push(e));
+    }
+    reported.extend(uploads.expire());
+
+    let reported: Vec<_> = reported
+        .iter()
+        .map(|e| (e.path.to_str().unwrap(), e.kind, e.renamed_from.clone()))
+        .collect();
+    assert_eq!(
+        reported,
+        [
+            ("/in/a", EventKind::FileReady, Some("/in/a.part".into())),
+            ("/in/old", EventKind::Modify, None),
+            ("/in/c", EventKind::FileReady, None),
+        ]
+    );
+    assert_eq!(uploads.deadline(), None);
+}
+
+#[test]
+fn debounced_flushes_once_the_window_passed() {
+    let mut fixture = Fixture::new();
+    let window = Duration::from_millis(100);
+    let mut events = Inotify::new()
+        .unwrap()
+        .watch(fixture.path().to_path_buf(), Mask::CLOSE_WRITE)
+        .unwrap()
+        .resolved()
+        .debounced(window);
+    let written = Instant::now();
+    fixture.write("a", "hello");
+    // no event follows, only the window ends the batch
+    let batch = next_item(&mut events).unwrap().unwrap();
+    assert!(written.elapsed() >= window);
+    assert_eq!(batch.len(), 1);
+    assert_eq!(batch[0].path, fixture.join("a"));
+    assert_eq!(batch[0].kind, EventKind::CloseWrite);
+}
+
+#[test]
+fn dedup_yields_the_repeats_once_the_window_passed() {
+    let mut fixture = Fixture::new();
+    let window = Duration::from_millis(100);
+    let mut events = Inotify::new()
+        .unwrap()
+        .watch(fixture.path().to_path_buf(), Mask::CLOSE_WRITE)
+        .unwrap()
+        .resolved()
+        .dedup(window);
+    // the kernel merges an event with the same one right before it
+    fixture
+        .write("a", "1")
+        .write("b", "1")
+        .write("a", "2")
+        .write("b", "2")
+        .write("a", "3");
+    let written = Instant::now();
+    let mut deduped = Vec::new();
+    while deduped.len() < 4 {
+        let event = next_item(&mut events).unwrap().unwrap();
+        let name = event.event.path.file_name().unwrap().to_owned();
+        deduped.push((name.into_string().unwrap(), event.count));
+    }
+    // no event follows the repeats, only the window ends them
+    assert!(written.elapsed() >= window);
+    deduped[2..].sort();
+    assert_eq!(
+        deduped,
+        [
+            ("a".to_string(), 1),
+            ("b".to_string(), 1),
+            ("a".to_string(), 2),
+            ("b".to_string(), 1),
+        ]
+    );
+}
+
+#[test]
+fn paired_renames_passes_moves_out_on() {
+    let mut fixture = Fixture::new();
+    let outside = Fixture::new();
+    let mut changes = Inotify::new()
+        .unwrap()
+        .watch(fixture.path().to_path_buf(), Mask::MOVE)
+        .unwrap()
+        .resolved()
+        .paired_renames();
+    fixture.create("a").rename("a", "b");
+    match next_item(&mut changes).unwrap().unwrap() {
+        Change::Renamed { from, to, .. } => {
+            assert_eq!(from, fixture.join("a"));
+            assert_eq!(to, fixture.join("b"));
+        }
+        change => panic!("expected a rename, got {:?}", change),
+    }
+    // the `MOVED_TO` lands outside, the stream has nothing ready after it
+    std::fs::rename(fixture.join("b"), outside.join("b")).unwrap();
+    match next_item(&mut changes).unwrap().unwrap() {
+        Change::Event(event) => {
+            assert_eq!(event.path, fixture.join("b"));
+            assert_eq!(event.kind, EventKind::MovedFrom);
+        }
+        change => panic!("expected a move out, got {:?}", change),
+    }
+}
+
+#[test]
+fn directory_created_again_is_watched() {
+    let mut fixture = Fixture::new();
+    fixture.mkdir("sub");
+    fixture.expect(&[("sub", EventKind::Create)]);
+    fixture.delete("sub");
+    fixture.expect(&[("sub", EventKind::Ignored), ("sub", EventKind::Delete)]);
+    // the new directory may get the inode of the removed one
+    fixture.mkdir("sub");
+    fixture.expect(&[("sub", EventKind::Create)]);
+    fixture.write("sub/a", "");
+    fixture.expect(&[
+        ("sub/a", EventKind::Create),
+        ("sub/a", EventKind::CloseWrite),
+    ]);
+}
+
+#[test]
+fn same_directory_through_another_path() {
+    let mut fixture = Fixture::new();
+    fixture.mkdir("dir").mkdir("other");
+    let dir = fixture.join("dir");
+    std::os::unix::fs::symlink(&dir, fixture.join("link")).unwrap();
+    let mut inotify = Inotify::new().unwrap();
+    let wd = match inotify.try_add_watch(dir.clone(), Mask::CREATE).unwrap() {
+        Added::Watch(wd) => wd,
+        added => panic!("expected a new watch, got {:?}", added),
+    };
+    // `..` is resolved, the path is the one already watched
+    let added = inotify
+        .try_add_watch(fixture.join("other/../dir"), Mask::CREATE)
+        .unwrap();
+    assert_eq!(
+        added,
+        Added::AlreadyWatched {
+            wd,
+            path: dir.clone(),
+            existing_mask: Mask::CREATE,
+        }
+    );
+    // the link leads to the same watch, which keeps its path
+    let added = inotify
+        .try_add_watch(fixture.join("link"), Mask::DELETE)
+        .unwrap();
+    assert_eq!(
+        added,
+        Added::AlreadyWatched {
+            wd,
+            path: dir.clone(),
+            existing_mask: Mask::CREATE,
+        }
+    );
+    assert_eq!(inotify.watches().count(), 1);
+}
+
+/// the kinds of the events the watch of `dir` reports for a file created and removed in it
+fn created_and_deleted(fixture: &mut Fixture, inotify: &mut Inotify) -> Vec<EventKind> {
+    fixture.create("dir/a").delete("dir/a");
+    let mut kinds = Vec::new();
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while !kinds.contains(&EventKind::Delete) && Instant::now() < deadline {
+        let Some(batch) = inotify.try_read().unwrap() else {
+            std::thread::sleep(Duration::from_millis(1));
+            continue;
+        };
+        for event in batch {
+            kinds.extend(inotify.resolve(&event).map(|event| event.kind));
+        }
+    }
+    kinds
+}
+
+#[test]
+fn another_path_replaces_the_mask() {
+    let mut fixture = Fixture::new();
+    fixture.mkdir("dir");
+    std::os::unix::fs::symlink(fixture.join("dir"), fixture.join("link")).unwrap();
+    let mut inotify = Inotify::with_flags(Flag::NONBLOCKING).unwrap();
+    inotify
+        .add_watch(fixture.join("dir"), Mask::CREATE)
+        .unwrap();
+    inotify
+        .add_watch(fixture.join("link"), Mask::DELETE)
+        .unwrap();
+    assert_eq!(
+        created_and_deleted(&mut fixture, &mut inotify),
+        [EventKind::Delete]
+    );
+}
+
+#[test]
+fn another_path_merges_the_mask() {
+    let mut fixture = Fixture::new();
+    fixture.mkdir("dir");
+    std::os::unix::fs::symlink(fixture.join("dir"), fixture.join("link")).unwrap();
+    let mut inotify = Inotify::with_flags(Flag::NONBLOCKING).unwrap();
+    inotify
+        .add_watch(fixture.join("dir"), Mask::CREATE)
+        .unwrap();
+    let added = inotify
+        .try_add_watch(fixture.join("link"), Mask::DELETE | Mask::MASK_ADD)
+        .unwrap();
+    assert!(matches!(added, Added::AlreadyWatched { .. }));
+    assert_eq!(
+        created_and_deleted(&mut fixture, &mut inotify),
+        [EventKind::Create, EventKind::Delete]
+    );
+}
+
+#[test]
+fn sharded_events_of_every_shard() {
+    const SHARDS: usize = 4;
+    let mut fixture = Fixture::new();
+    let mut sharded = ShardedInotify::new(SHARDS).unwrap();
+    // a directory on every shard
+    let mut dirs: HashMap<usize, PathBuf> = HashMap::new();
+    for i in 0..100 {
+        if dirs.len() == SHARDS {
+            break;
+        }
+        let dir = format!("dir-{i}");
+        fixture.mkdir(&dir);
+        let dir = fixture.join(dir);
+        sharded.add_watch(dir.clone(), Mask::CLOSE_WRITE).unwrap();
+        dirs.entry(sharded.shard_of(&dir).unwrap()).or_insert(dir);
+    }
+    assert_eq!(dirs.len(), SHARDS);
+
+    // nothing happened yet, the poll returns right away
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    assert!(sharded.poll_events(&mut cx).is_pending());
+
+    for dir in dirs.values() {
+        std::fs::write(dir.join("a"), "").unwrap();
+    }
+    let mut written: Vec<PathBuf> = Vec::new();
+    while written.len() < SHARDS {
+        let timeout = Delay::new(Duration::from_secs(1));
+        let events =
+            match futures::executor::block_on(future::select(sharded.next_events(), timeout)) {
+                Either::Left((events, _)) => events.unwrap().unwrap(),
+                Either::Right(_) => panic!("only {:?} were reported", written),
+            };
+        written.extend(events.into_iter().map(|event| event.path));
+    }
+    written.sort();
+    let mut expected: Vec<PathBuf> = dirs.values().map(|dir| dir.join("a")).collect();
+    expected.sort();
+    assert_eq!(written, expected);
+}
+
+#[test]
+fn dispatched_by_path_and_priority() {
+    let mut fixture = Fixture::new();
+    fixture.mkdir("config").mkdir("data").mkdir("data/sub");
+    let inotify = Inotify::new()
+        .unwrap()
+        .watch(fixture.join("config"), Mask::CLOSE_WRITE)
+        .unwrap()
+        .watch_recursive(fixture.join("data"), Mask::CREATE, Depth::any())
+        .unwrap();
+    let mut events = Dispatcher::new()
+        .channel("config", 10, 16)
+        .channel("bulk", 0, 4)
+        .route(fixture.join("config"), "config")
+        .route(fixture.join("data"), "bulk")
+        .spawn(inotify);
+
+    // the flood comes first, the configuration is still received before it
+    for i in 0..8 {
+        fixture.create(format!("data/sub/{i}"));
+    }
+    fixture.write("config/app.toml", "");
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while events.queued("config") == 0 {
+        assert!(Instant::now() < deadline, "the configuration wasn't routed");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(events.queued("bulk"), 4);
+
+    let mut received = Vec::new();
+    while received.len() < 6 {
+        let routed = next_item(&mut events).unwrap().unwrap();
+        // overflows have no path
+        let path = routed
+            .event
+            .path
+            .strip_prefix(fixture.path())
+            .unwrap_or(&routed.event.path);
+        received.push((
+            routed.channel.to_string(),
+            path.to_path_buf(),
+            routed.event.kind,
+        ));
+    }
+    let routed = |channel: &str, path: &str, kind| (channel.to_string(), PathBuf::from(path), kind);
+    assert_eq!(
+        received,
+        [
+            // only the events of the mask of the watch
+            routed("config", "config/app.toml", EventKind::CloseWrite),
+            routed("bulk", "data/sub/0", EventKind::Create),
+            routed("bulk", "data/sub/1", EventKind::Create),
+            routed("bulk", "data/sub/2", EventKind::Create),
+            routed("bulk", "data/sub/3", EventKind::Create),
+            // the queue of the channel was full
+            routed("bulk", "", EventKind::Overflow),
+        ]
+    );
+}
@@ -0,0 +1,114 @@
+use proptest::prelude::*;
+use std::os::unix::ffi::OsStrExt;
+use tube_inotify::{InotifyEvent, InotifyEventBatch, InotifyEventRef};
+
+/// an event as the test builds it, and expects it back
+#[derive(Debug, Clone, PartialEq)]
+struct Raw {
+    wd: i32,
+    mask: u32,
+    cookie: u32,
+    name: Vec<u8>,
+}
+
+impl Raw {
+    /// the event in the format the kernel writes it, the name is
+    /// padded with zeros to a multiple of the header size
+    fn bytes(&self) -> Vec<u8> {
+        let len = match self.name.len() {
+            0 => 0,
+            n => (n + 1).div_ceil(16) * 16,
+        };
+        let mut bytes = Vec::with_capacity(16 + len);
+        bytes.extend_from_slice(&self.wd.to_ne_bytes());
+        bytes.extend_from_slice(&self.mask.to_ne_bytes());
+        bytes.extend_from_slice(&self.cookie.to_ne_bytes());
+        bytes.extend_from_slice(&(len as u32).to_ne_bytes());
+        bytes.extend_from_slice(&self.name);
+        bytes.resize(16 + len, 0);
+        bytes
+    }
+
+    fn of(event: &InotifyEvent) -> Self {
+        Self {
+            wd: event.wd(),
+            mask: event.mask(),
+            cookie: event.cookie(),
+            name: event
+                .name()
+                .map(|name| name.as_bytes().to_vec())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+fn raw() -> impl Strategy<Value = Raw> {
+    // file names can't be empty (no name) or hold a zero byte, and are at most NAME_MAX
+    let name = prop_oneof![Just(Vec::new()), prop::collection::vec(1u8.., 1..=255),];
+    (any::<i32>(), any::<u32>(), any::<u32>(), name).prop_map(|(wd, mask, cookie, name)| Raw {
+        wd,
+        mask,
+        cookie,
+        name,
+    })
+}
+
+fn events(raws: &[Raw]) -> Vec<u8> {
+    raws.iter().flat_map(Raw::bytes).collect()
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_bytes_dont_panic(bytes in prop::collection::vec(any::<u8>(), 0..1024)) {
+        if let Some((size, _)) = InotifyEvent::from_buffer(&bytes) {
+            prop_assert!(size >= 16 && size <= bytes.len());
+        }
+        let _ = InotifyEventBatch::<4096>::from_bytes(bytes).count();
+    }
+
+    #[test]
+    fn events_round_trip(raws in prop::collection::vec(raw(), 0..64)) {
+        let parsed: Vec<Raw> = InotifyEventBatch::<4096>::from_bytes(events(&raws))
+            .map(|event| Raw::of(&event))
+            .collect();
+        prop_assert_eq!(parsed, raws);
+    }
+
+    #[test]
+    fn refs_match_owned(raws in prop::collection::vec(raw(), 0..64)) {
+        let batch = InotifyEventBatch::<4096>::from_bytes(events(&raws));
+        let refs: Vec<InotifyEvent> = batch.refs().map(|event| event.to_owned()).collect();
+        prop_assert_eq!(refs, batch.collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn single_event_size(raw in raw()) {
+        let bytes = raw.bytes();
+        let (size, event) = InotifyEvent::from_buffer(&bytes).unwrap();
+        prop_assert_eq!(size, bytes.len());
+        prop_assert_eq!(Raw::of(&event), raw);
+        let (size, event_ref) = InotifyEventRef::from_buffer(&bytes).unwrap();
+        prop_assert_eq!(size, bytes.len());
+        prop_assert_eq!(event_ref.to_owned(), event);
+    }
+
+    /// a buffer cut anywhere yields the events that are whole before the cut
+    #[test]
+    fn truncated_events(raws in prop::collection::vec(raw(), 1..32), cut in any::<prop::sample::Index>()) {
+        let bytes = events(&raws);
+        let cut = cut.index(bytes.len());
+        let mut whole = 0;
+        let mut end = 0;
+        for raw in &raws {
+            end += raw.bytes().len();
+            if end > cut {
+                break;
+            }
+            whole += 1;
+        }
+        let parsed: Vec<Raw> = InotifyEventBatch::<4096>::from_bytes(bytes[..cut].to_vec())
+            .map(|event| Raw::of(&event))
+            .collect();
+        prop_assert_eq!(parsed, raws[..whole].to_vec());
+    }
+}
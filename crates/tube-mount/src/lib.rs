@@ -0,0 +1,3 @@
+mod mount;
+
+pub use mount::*;
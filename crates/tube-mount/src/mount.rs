@@ -0,0 +1,222 @@
+use futures::stream::Stream;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tube_core::{AsyncFd, FdSource, POLLERR, POLLPRI};
+
+const MOUNTINFO: &str = "/proc/self/mountinfo";
+
+/// an entry of the mount table, a line of `/proc/self/mountinfo`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mount {
+    pub id: u32,
+    pub parent: u32,
+    /// the directory of the filesystem mounted, `/` unless it's a bind mount
+    pub root: PathBuf,
+    pub mount_point: PathBuf,
+    pub options: String,
+    pub fstype: String,
+    /// e.g. `/dev/sdb1`, `tmpfs`
+    pub source: String,
+}
+
+impl Mount {
+    /// parses a line of `/proc/self/mountinfo`, see proc(5)
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split_ascii_whitespace();
+        let id = fields.next()?.parse().ok()?;
+        let parent = fields.next()?.parse().ok()?;
+        let _device = fields.next()?;
+        let root = unescape(fields.next()?);
+        let mount_point = unescape(fields.next()?);
+        let options = fields.next()?.to_string();
+        // the optional fields end with a lone `-`
+        let mut fields = fields.skip_while(|&field| field != "-").skip(1);
+        Some(Self {
+            id,
+            parent,
+            root,
+            mount_point,
+            options,
+            fstype: fields.next()?.to_string(),
+            source: unescape(fields.next()?).to_string_lossy().into_owned(),
+        })
+    }
+}
+
+/// spaces, tabs, newlines and backslashes are escaped as octal, e.g. `\040`
+fn unescape(field: &str) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes
+            .get(i + 1..i + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match (bytes[i], octal) {
+            (b'\\', Some(byte)) => {
+                out.push(byte);
+                i += 4;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(std::ffi::OsString::from_vec(out))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MountEvent {
+    Mounted(Mount),
+    Unmounted(Mount),
+    /// mounted again with other options, e.g. `mount -o remount,ro`
+    Remounted(Mount),
+}
+
+impl MountEvent {
+    pub fn mount(&self) -> &Mount {
+        match self {
+            Self::Mounted(mount) | Self::Unmounted(mount) | Self::Remounted(mount) => mount,
+        }
+    }
+}
+
+/// yields the changes of the mount table of the process' mount namespace.
+/// inotify doesn't report mounts, a filesystem mounted on a watched directory
+/// hides it without any event and its files are not watched, the directory
+/// has to be watched again once the event arrives.
+///
+/// ```no_run
+/// # use tube_mount::MountMonitor;
+/// # fn main() -> std::io::Result<()> {
+/// // mounts under /media
+/// let mounts = MountMonitor::new()?.under("/media");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MountMonitor {
+    file: File,
+    io: AsyncFd,
+    mounts: BTreeMap<u32, Mount>,
+    under: Option<PathBuf>,
+    pending: VecDeque<MountEvent>,
+}
+
+impl MountMonitor {
+    pub fn new() -> io::Result<Self> {
+        let file = File::open(MOUNTINFO)?;
+        let mut monitor = Self {
+            // the mount table is always readable, changes are reported as exceptional
+            io: AsyncFd::new(file.as_raw_fd(), POLLPRI | POLLERR),
+            file,
+            mounts: BTreeMap::new(),
+            under: None,
+            pending: VecDeque::new(),
+        };
+        monitor.mounts = monitor.read()?;
+        Ok(monitor)
+    }
+
+    /// keeps only the events of mount points under the path
+    pub fn under(mut self, path: impl Into<PathBuf>) -> Self {
+        self.under = Some(path.into());
+        self
+    }
+
+    /// the current mount table, by mount id
+    pub fn mounts(&self) -> impl Iterator<Item = &Mount> {
+        self.mounts.values()
+    }
+
+    /// the mount the path is on, the one with the longest mount point it starts with
+    pub fn mount_of(&self, path: &Path) -> Option<&Mount> {
+        self.mounts
+            .values()
+            .filter(|mount| path.starts_with(&mount.mount_point))
+            .max_by_key(|mount| mount.mount_point.as_os_str().len())
+    }
+
+    fn read(&mut self) -> io::Result<BTreeMap<u32, Mount>> {
+        let mut table = String::new();
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_to_string(&mut table)?;
+        Ok(table
+            .lines()
+            .filter_map(Mount::parse)
+            .map(|mount| (mount.id, mount))
+            .collect())
+    }
+
+    fn is_watched(&self, mount: &Mount) -> bool {
+        self.under
+            .as_deref()
+            .is_none_or(|under| mount.mount_point.starts_with(under))
+    }
+}
+
+impl AsRawFd for MountMonitor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl FdSource for MountMonitor {
+    type Item = MountEvent;
+
+    // polling the mount table marks its changes as seen
+    const POLL_CLEARS: bool = true;
+
+    fn read_ready(&mut self) -> io::Result<Option<MountEvent>> {
+        let mounts = self.read()?;
+        let mut events = Vec::new();
+        for (id, mount) in &self.mounts {
+            match mounts.get(id) {
+                None => events.push(MountEvent::Unmounted(mount.clone())),
+                Some(new) if new.options != mount.options => {
+                    events.push(MountEvent::Remounted(new.clone()));
+                }
+                Some(_) => {}
+            }
+        }
+        events.extend(
+            mounts
+                .iter()
+                .filter(|(id, _)| !self.mounts.contains_key(id))
+                .map(|(_, mount)| MountEvent::Mounted(mount.clone())),
+        );
+        self.mounts = mounts;
+
+        for event in events {
+            if self.is_watched(event.mount()) {
+                self.pending.push_back(event);
+            }
+        }
+        Ok(self.pending.pop_front())
+    }
+
+    fn pending(&mut self) -> Option<MountEvent> {
+        self.pending.pop_front()
+    }
+
+    fn io(&mut self) -> &mut AsyncFd {
+        &mut self.io
+    }
+}
+
+impl Stream for MountMonitor {
+    type Item = io::Result<MountEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        tube_core::poll_source(self.get_mut(), cx)
+    }
+}
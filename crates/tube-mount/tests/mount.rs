@@ -0,0 +1,18 @@
+use futures::task::noop_waker;
+use futures::Stream;
+use std::pin::Pin;
+use std::task::Context;
+use std::time::{Duration, Instant};
+use tube_mount::MountMonitor;
+
+#[test]
+fn mounts_wait_without_blocking() {
+    // nothing is mounted there, every change is filtered out
+    let mut mounts = MountMonitor::new().unwrap().under("/tube-none");
+    assert!(mounts.mounts().count() > 0);
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    let polled = Instant::now();
+    assert!(Pin::new(&mut mounts).poll_next(&mut cx).is_pending());
+    assert!(polled.elapsed() < Duration::from_millis(100));
+}
@@ -0,0 +1,50 @@
+import { EventEmitter } from 'node:events'
+
+export interface Event {
+  path: string
+  /** e.g. `CLOSE_WRITE`, `MOVED_TO` */
+  kind: string
+  mask: number
+  /** the same for both events of a rename */
+  cookie: number
+  isDir: boolean
+}
+
+export declare const Mask: Readonly<{
+  ACCESS: number
+  MODIFY: number
+  ATTRIB: number
+  CLOSE_WRITE: number
+  CLOSE_NOWRITE: number
+  CLOSE: number
+  OPEN: number
+  MOVED_FROM: number
+  MOVED_TO: number
+  MOVE: number
+  CREATE: number
+  DELETE: number
+  DELETE_SELF: number
+  MOVE_SELF: number
+  ALL_EVENTS: number
+}>
+
+export interface WatchOptions {
+  mask?: number
+  recursive?: boolean
+}
+
+export declare class Watcher extends EventEmitter implements AsyncIterable<Event> {
+  constructor(options?: { hidden?: boolean })
+  watch(path: string, options?: WatchOptions): number
+  unwatch(wd: number): void
+  close(): void
+  on(event: 'event' | string, listener: (event: Event) => void): this
+  on(event: 'error', listener: (err: Error) => void): this
+  on(event: 'close', listener: () => void): this
+  [Symbol.asyncIterator](): AsyncIterator<Event>
+}
+
+export declare function watch(
+  paths: string | string[],
+  options?: WatchOptions & { hidden?: boolean },
+): Watcher
@@ -0,0 +1,97 @@
+'use strict'
+
+const { EventEmitter } = require('node:events')
+const { NativeWatcher } = require('./tube.node')
+
+/** the inotify events, combined with `|` into the mask given to `watch` */
+const Mask = Object.freeze({
+  ACCESS: 0x001,
+  MODIFY: 0x002,
+  ATTRIB: 0x004,
+  CLOSE_WRITE: 0x008,
+  CLOSE_NOWRITE: 0x010,
+  CLOSE: 0x018,
+  OPEN: 0x020,
+  MOVED_FROM: 0x040,
+  MOVED_TO: 0x080,
+  MOVE: 0x0c0,
+  CREATE: 0x100,
+  DELETE: 0x200,
+  DELETE_SELF: 0x400,
+  MOVE_SELF: 0x800,
+  ALL_EVENTS: 0xfff,
+})
+
+/**
+ * emits every event as `event` and as its kind (`create`, `close_write`...),
+ * renames keep the cookie the kernel gave both halves, errors are emitted as `error`
+ */
+class Watcher extends EventEmitter {
+  constructor(options = {}) {
+    super()
+    this.native = new NativeWatcher(options.hidden)
+    this.closed = false
+    this.native.start((err, event) => {
+      if (err) {
+        this.emit('error', err)
+        return
+      }
+      this.emit('event', event)
+      this.emit(event.kind.toLowerCase(), event)
+    })
+  }
+
+  /** returns the watch descriptor, for `unwatch` */
+  watch(path, { mask = Mask.ALL_EVENTS, recursive = false } = {}) {
+    return this.native.watch(path, mask, recursive)
+  }
+
+  unwatch(wd) {
+    this.native.unwatch(wd)
+  }
+
+  close() {
+    this.closed = true
+    this.native.close()
+    this.emit('close')
+  }
+
+  /** yields the events until the watcher is closed */
+  async *[Symbol.asyncIterator]() {
+    const queue = []
+    let wake = null
+    const onEvent = (event) => {
+      queue.push(event)
+      if (wake) wake()
+    }
+    const onClose = () => {
+      if (wake) wake()
+    }
+    this.on('event', onEvent)
+    this.once('close', onClose)
+    try {
+      while (!this.closed || queue.length > 0) {
+        if (queue.length === 0) {
+          await new Promise((resolve) => (wake = resolve))
+          wake = null
+          continue
+        }
+        yield queue.shift()
+      }
+    } finally {
+      this.off('event', onEvent)
+      this.off('close', onClose)
+    }
+  }
+}
+
+/** creates a watcher watching the paths */
+function watch(paths, options = {}) {
+  const watcher = new Watcher(options)
+  for (const path of [].concat(paths)) {
+    watcher.watch(path, options)
+  }
+  return watcher
+}
+
+module.exports = { Mask, Watcher, watch }
@@ -0,0 +1,139 @@
+//! the native part of the `@tube/watch` node package, `index.js` wraps
+//! it in an `EventEmitter` and an async iterator
+use napi::threadsafe_function::{ErrorStrategy, ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi::{Error, JsFunction, Result};
+use napi_derive::napi;
+use std::os::fd::AsRawFd;
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tube_inotify::{Depth, Flag, Inotify, Mask, WatchError};
+
+/// how long the reading thread waits for events before checking if the watcher was closed
+const POLL_TIMEOUT_MS: c_int = 100;
+
+#[repr(C)]
+struct pollfd {
+    fd: c_int,
+    events: i16,
+    revents: i16,
+}
+
+extern "C" {
+    fn poll(fds: *mut pollfd, nfds: u64, timeout: c_int) -> c_int;
+}
+
+fn error(e: WatchError) -> Error {
+    Error::from_reason(e.to_string())
+}
+
+#[napi(object)]
+pub struct Event {
+    pub path: String,
+    /// e.g. `CLOSE_WRITE`, `MOVED_TO`
+    pub kind: String,
+    pub mask: u32,
+    /// the same for both events of a rename
+    pub cookie: u32,
+    pub is_dir: bool,
+}
+
+impl From<tube_inotify::Event> for Event {
+    fn from(event: tube_inotify::Event) -> Self {
+        Self {
+            path: event.path.to_string_lossy().into_owned(),
+            kind: event.kind.to_string(),
+            mask: event.kind.mask(),
+            cookie: event.cookie,
+            is_dir: event.is_dir,
+        }
+    }
+}
+
+#[napi]
+pub struct NativeWatcher {
+    inotify: Arc<Mutex<Inotify>>,
+    stopped: Arc<AtomicBool>,
+}
+
+#[napi]
+impl NativeWatcher {
+    #[napi(constructor)]
+    pub fn new(hidden: Option<bool>) -> Result<Self> {
+        let inotify = Inotify::with_flags(Flag::NONBLOCKING).map_err(error)?;
+        Ok(Self {
+            inotify: Arc::new(Mutex::new(inotify.include_hidden(hidden.unwrap_or(false)))),
+            stopped: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// watches the path, directories under it too with `recursive`, including
+    /// the ones created later, returns the watch descriptor of the path
+    #[napi]
+    pub fn watch(&self, path: String, mask: Option<u32>, recursive: Option<bool>) -> Result<i32> {
+        let mask = mask.unwrap_or(Mask::ALL_EVENTS);
+        let path = std::path::PathBuf::from(path);
+        let mut inotify = self.inotify.lock().unwrap();
+        if recursive.unwrap_or(false) && path.is_dir() {
+            inotify
+                .add_recursive(path.clone(), mask, Depth::any())
+                .map_err(error)?;
+            let wd = inotify.watches().find(|(_, watched)| *watched == path);
+            return Ok(wd.map_or(-1, |(wd, _)| wd));
+        }
+        inotify.add_watch(path, mask).map_err(error)
+    }
+
+    #[napi]
+    pub fn unwatch(&self, wd: i32) -> Result<()> {
+        self.inotify.lock().unwrap().unwatch(wd).map_err(error)
+    }
+
+    /// calls the callback with every event from a reading thread, until `close`
+    #[napi(ts_args_type = "callback: (err: null | Error, event: Event) => void")]
+    pub fn start(&self, callback: JsFunction) -> Result<()> {
+        let callback: ThreadsafeFunction<Event, ErrorStrategy::CalleeHandled> =
+            callback.create_threadsafe_function(0, |ctx| Ok(vec![ctx.value]))?;
+        let inotify = self.inotify.clone();
+        let stopped = self.stopped.clone();
+        let fd = inotify.lock().unwrap().as_raw_fd();
+
+        std::thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                // waits without the lock, so watches can be added meanwhile
+                let mut fds = [pollfd {
+                    fd,
+                    events: 0x001,
+                    revents: 0,
+                }];
+                if unsafe { poll(fds.as_mut_ptr(), 1, POLL_TIMEOUT_MS) } <= 0 {
+                    continue;
+                }
+
+                let mut inotify = inotify.lock().unwrap();
+                let events: Vec<Event> = match inotify.try_read() {
+                    Ok(Some(batch)) => batch
+                        .filter_map(|event| inotify.resolve(&event))
+                        .map(Event::from)
+                        .collect(),
+                    Ok(None) => continue,
+                    Err(e) => {
+                        callback.call(Err(error(e)), ThreadsafeFunctionCallMode::NonBlocking);
+                        continue;
+                    }
+                };
+                drop(inotify);
+                for event in events {
+                    callback.call(Ok(event), ThreadsafeFunctionCallMode::NonBlocking);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// stops the reading thread, which lets the process exit
+    #[napi]
+    pub fn close(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,31 @@
+#![allow(non_camel_case_types, dead_code)]
+
+use std::os::raw::{c_int, c_long};
+
+pub const SYS_PIDFD_OPEN: c_long = 434;
+
+pub const P_PIDFD: c_int = 3;
+pub const WEXITED: c_int = 4;
+pub const WNOHANG: c_int = 1;
+
+pub const CLD_EXITED: c_int = 1;
+pub const CLD_KILLED: c_int = 2;
+pub const CLD_DUMPED: c_int = 3;
+
+/// the `SIGCHLD` layout of `siginfo_t`
+#[repr(C)]
+pub struct siginfo_t {
+    pub si_signo: c_int,
+    pub si_errno: c_int,
+    pub si_code: c_int,
+    _pad0: c_int,
+    pub si_pid: c_int,
+    pub si_uid: u32,
+    pub si_status: c_int,
+    _pad: [c_int; 25],
+}
+
+extern "C" {
+    pub fn syscall(num: c_long, ...) -> c_long;
+    pub fn waitid(idtype: c_int, id: u32, infop: *mut siginfo_t, options: c_int) -> c_int;
+}
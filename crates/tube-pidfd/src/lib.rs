@@ -0,0 +1,4 @@
+mod ffi;
+mod pidfd;
+
+pub use pidfd::*;
@@ -0,0 +1,118 @@
+use futures::stream::Stream;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::ExitStatusExt;
+use std::pin::Pin;
+use std::process::ExitStatus;
+use std::task::{Context, Poll};
+use tube_core::{AsyncFd, FdSource, POLLIN};
+
+use crate::ffi;
+
+/// yielded once the process exited
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exit {
+    pub pid: u32,
+    pub status: ExitStatus,
+}
+
+/// a `pidfd` of a child process, as a stream yielding its `Exit` once and
+/// ending. the child is reaped when its exit is read, so it shouldn't be waited
+/// for in any other way. children are supervised together by merging their streams
+///
+/// ```no_run
+/// # use tube_core::MergedEvents;
+/// # use tube_pidfd::PidFd;
+/// # fn main() -> std::io::Result<()> {
+/// let child = std::process::Command::new("sleep").arg("10").spawn()?;
+/// let mut events = MergedEvents::new();
+/// events.push("server", PidFd::open(child.id())?);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct PidFd {
+    fd: OwnedFd,
+    io: AsyncFd,
+    pid: u32,
+    exited: bool,
+}
+
+impl PidFd {
+    /// requires linux 5.3 or later
+    pub fn open(pid: u32) -> io::Result<Self> {
+        let fd = unsafe { ffi::syscall(ffi::SYS_PIDFD_OPEN, pid as i32, 0) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd as RawFd) },
+            io: AsyncFd::new(fd as RawFd, POLLIN),
+            pid,
+            exited: false,
+        })
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+}
+
+impl AsRawFd for PidFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl FdSource for PidFd {
+    type Item = Exit;
+
+    fn read_ready(&mut self) -> io::Result<Option<Exit>> {
+        let mut info = MaybeUninit::<ffi::siginfo_t>::zeroed();
+        let result = unsafe {
+            ffi::waitid(
+                ffi::P_PIDFD,
+                self.fd.as_raw_fd() as u32,
+                info.as_mut_ptr(),
+                ffi::WEXITED | ffi::WNOHANG,
+            )
+        };
+        if result == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let info = unsafe { info.assume_init() };
+        // still running, `WNOHANG` leaves the pid zeroed
+        if info.si_pid == 0 {
+            return Ok(None);
+        }
+        self.exited = true;
+        // back to the raw wait status `ExitStatus` is made of
+        let raw = match info.si_code {
+            ffi::CLD_EXITED => (info.si_status & 0xff) << 8,
+            ffi::CLD_KILLED => info.si_status,
+            ffi::CLD_DUMPED => info.si_status | 0x80,
+            _ => info.si_status,
+        };
+        Ok(Some(Exit {
+            pid: self.pid,
+            status: ExitStatus::from_raw(raw),
+        }))
+    }
+
+    fn is_done(&self) -> bool {
+        self.exited
+    }
+
+    fn io(&mut self) -> &mut AsyncFd {
+        &mut self.io
+    }
+}
+
+impl Stream for PidFd {
+    type Item = io::Result<Exit>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        tube_core::poll_source(self.get_mut(), cx)
+    }
+}
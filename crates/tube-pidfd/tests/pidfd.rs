@@ -0,0 +1,24 @@
+use futures::StreamExt;
+use std::process::Command;
+use tube_core::MergedEvents;
+use tube_pidfd::PidFd;
+
+#[test]
+// the children are reaped by their `PidFd`
+#[allow(clippy::zombie_processes)]
+fn children_exit_in_order() {
+    let slow = Command::new("sleep").arg("0.3").spawn().unwrap();
+    let fast = Command::new("sh").args(["-c", "exit 3"]).spawn().unwrap();
+    // the slow child is polled first, it must not hold back the fast one
+    let mut events = MergedEvents::new()
+        .with("slow", PidFd::open(slow.id()).unwrap())
+        .with("fast", PidFd::open(fast.id()).unwrap());
+    let exits: Vec<_> = futures::executor::block_on(async {
+        let mut exits = Vec::new();
+        while let Some((tag, exit)) = events.next().await {
+            exits.push((tag, exit.unwrap().status.code()));
+        }
+        exits
+    });
+    assert_eq!(exits, [("fast", Some(3)), ("slow", Some(0))]);
+}
@@ -0,0 +1,293 @@
+//! the `tube` python package, built with maturin
+use pyo3::exceptions::{PyOSError, PyStopIteration};
+use pyo3::prelude::*;
+use std::collections::VecDeque;
+use std::os::fd::AsRawFd;
+use std::os::raw::c_int;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tube_inotify::{Depth, Event as TubeEvent, EventKind as TubeEventKind, Flag, WatchError};
+
+/// how long a blocked iteration waits before checking for `KeyboardInterrupt`
+const POLL_TIMEOUT_MS: c_int = 100;
+
+#[repr(C)]
+struct pollfd {
+    fd: c_int,
+    events: i16,
+    revents: i16,
+}
+
+extern "C" {
+    fn poll(fds: *mut pollfd, nfds: u64, timeout: c_int) -> c_int;
+}
+
+/// `OSError.filename` is the path that failed, when there is one
+fn os_error(e: WatchError) -> PyErr {
+    let message = e.to_string();
+    match e.path {
+        Some(path) => PyOSError::new_err((e.errno.raw(), message, path)),
+        None => PyOSError::new_err((e.errno.raw(), message)),
+    }
+}
+
+/// the inotify events, combined with `|` into the mask given to `Inotify.watch`
+#[pyclass(frozen)]
+struct Mask;
+
+#[pymethods]
+impl Mask {
+    #[classattr]
+    const ACCESS: u32 = tube_inotify::Mask::ACCESS;
+    #[classattr]
+    const MODIFY: u32 = tube_inotify::Mask::MODIFY;
+    #[classattr]
+    const ATTRIB: u32 = tube_inotify::Mask::ATTRIB;
+    #[classattr]
+    const CREATE: u32 = tube_inotify::Mask::CREATE;
+    #[classattr]
+    const DELETE: u32 = tube_inotify::Mask::DELETE;
+    #[classattr]
+    const DELETE_SELF: u32 = tube_inotify::Mask::DELETE_SELF;
+    #[classattr]
+    const OPEN: u32 = tube_inotify::Mask::OPEN;
+    #[classattr]
+    const CLOSE: u32 = tube_inotify::Mask::CLOSE;
+    #[classattr]
+    const CLOSE_WRITE: u32 = tube_inotify::Mask::CLOSE_WRITE;
+    #[classattr]
+    const CLOSE_NOWRITE: u32 = tube_inotify::Mask::CLOSE_NOWRITE;
+    #[classattr]
+    const MOVED_FROM: u32 = tube_inotify::Mask::MOVED_FROM;
+    #[classattr]
+    const MOVED_TO: u32 = tube_inotify::Mask::MOVED_TO;
+    #[classattr]
+    const MOVE: u32 = tube_inotify::Mask::MOVE;
+    #[classattr]
+    const MOVE_SELF: u32 = tube_inotify::Mask::MOVE_SELF;
+    #[classattr]
+    const ALL_EVENTS: u32 = tube_inotify::Mask::ALL_EVENTS;
+}
+
+#[pyclass(eq, eq_int, frozen, rename_all = "SCREAMING_SNAKE_CASE")]
+#[derive(Clone, Copy, PartialEq)]
+enum EventKind {
+    Access,
+    Modify,
+    Attrib,
+    CloseWrite,
+    CloseNoWrite,
+    Open,
+    MovedFrom,
+    MovedTo,
+    Create,
+    Delete,
+    DeleteSelf,
+    MoveSelf,
+    Unmount,
+    Overflow,
+    Ignored,
+    /// kinds the bindings don't know yet
+    Other,
+}
+
+impl From<TubeEventKind> for EventKind {
+    fn from(kind: TubeEventKind) -> Self {
+        match kind {
+            TubeEventKind::Access => Self::Access,
+            TubeEventKind::Modify => Self::Modify,
+            TubeEventKind::Attrib => Self::Attrib,
+            TubeEventKind::CloseWrite => Self::CloseWrite,
+            TubeEventKind::CloseNoWrite => Self::CloseNoWrite,
+            TubeEventKind::Open => Self::Open,
+            TubeEventKind::MovedFrom => Self::MovedFrom,
+            TubeEventKind::MovedTo => Self::MovedTo,
+            TubeEventKind::Create => Self::Create,
+            TubeEventKind::Delete => Self::Delete,
+            TubeEventKind::DeleteSelf => Self::DeleteSelf,
+            TubeEventKind::MoveSelf => Self::MoveSelf,
+            TubeEventKind::Unmount => Self::Unmount,
+            TubeEventKind::Overflow => Self::Overflow,
+            TubeEventKind::Ignored => Self::Ignored,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// an event with the full path it happened on
+#[pyclass(frozen, get_all)]
+struct Event {
+    path: PathBuf,
+    kind: EventKind,
+    /// the `Mask` bit of the event kind
+    mask: u32,
+    /// the same for both events of a rename
+    cookie: u32,
+    is_dir: bool,
+}
+
+#[pymethods]
+impl Event {
+    fn __repr__(&self) -> String {
+        format!(
+            "Event(path={:?}, kind={}, cookie={}, is_dir={})",
+            self.path,
+            tube_inotify::Mask::names(self.mask),
+            self.cookie,
+            if self.is_dir { "True" } else { "False" },
+        )
+    }
+}
+
+impl From<TubeEvent> for Event {
+    fn from(event: TubeEvent) -> Self {
+        Self {
+            path: event.path,
+            kind: event.kind.into(),
+            mask: event.kind.mask(),
+            cookie: event.cookie,
+            is_dir: event.is_dir,
+        }
+    }
+}
+
+/// iterates over the events of the watched paths, `for` blocks until the next
+/// event and `async for` waits for it in the default executor of the loop
+///
+/// ```python
+/// inotify = tube.Inotify()
+/// inotify.watch("/tmp", tube.Mask.CLOSE_WRITE | tube.Mask.MOVED_TO, recursive=True)
+/// for event in inotify:
+///     print(event.kind, event.path)
+/// ```
+#[pyclass(frozen)]
+struct Inotify {
+    inotify: Mutex<tube_inotify::Inotify>,
+    // read along with an earlier event and not returned yet
+    events: Mutex<VecDeque<TubeEvent>>,
+}
+
+#[pymethods]
+impl Inotify {
+    #[new]
+    #[pyo3(signature = (hidden = false))]
+    fn new(hidden: bool) -> PyResult<Self> {
+        let inotify = tube_inotify::Inotify::with_flags(Flag::NONBLOCKING).map_err(os_error)?;
+        Ok(Self {
+            inotify: Mutex::new(inotify.include_hidden(hidden)),
+            events: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    /// watches the path, directories under it too with `recursive`, including
+    /// the ones created later, returns the watch descriptor of the path
+    #[pyo3(signature = (path, mask = tube_inotify::Mask::ALL_EVENTS, recursive = false))]
+    fn watch(&self, path: PathBuf, mask: u32, recursive: bool) -> PyResult<i32> {
+        let mut inotify = self.inotify.lock().unwrap();
+        if recursive && path.is_dir() {
+            inotify
+                .add_recursive(path.clone(), mask, Depth::any())
+                .map_err(os_error)?;
+            let wd = inotify.watches().find(|(_, watched)| *watched == path);
+            return Ok(wd.map_or(-1, |(wd, _)| wd));
+        }
+        inotify.add_watch(path, mask).map_err(os_error)
+    }
+
+    fn unwatch(&self, wd: i32) -> PyResult<()> {
+        self.inotify.lock().unwrap().unwatch(wd).map_err(os_error)
+    }
+
+    /// the watch descriptors and the paths they watch
+    fn watches(&self) -> Vec<(i32, PathBuf)> {
+        let inotify = self.inotify.lock().unwrap();
+        inotify
+            .watches()
+            .map(|(wd, path)| (wd, path.to_path_buf()))
+            .collect()
+    }
+
+    /// the inotify descriptor, for selectors and event loops
+    fn fileno(&self) -> i32 {
+        self.inotify.lock().unwrap().as_raw_fd()
+    }
+
+    /// waits for the next event, `None` if there was none within the timeout
+    #[pyo3(signature = (timeout = None))]
+    fn read(&self, py: Python<'_>, timeout: Option<f64>) -> PyResult<Option<Event>> {
+        let deadline = timeout.map(|timeout| {
+            std::time::Instant::now() + std::time::Duration::from_secs_f64(timeout.max(0.0))
+        });
+        loop {
+            if let Some(event) = self.events.lock().unwrap().pop_front() {
+                return Ok(Some(event.into()));
+            }
+            let wait = match deadline {
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(std::time::Instant::now());
+                    (left.as_millis() as c_int).min(POLL_TIMEOUT_MS)
+                }
+                None => POLL_TIMEOUT_MS,
+            };
+            let fd = self.fileno();
+            py.allow_threads(|| {
+                let mut fds = [pollfd {
+                    fd,
+                    events: 0x001,
+                    revents: 0,
+                }];
+                unsafe { poll(fds.as_mut_ptr(), 1, wait) }
+            });
+            py.check_signals()?;
+            self.read_ready()?;
+            if self.events.lock().unwrap().is_empty()
+                && deadline.is_some_and(|deadline| std::time::Instant::now() >= deadline)
+            {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Event> {
+        match self.read(py, None)? {
+            Some(event) => Ok(event),
+            None => Err(PyStopIteration::new_err(())),
+        }
+    }
+
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// the blocking `read` in the default executor of the running loop
+    fn __anext__<'py>(slf: Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        let py = slf.py();
+        let event_loop = py.import("asyncio")?.call_method0("get_running_loop")?;
+        event_loop.call_method1("run_in_executor", (py.None(), slf.getattr("read")?))
+    }
+}
+
+impl Inotify {
+    /// moves the events that are ready into the queue
+    fn read_ready(&self) -> PyResult<()> {
+        let mut inotify = self.inotify.lock().unwrap();
+        if let Some(batch) = inotify.try_read().map_err(os_error)? {
+            let mut events = self.events.lock().unwrap();
+            events.extend(batch.filter_map(|event| inotify.resolve(&event)));
+        }
+        Ok(())
+    }
+}
+
+#[pymodule]
+fn tube(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Mask>()?;
+    m.add_class::<EventKind>()?;
+    m.add_class::<Event>()?;
+    m.add_class::<Inotify>()?;
+    Ok(())
+}
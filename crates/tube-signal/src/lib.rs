@@ -0,0 +1,4 @@
+mod ffi;
+mod signal;
+
+pub use signal::*;
@@ -0,0 +1,187 @@
+use futures::stream::Stream;
+use std::fmt;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tube_core::{AsyncFd, FdSource, POLLIN};
+
+use crate::ffi;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Signal {
+    Hangup,
+    Interrupt,
+    Quit,
+    User1,
+    User2,
+    Pipe,
+    Alarm,
+    Terminate,
+    Child,
+    WindowChange,
+    /// any other signal by its number
+    Other(i32),
+}
+
+impl Signal {
+    pub fn from_raw(signo: i32) -> Self {
+        match signo {
+            1 => Self::Hangup,
+            2 => Self::Interrupt,
+            3 => Self::Quit,
+            10 => Self::User1,
+            12 => Self::User2,
+            13 => Self::Pipe,
+            14 => Self::Alarm,
+            15 => Self::Terminate,
+            17 => Self::Child,
+            28 => Self::WindowChange,
+            signo => Self::Other(signo),
+        }
+    }
+
+    pub fn raw(self) -> i32 {
+        match self {
+            Self::Hangup => 1,
+            Self::Interrupt => 2,
+            Self::Quit => 3,
+            Self::User1 => 10,
+            Self::User2 => 12,
+            Self::Pipe => 13,
+            Self::Alarm => 14,
+            Self::Terminate => 15,
+            Self::Child => 17,
+            Self::WindowChange => 28,
+            Self::Other(signo) => signo,
+        }
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Hangup => "SIGHUP",
+            Self::Interrupt => "SIGINT",
+            Self::Quit => "SIGQUIT",
+            Self::User1 => "SIGUSR1",
+            Self::User2 => "SIGUSR2",
+            Self::Pipe => "SIGPIPE",
+            Self::Alarm => "SIGALRM",
+            Self::Terminate => "SIGTERM",
+            Self::Child => "SIGCHLD",
+            Self::WindowChange => "SIGWINCH",
+            Self::Other(signo) => return write!(f, "signal {}", signo),
+        };
+        f.write_str(name)
+    }
+}
+
+/// a received signal and who sent it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignalInfo {
+    pub signal: Signal,
+    /// the sending process, 0 when sent by the kernel
+    pub pid: u32,
+    pub uid: u32,
+}
+
+/// a `signalfd` yielding the signals it was created for as a stream.
+///
+/// the signals are blocked in the calling thread, so they stop running their
+/// handlers and are only delivered through the stream. threads inherit the mask
+/// they were spawned with, create it before spawning threads, or signals sent to
+/// the process may be handled by one of them instead
+///
+/// ```no_run
+/// # use tube_core::MergedEvents;
+/// # use tube_signal::{Signal, Signals};
+/// # fn main() -> std::io::Result<()> {
+/// let mut events = MergedEvents::new();
+/// events.push(
+///     "signals",
+///     Signals::new(&[Signal::Hangup, Signal::Terminate, Signal::User1])?,
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Signals {
+    fd: OwnedFd,
+    io: AsyncFd,
+}
+
+impl Signals {
+    pub fn new(signals: &[Signal]) -> io::Result<Self> {
+        let mut set = unsafe {
+            let mut set = MaybeUninit::<ffi::sigset_t>::uninit();
+            ffi::sigemptyset(set.as_mut_ptr());
+            set.assume_init()
+        };
+        for signal in signals {
+            if unsafe { ffi::sigaddset(&mut set, signal.raw()) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        // returns the error instead of setting errno
+        match unsafe { ffi::pthread_sigmask(ffi::SIG_BLOCK, &set, std::ptr::null_mut()) } {
+            0 => {}
+            errno => return Err(io::Error::from_raw_os_error(errno)),
+        }
+        let fd = unsafe { ffi::signalfd(-1, &set, ffi::SFD_NONBLOCK | ffi::SFD_CLOEXEC) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            io: AsyncFd::new(fd, POLLIN),
+        })
+    }
+}
+
+impl AsRawFd for Signals {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl FdSource for Signals {
+    type Item = SignalInfo;
+
+    fn read_ready(&mut self) -> io::Result<Option<SignalInfo>> {
+        let mut info = MaybeUninit::<ffi::signalfd_siginfo>::uninit();
+        let n = unsafe {
+            ffi::read(
+                self.fd.as_raw_fd(),
+                info.as_mut_ptr().cast(),
+                std::mem::size_of::<ffi::signalfd_siginfo>(),
+            )
+        };
+        if n == -1 {
+            return match io::Error::last_os_error() {
+                e if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+                e => Err(e),
+            };
+        }
+        let info = unsafe { info.assume_init() };
+        Ok(Some(SignalInfo {
+            signal: Signal::from_raw(info.ssi_signo as i32),
+            pid: info.ssi_pid,
+            uid: info.ssi_uid,
+        }))
+    }
+
+    fn io(&mut self) -> &mut AsyncFd {
+        &mut self.io
+    }
+}
+
+impl Stream for Signals {
+    type Item = io::Result<SignalInfo>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        tube_core::poll_source(self.get_mut(), cx)
+    }
+}
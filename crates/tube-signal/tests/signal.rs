@@ -0,0 +1,26 @@
+use futures::task::noop_waker;
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use std::task::Context;
+use tube_signal::{Signal, Signals};
+
+extern "C" {
+    fn raise(signo: i32) -> i32;
+}
+
+#[test]
+fn signals_wait_without_blocking() {
+    let mut signals = Signals::new(&[Signal::User1]).unwrap();
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    // nothing was sent yet, the poll returns right away
+    assert!(Pin::new(&mut signals).poll_next(&mut cx).is_pending());
+
+    // sent to this thread, which blocked the signal
+    assert_eq!(unsafe { raise(Signal::User1.raw()) }, 0);
+    let info = futures::executor::block_on(signals.next())
+        .unwrap()
+        .unwrap();
+    assert_eq!(info.signal, Signal::User1);
+    assert_eq!(info.pid, std::process::id());
+}
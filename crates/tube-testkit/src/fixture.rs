@@ -0,0 +1,296 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tube_inotify::{Depth, EventKind, Flag, Inotify, Mask, PathMasks};
+
+/// how long `expect` waits for the events by default
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// the events the fixture watches by default, the ones a scripted operation
+/// causes the same way on every kernel and filesystem
+pub const DEFAULT_MASK: u32 =
+    Mask::CREATE | Mask::DELETE | Mask::CLOSE_WRITE | Mask::MOVE | Mask::ATTRIB;
+
+// tells the fixtures of the same process apart
+static FIXTURES: AtomicUsize = AtomicUsize::new(0);
+
+/// an event as the fixture compares it, the path is relative to the fixture directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizedEvent {
+    pub path: PathBuf,
+    pub kind: EventKind,
+}
+
+impl fmt::Display for NormalizedEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.kind, self.path.display())
+    }
+}
+
+/// a temporary directory watched recursively, removed on drop. the operations
+/// change it the way tests script them and `expect` checks the events they caused
+/// arrived in order. the operations panic when they fail, like the assertions
+///
+/// ```
+/// # use tube_inotify::EventKind;
+/// # use tube_testkit::Fixture;
+/// let mut fixture = Fixture::new();
+/// fixture.write("a", "hello").rename("a", "b").delete("b");
+/// fixture.expect(&[
+///     ("a", EventKind::Create),
+///     ("a", EventKind::CloseWrite),
+///     ("a", EventKind::MovedFrom),
+///     ("b", EventKind::MovedTo),
+///     ("b", EventKind::Delete),
+/// ]);
+/// ```
+pub struct Fixture {
+    dir: PathBuf,
+    inotify: Inotify,
+    timeout: Duration,
+    depth: Depth,
+    // read while waiting for new directories to be watched
+    received: VecDeque<NormalizedEvent>,
+}
+
+impl Fixture {
+    /// watches the `DEFAULT_MASK` events
+    pub fn new() -> Self {
+        Self::with_mask(DEFAULT_MASK)
+    }
+
+    pub fn with_mask(mask: u32) -> Self {
+        Self::with_masks(PathMasks::new(mask))
+    }
+
+    /// watches the subtrees with the masks of their rules, relative to the directory
+    pub fn with_masks(masks: PathMasks) -> Self {
+        Self::open(masks, Depth::any())
+    }
+
+    /// watches only as deep as `depth` goes
+    pub fn with_depth(mask: u32, depth: Depth) -> Self {
+        Self::open(PathMasks::new(mask), depth)
+    }
+
+    fn open(masks: PathMasks, depth: Depth) -> Self {
+        let name = format!(
+            "tube-testkit-{}-{}",
+            std::process::id(),
+            FIXTURES.fetch_add(1, Ordering::Relaxed)
+        );
+        let dir = std::env::temp_dir().join(name);
+        fs::create_dir_all(&dir)
+            .unwrap_or_else(|e| panic!("couldn't create `{}`: {}", dir.display(), e));
+        // canonical like the paths of the events
+        let dir = dir.canonicalize().unwrap();
+        let inotify = Inotify::with_flags(Flag::NONBLOCKING)
+            .and_then(|inotify| {
+                inotify
+                    .include_hidden(true)
+                    .watch_recursive_masks(dir.clone(), masks, depth)
+            })
+            .unwrap_or_else(|e| panic!("couldn't watch `{}`: {}", dir.display(), e));
+        Self {
+            dir,
+            inotify,
+            timeout: TIMEOUT,
+            depth,
+            received: VecDeque::new(),
+        }
+    }
+
+    /// how long `expect` waits for the events
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// reports what directories hold once they are watched, see
+    /// `Inotify::scan_new_directories`
+    pub fn scan_new_directories(mut self) -> Self {
+        self.inotify.set_scan_new_directories(true);
+        self
+    }
+
+    /// the watched directory
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// the absolute path of a path relative to the directory
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.dir.join(path)
+    }
+
+    /// creates an empty file
+    pub fn create<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        let path = self.join(path);
+        fs::File::create(&path).unwrap_or_else(|e| failed("create", &path, e));
+        self
+    }
+
+    /// creates the directory and waits until it is watched, so what happens in it
+    /// right after is reported, directories deeper than the depth are not waited for
+    pub fn mkdir<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        let level = path.as_ref().components().count();
+        let path = self.join(path);
+        fs::create_dir(&path).unwrap_or_else(|e| failed("create", &path, e));
+        if self.depth.max.is_some_and(|max| level >= max) {
+            return self;
+        }
+        // the watch is added when its creation is read
+        let deadline = Instant::now() + self.timeout;
+        while !self.inotify.watches().any(|(_, watched)| watched == path) {
+            assert!(
+                Instant::now() < deadline,
+                "`{}` was not watched in {:?}",
+                path.display(),
+                self.timeout
+            );
+            self.read();
+        }
+        self
+    }
+
+    /// replaces the content of the file, creating it if needed
+    pub fn write<P: AsRef<Path>, C: AsRef<[u8]>>(&mut self, path: P, content: C) -> &mut Self {
+        let path = self.join(path);
+        fs::write(&path, content).unwrap_or_else(|e| failed("write", &path, e));
+        self
+    }
+
+    pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, from: P, to: Q) -> &mut Self {
+        let from = self.join(from);
+        fs::rename(&from, self.join(to)).unwrap_or_else(|e| failed("rename", &from, e));
+        self
+    }
+
+    /// removes the file, or the directory and what is in it
+    pub fn delete<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        let path = self.join(path);
+        match path.is_dir() {
+            true => fs::remove_dir_all(&path),
+            false => fs::remove_file(&path),
+        }
+        .unwrap_or_else(|e| failed("delete", &path, e));
+        self
+    }
+
+    /// sets the permission bits of the file, e.g. `0o644`
+    pub fn chmod<P: AsRef<Path>>(&mut self, path: P, mode: u32) -> &mut Self {
+        let path = self.join(path);
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode))
+            .unwrap_or_else(|e| failed("chmod", &path, e));
+        self
+    }
+
+    /// waits for the next events and panics unless they are the expected ones, in
+    /// the same order. the same event repeated right after itself counts once
+    pub fn expect<P: AsRef<Path>>(&mut self, expected: &[(P, EventKind)]) {
+        let expected: Vec<NormalizedEvent> = expected
+            .iter()
+            .map(|(path, kind)| NormalizedEvent {
+                path: path.as_ref().to_path_buf(),
+                kind: *kind,
+            })
+            .collect();
+        let deadline = Instant::now() + self.timeout;
+        let mut received = Vec::new();
+        while received.len() < expected.len() {
+            match self.received.pop_front() {
+                Some(event) => push_normalized(&mut received, event),
+                None if Instant::now() < deadline => self.read(),
+                None => break,
+            }
+        }
+        if received != expected {
+            panic!(
+                "expected the events:\n{}\nreceived{}:\n{}",
+                list(&expected),
+                match received.len() < expected.len() {
+                    true => format!(" in {:?}", self.timeout),
+                    false => String::new(),
+                },
+                list(&received)
+            );
+        }
+    }
+
+    /// panics if any event arrives in the given time
+    pub fn expect_quiet(&mut self, duration: Duration) {
+        let deadline = Instant::now() + duration;
+        while self.received.is_empty() && Instant::now() < deadline {
+            self.read();
+        }
+        if !self.received.is_empty() {
+            panic!(
+                "expected no events, received:\n{}",
+                list(self.received.make_contiguous())
+            );
+        }
+    }
+
+    /// reads the events that are ready, waits a little when there are none
+    fn read(&mut self) {
+        let batch = match self.inotify.try_read() {
+            Ok(Some(batch)) => batch,
+            Ok(None) => {
+                std::thread::sleep(Duration::from_millis(1));
+                return;
+            }
+            Err(e) => panic!("couldn't read the events: {}", e),
+        };
+        for event in batch {
+            let Some(event) = self.inotify.resolve(&event) else {
+                continue;
+            };
+            let Ok(path) = event.path.strip_prefix(&self.dir) else {
+                continue;
+            };
+            self.received.push_back(NormalizedEvent {
+                path: path.to_path_buf(),
+                kind: event.kind,
+            });
+        }
+    }
+}
+
+impl Default for Fixture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// pushes the event unless it repeats the last one, e.g. a file written in a
+/// few chunks is modified more than once
+fn push_normalized(events: &mut Vec<NormalizedEvent>, event: NormalizedEvent) {
+    if events.last() != Some(&event) {
+        events.push(event);
+    }
+}
+
+fn list(events: &[NormalizedEvent]) -> String {
+    if events.is_empty() {
+        return "  (none)".to_string();
+    }
+    events
+        .iter()
+        .map(|event| format!("  {}", event))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn failed(op: &str, path: &Path, e: std::io::Error) -> ! {
+    panic!("couldn't {} `{}`: {}", op, path.display(), e)
+}
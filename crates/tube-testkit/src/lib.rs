@@ -0,0 +1,3 @@
+mod fixture;
+
+pub use fixture::*;
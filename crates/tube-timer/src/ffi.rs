@@ -0,0 +1,35 @@
+#![allow(non_camel_case_types, dead_code)]
+
+use std::os::raw::{c_int, c_long, c_void};
+
+pub const CLOCK_MONOTONIC: c_int = 1;
+
+pub const TFD_NONBLOCK: c_int = 0o4000;
+pub const TFD_CLOEXEC: c_int = 0o2000000;
+
+pub type time_t = i64;
+
+#[repr(C)]
+#[derive(Default)]
+pub struct timespec {
+    pub tv_sec: time_t,
+    pub tv_nsec: c_long,
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub struct itimerspec {
+    pub it_interval: timespec,
+    pub it_value: timespec,
+}
+
+extern "C" {
+    pub fn timerfd_create(clockid: c_int, flags: c_int) -> c_int;
+    pub fn timerfd_settime(
+        fd: c_int,
+        flags: c_int,
+        new_value: *const itimerspec,
+        old_value: *mut itimerspec,
+    ) -> c_int;
+    pub fn read(fd: c_int, buf: *mut c_void, count: usize) -> isize;
+}
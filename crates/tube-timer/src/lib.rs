@@ -0,0 +1,4 @@
+mod ffi;
+mod timer;
+
+pub use timer::*;
@@ -0,0 +1,137 @@
+use futures::stream::Stream;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tube_core::{AsyncFd, FdSource, POLLIN};
+
+use crate::ffi;
+
+/// yielded every time the timer fires
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tick {
+    /// how many times the timer fired since the last tick, more than 1
+    /// when the stream wasn't read for longer than the period
+    pub expirations: u64,
+}
+
+/// a `timerfd` on the monotonic clock, as a stream of ticks
+///
+/// ```no_run
+/// # use tube_core::MergedEvents;
+/// # use tube_timer::Timer;
+/// # use std::time::Duration;
+/// # fn main() -> std::io::Result<()> {
+/// let mut events = MergedEvents::new();
+/// events.push("housekeeping", Timer::interval(Duration::from_secs(60))?);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Timer {
+    fd: OwnedFd,
+    io: AsyncFd,
+    // one shot timers end their stream after their tick
+    once: bool,
+    fired: bool,
+}
+
+impl Timer {
+    /// fires every period, starting a period from now
+    pub fn interval(period: Duration) -> io::Result<Self> {
+        Self::interval_at(period, period)
+    }
+
+    /// fires after `first` and then every period
+    pub fn interval_at(first: Duration, period: Duration) -> io::Result<Self> {
+        Self::create(first, period, false)
+    }
+
+    /// fires once after the delay, the stream ends after its tick
+    pub fn once(delay: Duration) -> io::Result<Self> {
+        Self::create(delay, Duration::ZERO, true)
+    }
+
+    fn create(first: Duration, period: Duration, once: bool) -> io::Result<Self> {
+        if period.is_zero() && !once {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the timer period can't be zero",
+            ));
+        }
+        let fd = unsafe {
+            ffi::timerfd_create(ffi::CLOCK_MONOTONIC, ffi::TFD_NONBLOCK | ffi::TFD_CLOEXEC)
+        };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let timer = Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            io: AsyncFd::new(fd, POLLIN),
+            once,
+            fired: false,
+        };
+        // a zero `it_value` disarms the timer, so it fires right away instead
+        let spec = ffi::itimerspec {
+            it_interval: timespec(period),
+            it_value: timespec(first.max(Duration::from_nanos(1))),
+        };
+        if unsafe { ffi::timerfd_settime(fd, 0, &spec, std::ptr::null_mut()) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(timer)
+    }
+}
+
+fn timespec(duration: Duration) -> ffi::timespec {
+    ffi::timespec {
+        tv_sec: duration.as_secs() as ffi::time_t,
+        tv_nsec: duration.subsec_nanos().into(),
+    }
+}
+
+impl AsRawFd for Timer {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl FdSource for Timer {
+    type Item = Tick;
+
+    fn read_ready(&mut self) -> io::Result<Option<Tick>> {
+        let mut expirations = 0u64;
+        let n = unsafe {
+            ffi::read(
+                self.fd.as_raw_fd(),
+                &mut expirations as *mut u64 as *mut _,
+                std::mem::size_of::<u64>(),
+            )
+        };
+        if n == -1 {
+            return match io::Error::last_os_error() {
+                e if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+                e => Err(e),
+            };
+        }
+        self.fired = true;
+        Ok(Some(Tick { expirations }))
+    }
+
+    fn is_done(&self) -> bool {
+        self.once && self.fired
+    }
+
+    fn io(&mut self) -> &mut AsyncFd {
+        &mut self.io
+    }
+}
+
+impl Stream for Timer {
+    type Item = io::Result<Tick>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        tube_core::poll_source(self.get_mut(), cx)
+    }
+}
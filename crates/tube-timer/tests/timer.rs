@@ -0,0 +1,21 @@
+use futures::StreamExt;
+use std::time::Duration;
+use tube_core::MergedEvents;
+use tube_timer::Timer;
+
+#[test]
+fn merged_timers_fire_in_order() {
+    // the slow timer is polled first, it must not hold back the fast one
+    let mut events = MergedEvents::new()
+        .with("slow", Timer::once(Duration::from_millis(300)).unwrap())
+        .with("fast", Timer::once(Duration::from_millis(20)).unwrap());
+    let fired: Vec<_> = futures::executor::block_on(async {
+        let mut fired = Vec::new();
+        while let Some((tag, tick)) = events.next().await {
+            assert_eq!(tick.unwrap().expirations, 1);
+            fired.push(tag);
+        }
+        fired
+    });
+    assert_eq!(fired, ["fast", "slow"]);
+}
@@ -0,0 +1,27 @@
+#![allow(non_camel_case_types, dead_code)]
+
+use std::os::raw::{c_int, c_ushort, c_void};
+
+pub const AF_NETLINK: c_int = 16;
+pub const SOCK_DGRAM: c_int = 2;
+pub const SOCK_NONBLOCK: c_int = 0o4000;
+pub const SOCK_CLOEXEC: c_int = 0o2000000;
+pub const NETLINK_KOBJECT_UEVENT: c_int = 15;
+
+/// the multicast group of the uevents sent by the kernel, udevd
+/// sends its own processed ones to group 2
+pub const UEVENT_GROUP_KERNEL: u32 = 1;
+
+#[repr(C)]
+pub struct sockaddr_nl {
+    pub nl_family: c_ushort,
+    pub nl_pad: c_ushort,
+    pub nl_pid: u32,
+    pub nl_groups: u32,
+}
+
+extern "C" {
+    pub fn socket(domain: c_int, ty: c_int, protocol: c_int) -> c_int;
+    pub fn bind(fd: c_int, addr: *const sockaddr_nl, len: u32) -> c_int;
+    pub fn recv(fd: c_int, buf: *mut c_void, len: usize, flags: c_int) -> isize;
+}
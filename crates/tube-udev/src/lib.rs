@@ -0,0 +1,4 @@
+mod ffi;
+mod uevent;
+
+pub use uevent::*;
@@ -0,0 +1,231 @@
+use futures::stream::Stream;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tube_core::{AsyncFd, FdSource, POLLIN};
+
+use crate::ffi;
+
+/// uevents are a few hundred bytes, the kernel caps them at 2048 of environment
+const BUFFER_SIZE: usize = 8192;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Action {
+    Add,
+    Remove,
+    Change,
+    Move,
+    Online,
+    Offline,
+    Bind,
+    Unbind,
+    Other(String),
+}
+
+impl From<&str> for Action {
+    fn from(action: &str) -> Self {
+        match action {
+            "add" => Self::Add,
+            "remove" => Self::Remove,
+            "change" => Self::Change,
+            "move" => Self::Move,
+            "online" => Self::Online,
+            "offline" => Self::Offline,
+            "bind" => Self::Bind,
+            "unbind" => Self::Unbind,
+            action => Self::Other(action.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Add => "add",
+            Self::Remove => "remove",
+            Self::Change => "change",
+            Self::Move => "move",
+            Self::Online => "online",
+            Self::Offline => "offline",
+            Self::Bind => "bind",
+            Self::Unbind => "unbind",
+            Self::Other(action) => action,
+        })
+    }
+}
+
+/// a device event sent by the kernel
+#[derive(Debug, Clone)]
+pub struct Uevent {
+    pub action: Action,
+    /// under `/sys`, e.g. `/devices/pci0000:00/.../block/sdb/sdb1`
+    pub devpath: String,
+    /// every `KEY=value` the event came with
+    pub env: HashMap<String, String>,
+}
+
+impl Uevent {
+    /// parses a kernel uevent message, `ACTION@DEVPATH` followed
+    /// by nul separated `KEY=value` pairs
+    pub fn parse(message: &[u8]) -> Option<Self> {
+        let mut fields = message
+            .split(|&b| b == 0)
+            .filter(|field| !field.is_empty())
+            .map(String::from_utf8_lossy);
+        let header = fields.next()?;
+        let (action, devpath) = header.split_once('@')?;
+        let env = fields
+            .filter_map(|field| {
+                let (key, value) = field.split_once('=')?;
+                Some((key.to_string(), value.to_string()))
+            })
+            .collect();
+        Some(Self {
+            action: action.into(),
+            devpath: devpath.to_string(),
+            env,
+        })
+    }
+
+    /// e.g. `block`, `usb`, `net`
+    pub fn subsystem(&self) -> Option<&str> {
+        self.env.get("SUBSYSTEM").map(String::as_str)
+    }
+
+    /// e.g. `disk`, `partition`
+    pub fn devtype(&self) -> Option<&str> {
+        self.env.get("DEVTYPE").map(String::as_str)
+    }
+
+    /// the device node under `/dev`, e.g. `sdb1`
+    pub fn devname(&self) -> Option<&str> {
+        self.env.get("DEVNAME").map(String::as_str)
+    }
+}
+
+/// a netlink socket receiving the kernel's uevents, as a stream
+///
+/// the events are the kernel's, so they arrive before udev created the device
+/// node and before anything mounted it
+///
+/// ```no_run
+/// # use tube_udev::Uevents;
+/// # fn main() -> std::io::Result<()> {
+/// // partitions being plugged in
+/// let uevents = Uevents::new()?.subsystem("block").devtype("partition");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Uevents {
+    fd: OwnedFd,
+    io: AsyncFd,
+    subsystem: Option<String>,
+    devtype: Option<String>,
+    buffer: Box<[u8]>,
+}
+
+impl Uevents {
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe {
+            ffi::socket(
+                ffi::AF_NETLINK,
+                ffi::SOCK_DGRAM | ffi::SOCK_NONBLOCK | ffi::SOCK_CLOEXEC,
+                ffi::NETLINK_KOBJECT_UEVENT,
+            )
+        };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let addr = ffi::sockaddr_nl {
+            nl_family: ffi::AF_NETLINK as u16,
+            nl_pad: 0,
+            // assigned by the kernel
+            nl_pid: 0,
+            nl_groups: ffi::UEVENT_GROUP_KERNEL,
+        };
+        let len = std::mem::size_of::<ffi::sockaddr_nl>() as u32;
+        if unsafe { ffi::bind(fd.as_raw_fd(), &addr, len) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            io: AsyncFd::new(fd.as_raw_fd(), POLLIN),
+            fd,
+            subsystem: None,
+            devtype: None,
+            buffer: vec![0; BUFFER_SIZE].into_boxed_slice(),
+        })
+    }
+
+    /// keeps only the events of the subsystem
+    pub fn subsystem(mut self, subsystem: impl Into<String>) -> Self {
+        self.subsystem = Some(subsystem.into());
+        self
+    }
+
+    /// keeps only the events of the device type
+    pub fn devtype(mut self, devtype: impl Into<String>) -> Self {
+        self.devtype = Some(devtype.into());
+        self
+    }
+
+    fn matches(&self, event: &Uevent) -> bool {
+        self.subsystem
+            .as_deref()
+            .is_none_or(|subsystem| event.subsystem() == Some(subsystem))
+            && self
+                .devtype
+                .as_deref()
+                .is_none_or(|devtype| event.devtype() == Some(devtype))
+    }
+}
+
+impl AsRawFd for Uevents {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl FdSource for Uevents {
+    type Item = Uevent;
+
+    fn read_ready(&mut self) -> io::Result<Option<Uevent>> {
+        loop {
+            let n = unsafe {
+                ffi::recv(
+                    self.fd.as_raw_fd(),
+                    self.buffer.as_mut_ptr().cast(),
+                    self.buffer.len(),
+                    0,
+                )
+            };
+            if n == -1 {
+                return match io::Error::last_os_error() {
+                    e if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+                    e => Err(e),
+                };
+            }
+            match Uevent::parse(&self.buffer[..n as usize]) {
+                Some(event) if self.matches(&event) => return Ok(Some(event)),
+                // filtered out, or not a kernel uevent
+                _ => continue,
+            }
+        }
+    }
+
+    fn io(&mut self) -> &mut AsyncFd {
+        &mut self.io
+    }
+}
+
+impl Stream for Uevents {
+    type Item = io::Result<Uevent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        tube_core::poll_source(self.get_mut(), cx)
+    }
+}
@@ -0,0 +1,17 @@
+use futures::task::noop_waker;
+use futures::Stream;
+use std::pin::Pin;
+use std::task::Context;
+use std::time::{Duration, Instant};
+use tube_udev::Uevents;
+
+#[test]
+fn uevents_wait_without_blocking() {
+    // no device has the subsystem, every uevent is filtered out
+    let mut uevents = Uevents::new().unwrap().subsystem("tube-none");
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    let polled = Instant::now();
+    assert!(Pin::new(&mut uevents).poll_next(&mut cx).is_pending());
+    assert!(polled.elapsed() < Duration::from_millis(100));
+}
@@ -0,0 +1,150 @@
+use anyhow::Context;
+use clap::ValueEnum;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tube_inotify::{Echoes, Event, Mask};
+
+use crate::cli::ArchiveArgs;
+use crate::hash::Algorithm;
+use crate::watcher::Watcher;
+
+/// directory under the destination holding the content of `dedup` archives
+const OBJECTS: &str = ".objects";
+
+/// how long the events of a copy are expected after it was made
+const ECHO_WINDOW: Duration = Duration::from_secs(2);
+
+/// where the copies of a changed file are put under the destination
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum Layout {
+    /// `DEST/2024-05-01/12-30-00.250/PATH`, a copy per change
+    #[default]
+    Date,
+    /// `DEST/PATH/DIGEST`, a copy per distinct content of the file
+    Hash,
+    /// same as `date`, copies of the same content are hard links to a single file
+    Dedup,
+}
+
+/// copies every file written under the watched paths into the
+/// destination, keeping the older copies around
+pub async fn run(mut args: ArchiveArgs) -> anyhow::Result<()> {
+    // files are copied once they were written or moved in, never half written
+    args.watch.events = vec![Mask::CLOSE_WRITE | Mask::MOVED_TO];
+    let echoes = Echoes::new();
+    let mut batches = Watcher::open(&args.watch)?
+        .suppress_echoes(echoes.clone())
+        .spawn();
+    fs::create_dir_all(&args.dest)
+        .with_context(|| format!("couldn't create `{}`", args.dest.display()))?;
+    let archive = Archive {
+        roots: args
+            .watch
+            .paths()
+            .filter_map(|path| path.canonicalize().ok())
+            .collect(),
+        // canonical like the paths of the events
+        dest: args.dest.canonicalize()?,
+        layout: args.layout,
+        echoes,
+    };
+
+    while let Some(events) = batches.recv().await {
+        for event in events? {
+            if let Err(e) = archive.copy(&event) {
+                tracing::warn!("archive: `{}`: {:#}", event.path.display(), e);
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Archive {
+    roots: Vec<PathBuf>,
+    dest: PathBuf,
+    layout: Layout,
+    // the copies are not reported back when the archive is under a watched directory
+    echoes: Echoes,
+}
+
+impl Archive {
+    fn copy(&self, event: &Event) -> anyhow::Result<()> {
+        // files written to the archive by hand are not archived either
+        if event.is_dir || event.path.starts_with(&self.dest) {
+            return Ok(());
+        }
+        let rel = self.relative(&event.path);
+
+        let target = match self.layout {
+            Layout::Date => self.dest.join(timestamp()).join(rel),
+            Layout::Hash => {
+                let digest = Algorithm::Blake3.digest(&event.path)?;
+                let target = self.dest.join(rel).join(digest);
+                if target.exists() {
+                    return Ok(());
+                }
+                target
+            }
+            Layout::Dedup => {
+                let digest = Algorithm::Blake3.digest(&event.path)?;
+                let object = self.dest.join(OBJECTS).join(&digest);
+                if !object.exists() {
+                    self.copy_to(&event.path, &object)?;
+                }
+                let target = self.dest.join(timestamp()).join(rel);
+                create_parent(&target)?;
+                return fs::hard_link(&object, &target)
+                    .with_context(|| format!("couldn't link `{}`", target.display()));
+            }
+        };
+        self.copy_to(&event.path, &target)
+    }
+
+    fn copy_to(&self, src: &Path, dst: &Path) -> anyhow::Result<()> {
+        self.echoes.expect(&tmp_path(dst), ECHO_WINDOW);
+        self.echoes.expect(dst, ECHO_WINDOW);
+        copy(src, dst)
+    }
+
+    /// the path under the watched path it was found in, the file name for watched files
+    fn relative<'a>(&self, path: &'a Path) -> &'a Path {
+        self.roots
+            .iter()
+            .filter_map(|root| path.strip_prefix(root).ok())
+            .find(|rel| !rel.as_os_str().is_empty())
+            .or_else(|| path.file_name().map(Path::new))
+            .unwrap_or(path)
+    }
+}
+
+/// copies through a temporary file, so the archive never has a partial copy
+fn copy(src: &Path, dst: &Path) -> anyhow::Result<()> {
+    create_parent(dst)?;
+    let tmp = tmp_path(dst);
+    fs::copy(src, &tmp).with_context(|| format!("couldn't copy to `{}`", dst.display()))?;
+    fs::rename(&tmp, dst).with_context(|| format!("couldn't copy to `{}`", dst.display()))?;
+    Ok(())
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+fn create_parent(path: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("couldn't create `{}`", parent.display()))?;
+    }
+    Ok(())
+}
+
+/// the current UTC time as `2024-05-01/12-30-00.250`
+fn timestamp() -> String {
+    let now = humantime::format_rfc3339_millis(SystemTime::now()).to_string();
+    now.trim_end_matches('Z')
+        .replace('T', "/")
+        .replace(':', "-")
+}
@@ -0,0 +1,171 @@
+use anyhow::Context;
+use rusqlite::types::Value;
+use std::fs::OpenOptions;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::time::SystemTime;
+use tokio::signal::unix::{signal, SignalKind};
+use tube_inotify::{EventKind, Mask};
+
+use crate::cli::{AuditArgs, AuditCommand, QueryArgs, DEFAULT_EVENTS};
+use crate::diff::Differ;
+use crate::output::{Format, Origin, Printer, Record};
+use crate::sink::audit::{self, Audit};
+use crate::watcher::{Matcher, Watcher};
+
+pub async fn run(args: AuditArgs) -> anyhow::Result<()> {
+    match args.command {
+        Some(AuditCommand::Query(args)) => query(args),
+        None => watch(args).await,
+    }
+}
+
+/// records the changes under the watched paths in the audit database and prints them
+async fn watch(args: AuditArgs) -> anyhow::Result<()> {
+    let mut watch = args.watch;
+    // nothing under the paths is left out, even when events were lost
+    watch.recursive = true;
+    watch.hidden = true;
+    watch.no_gitignore = true;
+    watch.recover_overflow = true;
+    watch.meta_changes = true;
+    if watch.events.is_empty() {
+        watch.events = vec![DEFAULT_EVENTS, Mask::ATTRIB];
+    }
+
+    create_private(&args.db)?;
+    let audit = Audit::open(&args.db)?;
+    let mut differ = Differ::new(&Matcher::new(&watch)?);
+    let mut batches = Watcher::open(&watch)?.spawn();
+    let mut printer = Printer::new(std::io::stdout(), Format::Human)
+        .with_process()
+        .with_changes();
+    let mut terminate = signal(SignalKind::terminate())?;
+
+    loop {
+        let events = tokio::select! {
+            events = batches.recv() => events,
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => break,
+        };
+        let Some(events) = events else {
+            break;
+        };
+        let mut records = Vec::new();
+        for event in &events? {
+            let diff = differ.update(event);
+            let mut record = Record::new(event).into_owned();
+            // the content of the files only some users can read stays out of the database
+            record.diff = diff.filter(|_| is_public(&event.path));
+            printer.print_record(&record)?;
+            records.push(record);
+        }
+        printer.flush()?;
+        audit.write(records);
+    }
+    audit.close();
+    Ok(())
+}
+
+/// creates the database readable by its owner only, as it holds the diffs of the
+/// files, the ones that already exist are left as they are
+fn create_private(path: &Path) -> anyhow::Result<()> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .mode(0o600)
+        .open(path)
+        .with_context(|| format!("couldn't open audit database `{}`", path.display()))?;
+    if file.metadata()?.permissions().mode() & 0o077 != 0 {
+        tracing::warn!("`{}` can be read by other users", path.display());
+    }
+    Ok(())
+}
+
+/// anyone can read the file
+fn is_public(path: &Path) -> bool {
+    path.metadata()
+        .is_ok_and(|metadata| metadata.permissions().mode() & 0o004 != 0)
+}
+
+/// prints the events of the audit database matching all the given filters
+fn query(args: QueryArgs) -> anyhow::Result<()> {
+    let conn = audit::connect(&args.db)?;
+
+    let mut filters = Vec::new();
+    let mut values: Vec<Value> = Vec::new();
+    if let Some(glob) = args.path {
+        filters.push("path GLOB ?".to_string());
+        values.push(Value::Text(glob));
+    }
+    if !args.kind.is_empty() {
+        let mut kinds = Vec::new();
+        for kind in &args.kind {
+            kinds.push(kind.to_uppercase().parse::<EventKind>()?.to_string());
+        }
+        filters.push(format!("kind IN ({})", vec!["?"; kinds.len()].join(", ")));
+        values.extend(kinds.into_iter().map(Value::Text));
+    }
+    if let Some(rule) = args.rule {
+        filters.push("rule = ?".to_string());
+        values.push(Value::Text(rule));
+    }
+    // timestamps are stored in the same fixed width UTC format, so they compare as text
+    let ts = |time: SystemTime| Value::Text(humantime::format_rfc3339_micros(time).to_string());
+    if let Some(since) = args.since {
+        filters.push("ts >= ?".to_string());
+        values.push(ts(since));
+    }
+    if let Some(until) = args.until {
+        filters.push("ts <= ?".to_string());
+        values.push(ts(until));
+    }
+
+    let mut sql =
+        "SELECT id, ts, path, kind, cookie, is_dir, rule, pid, uid, exe, changes, diff FROM events"
+            .to_string();
+    if !filters.is_empty() {
+        sql += &format!(" WHERE {}", filters.join(" AND "));
+    }
+    if let Some(limit) = args.limit {
+        sql = format!("SELECT * FROM ({} ORDER BY id DESC LIMIT {})", sql, limit);
+    }
+    sql += " ORDER BY id";
+
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query(rusqlite::params_from_iter(values))?;
+    let stdout = std::io::stdout();
+    let mut printer = Printer::new(stdout.lock(), args.format)
+        .with_process()
+        .with_changes()
+        .with_diff();
+    while let Some(row) = rows.next()? {
+        let process = row.get::<_, Option<u32>>(7)?.map(|pid| Origin {
+            pid,
+            uid: row.get(8).ok().flatten(),
+            exe: row.get(9).ok().flatten(),
+        });
+        let changes: Option<String> = row.get(10)?;
+        let record = Record {
+            ts: row.get(1)?,
+            path: row.get::<_, String>(2)?.into(),
+            kind: row.get(3)?,
+            cookie: row.get(4)?,
+            is_dir: row.get(5)?,
+            rule: row.get(6)?,
+            stat: None,
+            hash: None,
+            diff: row.get(11)?,
+            process,
+            changes: changes
+                .map(|changes| changes.split(", ").map(str::to_string).collect())
+                .unwrap_or_default(),
+            inode: None,
+            renamed_from: None,
+        };
+        printer.print_record(&record)?;
+    }
+    printer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use anyhow::Context;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::task::JoinSet;
+
+use crate::cli::CaptureArgs;
+
+/// how long the output left in the pipes is waited for once the command exited,
+/// the processes it started in the background may hold them open
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// passes the output of the commands through tube, to prefix its lines and to
+/// write every run of the command to its own log file, see `CaptureArgs`
+#[derive(Clone)]
+pub struct Capture {
+    prefix: Option<String>,
+    log_dir: Option<PathBuf>,
+    keep: usize,
+}
+
+/// the output of a running command
+pub struct Output {
+    readers: JoinSet<()>,
+    log: Option<Arc<Mutex<File>>>,
+}
+
+impl Capture {
+    pub fn new(args: &CaptureArgs) -> anyhow::Result<Self> {
+        if let Some(dir) = &args.log_dir {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("couldn't create `{}`", dir.display()))?;
+        }
+        Ok(Self {
+            prefix: args.prefix.clone(),
+            log_dir: args.log_dir.clone(),
+            keep: args.log_keep,
+        })
+    }
+
+    /// pipes the output of the command when it has to pass through tube,
+    /// it goes to the terminal directly otherwise
+    pub fn prepare(&self, cmd: &mut Command) {
+        if self.prefix.is_some() || self.log_dir.is_some() {
+            cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+    }
+
+    /// forwards the output of the spawned command until it closes its pipes,
+    /// `title` is the first line of the log file, e.g. the command and its event
+    pub fn attach(&self, child: &mut Child, title: &str) -> Output {
+        let log = self.log_dir.as_deref().and_then(|dir| {
+            let opened = self.open_log(dir, child.id().unwrap_or_default(), title);
+            opened
+                .inspect_err(|e| tracing::warn!("couldn't create the log file: {:#}", e))
+                .ok()
+        });
+        let mut readers = JoinSet::new();
+        if let Some(stdout) = child.stdout.take() {
+            readers.spawn(forward(
+                stdout,
+                tokio::io::stdout(),
+                self.prefix.clone(),
+                log.clone(),
+            ));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            readers.spawn(forward(
+                stderr,
+                tokio::io::stderr(),
+                self.prefix.clone(),
+                log.clone(),
+            ));
+        }
+        Output { readers, log }
+    }
+
+    /// runs the command to completion, with its output forwarded
+    pub async fn status(&self, cmd: &mut Command, title: &str) -> io::Result<ExitStatus> {
+        self.prepare(cmd);
+        let mut child = cmd.spawn()?;
+        let output = self.attach(&mut child, title);
+        let status = child.wait().await;
+        output.finish(status.as_ref().ok()).await;
+        status
+    }
+
+    /// creates the log file of a run, named after the time it started, then
+    /// removes the oldest log files over `--log-keep`
+    fn open_log(&self, dir: &Path, pid: u32, title: &str) -> anyhow::Result<Arc<Mutex<File>>> {
+        let started = humantime::format_rfc3339_micros(SystemTime::now()).to_string();
+        let path = dir.join(format!("{}-{}.log", started.replace(':', "-"), pid));
+        let mut file =
+            File::create(&path).with_context(|| format!("couldn't create `{}`", path.display()))?;
+        writeln!(file, "# {}", title)?;
+        writeln!(file, "# started {}", started)?;
+
+        let mut logs: Vec<PathBuf> = std::fs::read_dir(dir)?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| is_log(path))
+            .collect();
+        // the names start with the time, so they sort oldest first
+        logs.sort();
+        let excess = logs.len().saturating_sub(self.keep.max(1));
+        for old in &logs[..excess] {
+            if let Err(e) = std::fs::remove_file(old) {
+                tracing::warn!("couldn't remove `{}`: {}", old.display(), e);
+            }
+        }
+        Ok(Arc::new(Mutex::new(file)))
+    }
+}
+
+/// whether the file is a log file of a run, named `<rfc3339>-<pid>.log` with
+/// the colons of the time replaced, the other files of the directory are kept
+fn is_log(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    let Some((started, pid)) = name
+        .strip_suffix(".log")
+        .and_then(|name| name.rsplit_once('-'))
+    else {
+        return false;
+    };
+    if pid.is_empty() || !pid.bytes().all(|b| b.is_ascii_digit()) {
+        return false;
+    }
+    // `2024-01-02T03-04-05.678901Z`, the hyphens of the time back to colons
+    match (
+        started.get(..13),
+        started.get(13..14),
+        started.get(14..16),
+        started.get(16..17),
+        started.get(17..),
+    ) {
+        (Some(date), Some("-"), Some(minutes), Some("-"), Some(seconds)) => {
+            let time = format!("{}:{}:{}", date, minutes, seconds);
+            humantime::parse_rfc3339(&time).is_ok()
+        }
+        _ => false,
+    }
+}
+
+impl Output {
+    /// waits for the rest of the output and ends the log file with the exit status,
+    /// `None` when tube stopped the command
+    pub async fn finish(mut self, status: Option<&ExitStatus>) {
+        let drained = async { while self.readers.join_next().await.is_some() {} };
+        if tokio::time::timeout(DRAIN_TIMEOUT, drained).await.is_err() {
+            self.readers.detach_all();
+        }
+        let Some(log) = self.log else {
+            return;
+        };
+        let ended = humantime::format_rfc3339_micros(SystemTime::now());
+        let mut file = log.lock().unwrap();
+        let _ = match status {
+            Some(status) => writeln!(file, "# {} at {}", status, ended),
+            None => writeln!(file, "# stopped by tube at {}", ended),
+        };
+    }
+}
+
+/// copies the lines read from the pipe to the terminal, after the prefix,
+/// and to the log file, as they are
+async fn forward(
+    pipe: impl AsyncRead + Unpin,
+    mut out: impl AsyncWrite + Unpin,
+    prefix: Option<String>,
+    log: Option<Arc<Mutex<File>>>,
+) {
+    let mut pipe = BufReader::new(pipe);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match pipe.read_until(b'\n', &mut line).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        if let Some(log) = &log {
+            let _ = log.lock().unwrap().write_all(&line);
+        }
+        // written at once, so the lines of stdout and stderr don't mix
+        let mut printed = Vec::with_capacity(line.len() + 16);
+        if let Some(prefix) = &prefix {
+            printed.extend_from_slice(format!("[{}] ", prefix).as_bytes());
+        }
+        printed.extend_from_slice(&line);
+        if !printed.ends_with(b"\n") {
+            printed.push(b'\n');
+        }
+        let _ = out.write_all(&printed).await;
+        let _ = out.flush().await;
+    }
+}
@@ -0,0 +1,1121 @@
+use clap::{ArgAction, ArgGroup, Args, Parser, Subcommand};
+use regex::Regex;
+use std::ffi::OsStr;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tube_inotify::{Depth, Event, EventKind, Mask};
+
+use crate::archive::Layout;
+use crate::exec::{OnBusy, OnFailure};
+use crate::hash::Algorithm;
+use crate::ignore::Preset;
+use crate::logging::Color;
+use crate::output::Format;
+use crate::rate::Rate;
+use crate::sink::chat::ChatService;
+use crate::sink::dbus::Bus;
+use crate::sink::journal::LogOutput;
+use crate::watcher::{Pattern, Symlinks};
+
+/// events reported when none are requested
+pub const DEFAULT_EVENTS: u32 = Mask::CREATE | Mask::MODIFY | Mask::DELETE | Mask::MOVE;
+
+/// events reported with a preset when none are requested, files are
+/// reported when they are done being written
+const PRESET_EVENTS: u32 = Mask::CLOSE_WRITE | Mask::CREATE | Mask::DELETE | Mask::MOVE;
+
+/// watch files and directories for changes and print the events
+#[derive(Debug, Parser)]
+#[command(
+    name = "tube",
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub watch: WatchArgs,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    #[command(flatten)]
+    pub sinks: SinkArgs,
+
+    #[command(flatten)]
+    pub log: LogArgs,
+
+    /// expose prometheus metrics on the address
+    #[arg(long, value_name = "ADDR")]
+    pub metrics_listen: Option<SocketAddr>,
+
+    #[command(flatten)]
+    pub stats: StatsArgs,
+
+    /// run the rules declared in the given configuration file
+    #[arg(short, long, value_name = "FILE", group = "targets", conflicts_with_all = ["paths", "watch", "paths_from", "paths_from0"])]
+    pub config: Option<PathBuf>,
+}
+
+/// arguments that define how events are printed
+#[derive(Debug, Args)]
+pub struct OutputArgs {
+    /// the format events are printed in
+    #[arg(short, long, value_enum, default_value_t)]
+    pub format: Format,
+
+    /// collect events until none arrived for the duration, keeping only
+    /// the last event of every path
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub debounce: Option<Duration>,
+
+    /// print and deliver at most N events per period, e.g. `10/min`, the
+    /// events over the rate are dropped
+    #[arg(long, value_name = "RATE")]
+    pub max_rate: Option<Rate>,
+
+    /// add the size, mode, owner and mtime of the files to the printed events
+    #[arg(long)]
+    pub stat: bool,
+
+    /// print a unified diff of the changes written to text files, the files
+    /// under the watched paths are read once at startup to compare against
+    #[arg(long)]
+    pub diff: bool,
+
+    /// print only the paths of the events, one per line
+    #[arg(long)]
+    pub paths_only: bool,
+
+    /// print the paths relative to the directory instead of absolute
+    #[arg(long, value_name = "BASE")]
+    pub relative: Option<PathBuf>,
+
+    /// separate the printed paths with NUL instead of newlines, for `xargs -0`, implies `--paths-only`
+    #[arg(short = '0', long)]
+    pub print0: bool,
+
+    /// exit after N events were printed
+    #[arg(short = 'n', long, value_name = "N")]
+    pub count: Option<usize>,
+
+    /// exit with 124 if the duration passed before `--count` events were printed,
+    /// without `--count` tube just exits with 124 once the duration passed
+    #[arg(short, long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub timeout: Option<Duration>,
+}
+
+/// arguments controlling the colors and how much tube logs, for all the subcommands
+#[derive(Debug, Args)]
+pub struct LogArgs {
+    /// when to color the logs and the printed events
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t, global = true)]
+    pub color: Color,
+
+    /// only log errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// log what tube is doing, `-vv` logs every event read
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+}
+
+/// arguments of the reports summarizing what tube did, also written on SIGUSR1
+#[derive(Debug, Clone, Args)]
+pub struct StatsArgs {
+    /// report the events seen, the rules triggered and the commands run when tube stops
+    #[arg(long)]
+    pub stats: bool,
+
+    /// report every interval
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub stats_interval: Option<Duration>,
+
+    /// write the reports to the system log instead of stderr
+    #[arg(long, value_enum, value_name = "LOG")]
+    pub stats_output: Option<LogOutput>,
+}
+
+/// arguments of the destinations events are delivered to, besides stdout
+#[derive(Debug, Args)]
+pub struct SinkArgs {
+    /// POST every event batch as JSON to the URL
+    #[arg(long, value_name = "URL")]
+    pub webhook: Option<String>,
+
+    /// header added to the webhook requests, as `Name: value`
+    #[arg(long, value_name = "HEADER", requires = "webhook")]
+    pub webhook_header: Vec<String>,
+
+    /// how many times a failed webhook request is retried
+    #[arg(long, value_name = "N", default_value_t = 3, requires = "webhook")]
+    pub webhook_retries: u32,
+
+    /// max number of batches waiting to be posted, new batches are dropped when full
+    #[arg(long, value_name = "N", default_value_t = 64, requires = "webhook")]
+    pub webhook_queue: usize,
+
+    /// broadcast newline delimited JSON events to the clients of a unix socket
+    #[arg(long, value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+
+    /// write an entry per event to the system log, can be given multiple times
+    #[arg(long, value_enum)]
+    pub output: Vec<LogOutput>,
+
+    /// emit an `org.tube.FileEvent` signal per event on the session (default) or system bus
+    #[arg(long, value_enum, value_name = "BUS", num_args = 0..=1, default_missing_value = "session")]
+    pub dbus: Option<Bus>,
+
+    /// show a desktop notification per event batch, best used with `--debounce`
+    #[arg(long)]
+    pub notify: bool,
+
+    /// append every event to an SQLite database, see `tube audit query`
+    #[arg(long, value_name = "FILE")]
+    pub audit_db: Option<PathBuf>,
+
+    /// publish every event as a JSON message to the MQTT broker at `tcp://host:port`
+    #[arg(long, value_name = "URL")]
+    pub mqtt: Option<String>,
+
+    /// topic the MQTT messages are published to, `{hostname}`, `{rule}`
+    /// and `{kind}` are replaced per event, `{rule}` is empty outside of rules
+    #[arg(
+        long,
+        value_name = "TOPIC",
+        default_value = "tube/{hostname}",
+        requires = "mqtt"
+    )]
+    pub topic: String,
+
+    /// MQTT quality of service of the published messages
+    #[arg(long, value_name = "QOS", default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=2), requires = "mqtt")]
+    pub qos: u8,
+
+    /// publish the MQTT messages as retained, so new subscribers get the last event
+    #[arg(long, requires = "mqtt")]
+    pub retain: bool,
+
+    /// produce every event to a Kafka topic, keyed by its path, given as `KEY=VALUE`
+    /// options: `brokers=host:9092,..` and `topic=NAME` are required, other
+    /// options are passed to librdkafka as is (e.g. `linger.ms=50`)
+    #[cfg(feature = "kafka")]
+    #[arg(long, value_name = "KEY=VALUE")]
+    pub kafka: Vec<String>,
+
+    /// deliver every event as JSON to the Redis server at the URL (`redis://host`),
+    /// through `--channel` and/or `--redis-stream`
+    #[arg(long, value_name = "URL")]
+    pub redis: Option<String>,
+
+    /// Redis pub/sub channel the events are published to, expanded like `--topic`
+    #[arg(long, value_name = "CHANNEL", requires = "redis")]
+    pub channel: Option<String>,
+
+    /// Redis stream the events are appended to with `XADD`, expanded like `--topic`
+    #[arg(long, value_name = "KEY", requires = "redis")]
+    pub redis_stream: Option<String>,
+
+    /// publish every event as a JSON message to the NATS server at the URL (`nats://host`)
+    #[arg(long, value_name = "URL")]
+    pub nats: Option<String>,
+
+    /// subject the NATS messages are published to, expanded like `--topic`,
+    /// NATS doesn't allow empty tokens, so only use `{rule}` with `--config`
+    #[arg(
+        long,
+        value_name = "SUBJECT",
+        default_value = "tube.{kind}",
+        requires = "nats"
+    )]
+    pub subject: String,
+
+    /// stream the events to a collector at `tcp://host:port`, as JSON
+    /// objects prefixed by their length as a big endian u32
+    #[arg(long, value_name = "URL")]
+    pub forward: Option<String>,
+
+    /// file the events are kept in while the collector is unreachable
+    #[arg(long, value_name = "FILE", requires = "forward")]
+    pub forward_buffer: Option<PathBuf>,
+
+    /// email a digest of the events through the SMTP server at the URL,
+    /// `smtp://[user:password@]host[:port]`, upgraded with STARTTLS when the server
+    /// offers it, or `smtps://` for TLS from the start. the credentials are only sent
+    /// over TLS, `smtp+insecure://` sends them to servers without STARTTLS as well
+    #[arg(long, value_name = "URL", requires = "email_to")]
+    pub email: Option<String>,
+
+    /// address the digests are sent to, can be given multiple times
+    #[arg(long, value_name = "ADDRESS", requires = "email")]
+    pub email_to: Vec<String>,
+
+    /// address the digests are sent from [default: tube@HOSTNAME]
+    #[arg(long, value_name = "ADDRESS", requires = "email")]
+    pub email_from: Option<String>,
+
+    /// subject of the digests, `{count}`, `{hostname}` and `{rule}` are replaced,
+    /// `{rule}` lists the rules the events triggered
+    #[arg(
+        long,
+        value_name = "SUBJECT",
+        default_value = "tube: {count} changes on {hostname}",
+        requires = "email"
+    )]
+    pub email_subject: String,
+
+    /// how long the events are collected after the first one before the digest is sent
+    #[arg(long, value_name = "DURATION", default_value = "1m", value_parser = humantime::parse_duration, requires = "email")]
+    pub email_digest: Duration,
+
+    /// post the events as a message to a Slack, Discord or Teams incoming webhook,
+    /// one message per event batch, or per `--chat-digest`
+    #[arg(long, value_name = "URL")]
+    pub chat: Option<String>,
+
+    /// the service the webhook belongs to, recognized from the URL by default
+    #[arg(long, value_enum, value_name = "SERVICE", requires = "chat")]
+    pub chat_service: Option<ChatService>,
+
+    /// line of the message for every event, `{path}`, `{name}`, `{dir}`, `{kind}`,
+    /// `{hostname}` and `{rule}` are replaced
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        default_value = "{kind} {path} on {hostname}",
+        requires = "chat"
+    )]
+    pub chat_template: String,
+
+    /// collect the events for DURATION after the first one and post them in one message
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration, requires = "chat")]
+    pub chat_digest: Option<Duration>,
+
+    /// post at most N messages per period, e.g. `10/min`, the messages over the
+    /// rate are dropped
+    #[arg(long, value_name = "RATE", requires = "chat")]
+    pub chat_max_rate: Option<Rate>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// run a command for every matching event
+    ///
+    /// the placeholders `{path}`, `{event}`, `{dir}` and `{name}` in the command
+    /// arguments are replaced with the event values, the same values are also exported
+    /// to the command as `TUBE_PATH`, `TUBE_EVENT`, `TUBE_DIR` and `TUBE_NAME`
+    Exec(ExecArgs),
+
+    /// start a long running command and restart it whenever a matching event fires
+    Run(RunArgs),
+
+    /// run the rules of a configuration file in the background
+    ///
+    /// the configuration is reloaded when the file changes and on SIGHUP, only the
+    /// rules that changed are restarted, SIGTERM stops the daemon after the commands
+    /// that are running exit
+    Daemon(Box<DaemonArgs>),
+
+    /// wait until a matching event happens on the path, print it and exit
+    ///
+    /// the path doesn't have to exist, in that case its parent directory is watched,
+    /// exits with 124 if the timeout passed before the event happened
+    Wait(WaitArgs),
+
+    /// print the content appended to files as they grow
+    ///
+    /// files are followed by name, truncated, rotated, and recreated files are
+    /// printed again from their start
+    Tail(TailArgs),
+
+    /// copy the files written under the paths into a backup directory
+    ///
+    /// every time a file is written or moved in, a copy of it is added to the
+    /// destination, the older copies are kept
+    Archive(ArchiveArgs),
+
+    /// mirror a directory into another one and keep it up to date
+    Sync(SyncArgs),
+
+    /// serve the live events over HTTP
+    ///
+    /// events are streamed as Server-Sent Events on `/events` and over a
+    /// WebSocket on `/ws`, one JSON object per event
+    Serve(ServeArgs),
+
+    /// serve the live events over gRPC
+    ///
+    /// clients call `tube.v1.Tube/Watch` with the paths and kinds they are
+    /// interested in and get a stream of the matching events
+    ServeGrpc(ServeGrpcArgs),
+
+    /// write the matching events to a file, one JSON object per line
+    Record(RecordArgs),
+
+    /// feed recorded events back, keeping the time between them
+    ///
+    /// events are run through the rules of a configuration file, through
+    /// a command as with `exec`, or printed if neither is given
+    Replay(ReplayArgs),
+
+    /// write a manifest of the files under the paths, to compare with `tube diff`
+    ///
+    /// the paths are walked recursively with the same filters as when watching them
+    Snapshot(SnapshotArgs),
+
+    /// print the files created, modified and deleted since a snapshot
+    ///
+    /// changes are printed as `CREATE`, `MODIFY` and `DELETE` events, tube
+    /// exits with 1 if any file changed and 0 otherwise
+    Diff(DiffArgs),
+
+    /// record who changed what under the paths, or inspect the audit database
+    ///
+    /// every change under the paths is recorded in the audit database, with the
+    /// metadata that changed and the diff of the text files anyone can read, and with
+    /// `--attribution` the process behind it. the paths are watched recursively, hidden
+    /// and ignored files included, and rescanned when events were lost. `tube audit query`
+    /// reads the database, this one or one written with `--audit-db`
+    Audit(AuditArgs),
+
+    /// interactive dashboard of the live events and the watches
+    ///
+    /// shows the events as they happen, the event count of every watched path and
+    /// the watch descriptors in use against the kernel limit, watches can be added
+    /// and removed while running
+    Tui(TuiArgs),
+
+    /// live view of the directories with the most events
+    ///
+    /// events are counted per directory over a sliding window and the busiest ones
+    /// are shown with their rate, refreshed every second. with `--config` the events
+    /// are also counted per rule, and with `--attribute` per process
+    Top(TopArgs),
+
+    /// print the inotify limits and usage, and check the paths can be watched
+    #[command(visible_alias = "limits")]
+    Doctor(DoctorArgs),
+
+    /// print the shell completions of tube
+    ///
+    /// e.g. `tube completions bash > /usr/share/bash-completion/completions/tube`
+    Completions(CompletionsArgs),
+
+    /// print the man page of tube, or write the pages of all the subcommands
+    Man(ManArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct CompletionsArgs {
+    /// the shell the completions are for
+    #[arg(value_enum)]
+    pub shell: clap_complete::Shell,
+}
+
+#[derive(Debug, Args)]
+pub struct ManArgs {
+    /// write `tube.1` and a page for every subcommand, e.g. `tube-exec.1`, into
+    /// the directory instead
+    #[arg(long, value_name = "DIR")]
+    pub dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct DoctorArgs {
+    /// paths to check
+    pub paths: Vec<PathBuf>,
+
+    /// count the watches the paths take when watched recursively
+    #[arg(short, long)]
+    pub recursive: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct TuiArgs {
+    #[command(flatten)]
+    pub watch: WatchArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct TopArgs {
+    #[command(flatten)]
+    pub watch: WatchArgs,
+
+    /// also watch the paths of the rules of this configuration file, counting
+    /// their events per rule
+    #[arg(short, long, value_name = "FILE", group = "targets")]
+    pub config: Option<PathBuf>,
+
+    /// count the events of the last DURATION
+    #[arg(long, value_name = "DURATION", default_value = "10s", value_parser = humantime::parse_duration)]
+    pub window: Duration,
+}
+
+#[derive(Debug, Args)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct AuditArgs {
+    #[command(subcommand)]
+    pub command: Option<AuditCommand>,
+
+    #[command(flatten)]
+    pub watch: WatchArgs,
+
+    /// the audit database the changes are recorded in, created readable by its owner only
+    #[arg(long, value_name = "FILE", default_value = "tube.db")]
+    pub db: PathBuf,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AuditCommand {
+    /// print the recorded events, oldest first
+    Query(QueryArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct QueryArgs {
+    /// the audit database
+    #[arg(long, value_name = "FILE", default_value = "tube.db")]
+    pub db: PathBuf,
+
+    /// only events on paths matching the glob (e.g. `/etc/*.conf`)
+    #[arg(long, value_name = "GLOB")]
+    pub path: Option<String>,
+
+    /// only events of the kind, can be given multiple times
+    #[arg(long, value_name = "EVENT")]
+    pub kind: Vec<String>,
+
+    /// only events that triggered the rule
+    #[arg(long, value_name = "NAME")]
+    pub rule: Option<String>,
+
+    /// only events since the time, RFC 3339 or a duration ago (e.g. `1h`)
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub since: Option<SystemTime>,
+
+    /// only events until the time, RFC 3339 or a duration ago
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    pub until: Option<SystemTime>,
+
+    /// print at most N events, the latest ones
+    #[arg(short = 'n', long, value_name = "N")]
+    pub limit: Option<u32>,
+
+    /// the format events are printed in
+    #[arg(short, long, value_enum, default_value_t)]
+    pub format: Format,
+}
+
+#[derive(Debug, Args)]
+pub struct RecordArgs {
+    #[command(flatten)]
+    pub watch: WatchArgs,
+
+    /// file to write the events to, appended to if it exists
+    #[arg(short, long, value_name = "FILE")]
+    pub output: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct ReplayArgs {
+    /// file written by `tube record`
+    pub file: PathBuf,
+
+    /// how much faster than recorded to replay (e.g. `2x`, `0.5x`), `max` replays without delays
+    #[arg(short, long, default_value = "1x", value_parser = parse_speed)]
+    pub speed: f64,
+
+    /// run the rules of this configuration file on the events
+    #[arg(short, long, value_name = "FILE", conflicts_with = "command")]
+    pub config: Option<PathBuf>,
+
+    /// the format events are printed in when there is nothing to run
+    #[arg(short, long, value_enum, default_value_t)]
+    pub format: Format,
+
+    /// the command to run for every event, as with `exec`
+    #[arg(last = true, value_name = "COMMAND")]
+    pub command: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct SnapshotArgs {
+    #[command(flatten)]
+    pub watch: WatchArgs,
+
+    /// file to write the manifest to, stdout by default
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct DiffArgs {
+    /// file written by `tube snapshot`
+    pub snapshot: PathBuf,
+
+    #[command(flatten)]
+    pub watch: WatchArgs,
+
+    /// the format the changes are printed in
+    #[arg(short, long, value_enum, default_value_t)]
+    pub format: Format,
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    #[command(flatten)]
+    pub watch: WatchArgs,
+
+    /// address to listen on, `:PORT` listens on all interfaces
+    #[arg(short, long, value_name = "ADDR", default_value = "127.0.0.1:8080", value_parser = parse_listen)]
+    pub listen: SocketAddr,
+}
+
+#[derive(Debug, Args)]
+pub struct ServeGrpcArgs {
+    #[command(flatten)]
+    pub watch: WatchArgs,
+
+    /// address to listen on, `:PORT` listens on all interfaces
+    #[arg(short, long, value_name = "ADDR", default_value = "127.0.0.1:7443", value_parser = parse_listen)]
+    pub listen: SocketAddr,
+}
+
+#[derive(Debug, Args)]
+pub struct SyncArgs {
+    /// the directory to mirror
+    pub src: PathBuf,
+
+    /// the directory to mirror into, files that are not in the source are removed
+    pub dst: PathBuf,
+
+    /// only print what would be done
+    #[arg(short = 'n', long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ArchiveArgs {
+    #[command(flatten)]
+    pub watch: WatchArgs,
+
+    /// the directory the copies are put in
+    #[arg(long, value_name = "DIR")]
+    pub dest: PathBuf,
+
+    /// how the copies are laid out in the destination
+    #[arg(long, value_enum, default_value_t)]
+    pub layout: Layout,
+}
+
+#[derive(Debug, Args)]
+pub struct TailArgs {
+    /// the files to follow, they don't have to exist yet
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct WaitArgs {
+    /// the file or directory to wait on
+    pub path: PathBuf,
+
+    /// comma separated list of events to wait for
+    #[arg(
+        long = "for",
+        value_name = "EVENTS",
+        value_delimiter = ',',
+        value_parser = parse_event,
+        default_value = "create,modify"
+    )]
+    pub events: Vec<u32>,
+
+    /// give up after the given duration
+    #[arg(short, long, value_parser = humantime::parse_duration)]
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug, Args)]
+pub struct DaemonArgs {
+    /// the configuration file to run
+    #[arg(short, long, value_name = "FILE")]
+    pub config: PathBuf,
+
+    /// write the daemon pid to the given file
+    #[arg(short, long, value_name = "FILE")]
+    pub pidfile: Option<PathBuf>,
+
+    /// don't detach from the terminal, for running under a service manager
+    #[arg(short = 'F', long)]
+    pub foreground: bool,
+
+    /// file to redirect stdout and stderr to once detached, discarded by default
+    #[arg(short, long, value_name = "FILE")]
+    pub log_file: Option<PathBuf>,
+
+    /// expose prometheus metrics on the address
+    #[arg(long, value_name = "ADDR")]
+    pub metrics_listen: Option<SocketAddr>,
+
+    #[command(flatten)]
+    pub stats: StatsArgs,
+
+    /// don't reload the config when the file changes, only on SIGHUP
+    #[arg(long)]
+    pub no_watch_config: bool,
+
+    /// directory the rules remember the files they watch in when the daemon stops,
+    /// on startup the changes made while it was down are run through the rules as
+    /// `CREATE`, `MODIFY` and `DELETE` events before watching
+    #[arg(long, value_name = "DIR")]
+    pub state: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub sinks: SinkArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ExecArgs {
+    #[command(flatten)]
+    pub watch: WatchArgs,
+
+    /// max number of commands running at the same time
+    #[arg(
+        short,
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        conflicts_with = "serial"
+    )]
+    pub jobs: usize,
+
+    /// run one command at a time, the default
+    #[arg(long)]
+    pub serial: bool,
+
+    /// keep only the latest waiting event of every path
+    #[arg(long)]
+    pub collapse: bool,
+
+    /// clear the terminal before every run of the command
+    #[arg(short, long)]
+    pub clear: bool,
+
+    /// run the command once at startup, before any event, with empty placeholders
+    #[arg(long)]
+    pub init: bool,
+
+    /// run the command for at most N events per period, e.g. `10/min`, the
+    /// events over the rate are dropped
+    #[arg(long, value_name = "RATE")]
+    pub max_rate: Option<Rate>,
+
+    /// what to do with events that arrive while `--jobs` commands are running
+    #[arg(long, value_enum, default_value_t)]
+    pub on_busy: OnBusy,
+
+    /// what to do when a command fails: `ignore`, `retry:N` or `stop`
+    #[arg(long, value_name = "POLICY", default_value = "ignore")]
+    pub on_failure: OnFailure,
+
+    /// shell command run once a command failed for good, the event values are
+    /// available as `TUBE_PATH`, `TUBE_EVENT`, `TUBE_DIR` and `TUBE_NAME`, never
+    /// substituted into the script, and the exit code as `TUBE_EXIT_CODE`
+    #[arg(long, value_name = "CMD")]
+    pub on_failure_cmd: Option<String>,
+
+    #[command(flatten)]
+    pub sandbox: SandboxArgs,
+
+    #[command(flatten)]
+    pub conditions: ConditionArgs,
+
+    #[command(flatten)]
+    pub capture: CaptureArgs,
+
+    /// the command to run and its arguments
+    #[arg(last = true, required = true, value_name = "CMD")]
+    pub command: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub watch: WatchArgs,
+
+    /// signal sent to the command to stop it before restarting
+    #[arg(short, long, value_parser = parse_signal, default_value = "TERM")]
+    pub signal: i32,
+
+    /// how long to wait for the command to exit after the signal was
+    /// sent, before killing it
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "5s")]
+    pub stop_timeout: Duration,
+
+    /// clear the terminal before every start of the command
+    #[arg(short, long)]
+    pub clear: bool,
+
+    #[command(flatten)]
+    pub sandbox: SandboxArgs,
+
+    #[command(flatten)]
+    pub conditions: ConditionArgs,
+
+    #[command(flatten)]
+    pub capture: CaptureArgs,
+
+    /// the command to run and its arguments
+    #[arg(last = true, required = true, value_name = "CMD")]
+    pub command: Vec<String>,
+}
+
+/// what happens to the output of the commands
+#[derive(Debug, Args)]
+pub struct CaptureArgs {
+    /// start every line the commands print with `[PREFIX] `, to tell their output apart
+    #[arg(long, value_name = "PREFIX")]
+    pub prefix: Option<String>,
+
+    /// also write the output of every run of the command to its own file in the
+    /// directory, named after the time the run started, with the exit status at the end
+    #[arg(long, value_name = "DIR")]
+    pub log_dir: Option<PathBuf>,
+
+    /// how many log files are kept in `--log-dir`, the oldest ones are removed
+    #[arg(long, value_name = "N", default_value_t = 100, requires = "log_dir")]
+    pub log_keep: usize,
+}
+
+/// conditions on the content of the files for the command to run
+#[derive(Debug, Args)]
+pub struct ConditionArgs {
+    /// only run the command when the file content matches the regex
+    #[arg(long, value_name = "REGEX")]
+    pub if_contains: Option<Regex>,
+
+    /// only run the command when a line added or removed by the change matches the
+    /// regex (e.g. `^serde = ` for `Cargo.toml`), the text files under the watched
+    /// paths are read once at startup to compare against
+    #[arg(long, value_name = "REGEX")]
+    pub if_changed_lines: Option<Regex>,
+}
+
+/// restrictions for the commands tube runs, for when tube itself has to run as root
+#[derive(Debug, Args)]
+pub struct SandboxArgs {
+    /// run the commands as the user, a name or a uid
+    #[arg(long, value_name = "USER")]
+    pub user: Option<String>,
+
+    /// run the commands with the group, a name or a gid, defaults to the group of `--user`
+    #[arg(long, value_name = "GROUP")]
+    pub group: Option<String>,
+
+    /// set `no_new_privs` on the commands, so setuid binaries can't gain privileges
+    #[arg(long)]
+    pub no_new_privs: bool,
+
+    /// run the commands with only `PATH`, `LANG`, `TERM`, `TZ` and the `TUBE_*` variables
+    #[arg(long)]
+    pub clean_env: bool,
+
+    /// variable passed to the commands despite `--clean-env`, can be given multiple times
+    #[arg(long, value_name = "NAME", requires = "clean_env")]
+    pub keep_env: Vec<String>,
+}
+
+/// arguments that define what is watched, shared between the different modes
+#[derive(Debug, Clone, Args)]
+#[command(group(
+    ArgGroup::new("targets")
+        .args(["paths", "watch", "paths_from", "paths_from0"])
+        .required(true)
+        .multiple(true)
+))]
+pub struct WatchArgs {
+    /// paths to watch
+    pub paths: Vec<PathBuf>,
+
+    /// path to watch, same as the positional paths, can be given multiple times
+    #[arg(short, long, value_name = "PATH")]
+    pub watch: Vec<PathBuf>,
+
+    /// read the paths to watch from the file, one per line, `-` reads them from stdin
+    #[arg(long, value_name = "FILE", value_parser = parse_paths_from)]
+    pub paths_from: Option<PathList>,
+
+    /// same as `--paths-from` but the paths are separated by NUL, for `find -print0`
+    #[arg(long, value_name = "FILE", value_parser = parse_paths_from0, conflicts_with = "paths_from")]
+    pub paths_from0: Option<PathList>,
+
+    /// watch directories recursively
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// how deep to go below the watched paths, counted like `find -maxdepth`: 1 reports
+    /// the entries of the paths without watching the directories in them, implies `--recursive`
+    #[arg(long, value_name = "N")]
+    pub max_depth: Option<usize>,
+
+    /// leave out the events less deep than N, counted like `find -mindepth`: 1 leaves
+    /// out the events on the watched paths themselves, implies `--recursive`
+    #[arg(long, value_name = "N")]
+    pub min_depth: Option<usize>,
+
+    /// comma separated list of events to report [default: create,modify,delete,move]
+    #[arg(short, long, value_delimiter = ',', value_parser = parse_event)]
+    pub events: Vec<u32>,
+
+    /// include hidden files and directories
+    #[arg(short = 'H', long)]
+    pub hidden: bool,
+
+    /// ignore patterns and default events for a project type
+    #[arg(short, long, value_enum)]
+    pub preset: Option<Preset>,
+
+    /// don't ignore the paths listed in `.gitignore` files
+    #[arg(long)]
+    pub no_gitignore: bool,
+
+    /// only report paths matching the pattern, a glob or a regex prefixed
+    /// with `re:` (e.g. `re:^.+\.tmp$`), can be given multiple times
+    #[arg(short, long, value_name = "PATTERN")]
+    pub include: Vec<String>,
+
+    /// don't report paths matching the pattern, same syntax as `--include`
+    #[arg(short = 'x', long, value_name = "PATTERN")]
+    pub exclude: Vec<String>,
+
+    /// file with exclude patterns, one per line (e.g. `.tubeignore`)
+    #[arg(long, value_name = "FILE")]
+    pub ignore_file: Option<PathBuf>,
+
+    /// report the pid, uid and executable of the process that changed the file,
+    /// through fanotify when running as root, by looking in /proc otherwise
+    #[arg(long, visible_alias = "attribution")]
+    pub attribute: bool,
+
+    /// how symlinks are watched: `follow` watches their target under the link path,
+    /// `dont-follow` the links themselves and `resolve` their target under its own path
+    #[arg(long, value_enum, default_value_t, value_name = "POLICY")]
+    pub symlinks: Symlinks,
+
+    /// report what changed on ATTRIB events (mode, owner, xattrs, timestamps),
+    /// by comparing with the metadata the files had when watching started
+    #[arg(long)]
+    pub meta_changes: bool,
+
+    /// report the inode of the files, so events on hard links can be grouped, and
+    /// the path a MOVED_TO came from when the same inode was moved away before
+    #[arg(long)]
+    pub inodes: bool,
+
+    /// when events were lost because the kernel queue overflowed, scan the watched
+    /// directories again and report what changed as CREATE, MODIFY and DELETE events
+    #[arg(long)]
+    pub recover_overflow: bool,
+
+    /// report what directories created under recursive watches already hold once they
+    /// are watched as CREATE events, files written right after `mkdir -p` are missed otherwise
+    #[arg(long)]
+    pub scan_new_dirs: bool,
+
+    /// recognize a sequence of events and report it as one, `upload` reports a file
+    /// written then renamed to its final name (`file.part` then `file`), or written
+    /// in place and left alone for `--settle`, as a single FILE_READY event
+    #[arg(long, value_enum, value_name = "PATTERN")]
+    pub pattern: Option<Pattern>,
+
+    /// how long a file written in place stays untouched before it is ready [default: 1s]
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration, requires = "pattern")]
+    pub settle: Option<Duration>,
+
+    /// scan the watched paths again every DURATION in the background and report the
+    /// changes no event came for (network filesystems, lost events) as CREATE, MODIFY
+    /// and DELETE events, the scans are spread by a random delay and postponed while
+    /// the load average is above the number of CPUs
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub rescan_interval: Option<Duration>,
+
+    /// hash the content of the written files, the digest is added to the printed
+    /// `CLOSE_WRITE` events, and recorded for every file by `tube snapshot`, so
+    /// `tube diff` only reports the files whose content changed
+    #[arg(long, value_enum, value_name = "ALGORITHM")]
+    pub hash: Option<Algorithm>,
+
+    /// drop the `CLOSE_WRITE` events of files whose content didn't change since their
+    /// last event, e.g. after a `touch` or a rewrite with the same content, in every
+    /// mode, so commands aren't run again for them
+    #[arg(long, requires = "hash")]
+    pub skip_unchanged: bool,
+}
+
+impl ExecArgs {
+    pub fn jobs(&self) -> usize {
+        match self.serial {
+            true => 1,
+            false => self.jobs.max(1),
+        }
+    }
+}
+
+impl WatchArgs {
+    /// returns all the paths to watch, positional and from `--watch`
+    pub fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        let from = self.paths_from.iter().chain(self.paths_from0.iter());
+        self.paths
+            .iter()
+            .chain(self.watch.iter())
+            .chain(from.flat_map(|list| list.0.iter()))
+    }
+
+    /// returns the combined mask of all the requested events, if no event
+    /// was requested the preset events (or the default events) are used
+    pub fn mask(&self) -> u32 {
+        match (self.events.is_empty(), self.preset) {
+            (false, _) => self.events.iter().fold(0, |mask, event| mask | event),
+            (true, Some(_)) => PRESET_EVENTS,
+            (true, None) => DEFAULT_EVENTS,
+        }
+    }
+
+    pub fn is_recursive(&self) -> bool {
+        self.recursive || self.max_depth.is_some() || self.min_depth.is_some()
+    }
+
+    /// the depths watched below the paths, only the entries of the paths without `--recursive`
+    pub fn depth(&self) -> Depth {
+        if !self.is_recursive() {
+            return Depth::any().max_depth(1);
+        }
+        Depth {
+            min: self.min_depth.unwrap_or(0),
+            max: self.max_depth,
+        }
+    }
+
+    /// checks if the event was requested by the user, the kernel may report
+    /// events that are not part of the mask (e.g. `CREATE` for recursive watches)
+    pub fn matches(&self, event: &Event) -> bool {
+        // the events of a pattern are reported whatever was asked for
+        if event.kind.mask() & self.mask() == 0 && event.kind != EventKind::FileReady {
+            return false;
+        }
+        self.hidden || !is_hidden(event)
+    }
+}
+
+/// paths read by `--paths-from`, read while parsing so stdin is only consumed once
+#[derive(Debug, Clone, Default)]
+pub struct PathList(pub Vec<PathBuf>);
+
+fn parse_paths_from(source: &str) -> Result<PathList, String> {
+    read_paths(source, b'\n')
+}
+
+fn parse_paths_from0(source: &str) -> Result<PathList, String> {
+    read_paths(source, b'\0')
+}
+
+fn read_paths(source: &str, delimiter: u8) -> Result<PathList, String> {
+    let content = match source {
+        "-" => {
+            let mut content = Vec::new();
+            std::io::stdin().read_to_end(&mut content).map(|_| content)
+        }
+        path => std::fs::read(path),
+    }
+    .map_err(|e| format!("couldn't read `{}`: {}", source, e))?;
+
+    let paths = content
+        .split(|b| *b == delimiter)
+        .filter(|path| !path.is_empty())
+        .map(|path| PathBuf::from(OsStr::from_bytes(path)))
+        .collect();
+    Ok(PathList(paths))
+}
+
+fn parse_listen(addr: &str) -> Result<SocketAddr, String> {
+    let addr = match addr.strip_prefix(':') {
+        Some(port) => format!("0.0.0.0:{}", port),
+        None => addr.to_string(),
+    };
+    addr.parse()
+        .map_err(|_| format!("invalid address `{}`", addr))
+}
+
+fn parse_time(time: &str) -> Result<SystemTime, String> {
+    if let Ok(time) = humantime::parse_rfc3339_weak(time) {
+        return Ok(time);
+    }
+    humantime::parse_duration(time)
+        .map(|ago| SystemTime::now() - ago)
+        .map_err(|_| format!("invalid time `{}`", time))
+}
+
+fn parse_speed(speed: &str) -> Result<f64, String> {
+    if speed == "max" {
+        return Ok(f64::INFINITY);
+    }
+    match speed.strip_suffix('x').unwrap_or(speed).parse::<f64>() {
+        Ok(speed) if speed > 0.0 => Ok(speed),
+        _ => Err(format!("invalid speed `{}`", speed)),
+    }
+}
+
+fn is_hidden(event: &Event) -> bool {
+    event
+        .path
+        .file_name()
+        .is_some_and(|name| name.as_encoded_bytes().first() == Some(&b'.'))
+}
+
+/// parses a single event name given to `--events` into its mask
+pub fn parse_event(name: &str) -> Result<u32, String> {
+    let mask = match name.trim().to_lowercase().as_str() {
+        "access" => Mask::ACCESS,
+        "modify" => Mask::MODIFY,
+        "attrib" => Mask::ATTRIB,
+        "open" => Mask::OPEN,
+        "close" => Mask::CLOSE,
+        "close_write" => Mask::CLOSE_WRITE,
+        "close_nowrite" => Mask::CLOSE_NOWRITE,
+        "create" => Mask::CREATE,
+        "delete" => Mask::DELETE,
+        "delete_self" => Mask::DELETE_SELF,
+        "move" => Mask::MOVE,
+        "moved_from" => Mask::MOVED_FROM,
+        "moved_to" => Mask::MOVED_TO,
+        "move_self" => Mask::MOVE_SELF,
+        "all" => Mask::ALL_EVENTS,
+        _ => return Err(format!("unknown event `{}`", name)),
+    };
+    Ok(mask)
+}
+
+/// parses a signal name (with or without the `SIG` prefix) into its number
+fn parse_signal(name: &str) -> Result<i32, String> {
+    let upper = name.to_uppercase();
+    let signal = match upper.strip_prefix("SIG").unwrap_or(&upper) {
+        "TERM" => libc::SIGTERM,
+        "INT" => libc::SIGINT,
+        "KILL" => libc::SIGKILL,
+        "HUP" => libc::SIGHUP,
+        "QUIT" => libc::SIGQUIT,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        _ => return Err(format!("unknown signal `{}`", name)),
+    };
+    Ok(signal)
+}
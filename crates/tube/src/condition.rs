@@ -0,0 +1,43 @@
+use tube_inotify::Event;
+
+use crate::cli::{ConditionArgs, WatchArgs};
+use crate::diff::{self, Differ};
+use crate::watcher::Matcher;
+
+/// decides from the content of the event file if the command runs for the event,
+/// for `--if-contains` and `--if-changed-lines`
+pub struct Conditions<'a> {
+    args: &'a ConditionArgs,
+    // the cached copies the changed lines are found with
+    differ: Option<Differ>,
+}
+
+impl<'a> Conditions<'a> {
+    pub fn new(args: &'a ConditionArgs, watch: &WatchArgs) -> anyhow::Result<Self> {
+        let differ = match args.if_changed_lines {
+            Some(_) => Some(Differ::new(&Matcher::new(watch)?)),
+            None => None,
+        };
+        Ok(Self { args, differ })
+    }
+
+    /// checks the event file against the conditions, the cached copy of the file
+    /// is updated for every event, even the ones already left out by another condition
+    pub fn allows(&mut self, event: &Event) -> bool {
+        let changed = match (&mut self.differ, &self.args.if_changed_lines) {
+            (Some(differ), Some(regex)) => differ
+                .changed_lines(event)
+                .iter()
+                .any(|line| regex.is_match(line)),
+            _ => true,
+        };
+        let contains = match &self.args.if_contains {
+            Some(regex) => {
+                !event.is_dir
+                    && diff::read_text(&event.path).is_some_and(|content| regex.is_match(&content))
+            }
+            None => true,
+        };
+        changed && contains
+    }
+}
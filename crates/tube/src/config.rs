@@ -0,0 +1,298 @@
+use anyhow::Context;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::process::Command;
+use tube_inotify::Event;
+
+use crate::cli::{parse_event, CaptureArgs, WatchArgs};
+use crate::exec::{self, OnBusy};
+use crate::hash::Algorithm;
+use crate::ignore::Preset;
+use crate::rate::Rate;
+use crate::watcher::Pattern;
+
+/// the configuration file, declaring multiple independent watch rules
+///
+/// ```toml
+/// [[rule]]
+/// name = "build"
+/// paths = ["src"]
+/// events = ["create", "modify", "delete"]
+/// recursive = true
+/// include = ["*.rs"]
+/// exclude = ["target/**"]
+/// debounce = "500ms"
+/// max_rate = "10/min"
+/// command = ["cargo", "build"]
+/// cwd = "."
+///
+/// # the output of every build in its own file
+/// log_dir = "logs/build"
+///
+/// [rule.env]
+/// RUSTFLAGS = "-D warnings"
+///
+/// # runs after every successful build, instead of watching paths
+/// [[rule]]
+/// name = "deploy"
+/// on = "rule:build"
+/// command = ["./deploy.sh"]
+/// # never runs at the same time as the other rules of the group
+/// group = "deploy"
+/// on_busy = "drop"
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(rename = "rule", default)]
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// name of the rule, used in logs, defaults to the rule index
+    pub name: Option<String>,
+    #[serde(default)]
+    pub paths: Vec<PathBuf>,
+    /// `rule:NAME` runs the command after every command the other rule ran
+    /// successfully, for the same event, in place of watching paths
+    pub on: Option<String>,
+    /// defaults to the preset events, or `create,modify,delete,move`
+    #[serde(default)]
+    pub events: Vec<String>,
+    #[serde(default)]
+    pub recursive: bool,
+    pub max_depth: Option<usize>,
+    pub min_depth: Option<usize>,
+    #[serde(default)]
+    pub hidden: bool,
+    pub preset: Option<Preset>,
+    #[serde(default = "default_gitignore")]
+    pub gitignore: bool,
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// events are collected until no new event arrived for the duration
+    /// of the window, the command then runs once per changed path
+    #[serde(default, with = "humantime_serde")]
+    pub debounce: Option<Duration>,
+    /// scans the paths again in the background, see `--rescan-interval`
+    #[serde(default, with = "humantime_serde")]
+    pub rescan_interval: Option<Duration>,
+    /// reports the events of uploads as a single `FILE_READY`, see `--pattern`
+    pub pattern: Option<Pattern>,
+    /// events over the rate are neither delivered to the sinks nor run the command
+    pub max_rate: Option<Rate>,
+    /// the digest compared by `skip_unchanged`
+    pub hash: Option<Algorithm>,
+    /// files rewritten with the same content don't run the command, see `--skip-unchanged`
+    #[serde(default)]
+    pub skip_unchanged: bool,
+    pub command: Vec<String>,
+    /// directory the command runs in, tube's own by default
+    pub cwd: Option<PathBuf>,
+    /// runs the command through a shell instead of directly, e.g. `/bin/sh -c`
+    /// or `fish -c`, the command is then a single script, the event values are
+    /// only available as `TUBE_PATH`, `TUBE_EVENT`, `TUBE_DIR` and `TUBE_NAME`
+    pub shell: Option<String>,
+    /// variables added to the environment of the command
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// starts every line the command prints with `[PREFIX] `, the name of the
+    /// rule by default, an empty prefix leaves the output as it is
+    pub prefix: Option<String>,
+    /// also writes the output of every run of the command to its own file
+    /// in the directory, see `--log-dir`
+    pub log_dir: Option<PathBuf>,
+    /// how many log files are kept in `log_dir`, 100 by default
+    pub log_keep: Option<usize>,
+    /// the commands of the rules in the same group never run at the same time,
+    /// the groups run in parallel
+    pub group: Option<String>,
+    /// what happens to the events arriving while a command of the group runs,
+    /// `queue` (the default) or `drop`
+    pub on_busy: Option<OnBusy>,
+}
+
+fn default_gitignore() -> bool {
+    true
+}
+
+impl Config {
+    /// reads and validates the configuration file in the given path
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("couldn't read config `{}`", path.display()))?;
+        let mut config: Self = toml::from_str(&content)
+            .with_context(|| format!("invalid config `{}`", path.display()))?;
+
+        if config.rules.is_empty() {
+            anyhow::bail!("config `{}` doesn't define any rule", path.display());
+        }
+        // the rules are told apart by name, e.g. when the file is reloaded
+        let mut names = HashSet::new();
+        for (i, rule) in config.rules.iter_mut().enumerate() {
+            let name = rule.name.get_or_insert_with(|| i.to_string()).clone();
+            if !names.insert(name.clone()) {
+                anyhow::bail!("rule `{}` is defined more than once", name);
+            }
+            match (&rule.on, rule.paths.is_empty()) {
+                (None, true) => anyhow::bail!("rule `{}` doesn't define any path", name),
+                (Some(_), false) => anyhow::bail!("rule `{}` defines both `on` and paths", name),
+                (Some(on), true) if rule.upstream().is_none() => {
+                    anyhow::bail!(
+                        "rule `{}`: expected `on = \"rule:NAME\"`, got `{}`",
+                        name,
+                        on
+                    )
+                }
+                _ => {}
+            }
+            if rule.command.is_empty() {
+                anyhow::bail!("rule `{}` doesn't define a command", name);
+            }
+            match (&rule.group, rule.on_busy) {
+                (None, Some(_)) => anyhow::bail!("rule `{}`: `on_busy` needs a `group`", name),
+                (_, Some(OnBusy::Restart)) => {
+                    anyhow::bail!("rule `{}`: `on_busy` is `queue` or `drop`", name)
+                }
+                _ => {}
+            }
+            if rule.skip_unchanged && rule.hash.is_none() {
+                anyhow::bail!("rule `{}`: `skip_unchanged` needs a `hash`", name);
+            }
+            if rule
+                .shell
+                .as_deref()
+                .is_some_and(|shell| shell.trim().is_empty())
+            {
+                anyhow::bail!("rule `{}` has an empty shell", name);
+            }
+            if rule.shell.is_some() && rule.command.len() > 1 {
+                anyhow::bail!(
+                    "rule `{}`: with a `shell` the command is a single script",
+                    name
+                );
+            }
+            if let Some(cwd) = &rule.cwd {
+                if !cwd.is_dir() {
+                    anyhow::bail!("rule `{}`: `{}` is not a directory", name, cwd.display());
+                }
+            }
+            rule.watch_args()
+                .with_context(|| format!("invalid rule `{}`", name))?;
+        }
+        config.check_chains()?;
+        Ok(config)
+    }
+
+    /// the rules other rules run after exist, and no rule ends up running after itself
+    fn check_chains(&self) -> anyhow::Result<()> {
+        let upstream = |name: &str| {
+            self.rules
+                .iter()
+                .find(|rule| rule.name() == name)
+                .map(Rule::upstream)
+        };
+        for rule in &self.rules {
+            let mut next = rule.upstream();
+            let mut steps = 0;
+            while let Some(name) = next {
+                next = match upstream(name) {
+                    Some(next) => next,
+                    None => {
+                        anyhow::bail!("rule `{}` runs after unknown rule `{}`", rule.name(), name)
+                    }
+                };
+                steps += 1;
+                if steps > self.rules.len() {
+                    anyhow::bail!("rule `{}` runs after a loop of rules", rule.name());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Rule {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or_default()
+    }
+
+    /// the rule this one runs after, for `on = "rule:NAME"`
+    pub fn upstream(&self) -> Option<&str> {
+        self.on
+            .as_deref()?
+            .strip_prefix("rule:")
+            .filter(|name| !name.is_empty())
+    }
+
+    /// builds the command to run for the event, in the rule working
+    /// directory, shell and environment
+    pub fn command(&self, event: &Event) -> Command {
+        let mut cmd = match &self.shell {
+            Some(shell) => {
+                let shell: Vec<&str> = shell.split_whitespace().collect();
+                exec::shell_command(&shell, &self.command[0], event)
+            }
+            None => exec::command(&self.command, event),
+        };
+        if let Some(cwd) = &self.cwd {
+            cmd.current_dir(cwd);
+        }
+        cmd.envs(&self.env);
+        cmd
+    }
+
+    /// what happens to the output of the command
+    pub fn capture_args(&self) -> CaptureArgs {
+        let prefix = self.prefix.as_deref().unwrap_or(self.name());
+        CaptureArgs {
+            prefix: Some(prefix.to_string()).filter(|prefix| !prefix.is_empty()),
+            log_dir: self.log_dir.clone(),
+            log_keep: self.log_keep.unwrap_or(100),
+        }
+    }
+
+    /// converts the rule into the arguments used to open a watcher
+    pub fn watch_args(&self) -> anyhow::Result<WatchArgs> {
+        let events = self
+            .events
+            .iter()
+            .map(|event| parse_event(event).map_err(anyhow::Error::msg))
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(WatchArgs {
+            paths: self.paths.clone(),
+            watch: Vec::new(),
+            paths_from: None,
+            paths_from0: None,
+            recursive: self.recursive,
+            max_depth: self.max_depth,
+            min_depth: self.min_depth,
+            events,
+            hidden: self.hidden,
+            preset: self.preset,
+            no_gitignore: !self.gitignore,
+            // the rule filter is applied by the rule itself
+            include: Vec::new(),
+            exclude: Vec::new(),
+            ignore_file: None,
+            attribute: false,
+            symlinks: Default::default(),
+            meta_changes: false,
+            inodes: false,
+            recover_overflow: false,
+            scan_new_dirs: false,
+            pattern: self.pattern,
+            settle: None,
+            rescan_interval: self.rescan_interval,
+            hash: self.hash,
+            skip_unchanged: self.skip_unchanged,
+        })
+    }
+}
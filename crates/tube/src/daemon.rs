@@ -0,0 +1,221 @@
+use anyhow::Context;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::unix::AsyncFd;
+use tokio::signal::unix::{signal, SignalKind};
+use tube_inotify::{Flag, Inotify, Mask};
+
+use crate::cli::DaemonArgs;
+use crate::config::Config;
+use crate::rule::Supervisor;
+use crate::sink::Sinks;
+use crate::systemd::{self, Watchdog};
+
+/// how long to wait for the rest of a save before reloading the config
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// loads the configuration and checks the pidfile, then detaches the process
+/// unless `--foreground` is given, has to be called before the async runtime
+/// is started since forking only keeps the calling thread. the errors are
+/// reported before detaching, they would be lost and the parent would have
+/// exited successfully after it
+pub fn prepare(args: &DaemonArgs) -> anyhow::Result<Config> {
+    let config = Config::load(&args.config)?;
+    if let Some(path) = &args.pidfile {
+        Pidfile::check(path)?;
+    }
+    if !args.foreground {
+        detach(args.log_file.as_deref())?;
+    }
+    Ok(config)
+}
+
+/// detaches the process from the terminal with the usual double fork
+fn detach(log_file: Option<&Path>) -> anyhow::Result<()> {
+    let null = File::open("/dev/null")?;
+    let out = match log_file {
+        Some(path) => OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("couldn't open log file `{}`", path.display()))?,
+        None => OpenOptions::new().write(true).open("/dev/null")?,
+    };
+
+    fork().context("couldn't fork")?;
+    if unsafe { libc::setsid() } == -1 {
+        return Err(std::io::Error::last_os_error()).context("couldn't create session");
+    }
+    fork().context("couldn't fork")?;
+    unsafe {
+        libc::dup2(null.as_raw_fd(), libc::STDIN_FILENO);
+        libc::dup2(out.as_raw_fd(), libc::STDOUT_FILENO);
+        libc::dup2(out.as_raw_fd(), libc::STDERR_FILENO);
+    }
+    Ok(())
+}
+
+/// forks the process, the parent exits right away
+fn fork() -> std::io::Result<()> {
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error()),
+        0 => Ok(()),
+        _ => std::process::exit(0),
+    }
+}
+
+/// runs the configuration rules until SIGTERM or SIGINT, reloading
+/// the configuration on SIGHUP and whenever the file changes, `config`
+/// is the one `prepare` loaded
+pub async fn run(args: DaemonArgs, config: Config) -> anyhow::Result<()> {
+    let _pidfile = args.pidfile.as_deref().map(Pidfile::create).transpose()?;
+    let sinks = Sinks::open(&args.sinks)?;
+    let mut supervisor = Supervisor::start(config, sinks.sender(), args.state.clone())?;
+    let mut config_watch = match args.no_watch_config {
+        true => None,
+        false => Some(ConfigWatch::new(&args.config)?),
+    };
+    let mut watchdog = Watchdog::new();
+    systemd::notify("READY=1");
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => reload(&mut supervisor, &args.config).await,
+            changed = ConfigWatch::changed(config_watch.as_mut()) => {
+                if let Err(e) = changed {
+                    tracing::error!("stopped watching the config: {:#}", e);
+                    config_watch = None;
+                    continue;
+                }
+                tracing::debug!("`{}` changed", args.config.display());
+                reload(&mut supervisor, &args.config).await;
+            }
+            _ = watchdog.tick() => watchdog.pet(),
+            _ = terminate.recv() => break,
+            _ = interrupt.recv() => break,
+        }
+    }
+
+    tracing::info!("shutting down");
+    systemd::notify("STOPPING=1");
+    supervisor.shutdown().await;
+    sinks.close().await;
+    Ok(())
+}
+
+async fn reload(supervisor: &mut Supervisor, path: &Path) {
+    systemd::notify("RELOADING=1");
+    let result = match Config::load(path) {
+        Ok(config) => supervisor.reload(config).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(changes) => tracing::info!("config reloaded, {}", changes),
+        Err(e) => tracing::warn!("couldn't reload config, keeping the current one: {:#}", e),
+    }
+    systemd::notify("READY=1");
+}
+
+/// watches the configuration file, through its directory since editors
+/// usually save by replacing the file rather than writing to it
+struct ConfigWatch {
+    inotify: AsyncFd<Inotify>,
+    name: OsString,
+}
+
+impl ConfigWatch {
+    fn new(path: &Path) -> anyhow::Result<Self> {
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("couldn't resolve `{}`", path.display()))?;
+        let name = path.file_name().context("config is not a file")?.to_owned();
+        let dir = path.parent().context("config is not a file")?.to_path_buf();
+        let inotify = Inotify::with_flags(Flag::NONBLOCKING)?
+            .include_hidden(true)
+            .watch(dir, Mask::CLOSE_WRITE | Mask::MOVED_TO | Mask::CREATE)
+            .context("couldn't watch the config")?;
+        Ok(Self {
+            inotify: AsyncFd::new(inotify)?,
+            name,
+        })
+    }
+
+    /// waits until the file was written or replaced, the events that follow
+    /// shortly after are folded in, so a save only reloads once, never
+    /// returns if the config isn't watched
+    async fn changed(watch: Option<&mut Self>) -> anyhow::Result<()> {
+        let Some(watch) = watch else {
+            return std::future::pending().await;
+        };
+        while !watch.read().await? {}
+        tokio::time::sleep(SETTLE).await;
+        while watch.try_read()?.is_some() {}
+        Ok(())
+    }
+
+    /// waits for the next events, returns true if one was about the config file
+    async fn read(&mut self) -> anyhow::Result<bool> {
+        loop {
+            if let Some(changed) = self.try_read()? {
+                return Ok(changed);
+            }
+            self.inotify.readable_mut().await?.clear_ready();
+        }
+    }
+
+    fn try_read(&mut self) -> anyhow::Result<Option<bool>> {
+        let inotify = self.inotify.get_mut();
+        let Some(batch) = inotify.try_read()? else {
+            return Ok(None);
+        };
+        let changed = batch
+            .filter_map(|event| inotify.resolve(&event))
+            .any(|event| event.path.file_name() == Some(&self.name));
+        Ok(Some(changed))
+    }
+}
+
+/// a file holding the daemon pid, removed when dropped
+struct Pidfile(PathBuf);
+
+impl Pidfile {
+    /// fails if the pid in the file is running, or the file can't be written
+    fn check(path: &Path) -> anyhow::Result<()> {
+        if let Some(pid) = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|pid| pid.trim().parse::<i32>().ok())
+        {
+            if unsafe { libc::kill(pid, 0) } == 0 {
+                anyhow::bail!("daemon is already running with pid {}", pid);
+            }
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("couldn't create pidfile `{}`", path.display()))?;
+        Ok(())
+    }
+
+    fn create(path: &Path) -> anyhow::Result<Self> {
+        Self::check(path)?;
+        let mut file = File::create(path)
+            .with_context(|| format!("couldn't create pidfile `{}`", path.display()))?;
+        writeln!(file, "{}", std::process::id())?;
+        Ok(Self(path.to_path_buf()))
+    }
+}
+
+impl Drop for Pidfile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use tube_inotify::Event;
+
+use crate::watcher::Batches;
+
+/// receives the next batch that has at least one event passing the filter,
+/// when a window is given batches keep being collected until no batch arrived
+/// for the whole window, and only the last event of every path is kept.
+/// returns `None` once the watcher stopped
+pub async fn next<F>(
+    batches: &mut Batches,
+    window: Option<Duration>,
+    filter: F,
+) -> Option<anyhow::Result<Vec<Event>>>
+where
+    F: Fn(&Event) -> bool,
+{
+    loop {
+        let events: Vec<Event> = match batches.recv().await? {
+            Ok(events) => events.into_iter().filter(|e| filter(e)).collect(),
+            Err(e) => return Some(Err(e)),
+        };
+        if events.is_empty() {
+            continue;
+        }
+
+        let Some(window) = window else {
+            return Some(Ok(events));
+        };
+
+        let mut pending = Pending::default();
+        pending.extend(events);
+        while let Ok(Some(batch)) = tokio::time::timeout(window, batches.recv()).await {
+            match batch {
+                Ok(events) => pending.extend(events.into_iter().filter(|e| filter(e))),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        return Some(Ok(pending.events));
+    }
+}
+
+/// events waiting for the debounce window to end, only
+/// the last event of every path is kept
+#[derive(Default)]
+struct Pending {
+    events: Vec<Event>,
+    index: HashMap<PathBuf, usize>,
+}
+
+impl Pending {
+    fn extend(&mut self, events: impl IntoIterator<Item = Event>) {
+        for event in events {
+            match self.index.get(&event.path) {
+                Some(&i) => self.events[i] = event,
+                None => {
+                    self.index.insert(event.path.clone(), self.events.len());
+                    self.events.push(event);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use similar::{ChangeTag, TextDiff};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tube_inotify::{Event, EventKind};
+
+use crate::watcher::Matcher;
+
+/// files bigger than this are not cached, nor diffed
+const MAX_SIZE: u64 = 1024 * 1024;
+
+/// keeps the content of the watched text files, so the changes written
+/// to them can be shown as a unified diff, for `--diff`
+pub struct Differ {
+    contents: HashMap<PathBuf, String>,
+}
+
+impl Differ {
+    /// caches the text files that already exist under the watched paths,
+    /// so the first change to them has something to be compared to
+    pub fn new(matcher: &Matcher) -> Self {
+        let contents = matcher
+            .files()
+            .into_iter()
+            .filter_map(|path| {
+                let content = read_text(&path)?;
+                Some((path, content))
+            })
+            .collect();
+        Self { contents }
+    }
+
+    /// updates the cached copy of the event file, returns the diff against
+    /// the previous copy when the content changed
+    pub fn update(&mut self, event: &Event) -> Option<String> {
+        let previous = self.swap(event)?;
+        let content = &self.contents[&event.path];
+        let path = event.path.to_string_lossy();
+        let diff = TextDiff::from_lines(&previous, content)
+            .unified_diff()
+            .header(&path, &path)
+            .to_string();
+        Some(diff)
+    }
+
+    /// same as `update` but returns the lines that were added or removed
+    pub fn changed_lines(&mut self, event: &Event) -> Vec<String> {
+        let Some(previous) = self.swap(event) else {
+            return Vec::new();
+        };
+        let content = &self.contents[&event.path];
+        TextDiff::from_lines(&previous, content)
+            .iter_all_changes()
+            .filter(|change| change.tag() != ChangeTag::Equal)
+            .map(|change| change.value().trim_end_matches('\n').to_string())
+            .collect()
+    }
+
+    /// caches the content the event file has now, returns the previous copy
+    /// when the content changed
+    fn swap(&mut self, event: &Event) -> Option<String> {
+        if event.is_dir {
+            return None;
+        }
+        match event.kind {
+            // `MOVED_TO` is how editors that write to a temporary file and rename it save
+            EventKind::Modify
+            | EventKind::CloseWrite
+            | EventKind::MovedTo
+            | EventKind::FileReady => {}
+            EventKind::Create => {
+                if let Some(content) = read_text(&event.path) {
+                    self.contents.insert(event.path.clone(), content);
+                }
+                return None;
+            }
+            EventKind::Delete | EventKind::MovedFrom => {
+                self.contents.remove(&event.path);
+                return None;
+            }
+            _ => return None,
+        }
+
+        let Some(content) = read_text(&event.path) else {
+            // binary now, or gone already
+            self.contents.remove(&event.path);
+            return None;
+        };
+        let previous = self.contents.insert(event.path.clone(), content)?;
+        (previous != self.contents[&event.path]).then_some(previous)
+    }
+}
+
+/// reads the file if it is small enough and looks like text
+pub fn read_text(path: &Path) -> Option<String> {
+    let metadata = fs::metadata(path).ok()?;
+    if !metadata.is_file() || metadata.len() > MAX_SIZE {
+        return None;
+    }
+    let content = String::from_utf8(fs::read(path).ok()?).ok()?;
+    (!content.contains('\0')).then_some(content)
+}
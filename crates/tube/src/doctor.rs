@@ -0,0 +1,223 @@
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::cli::DoctorArgs;
+use crate::limits::Limits;
+
+/// filesystems where changes made by other hosts are not reported
+const NETWORK_FILESYSTEMS: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "9p",
+    "ceph",
+    "glusterfs",
+    "afs",
+    "lustre",
+    "gpfs",
+    "fuse.sshfs",
+    "fuse.rclone",
+    "vboxsf",
+];
+
+/// inotify usage of a single process
+struct Usage {
+    pid: u32,
+    name: String,
+    instances: u64,
+    watches: u64,
+}
+
+/// prints the kernel limits, the usage of the current user, and checks
+/// the paths can be watched, ends with suggestions for the problems found
+pub fn run(args: DoctorArgs) -> anyhow::Result<()> {
+    let limits = Limits::read();
+    let mut suggestions = Vec::new();
+
+    println!("kernel limits");
+    println!("  max_user_watches    {}", show(limits.max_user_watches));
+    println!("  max_user_instances  {}", show(limits.max_user_instances));
+    println!("  max_queued_events   {}", show(limits.max_queued_events));
+
+    let usage = usage();
+    let instances: u64 = usage.iter().map(|u| u.instances).sum();
+    let watches: u64 = usage.iter().map(|u| u.watches).sum();
+    println!();
+    println!("usage of uid {}", unsafe { libc::getuid() });
+    println!(
+        "  instances  {} / {}",
+        instances,
+        show(limits.max_user_instances)
+    );
+    println!(
+        "  watches    {} / {}",
+        watches,
+        show(limits.max_user_watches)
+    );
+    for process in &usage {
+        println!(
+            "    {:>7} {:<16} {} instances, {} watches",
+            process.pid, process.name, process.instances, process.watches
+        );
+    }
+    if limits
+        .max_user_instances
+        .is_some_and(|max| instances + 1 > max)
+    {
+        suggestions.push(format!(
+            "no inotify instance is left, raise the limit with `sysctl fs.inotify.max_user_instances={}`",
+            instances * 2
+        ));
+    }
+
+    let mut needed = 0;
+    if !args.paths.is_empty() {
+        println!();
+        println!("paths");
+    }
+    for path in &args.paths {
+        let path = match path.canonicalize() {
+            Ok(path) => path,
+            Err(e) => {
+                println!("  {}  {}", path.display(), e);
+                continue;
+            }
+        };
+        let fstype = filesystem(&path).unwrap_or_else(|| "unknown".to_string());
+        let dirs = if args.recursive && path.is_dir() {
+            count_dirs(&path)
+        } else {
+            1
+        };
+        needed += dirs;
+        println!("  {}  {}, {} watches needed", path.display(), fstype, dirs);
+
+        if NETWORK_FILESYSTEMS.contains(&fstype.as_str()) {
+            suggestions.push(format!(
+                "`{}` is on {}, changes made by other hosts are not reported, consider polling it instead",
+                path.display(),
+                fstype
+            ));
+        } else if fstype.starts_with("fuse") {
+            suggestions.push(format!(
+                "`{}` is on {}, only changes made through this mount may be reported",
+                path.display(),
+                fstype
+            ));
+        }
+    }
+    if let Some(max) = limits.max_user_watches {
+        if watches + needed > max {
+            suggestions.push(format!(
+                "not enough watches are left, raise the limit with `sysctl fs.inotify.max_user_watches={}`",
+                ((watches + needed) * 2).next_power_of_two()
+            ));
+        }
+    }
+
+    println!();
+    if suggestions.is_empty() {
+        println!("no problems found");
+    } else {
+        println!("suggestions");
+        for suggestion in suggestions {
+            println!("  - {}", suggestion);
+        }
+    }
+    Ok(())
+}
+
+fn show(limit: Option<u64>) -> String {
+    limit.map_or_else(|| "?".to_string(), |limit| limit.to_string())
+}
+
+/// goes over the processes of the current user and counts their inotify
+/// instances and watches, processes that can't be read are skipped
+fn usage() -> Vec<Usage> {
+    let uid = unsafe { libc::getuid() };
+    let mut usage = Vec::new();
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return usage;
+    };
+    for entry in entries.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|s| s.parse().ok()) else {
+            continue;
+        };
+        if !entry.metadata().is_ok_and(|m| m.uid() == uid) {
+            continue;
+        }
+        let Ok(fds) = fs::read_dir(entry.path().join("fd")) else {
+            continue;
+        };
+
+        let mut process = Usage {
+            pid,
+            name: fs::read_to_string(entry.path().join("comm"))
+                .map(|name| name.trim().to_string())
+                .unwrap_or_default(),
+            instances: 0,
+            watches: 0,
+        };
+        for fd in fds.flatten() {
+            if fs::read_link(fd.path()).is_ok_and(|link| link == Path::new("anon_inode:inotify")) {
+                process.instances += 1;
+                let info = entry.path().join("fdinfo").join(fd.file_name());
+                process.watches += fs::read_to_string(info)
+                    .map(|info| {
+                        info.lines()
+                            .filter(|l| l.starts_with("inotify wd:"))
+                            .count()
+                    })
+                    .unwrap_or(0) as u64;
+            }
+        }
+        if process.instances > 0 {
+            usage.push(process);
+        }
+    }
+    usage.sort_by_key(|u| std::cmp::Reverse(u.watches));
+    usage
+}
+
+/// returns the filesystem type of the mount the path is on
+fn filesystem(path: &Path) -> Option<String> {
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo").ok()?;
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            // fields after the `-` separator are the type and the source
+            let (mount, fs) = line.split_once(" - ")?;
+            let point = PathBuf::from(unescape(mount.split(' ').nth(4)?));
+            let fstype = fs.split(' ').next()?;
+            Some((point, fstype.to_string()))
+        })
+        .filter(|(point, _)| path.starts_with(point))
+        .max_by_key(|(point, _)| point.components().count())
+        .map(|(_, fstype)| fstype)
+}
+
+/// mountinfo escapes spaces, tabs, newlines and backslashes as octal
+fn unescape(field: &str) -> String {
+    field
+        .replace("\\040", " ")
+        .replace("\\011", "\t")
+        .replace("\\012", "\n")
+        .replace("\\134", "\\")
+}
+
+/// counts the directories in the tree, the number of watches a recursive watch takes
+fn count_dirs(root: &Path) -> u64 {
+    let mut count = 1;
+    let Ok(entries) = fs::read_dir(root) else {
+        return count;
+    };
+    for entry in entries.flatten() {
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            count += count_dirs(&entry.path());
+        }
+    }
+    count
+}
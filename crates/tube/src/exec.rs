@@ -0,0 +1,373 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::ExitStatus;
+use std::str::FromStr;
+use tokio::process::Command;
+use tokio::task::{Id, JoinSet};
+use tokio_util::sync::CancellationToken;
+use tube_inotify::Event;
+
+use crate::capture::Capture;
+use crate::cli::ExecArgs;
+use crate::condition::Conditions;
+use crate::rate::Limiter;
+use crate::sandbox::Sandbox;
+use crate::watcher::Watcher;
+
+/// what happens to events that arrive while all the job slots are busy
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnBusy {
+    /// wait for a slot to free up
+    #[default]
+    Queue,
+    /// discard the event
+    Drop,
+    /// kill the oldest running command and run the new one instead
+    Restart,
+}
+
+/// what happens when a command fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnFailure {
+    /// keep handling the next events
+    Ignore,
+    /// run the command again for the same event up to N more times
+    Retry(u32),
+    /// stop tube, with the exit code of the command
+    Stop,
+}
+
+impl FromStr for OnFailure {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(Self::Ignore),
+            "stop" => Ok(Self::Stop),
+            _ => s
+                .strip_prefix("retry:")
+                .and_then(|n| n.parse().ok())
+                .map(Self::Retry)
+                .ok_or_else(|| format!("expected `ignore`, `retry:N` or `stop`, got `{}`", s)),
+        }
+    }
+}
+
+/// exit code reported for commands that couldn't be started, same as the shell
+const NOT_FOUND_EXIT_CODE: i32 = 127;
+
+/// runs the command from the arguments for every matching event, up to `--jobs`
+/// commands run at the same time, events are handled in the order they arrived
+pub async fn run(args: ExecArgs) -> anyhow::Result<()> {
+    let mut batches = Watcher::open(&args.watch)?.spawn();
+    let mut pool = Pool {
+        args: &args,
+        sandbox: Sandbox::new(&args.sandbox)?,
+        capture: Capture::new(&args.capture)?,
+        jobs: JoinSet::new(),
+        running: VecDeque::new(),
+        pending: VecDeque::new(),
+        limiter: args.max_rate.map(|rate| Limiter::new("exec", rate)),
+        conditions: Conditions::new(&args.conditions, &args.watch)?,
+    };
+
+    if args.init {
+        if args.clear {
+            clear_screen();
+        }
+        let mut cmd = startup_command(&args.command);
+        pool.sandbox.apply(&mut cmd);
+        let status = pool.capture.status(&mut cmd, &args.command.join(" ")).await;
+        match status {
+            Ok(status) if !status.success() => {
+                tracing::warn!("`{}` exited with {}", args.command[0], status);
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("couldn't run `{}`: {}", args.command[0], e),
+        }
+    }
+
+    loop {
+        tokio::select! {
+            events = batches.recv() => match events {
+                Some(events) => {
+                    for event in events? {
+                        pool.push(event);
+                    }
+                }
+                None => break,
+            },
+            Some(finished) = pool.jobs.join_next_with_id() => {
+                if let Ok((id, job)) = finished {
+                    pool.finished(id, job).await;
+                }
+                pool.start_pending();
+            }
+        }
+    }
+
+    // the watcher is gone, finish what was already started and queued
+    while let Some(finished) = pool.jobs.join_next_with_id().await {
+        if let Ok((id, job)) = finished {
+            pool.finished(id, job).await;
+        }
+        pool.start_pending();
+    }
+    Ok(())
+}
+
+/// the running commands and the events waiting for a free slot
+struct Pool<'a> {
+    args: &'a ExecArgs,
+    sandbox: Sandbox,
+    capture: Capture,
+    jobs: JoinSet<Job>,
+    // running jobs, oldest first, their token kills the command
+    running: VecDeque<(Id, CancellationToken)>,
+    pending: VecDeque<Event>,
+    limiter: Option<Limiter>,
+    conditions: Conditions<'a>,
+}
+
+/// a finished command, `status` is `None` if it was killed
+struct Job {
+    event: Event,
+    attempt: u32,
+    status: io::Result<Option<ExitStatus>>,
+}
+
+impl Pool<'_> {
+    fn push(&mut self, event: Event) {
+        if !self.conditions.allows(&event) {
+            return;
+        }
+        if !self.limiter.as_mut().is_none_or(|limiter| limiter.allow()) {
+            return;
+        }
+        if self.running.len() < self.args.jobs() {
+            return self.start(event, 0);
+        }
+        match self.args.on_busy {
+            OnBusy::Queue => {
+                if self.args.collapse {
+                    self.pending.retain(|pending| pending.path != event.path);
+                }
+                self.pending.push_back(event);
+            }
+            OnBusy::Drop => {}
+            OnBusy::Restart => {
+                if let Some((_, token)) = self.running.pop_front() {
+                    token.cancel();
+                }
+                self.start(event, 0);
+            }
+        }
+    }
+
+    fn start(&mut self, event: Event, attempt: u32) {
+        if self.args.clear {
+            clear_screen();
+        }
+        tracing::debug!(
+            "running `{}` for {} {}",
+            self.args.command[0],
+            event.kind,
+            event.path.display()
+        );
+        let mut cmd = command(&self.args.command, &event);
+        self.sandbox.apply(&mut cmd);
+        self.capture.prepare(&mut cmd);
+        let capture = self.capture.clone();
+        let title = format!(
+            "{} ({} {})",
+            self.args.command.join(" "),
+            event.kind,
+            event.path.display()
+        );
+        let token = CancellationToken::new();
+        let cancelled = token.clone();
+        let handle = self.jobs.spawn(async move {
+            let status = match cmd.spawn() {
+                Ok(mut child) => {
+                    let output = capture.attach(&mut child, &title);
+                    let status = tokio::select! {
+                        status = child.wait() => status.map(Some),
+                        _ = cancelled.cancelled() => child.kill().await.map(|_| None),
+                    };
+                    output
+                        .finish(status.as_ref().ok().and_then(Option::as_ref))
+                        .await;
+                    status
+                }
+                Err(e) => Err(e),
+            };
+            Job {
+                event,
+                attempt,
+                status,
+            }
+        });
+        self.running.push_back((handle.id(), token));
+    }
+
+    fn start_pending(&mut self) {
+        while self.running.len() < self.args.jobs() {
+            match self.pending.pop_front() {
+                Some(event) => self.start(event, 0),
+                None => break,
+            }
+        }
+    }
+
+    async fn finished(&mut self, id: Id, job: Job) {
+        self.running.retain(|(running, _)| *running != id);
+        let code = match &job.status {
+            Ok(Some(status)) if !status.success() => {
+                tracing::warn!("`{}` exited with {}", self.args.command[0], status);
+                // killed by a signal, reported the way the shell does
+                status
+                    .code()
+                    .unwrap_or_else(|| 128 + status.signal().unwrap_or(0))
+            }
+            // succeeded, or killed by `--on-busy restart`
+            Ok(_) => return,
+            Err(e) => {
+                tracing::warn!("couldn't run `{}`: {}", self.args.command[0], e);
+                NOT_FOUND_EXIT_CODE
+            }
+        };
+
+        match self.args.on_failure {
+            OnFailure::Retry(retries) if job.attempt < retries => {
+                return self.start(job.event, job.attempt + 1);
+            }
+            _ => {}
+        }
+        if let Some(hook) = &self.args.on_failure_cmd {
+            let mut cmd = shell_command(&["sh", "-c"], hook, &job.event);
+            cmd.env("TUBE_EXIT_CODE", code.to_string());
+            self.sandbox.apply(&mut cmd);
+            let status = cmd.status().await;
+            if let Err(e) = status {
+                tracing::warn!("couldn't run the failure hook: {}", e);
+            }
+        }
+        if self.args.on_failure == OnFailure::Stop {
+            for (_, token) in &self.running {
+                token.cancel();
+            }
+            while self.jobs.join_next().await.is_some() {}
+            std::process::exit(code);
+        }
+    }
+}
+
+/// clears the terminal and its scrollback, for `--clear`
+pub fn clear_screen() {
+    print!("\x1b[2J\x1b[3J\x1b[H");
+    let _ = std::io::stdout().flush();
+}
+
+/// builds the command for the given event, substituting the placeholders
+/// in the arguments and exporting the event values to the environment
+pub fn command(template: &[String], event: &Event) -> Command {
+    build(template, Vars::new(event))
+}
+
+/// builds the command for a run that wasn't triggered by an event,
+/// the placeholders and the variables are left empty
+pub fn startup_command(template: &[String]) -> Command {
+    build(template, Vars::default())
+}
+
+/// builds the command running the script through the shell, the event values
+/// are only exported to the environment, they are never part of the script
+pub fn shell_command(shell: &[&str], script: &str, event: &Event) -> Command {
+    let mut cmd = Command::new(shell[0]);
+    cmd.args(&shell[1..]).arg(script);
+    Vars::new(event).export(&mut cmd);
+    cmd
+}
+
+fn build(template: &[String], vars: Vars) -> Command {
+    let mut cmd = Command::new(vars.substitute(&template[0]));
+    cmd.args(template[1..].iter().map(|arg| vars.substitute(arg)));
+    vars.export(&mut cmd);
+    cmd
+}
+
+/// the values available to command templates
+#[derive(Default)]
+struct Vars {
+    path: String,
+    event: String,
+    dir: String,
+    name: String,
+}
+
+impl Vars {
+    fn new(event: &Event) -> Self {
+        let lossy = |p: Option<&Path>| p.map(|p| p.to_string_lossy().into_owned());
+        Self {
+            path: event.path.to_string_lossy().into_owned(),
+            event: event.kind.to_string(),
+            dir: lossy(event.path.parent()).unwrap_or_default(),
+            name: lossy(event.path.file_name().map(Path::new)).unwrap_or_default(),
+        }
+    }
+
+    fn export(&self, cmd: &mut Command) {
+        cmd.env("TUBE_PATH", &self.path)
+            .env("TUBE_EVENT", &self.event)
+            .env("TUBE_DIR", &self.dir)
+            .env("TUBE_NAME", &self.name);
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        match key {
+            "path" => Some(&self.path),
+            "event" => Some(&self.event),
+            "dir" => Some(&self.dir),
+            "name" => Some(&self.name),
+            _ => None,
+        }
+    }
+
+    fn substitute(&self, arg: &str) -> String {
+        substitute(arg, |key| self.get(key))
+    }
+}
+
+/// replaces the placeholders of the template with the values `get` gives for their
+/// names in a single pass, so values that contain placeholders themselves (e.g. a
+/// file named `{name}`) are kept as is, unknown placeholders are left untouched
+pub fn substitute<'a>(template: &str, get: impl Fn(&str) -> Option<&'a str>) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let value = rest
+            .find('}')
+            .and_then(|end| Some((end, get(&rest[1..end])?)));
+        match value {
+            Some((end, value)) => {
+                result.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
@@ -0,0 +1,87 @@
+use anyhow::Context;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use regex::RegexSet;
+use std::path::Path;
+
+/// include and exclude patterns applied to event paths, a path matches if it
+/// matches any include pattern (or there are none) and doesn't match any exclude pattern.
+///
+/// patterns are globs, unless prefixed with `re:` in which case they are regexes
+/// matched against the full path. globs that are not absolute match anywhere in the
+/// path, so `*.rs` and `target/**` behave like they do in `.gitignore`
+#[derive(Debug, Clone)]
+pub struct Filter {
+    include: Option<Patterns>,
+    exclude: Patterns,
+}
+
+#[derive(Debug, Clone)]
+struct Patterns {
+    globs: GlobSet,
+    regexes: RegexSet,
+}
+
+impl Filter {
+    pub fn new(include: &[String], exclude: &[String]) -> anyhow::Result<Self> {
+        let include = match include.is_empty() {
+            true => None,
+            false => Some(Patterns::new(include)?),
+        };
+        Ok(Self {
+            include,
+            exclude: Patterns::new(exclude)?,
+        })
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        if self.exclude.is_match(path) {
+            return false;
+        }
+        self.include.as_ref().is_none_or(|set| set.is_match(path))
+    }
+}
+
+impl Patterns {
+    fn new(patterns: &[String]) -> anyhow::Result<Self> {
+        let mut globs = GlobSetBuilder::new();
+        let mut regexes = Vec::new();
+        for pattern in patterns {
+            if let Some(regex) = pattern.strip_prefix("re:") {
+                regexes.push(regex);
+                continue;
+            }
+            let pattern = match pattern.starts_with('/') || pattern.starts_with("**") {
+                true => pattern.clone(),
+                false => format!("**/{}", pattern),
+            };
+            // `dir/**` also matches the directory itself, so it can be
+            // used to skip the directory and not only its content
+            if let Some(dir) = pattern.strip_suffix("/**") {
+                globs.add(Glob::new(dir)?);
+            }
+            globs.add(Glob::new(&pattern)?);
+        }
+        Ok(Self {
+            globs: globs.build()?,
+            regexes: RegexSet::new(regexes)?,
+        })
+    }
+
+    fn is_match(&self, path: &Path) -> bool {
+        self.globs.is_match(path)
+            || (!self.regexes.is_empty() && self.regexes.is_match(&path.to_string_lossy()))
+    }
+}
+
+/// reads the patterns of an ignore file, one per line, empty
+/// lines and lines starting with `#` are skipped
+pub fn read_ignore_file(path: &Path) -> anyhow::Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("couldn't read `{}`", path.display()))?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
@@ -0,0 +1,34 @@
+use anyhow::Context;
+use clap::CommandFactory;
+use std::io::Write;
+
+use crate::cli::{Cli, CompletionsArgs, ManArgs};
+
+/// writes the completions of the shell to stdout, generated from the clap
+/// definition of the command line
+pub fn completions(args: CompletionsArgs) -> anyhow::Result<()> {
+    let mut cmd = Cli::command();
+    let name = cmd.get_name().to_string();
+    // generated in memory first, clap_complete panics when it can't write
+    let mut script = Vec::new();
+    clap_complete::generate(args.shell, &mut cmd, name, &mut script);
+    std::io::stdout().write_all(&script)?;
+    Ok(())
+}
+
+/// writes the man page to stdout, or the pages of every subcommand to `--dir`
+pub fn man(args: ManArgs) -> anyhow::Result<()> {
+    let cmd = Cli::command();
+    match args.dir {
+        Some(dir) => {
+            std::fs::create_dir_all(&dir)
+                .with_context(|| format!("couldn't create `{}`", dir.display()))?;
+            clap_mangen::generate_to(cmd, &dir)
+                .with_context(|| format!("couldn't write the man pages to `{}`", dir.display()))
+        }
+        None => {
+            clap_mangen::Man::new(cmd).render(&mut std::io::stdout())?;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use futures::stream::{self, BoxStream, StreamExt};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use tube_inotify::EventKind;
+
+use crate::cli::ServeGrpcArgs;
+use crate::filter::Filter;
+use crate::output::Record;
+use crate::systemd::{self, Watchdog};
+use crate::watcher::{Batches, Watcher};
+
+pub mod proto {
+    tonic::include_proto!("tube.v1");
+}
+
+use proto::tube_server::{Tube, TubeServer};
+use proto::{Event, WatchRequest};
+
+/// how many events a subscriber can fall behind before its stream is ended
+const SUBSCRIBER_BUFFER: usize = 1024;
+
+type Events = broadcast::Sender<Arc<Event>>;
+
+/// serves the live event stream over gRPC, see `proto/tube.proto`
+pub async fn run(args: ServeGrpcArgs) -> anyhow::Result<()> {
+    let batches = Watcher::open(&args.watch)?.spawn();
+    let (events, _) = broadcast::channel(SUBSCRIBER_BUFFER);
+
+    let service = TubeServer::new(Service {
+        events: events.clone(),
+    });
+    let listener = match systemd::listener() {
+        Some(listener) => TcpListener::from_std(listener)?,
+        None => TcpListener::bind(args.listen).await?,
+    };
+    tracing::info!("serving gRPC on {}", listener.local_addr()?);
+    let incoming = TcpIncoming::from_listener(listener, true, None)
+        .map_err(|e| anyhow::anyhow!("couldn't listen: {}", e))?;
+    systemd::notify("READY=1");
+
+    tokio::select! {
+        result = Server::builder().add_service(service).serve_with_incoming(incoming) => result?,
+        result = publish(batches, events) => result?,
+        _ = Watchdog::new().run() => {}
+    }
+    Ok(())
+}
+
+/// converts every event once and broadcasts it to all the subscribers
+async fn publish(mut batches: Batches, events: Events) -> anyhow::Result<()> {
+    while let Some(batch) = batches.recv().await {
+        for event in batch? {
+            let record = Record::new(&event);
+            let event = Event {
+                path: record.path.into_owned(),
+                kind: record.kind,
+                cookie: record.cookie,
+                is_dir: record.is_dir,
+                ts: record.ts,
+            };
+            // no subscribers is not an error, the event is just not delivered
+            let _ = events.send(Arc::new(event));
+        }
+    }
+    Ok(())
+}
+
+struct Service {
+    events: Events,
+}
+
+/// the filters a client asked for in its `WatchRequest`
+struct Subscription {
+    filter: Filter,
+    kinds: Vec<String>,
+}
+
+impl Subscription {
+    fn new(request: WatchRequest) -> anyhow::Result<Self> {
+        let filter = Filter::new(&request.include, &request.exclude)?;
+        // normalized to the names events are sent with
+        let kinds = request
+            .kinds
+            .iter()
+            .map(|kind| kind.to_uppercase().parse::<EventKind>())
+            .map(|kind| kind.map(|kind| kind.to_string()))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { filter, kinds })
+    }
+
+    fn matches(&self, event: &Event) -> bool {
+        (self.kinds.is_empty() || self.kinds.contains(&event.kind))
+            && self.filter.matches(Path::new(&event.path))
+    }
+}
+
+#[tonic::async_trait]
+impl Tube for Service {
+    type WatchStream = BoxStream<'static, Result<Event, Status>>;
+
+    async fn watch(
+        &self,
+        request: Request<WatchRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        let subscription = Subscription::new(request.into_inner())
+            .map_err(|e| Status::invalid_argument(format!("{:#}", e)))?;
+        let rx = self.events.subscribe();
+
+        let stream = stream::unfold(Some((rx, subscription)), |state| async move {
+            let (mut rx, subscription) = state?;
+            loop {
+                match rx.recv().await {
+                    Ok(event) if subscription.matches(&event) => {
+                        return Some((Ok(Event::clone(&event)), Some((rx, subscription))));
+                    }
+                    Ok(_) => continue,
+                    // ends the stream, the client can subscribe again
+                    Err(RecvError::Lagged(n)) => {
+                        let status = Status::data_loss(format!("fell behind by {} events", n));
+                        return Some((Err(status), None));
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+        Ok(Response::new(stream.boxed()))
+    }
+}
@@ -0,0 +1,96 @@
+use clap::ValueEnum;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use tube_inotify::{Event, EventKind};
+
+/// the digest computed for `--hash`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    Blake3,
+    Sha256,
+}
+
+impl Algorithm {
+    /// hashes the content of the file, returned as lowercase hex
+    pub fn digest(self, path: &Path) -> io::Result<String> {
+        let mut file = File::open(path)?;
+        match self {
+            Self::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                io::copy(&mut file, &mut hasher)?;
+                Ok(hasher.finalize().to_hex().to_string())
+            }
+            Self::Sha256 => {
+                let mut hasher = Sha256::new();
+                let mut buf = [0; 64 * 1024];
+                loop {
+                    match file.read(&mut buf)? {
+                        0 => break,
+                        n => hasher.update(&buf[..n]),
+                    }
+                }
+                Ok(hasher
+                    .finalize()
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect())
+            }
+        }
+    }
+}
+
+/// hashes the files that were written and remembers the last digest of every path,
+/// so rewrites that didn't change the content can be told apart
+pub struct Hasher {
+    algorithm: Algorithm,
+    skip_unchanged: bool,
+    digests: HashMap<PathBuf, String>,
+}
+
+impl Hasher {
+    pub fn new(algorithm: Algorithm, skip_unchanged: bool) -> Self {
+        Self {
+            algorithm,
+            skip_unchanged,
+            digests: HashMap::new(),
+        }
+    }
+
+    /// hashes the file of a `CLOSE_WRITE` event, returns false if the event should
+    /// be dropped because the content is the same as the last time
+    pub fn update(&mut self, event: &Event) -> bool {
+        match event.kind {
+            EventKind::CloseWrite if !event.is_dir => {}
+            // the file at the path is gone or was replaced by another one
+            EventKind::Delete | EventKind::MovedFrom | EventKind::MovedTo => {
+                self.digests.remove(&event.path);
+                return true;
+            }
+            _ => return true,
+        }
+
+        let digest = match self.algorithm.digest(&event.path) {
+            Ok(digest) => digest,
+            // already gone or unreadable, nothing to compare
+            Err(_) => {
+                self.digests.remove(&event.path);
+                return true;
+            }
+        };
+        let unchanged = self.digests.get(&event.path) == Some(&digest);
+        self.digests.insert(event.path.clone(), digest);
+        !(unchanged && self.skip_unchanged)
+    }
+
+    /// the digest of the file of a `CLOSE_WRITE` event, after `update` saw it
+    pub fn digest(&self, event: &Event) -> Option<&str> {
+        match event.kind {
+            EventKind::CloseWrite => self.digests.get(&event.path).map(String::as_str),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use clap::ValueEnum;
+use ignore::gitignore::Gitignore;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::filter::Filter;
+
+/// files editors create next to the files being edited
+const EDITOR_IGNORES: &[&str] = &[
+    "*.swp",
+    "*.swo",
+    "*.swx",
+    "*~",
+    ".#*",
+    "#*#",
+    "4913",
+    ".DS_Store",
+];
+
+/// sets of ignore patterns for common project types
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    /// rust projects, ignores `target/`
+    Cargo,
+    /// javascript projects, ignores `node_modules/` and build output
+    Node,
+    /// python projects, ignores virtualenvs and bytecode
+    Python,
+}
+
+impl Preset {
+    fn ignores(&self) -> &'static [&'static str] {
+        match self {
+            Self::Cargo => &["target/**"],
+            Self::Node => &["node_modules/**", "dist/**", "build/**", ".next/**"],
+            Self::Python => &[
+                "__pycache__/**",
+                "*.pyc",
+                ".venv/**",
+                "venv/**",
+                ".tox/**",
+                ".mypy_cache/**",
+                ".pytest_cache/**",
+            ],
+        }
+    }
+}
+
+/// decides which paths are ignored, from the preset patterns and
+/// the `.gitignore` files of the watched paths
+pub struct Ignore {
+    globs: Filter,
+    gitignores: Vec<Gitignore>,
+}
+
+impl Ignore {
+    pub fn new<'a>(
+        preset: Option<Preset>,
+        gitignore: bool,
+        roots: impl IntoIterator<Item = &'a Path>,
+    ) -> anyhow::Result<Self> {
+        let mut patterns: Vec<String> = Vec::new();
+        if let Some(preset) = preset {
+            patterns.extend(preset.ignores().iter().map(|p| p.to_string()));
+            patterns.extend(EDITOR_IGNORES.iter().map(|p| p.to_string()));
+        }
+
+        let mut gitignores = Vec::new();
+        if gitignore {
+            patterns.push(".git/**".to_string());
+            let mut files: Vec<PathBuf> = Vec::new();
+            for root in roots {
+                for file in gitignore_files(root) {
+                    if !files.contains(&file) {
+                        files.push(file);
+                    }
+                }
+            }
+            for file in files {
+                let (gitignore, err) = Gitignore::new(&file);
+                if let Some(err) = err {
+                    tracing::warn!("couldn't parse `{}`: {}", file.display(), err);
+                }
+                gitignores.push(gitignore);
+            }
+            // deeper files take precedence, so they are checked first
+            gitignores.sort_by_key(|g| std::cmp::Reverse(g.path().components().count()));
+        }
+
+        Ok(Self {
+            globs: Filter::new(&[], &patterns)?,
+            gitignores,
+        })
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if !self.globs.matches(path) {
+            return true;
+        }
+        self.gitignores
+            .iter()
+            .filter(|g| path.starts_with(g.path()))
+            .map(|g| g.matched_path_or_any_parents(path, is_dir))
+            .find(|m| !m.is_none())
+            .is_some_and(|m| m.is_ignore())
+    }
+}
+
+/// returns the `.gitignore` files that apply to the path, from the
+/// repository root down to the path itself
+fn gitignore_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for dir in path.ancestors().filter(|dir| dir.is_dir()) {
+        let file = dir.join(".gitignore");
+        if file.is_file() {
+            files.push(file);
+        }
+        if dir.join(".git").exists() {
+            break;
+        }
+    }
+    files.reverse();
+    files
+}
@@ -0,0 +1,26 @@
+use std::fs;
+
+/// the inotify limits of the kernel, `None` for values that couldn't be read
+pub struct Limits {
+    pub max_user_watches: Option<u64>,
+    pub max_user_instances: Option<u64>,
+    pub max_queued_events: Option<u64>,
+}
+
+impl Limits {
+    pub fn read() -> Self {
+        Self {
+            max_user_watches: read("max_user_watches"),
+            max_user_instances: read("max_user_instances"),
+            max_queued_events: read("max_queued_events"),
+        }
+    }
+}
+
+fn read(name: &str) -> Option<u64> {
+    fs::read_to_string(format!("/proc/sys/fs/inotify/{}", name))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
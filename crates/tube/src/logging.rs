@@ -0,0 +1,104 @@
+use clap::ValueEnum;
+use std::fmt;
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::cli::LogArgs;
+
+static COLOR: OnceLock<Color> = OnceLock::new();
+
+/// when colors are used in the logs and the printed events
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Color {
+    /// when writing to a terminal and `NO_COLOR` isn't set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl Color {
+    fn enabled(self, stream: &impl IsTerminal) -> bool {
+        match self {
+            Self::Auto => stream.is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+/// whether the events printed to stdout should be colored
+pub fn stdout_color() -> bool {
+    COLOR
+        .get()
+        .copied()
+        .unwrap_or_default()
+        .enabled(&std::io::stdout())
+}
+
+/// logs to stderr, `--quiet` keeps only the errors and every `-v` adds a level,
+/// the libraries tube uses only log their warnings and errors
+pub fn init(args: &LogArgs) {
+    let _ = COLOR.set(args.color);
+    let level = match (args.quiet, args.verbose) {
+        (true, _) => LevelFilter::ERROR,
+        (false, 0) => LevelFilter::INFO,
+        (false, 1) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    };
+    let targets = Targets::new()
+        .with_target(env!("CARGO_CRATE_NAME"), level)
+        .with_default(level.min(LevelFilter::WARN));
+    let format = Format {
+        color: args.color.enabled(&std::io::stderr()),
+    };
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .event_format(format)
+                .with_writer(std::io::stderr),
+        )
+        .with(targets)
+        .init();
+}
+
+/// `tube: message`, with the level before the message when it isn't info
+struct Format {
+    color: bool,
+}
+
+impl<S, N> FormatEvent<S, N> for Format
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        write!(writer, "tube: ")?;
+        let (label, style) = match *event.metadata().level() {
+            Level::ERROR => ("error", "1;31"),
+            Level::WARN => ("warning", "1;33"),
+            Level::INFO => ("", ""),
+            Level::DEBUG => ("debug", "34"),
+            Level::TRACE => ("trace", "2"),
+        };
+        match (label, self.color) {
+            ("", _) => {}
+            (label, true) => write!(writer, "\x1b[{}m{}:\x1b[0m ", style, label)?,
+            (label, false) => write!(writer, "{}: ", label)?,
+        }
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
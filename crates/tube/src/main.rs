@@ -1,23 +1,229 @@
-use futures::StreamExt;
-use tube_inotify::{Flag, Inotify, Mask};
-
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let mut inotify = Inotify::with_flags(Flag::NONBLOCKING)
-        .expect("couldn't create inotify")
-        .watch("foo".into(), Mask::CREATE | Mask::DELETE)?;
-    println!("Hello, world!");
-
-    while let Some(events) = inotify.next().await {
-        if events.is_err() {
-            break;
+use anyhow::Context;
+use clap::Parser;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::Instant;
+
+mod archive;
+mod audit;
+mod capture;
+mod cli;
+mod condition;
+mod config;
+mod daemon;
+mod debounce;
+mod diff;
+mod doctor;
+mod exec;
+mod filter;
+mod generate;
+mod grpc;
+mod hash;
+mod ignore;
+mod limits;
+mod logging;
+mod metrics;
+mod output;
+mod rate;
+mod reconcile;
+mod replay;
+mod rule;
+mod run;
+mod sandbox;
+mod serve;
+mod sink;
+mod snapshot;
+mod stats;
+mod sync;
+mod systemd;
+mod tail;
+mod top;
+mod tui;
+mod wait;
+mod watcher;
+
+use cli::{Cli, Command, OutputArgs, SinkArgs, WatchArgs};
+use config::Config;
+use diff::Differ;
+use hash::Hasher;
+use output::Printer;
+use rate::Limiter;
+use sink::Sinks;
+use stats::Reporter;
+use watcher::{Matcher, Watcher};
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    logging::init(&cli.log);
+
+    let daemon = match &cli.command {
+        Some(Command::Daemon(args)) => Some(daemon::prepare(args)?),
+        _ => None,
+    };
+
+    let result = tokio::runtime::Runtime::new()?.block_on(run(cli, daemon));
+    // the stats were reported and the runtime is dropped by now
+    if result.as_ref().is_err_and(|e| e.is::<wait::TimedOut>()) {
+        std::process::exit(wait::TIMEOUT_EXIT_CODE);
+    }
+    result
+}
+
+/// `daemon` is the configuration of the daemon, loaded before it detached
+async fn run(cli: Cli, daemon: Option<Config>) -> anyhow::Result<()> {
+    let metrics_listen = match &cli.command {
+        Some(Command::Daemon(args)) => args.metrics_listen,
+        _ => cli.metrics_listen,
+    };
+    if let Some(addr) = metrics_listen {
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(addr).await {
+                tracing::error!("metrics server stopped: {:#}", e);
+            }
+        });
+    }
+
+    let stats = match &cli.command {
+        Some(Command::Daemon(args)) => args.stats.clone(),
+        _ => cli.stats.clone(),
+    };
+    let reporter = Reporter::new(&stats)?;
+
+    let result = match cli.command {
+        Some(Command::Exec(args)) => exec::run(args).await,
+        Some(Command::Run(args)) => run::run(args).await,
+        Some(Command::Daemon(args)) => {
+            let config = daemon.expect("the daemon config is loaded before the runtime");
+            daemon::run(*args, config).await
         }
+        Some(Command::Wait(args)) => wait::run(args).await,
+        Some(Command::Tail(args)) => tail::run(args).await,
+        Some(Command::Archive(args)) => archive::run(args).await,
+        Some(Command::Sync(args)) => sync::run(args).await,
+        Some(Command::Serve(args)) => serve::run(args).await,
+        Some(Command::ServeGrpc(args)) => grpc::run(args).await,
+        Some(Command::Record(args)) => replay::record(args).await,
+        Some(Command::Replay(args)) => replay::replay(args).await,
+        Some(Command::Snapshot(args)) => snapshot::snapshot(args),
+        Some(Command::Diff(args)) => snapshot::diff(args),
+        Some(Command::Audit(args)) => audit::run(args).await,
+        Some(Command::Tui(args)) => tui::run(args).await,
+        Some(Command::Top(args)) => top::run(args).await,
+        Some(Command::Doctor(args)) => doctor::run(args),
+        Some(Command::Completions(args)) => generate::completions(args),
+        Some(Command::Man(args)) => generate::man(args),
+        None => match cli.config {
+            Some(path) => {
+                let sinks = Sinks::open(&cli.sinks)?;
+                rule::run(Config::load(&path)?, sinks).await
+            }
+            None => print(cli.watch, cli.output, cli.sinks).await,
+        },
+    };
+    if stats.stats {
+        reporter.report();
+    }
+    result
+}
+
+/// prints every matching event to stdout and delivers them to the sinks
+async fn print(args: WatchArgs, output: OutputArgs, sinks: SinkArgs) -> anyhow::Result<()> {
+    let mut batches = Watcher::open(&args)?.spawn();
+    let mut printer = Printer::new(std::io::stdout(), output.format);
+    if logging::stdout_color() {
+        printer = printer.with_color();
+    }
+    if output.stat {
+        printer = printer.with_stat();
+    }
+    if args.hash.is_some() {
+        printer = printer.with_hash();
+    }
+    if args.attribute {
+        printer = printer.with_process();
+    }
+    if args.meta_changes {
+        printer = printer.with_changes();
+    }
+    if args.inodes {
+        printer = printer.with_inodes();
+    }
+    let mut differ = None;
+    if output.diff {
+        printer = printer.with_diff();
+        differ = Some(Differ::new(&Matcher::new(&args)?));
+    }
+    if let Some(base) = &output.relative {
+        let base = base
+            .canonicalize()
+            .with_context(|| format!("couldn't resolve `{}`", base.display()))?;
+        printer = printer.relative_to(base);
+    }
+    if output.print0 {
+        printer = printer.paths_only(b'\0');
+    } else if output.paths_only {
+        printer = printer.paths_only(b'\n');
+    }
+    // the unchanged files are already left out by the watcher
+    let mut hasher = args.hash.map(|algorithm| Hasher::new(algorithm, false));
+    let mut limiter = output.max_rate.map(|rate| Limiter::new("output", rate));
+    let sinks = Sinks::open(&sinks)?;
+    let deadline = output.timeout.map(|timeout| Instant::now() + timeout);
+    let mut remaining = output.count.unwrap_or(usize::MAX);
 
-        let events = unsafe { events.unwrap_unchecked() };
-        for event in events {
-            println!("event {:?}", event);
+    let mut terminate = signal(SignalKind::terminate())?;
+
+    let mut timed_out = false;
+    while remaining > 0 {
+        let next = debounce::next(&mut batches, output.debounce, |_| true);
+        let next = async {
+            match deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline, next).await.ok(),
+                None => Some(next.await),
+            }
+        };
+        // stopped by a signal or the timeout, the sinks still get to deliver what they have
+        let events = tokio::select! {
+            events = next => events,
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => break,
+        };
+        let Some(events) = events else {
+            timed_out = true;
+            break;
+        };
+        let Some(events) = events else {
+            break;
+        };
+
+        let mut events = events?;
+        if let Some(hasher) = &mut hasher {
+            for event in &events {
+                hasher.update(event);
+            }
         }
-        println!("event end!");
+        if let Some(limiter) = &mut limiter {
+            events.retain(|_| limiter.allow());
+        }
+        if events.is_empty() {
+            continue;
+        }
+        events.truncate(remaining);
+        remaining -= events.len();
+        for event in &events {
+            let hash = hasher.as_ref().and_then(|hasher| hasher.digest(event));
+            let diff = differ.as_mut().and_then(|differ| differ.update(event));
+            printer.print_with(event, |record| {
+                record.hash = hash.map(str::to_string);
+                record.diff = diff;
+            })?;
+        }
+        // stdout is only flushed on newlines, which `--print0` doesn't write
+        printer.flush()?;
+        sinks.send(events);
+    }
+    sinks.close().await;
+    match timed_out {
+        true => Err(wait::TimedOut.into()),
+        false => Ok(()),
     }
-    Ok(())
 }
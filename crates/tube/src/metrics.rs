@@ -0,0 +1,305 @@
+use axum::routing::get;
+use axum::Router;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// upper bounds of the command duration histogram buckets, in seconds
+const DURATION_BUCKETS: &[f64] = &[0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0];
+
+/// the process wide metrics, updated from everywhere events
+/// pass through and exposed by `serve`
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    events: BTreeMap<String, u64>,
+    triggers: BTreeMap<String, u64>,
+    dropped: BTreeMap<String, u64>,
+    undelivered: BTreeMap<String, u64>,
+    failures: BTreeMap<String, u64>,
+    rate_limited: BTreeMap<String, u64>,
+    durations: BTreeMap<String, Histogram>,
+    latencies: BTreeMap<String, Latency>,
+}
+
+/// time from a batch being read to the commands it triggers starting
+#[derive(Default)]
+struct Latency {
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; DURATION_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Metrics {
+    pub fn event(&self, kind: &str) {
+        *self
+            .inner
+            .lock()
+            .unwrap()
+            .events
+            .entry(kind.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn rule_triggered(&self, rule: &str) {
+        *self
+            .inner
+            .lock()
+            .unwrap()
+            .triggers
+            .entry(rule.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn dropped(&self, sink: &str) {
+        *self
+            .inner
+            .lock()
+            .unwrap()
+            .dropped
+            .entry(sink.to_string())
+            .or_default() += 1;
+    }
+
+    /// records events a sink gave up delivering
+    pub fn undelivered(&self, sink: &str, events: usize) {
+        *self
+            .inner
+            .lock()
+            .unwrap()
+            .undelivered
+            .entry(sink.to_string())
+            .or_default() += events as u64;
+    }
+
+    /// records an event dropped for being over the rate limit of the rule
+    pub fn rate_limited(&self, rule: &str) {
+        *self
+            .inner
+            .lock()
+            .unwrap()
+            .rate_limited
+            .entry(rule.to_string())
+            .or_default() += 1;
+    }
+
+    /// records a finished command run of the rule
+    pub fn command(&self, rule: &str, duration: Duration, success: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        if !success {
+            *inner.failures.entry(rule.to_string()).or_default() += 1;
+        }
+
+        let seconds = duration.as_secs_f64();
+        let histogram = inner.durations.entry(rule.to_string()).or_default();
+        for (bucket, bound) in histogram.buckets.iter_mut().zip(DURATION_BUCKETS) {
+            if seconds <= *bound {
+                *bucket += 1;
+            }
+        }
+        histogram.sum += seconds;
+        histogram.count += 1;
+    }
+
+    /// records how long a command of the rule waited to start after its event was read
+    pub fn latency(&self, rule: &str, latency: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        let entry = inner.latencies.entry(rule.to_string()).or_default();
+        entry.sum += latency.as_secs_f64();
+        entry.count += 1;
+    }
+
+    /// a human readable summary of the metrics, one line per kind of metric
+    /// and one per rule and sink
+    pub fn summary(&self) -> String {
+        let inner = self.inner.lock().unwrap();
+        let mut out = String::new();
+
+        let events: Vec<String> = inner
+            .events
+            .iter()
+            .map(|(kind, count)| format!("{} {}", kind, count))
+            .collect();
+        let total: u64 = inner.events.values().sum();
+        let _ = write!(out, "events: {}", total);
+        if !events.is_empty() {
+            let _ = write!(out, " ({})", events.join(", "));
+        }
+
+        let mut rules: Vec<&String> = inner
+            .triggers
+            .keys()
+            .chain(inner.durations.keys())
+            .collect();
+        rules.sort();
+        rules.dedup();
+        for rule in rules {
+            let count = |values: &BTreeMap<String, u64>| values.get(rule).copied().unwrap_or(0);
+            let commands = inner.durations.get(rule).map_or(0, |h| h.count);
+            let _ = write!(
+                out,
+                "\nrule `{}`: {} triggers, {} commands run, {} failed",
+                rule,
+                count(&inner.triggers),
+                commands,
+                count(&inner.failures),
+            );
+            if let Some(latency) = inner.latencies.get(rule).filter(|l| l.count > 0) {
+                let average = Duration::from_secs_f64(latency.sum / latency.count as f64);
+                let _ = write!(out, ", {:?} average latency", average);
+            }
+            if count(&inner.rate_limited) > 0 {
+                let _ = write!(out, ", {} rate limited", count(&inner.rate_limited));
+            }
+        }
+
+        let mut sinks: Vec<&String> = inner
+            .dropped
+            .keys()
+            .chain(inner.undelivered.keys())
+            .collect();
+        sinks.sort();
+        sinks.dedup();
+        for sink in sinks {
+            let _ = write!(
+                out,
+                "\nsink `{}`: {} batches dropped, {} events undelivered",
+                sink,
+                inner.dropped.get(sink).copied().unwrap_or(0),
+                inner.undelivered.get(sink).copied().unwrap_or(0),
+            );
+        }
+        out
+    }
+
+    /// renders the metrics in the prometheus text format
+    pub fn render(&self) -> String {
+        let inner = self.inner.lock().unwrap();
+        let mut out = String::new();
+
+        counter(
+            &mut out,
+            "tube_events_total",
+            "events seen, by kind",
+            "kind",
+            &inner.events,
+        );
+        counter(
+            &mut out,
+            "tube_rule_triggers_total",
+            "events that triggered a rule",
+            "rule",
+            &inner.triggers,
+        );
+        counter(
+            &mut out,
+            "tube_dropped_batches_total",
+            "event batches dropped by a sink that couldn't keep up",
+            "sink",
+            &inner.dropped,
+        );
+        counter(
+            &mut out,
+            "tube_undelivered_events_total",
+            "events a sink failed to deliver",
+            "sink",
+            &inner.undelivered,
+        );
+        counter(
+            &mut out,
+            "tube_command_failures_total",
+            "commands that failed to run or exited with an error",
+            "rule",
+            &inner.failures,
+        );
+        counter(
+            &mut out,
+            "tube_rate_limited_events_total",
+            "events dropped for being over the rate limit",
+            "rule",
+            &inner.rate_limited,
+        );
+
+        let name = "tube_command_latency_seconds";
+        let _ = writeln!(
+            out,
+            "# HELP {} time from an event being read to its command starting",
+            name
+        );
+        let _ = writeln!(out, "# TYPE {} summary", name);
+        for (rule, latency) in &inner.latencies {
+            let rule = escape(rule);
+            let _ = writeln!(out, "{}_sum{{rule=\"{}\"}} {}", name, rule, latency.sum);
+            let _ = writeln!(out, "{}_count{{rule=\"{}\"}} {}", name, rule, latency.count);
+        }
+
+        let name = "tube_command_duration_seconds";
+        let _ = writeln!(out, "# HELP {} duration of the commands run by rules", name);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (rule, histogram) in &inner.durations {
+            let rule = escape(rule);
+            for (count, bound) in histogram.buckets.iter().zip(DURATION_BUCKETS) {
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{rule=\"{}\",le=\"{}\"}} {}",
+                    name, rule, bound, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_bucket{{rule=\"{}\",le=\"+Inf\"}} {}",
+                name, rule, histogram.count
+            );
+            let _ = writeln!(out, "{}_sum{{rule=\"{}\"}} {}", name, rule, histogram.sum);
+            let _ = writeln!(
+                out,
+                "{}_count{{rule=\"{}\"}} {}",
+                name, rule, histogram.count
+            );
+        }
+        out
+    }
+}
+
+fn counter(out: &mut String, name: &str, help: &str, label: &str, values: &BTreeMap<String, u64>) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    for (value, count) in values {
+        let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, escape(value), count);
+    }
+}
+
+/// escapes a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// serves the metrics on `/metrics` until the process exits
+pub async fn serve(addr: SocketAddr) -> anyhow::Result<()> {
+    let app = Router::new().route("/metrics", get(|| async { METRICS.render() }));
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!(
+        "serving metrics on http://{}/metrics",
+        listener.local_addr()?
+    );
+    axum::serve(listener, app).await?;
+    Ok(())
+}
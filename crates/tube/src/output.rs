@@ -0,0 +1,486 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+use tube_inotify::{Event, EventKind, Mask, Process};
+
+/// the format events are printed in
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum Format {
+    /// `KIND path` per line
+    #[default]
+    Human,
+    /// one JSON object per line
+    Json,
+    /// comma separated values with a header line
+    Csv,
+}
+
+/// a single event as written by `Format::Json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record<'a> {
+    pub path: Cow<'a, str>,
+    pub kind: String,
+    pub cookie: u32,
+    pub is_dir: bool,
+    pub ts: String,
+    /// the rule the event triggered, only known to the audit log
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+    #[serde(flatten)]
+    pub stat: Option<Stat>,
+    /// digest of the file content, for `--hash`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// unified diff of the file content against the last version, for `--diff`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+    /// the process that caused the event, for `--attribute`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub process: Option<Origin>,
+    /// what metadata changed, for `--meta-changes`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<String>,
+    /// the inode number of the file, for `--inodes`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inode: Option<u64>,
+    /// the path a `MOVED_TO` was renamed from, for `--inodes`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renamed_from: Option<String>,
+}
+
+/// the process behind an event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Origin {
+    pub pid: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exe: Option<String>,
+}
+
+impl From<&Process> for Origin {
+    fn from(process: &Process) -> Self {
+        Self {
+            pid: process.pid,
+            uid: process.uid,
+            exe: process
+                .exe
+                .as_ref()
+                .map(|exe| exe.to_string_lossy().into_owned()),
+        }
+    }
+}
+
+impl From<&Origin> for Process {
+    fn from(origin: &Origin) -> Self {
+        Self {
+            pid: origin.pid,
+            uid: origin.uid,
+            exe: origin.exe.as_ref().map(PathBuf::from),
+        }
+    }
+}
+
+/// metadata of the file at the time the event was printed, for `--stat`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stat {
+    pub size: u64,
+    /// permission bits in octal, e.g. `0644`
+    pub mode: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub mtime: String,
+}
+
+impl Stat {
+    /// stats the path without following symlinks, `None` if the path is already gone
+    pub fn read(path: &Path) -> Option<Self> {
+        let metadata = fs::symlink_metadata(path).ok()?;
+        let mtime = metadata.modified().ok()?;
+        Some(Self {
+            size: metadata.size(),
+            mode: format!("{:04o}", metadata.mode() & 0o7777),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            mtime: humantime::format_rfc3339_micros(mtime).to_string(),
+        })
+    }
+}
+
+impl<'a> Record<'a> {
+    /// creates the record of the event, timestamped now
+    pub fn new(event: &'a Event) -> Self {
+        Self {
+            path: event.path.to_string_lossy(),
+            kind: event.kind.to_string(),
+            cookie: event.cookie,
+            is_dir: event.is_dir,
+            ts: humantime::format_rfc3339_micros(SystemTime::now()).to_string(),
+            rule: None,
+            stat: None,
+            hash: None,
+            diff: None,
+            process: event.process.as_ref().map(Origin::from),
+            changes: event.changes.iter().map(ToString::to_string).collect(),
+            inode: event.inode,
+            renamed_from: event
+                .renamed_from
+                .as_ref()
+                .map(|from| from.to_string_lossy().into_owned()),
+        }
+    }
+
+    /// sets the rule the event triggered
+    pub fn with_rule(mut self, rule: Option<&str>) -> Self {
+        self.rule = rule.map(str::to_string);
+        self
+    }
+
+    pub fn into_owned(self) -> Record<'static> {
+        Record {
+            path: Cow::Owned(self.path.into_owned()),
+            kind: self.kind,
+            cookie: self.cookie,
+            is_dir: self.is_dir,
+            ts: self.ts,
+            rule: self.rule,
+            stat: self.stat,
+            hash: self.hash,
+            diff: self.diff,
+            process: self.process,
+            changes: self.changes,
+            inode: self.inode,
+            renamed_from: self.renamed_from,
+        }
+    }
+
+    /// turns the record back into the event it was created from
+    pub fn event(&self) -> anyhow::Result<Event> {
+        Ok(Event {
+            path: PathBuf::from(self.path.as_ref()),
+            kind: self.kind.parse::<EventKind>()?,
+            cookie: self.cookie,
+            is_dir: self.is_dir,
+            process: self.process.as_ref().map(Process::from),
+            // only kept as text, the event is the same without them
+            changes: Vec::new(),
+            inode: self.inode,
+            renamed_from: self.renamed_from.as_ref().map(PathBuf::from),
+        })
+    }
+
+    /// returns the time the record was created at
+    pub fn time(&self) -> anyhow::Result<SystemTime> {
+        Ok(humantime::parse_rfc3339(&self.ts)?)
+    }
+}
+
+/// writes events to the underlying writer in the requested format
+pub struct Printer<W: Write> {
+    out: W,
+    format: Format,
+    header: bool,
+    // set when only paths are printed, followed by this byte
+    paths_only: Option<u8>,
+    // set when paths are printed relative to this directory
+    relative: Option<PathBuf>,
+    color: bool,
+    stat: bool,
+    hash: bool,
+    diff: bool,
+    process: bool,
+    changes: bool,
+    inodes: bool,
+}
+
+impl<W: Write> Printer<W> {
+    pub fn new(out: W, format: Format) -> Self {
+        Self {
+            out,
+            format,
+            header: false,
+            paths_only: None,
+            relative: None,
+            color: false,
+            stat: false,
+            hash: false,
+            diff: false,
+            process: false,
+            changes: false,
+            inodes: false,
+        }
+    }
+
+    /// colors the event kinds in the human format
+    pub fn with_color(mut self) -> Self {
+        self.color = true;
+        self
+    }
+
+    /// adds the metadata of the files to the printed events
+    pub fn with_stat(mut self) -> Self {
+        self.stat = true;
+        self
+    }
+
+    /// adds the content digest to the printed events, set by the caller in `print_with`
+    pub fn with_hash(mut self) -> Self {
+        self.hash = true;
+        self
+    }
+
+    /// adds the content changes to the printed events, set by the caller in `print_with`
+    pub fn with_diff(mut self) -> Self {
+        self.diff = true;
+        self
+    }
+
+    /// adds the pid, uid and executable of the process behind the events
+    pub fn with_process(mut self) -> Self {
+        self.process = true;
+        self
+    }
+
+    /// adds what metadata changed to the `ATTRIB` events
+    pub fn with_changes(mut self) -> Self {
+        self.changes = true;
+        self
+    }
+
+    /// adds the inode of the files and the path renames came from
+    pub fn with_inodes(mut self) -> Self {
+        self.inodes = true;
+        self
+    }
+
+    /// prints only the paths, each followed by the terminator instead of a
+    /// newline, the format is ignored then
+    pub fn paths_only(mut self, terminator: u8) -> Self {
+        self.paths_only = Some(terminator);
+        self
+    }
+
+    /// prints the paths relative to the directory, which has to be
+    /// canonical like the paths of the events
+    pub fn relative_to(mut self, base: PathBuf) -> Self {
+        self.relative = Some(base);
+        self
+    }
+
+    pub fn print(&mut self, event: &Event) -> io::Result<()> {
+        self.print_with(event, |_| {})
+    }
+
+    /// prints the event after `fill` added the values only the caller
+    /// knows about to its record, like the digest of the file
+    pub fn print_with(&mut self, event: &Event, fill: impl FnOnce(&mut Record)) -> io::Result<()> {
+        let path = match &self.relative {
+            Some(base) => Cow::Owned(relative(&event.path, base)),
+            None => Cow::Borrowed(event.path.as_path()),
+        };
+        if let Some(terminator) = self.paths_only {
+            // written as is, so paths that are not valid utf-8 survive
+            self.out.write_all(path.as_os_str().as_bytes())?;
+            return self.out.write_all(&[terminator]);
+        }
+        let mut record = Record::new(event);
+        if self.relative.is_some() {
+            record.path = Cow::Owned(path.to_string_lossy().into_owned());
+        }
+        if self.stat {
+            record.stat = Stat::read(&event.path);
+        }
+        fill(&mut record);
+        self.print_record(&record)
+    }
+
+    pub fn print_record(&mut self, record: &Record) -> io::Result<()> {
+        if let Some(terminator) = self.paths_only {
+            self.out.write_all(record.path.as_bytes())?;
+            return self.out.write_all(&[terminator]);
+        }
+        match self.format {
+            Format::Human => {
+                let kind = human_kind(record);
+                match (self.color, kind_color(&record.kind)) {
+                    (true, Some(color)) => write!(self.out, "\x1b[{}m{}\x1b[0m", color, kind)?,
+                    _ => write!(self.out, "{}", kind)?,
+                }
+                write!(self.out, " {}", record.path)?;
+                if let Some(stat) = &record.stat {
+                    write!(
+                        self.out,
+                        " size={} mode={} uid={} gid={} mtime={}",
+                        stat.size, stat.mode, stat.uid, stat.gid, stat.mtime
+                    )?;
+                }
+                if let Some(hash) = &record.hash {
+                    write!(self.out, " hash={}", hash)?;
+                }
+                if let Some(process) = &record.process {
+                    write!(self.out, " pid={}", process.pid)?;
+                    if let Some(uid) = process.uid {
+                        write!(self.out, " uid={}", uid)?;
+                    }
+                    if let Some(exe) = &process.exe {
+                        write!(self.out, " exe={}", exe)?;
+                    }
+                }
+                if !record.changes.is_empty() {
+                    write!(self.out, " ({})", record.changes.join(", "))?;
+                }
+                if let Some(inode) = record.inode {
+                    write!(self.out, " inode={}", inode)?;
+                }
+                if let Some(from) = &record.renamed_from {
+                    write!(self.out, " from={}", from)?;
+                }
+                writeln!(self.out)?;
+                match &record.diff {
+                    Some(diff) if diff.ends_with('\n') => write!(self.out, "{}", diff),
+                    Some(diff) => writeln!(self.out, "{}", diff),
+                    None => Ok(()),
+                }
+            }
+            Format::Json => {
+                serde_json::to_writer(&mut self.out, record)?;
+                writeln!(self.out)
+            }
+            Format::Csv => {
+                if !self.header {
+                    write!(self.out, "path,kind,cookie,is_dir,ts")?;
+                    if self.stat {
+                        write!(self.out, ",size,mode,uid,gid,mtime")?;
+                    }
+                    if self.hash {
+                        write!(self.out, ",hash")?;
+                    }
+                    if self.diff {
+                        write!(self.out, ",diff")?;
+                    }
+                    if self.process {
+                        write!(self.out, ",pid,uid,exe")?;
+                    }
+                    if self.changes {
+                        write!(self.out, ",changes")?;
+                    }
+                    if self.inodes {
+                        write!(self.out, ",inode,from")?;
+                    }
+                    writeln!(self.out)?;
+                    self.header = true;
+                }
+                write!(
+                    self.out,
+                    "{},{},{},{},{}",
+                    csv_field(&record.path),
+                    record.kind,
+                    record.cookie,
+                    record.is_dir,
+                    record.ts
+                )?;
+                // the columns are there for every row, empty if the file is gone
+                match (&record.stat, self.stat) {
+                    (Some(stat), true) => write!(
+                        self.out,
+                        ",{},{},{},{},{}",
+                        stat.size, stat.mode, stat.uid, stat.gid, stat.mtime
+                    )?,
+                    (None, true) => write!(self.out, ",,,,,")?,
+                    (_, false) => {}
+                }
+                if self.hash {
+                    write!(self.out, ",{}", record.hash.as_deref().unwrap_or_default())?;
+                }
+                if self.diff {
+                    let diff = record.diff.as_deref().unwrap_or_default();
+                    write!(self.out, ",{}", csv_field(diff))?;
+                }
+                if self.process {
+                    match &record.process {
+                        Some(process) => write!(
+                            self.out,
+                            ",{},{},{}",
+                            process.pid,
+                            process.uid.map(|uid| uid.to_string()).unwrap_or_default(),
+                            csv_field(process.exe.as_deref().unwrap_or_default())
+                        )?,
+                        None => write!(self.out, ",,,")?,
+                    }
+                }
+                if self.changes {
+                    write!(self.out, ",{}", csv_field(&record.changes.join("; ")))?;
+                }
+                if self.inodes {
+                    write!(
+                        self.out,
+                        ",{},{}",
+                        record
+                            .inode
+                            .map(|inode| inode.to_string())
+                            .unwrap_or_default(),
+                        csv_field(record.renamed_from.as_deref().unwrap_or_default())
+                    )?;
+                }
+                writeln!(self.out)
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// quotes the field if it contains characters that have a meaning in csv
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// the path as seen from the base directory, going up with `..` for
+/// paths outside of it, both paths have to be absolute
+fn relative(path: &Path, base: &Path) -> PathBuf {
+    let mut path = path.components().peekable();
+    let mut base = base.components().peekable();
+    while let (Some(a), Some(b)) = (path.peek(), base.peek()) {
+        if a != b {
+            break;
+        }
+        path.next();
+        base.next();
+    }
+    let relative: PathBuf = base.map(|_| Component::ParentDir).chain(path).collect();
+    match relative.as_os_str().is_empty() {
+        true => PathBuf::from("."),
+        false => relative,
+    }
+}
+
+/// the ANSI color of the event kind, additions are green, removals red and changes yellow
+/// the kind with the flags of its mask, `CREATE|ISDIR` for directories
+fn human_kind<'a>(record: &'a Record) -> Cow<'a, str> {
+    match record.kind.parse::<EventKind>() {
+        Ok(kind) if record.is_dir => Cow::Owned(Mask::names(kind.mask() | Mask::ISDIR).to_string()),
+        _ => Cow::Borrowed(&record.kind),
+    }
+}
+
+fn kind_color(kind: &str) -> Option<&'static str> {
+    match kind {
+        "CREATE" | "MOVED_TO" => Some("32"),
+        "DELETE" | "DELETE_SELF" | "MOVED_FROM" | "MOVE_SELF" => Some("31"),
+        "MODIFY" | "CLOSE_WRITE" | "ATTRIB" => Some("33"),
+        "OVERFLOW" => Some("1;35"),
+        _ => None,
+    }
+}
@@ -0,0 +1,119 @@
+use anyhow::Context;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::metrics::METRICS;
+
+/// a max number of events per period, written `10/min`, `5/s` or `100/10m`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct Rate {
+    pub events: u32,
+    pub per: Duration,
+}
+
+impl FromStr for Rate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (events, per) = s
+            .split_once('/')
+            .with_context(|| format!("invalid rate `{}`, expected e.g. `10/min`", s))?;
+        let events = events
+            .trim()
+            .parse::<u32>()
+            .with_context(|| format!("invalid number of events `{}`", events))?;
+        let per = match per.trim() {
+            "s" | "sec" | "second" => Duration::from_secs(1),
+            "m" | "min" | "minute" => Duration::from_secs(60),
+            "h" | "hour" => Duration::from_secs(60 * 60),
+            "d" | "day" => Duration::from_secs(24 * 60 * 60),
+            per => humantime::parse_duration(per)
+                .with_context(|| format!("invalid rate period `{}`", per))?,
+        };
+        if events == 0 || per.is_zero() {
+            anyhow::bail!("rate `{}` doesn't allow any event", s);
+        }
+        Ok(Self { events, per })
+    }
+}
+
+impl TryFrom<String> for Rate {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> anyhow::Result<Self> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}",
+            self.events,
+            humantime::format_duration(self.per)
+        )
+    }
+}
+
+/// token bucket letting bursts of up to `rate.events` events through, refilled
+/// over the rate period, the events over the rate are counted and reported
+/// once events are let through again
+pub struct Limiter {
+    name: String,
+    rate: Rate,
+    tokens: f64,
+    refilled: Instant,
+    suppressed: u64,
+}
+
+impl Limiter {
+    /// `name` is what the suppressed events are reported for, e.g. the rule
+    pub fn new(name: impl Into<String>, rate: Rate) -> Self {
+        Self {
+            name: name.into(),
+            rate,
+            tokens: rate.events as f64,
+            refilled: Instant::now(),
+            suppressed: 0,
+        }
+    }
+
+    /// takes a token, returns false if the event is over the rate
+    pub fn allow(&mut self) -> bool {
+        let now = Instant::now();
+        let refill = now.duration_since(self.refilled).as_secs_f64() / self.rate.per.as_secs_f64();
+        self.tokens = (self.tokens + refill * self.rate.events as f64).min(self.rate.events as f64);
+        self.refilled = now;
+
+        if self.tokens < 1.0 {
+            METRICS.rate_limited(&self.name);
+            self.suppressed += 1;
+            return false;
+        }
+        self.tokens -= 1.0;
+        self.report();
+        true
+    }
+
+    fn report(&mut self) {
+        if self.suppressed > 0 {
+            tracing::warn!(
+                "{}: suppressed {} events over the rate of {}",
+                self.name,
+                self.suppressed,
+                self.rate
+            );
+            self.suppressed = 0;
+        }
+    }
+}
+
+impl Drop for Limiter {
+    fn drop(&mut self) {
+        self.report();
+    }
+}
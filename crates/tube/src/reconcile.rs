@@ -0,0 +1,99 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tube_inotify::Event;
+
+use crate::cli::WatchArgs;
+use crate::snapshot::Manifest;
+
+/// nice value of the scanning thread, the scans give way to everything else
+const NICENESS: i32 = 19;
+
+/// scans the watched paths again every `--rescan-interval` and reports the changes
+/// no event was received for, the manifest of the last scan is kept up to date with
+/// the events the watcher reports in between, so only the discrepancies are reported
+pub struct Reconciler {
+    args: WatchArgs,
+    interval: Duration,
+    known: Arc<Mutex<Manifest>>,
+}
+
+impl Reconciler {
+    pub fn new(args: &WatchArgs, interval: Duration) -> anyhow::Result<Self> {
+        Ok(Self {
+            args: args.clone(),
+            interval,
+            known: Arc::new(Mutex::new(Manifest::scan(args, None)?)),
+        })
+    }
+
+    /// updates the files of the events reported by the watcher
+    pub fn seen(&self, events: &[Event]) {
+        let mut known = self.known.lock().unwrap();
+        for event in events {
+            known.refresh(&event.path);
+        }
+    }
+
+    /// scans in its own thread until the receiver is dropped, the changes found are
+    /// sent as their own batch
+    pub fn start(&self, tx: mpsc::UnboundedSender<anyhow::Result<Vec<Event>>>) {
+        let args = self.args.clone();
+        let interval = self.interval;
+        let known = self.known.clone();
+        std::thread::spawn(move || {
+            unsafe {
+                libc::setpriority(libc::PRIO_PROCESS, libc::gettid() as libc::id_t, NICENESS)
+            };
+            loop {
+                std::thread::sleep(interval + jitter(interval));
+                // postponed while the system is busy, by an interval at most
+                let mut postponed = Duration::ZERO;
+                while overloaded() && postponed < interval {
+                    let wait = interval / 10;
+                    std::thread::sleep(wait);
+                    postponed += wait;
+                }
+                if tx.is_closed() {
+                    break;
+                }
+
+                let scanned = match Manifest::scan(&args, None) {
+                    Ok(scanned) => scanned,
+                    Err(e) => {
+                        tracing::warn!("couldn't scan the watched paths: {:#}", e);
+                        continue;
+                    }
+                };
+                let mut events = known.lock().unwrap().reconcile(&scanned);
+                events.retain(|event| args.matches(event));
+                if events.is_empty() {
+                    continue;
+                }
+                tracing::info!("the rescan found {} missed changes", events.len());
+                if tx.send(Ok(events)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+/// a tenth of the interval at most, so the watchers started together
+/// don't all scan at the same time
+fn jitter(interval: Duration) -> Duration {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    (interval / 10).mul_f64(nanos as f64 / 1e9)
+}
+
+/// the one minute load average is above the number of CPUs
+fn overloaded() -> bool {
+    let load = std::fs::read_to_string("/proc/loadavg")
+        .ok()
+        .and_then(|loadavg| loadavg.split_whitespace().next()?.parse::<f64>().ok());
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+    load.is_some_and(|load| load > cpus as f64)
+}
@@ -0,0 +1,106 @@
+use anyhow::Context;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::cli::{RecordArgs, ReplayArgs};
+use crate::config::Config;
+use crate::exec;
+use crate::logging;
+use crate::output::{Format, Printer, Record};
+use crate::rule;
+use crate::watcher::Watcher;
+
+/// appends every matching event to the output file until interrupted,
+/// the file is flushed after each batch so it can be replayed while recording
+pub async fn record(args: RecordArgs) -> anyhow::Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&args.output)
+        .with_context(|| format!("couldn't open `{}`", args.output.display()))?;
+    let mut out = BufWriter::new(file);
+    let mut printer = Printer::new(&mut out, Format::Json);
+    let mut batches = Watcher::open(&args.watch)?.spawn();
+
+    loop {
+        let events = tokio::select! {
+            events = batches.recv() => events,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        let Some(events) = events else {
+            break;
+        };
+        for event in events? {
+            printer.print(&event)?;
+        }
+        printer.flush()?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// feeds the recorded events to the rules, the command, or stdout, each event
+/// is delivered after the same delay it had from the first one, divided by the speed
+pub async fn replay(args: ReplayArgs) -> anyhow::Result<()> {
+    let records = load(&args)?;
+    let rules = match &args.config {
+        Some(path) => Some(rule::Replay::start(Config::load(path)?)?),
+        None => None,
+    };
+    let mut printer = Printer::new(std::io::stdout(), args.format);
+    if logging::stdout_color() {
+        printer = printer.with_color();
+    }
+
+    let start = Instant::now();
+    for (offset, record) in records {
+        if args.speed.is_finite() {
+            tokio::time::sleep_until(start + offset.div_f64(args.speed)).await;
+        }
+
+        if let Some(rules) = &rules {
+            rules.feed(&record)?;
+        } else if !args.command.is_empty() {
+            let event = record.event()?;
+            match exec::command(&args.command, &event).status().await {
+                Ok(status) if !status.success() => {
+                    tracing::warn!("`{}` exited with {}", args.command[0], status);
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("couldn't run `{}`: {}", args.command[0], e),
+            }
+        } else {
+            printer.print(&record.event()?)?;
+        }
+    }
+
+    if let Some(rules) = rules {
+        rules.finish().await;
+    }
+    Ok(())
+}
+
+/// reads the records of the file along with their offset from the first record
+fn load(args: &ReplayArgs) -> anyhow::Result<Vec<(Duration, Record<'static>)>> {
+    let file = File::open(&args.file)
+        .with_context(|| format!("couldn't open `{}`", args.file.display()))?;
+
+    let mut records = Vec::new();
+    let mut first = None;
+    for (n, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: Record = serde_json::from_str(&line)
+            .with_context(|| format!("{}:{}: invalid record", args.file.display(), n + 1))?;
+        let time = record.time()?;
+        let first = *first.get_or_insert(time);
+        // records are written in order, but clocks can step back
+        let offset = time.duration_since(first).unwrap_or_default();
+        records.push((offset, record));
+    }
+    Ok(records)
+}
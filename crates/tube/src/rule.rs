@@ -0,0 +1,505 @@
+use anyhow::Context;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tube_inotify::Event;
+
+use crate::capture::Capture;
+use crate::cli::WatchArgs;
+use crate::config::{Config, Rule};
+use crate::debounce;
+use crate::exec::OnBusy;
+use crate::filter::Filter;
+use crate::metrics::METRICS;
+use crate::output::Record;
+use crate::rate::Limiter;
+use crate::sink::{Sender, Sinks};
+use crate::snapshot::Manifest;
+use crate::systemd::{self, Watchdog};
+use crate::watcher::{Batches, Matcher, Watcher};
+
+/// runs all the rules in the configuration until SIGINT or SIGTERM is received,
+/// the events that triggered the rules are delivered to the sinks
+pub async fn run(config: Config, sinks: Sinks) -> anyhow::Result<()> {
+    let supervisor = Supervisor::start(config, sinks.sender(), None)?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut watchdog = Watchdog::new();
+    systemd::notify("READY=1");
+
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => break,
+            _ = watchdog.tick() => watchdog.pet(),
+        }
+    }
+    systemd::notify("STOPPING=1");
+    supervisor.shutdown().await;
+    sinks.close().await;
+    Ok(())
+}
+
+/// keeps track of the running rules, each rule has its own
+/// watcher and runs independently of the others
+pub struct Supervisor {
+    running: HashMap<String, Running>,
+    sinks: Sender,
+    // where the rules remember their files between runs, see `daemon --state`
+    state: Option<PathBuf>,
+    chains: Chains,
+    groups: Groups,
+}
+
+/// the rules that run after others (`on = "rule:NAME"`), by the name of the rule
+/// they run after, looked up when a command finished so restarted rules are found
+#[derive(Clone, Default)]
+struct Chains {
+    next: Arc<Mutex<HashMap<String, Vec<Next>>>>,
+}
+
+type Next = mpsc::UnboundedSender<anyhow::Result<Vec<Event>>>;
+
+impl Chains {
+    /// the events the commands of the rule succeeded for
+    fn after(&self, rule: &str) -> Batches {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut next = self.next.lock().unwrap();
+        next.entry(rule.to_string()).or_default().push(tx);
+        rx
+    }
+
+    /// hands the event to the rules running after the rule, the ones that
+    /// were stopped since are forgotten
+    fn succeeded(&self, rule: &str, event: &Event) {
+        let mut next = self.next.lock().unwrap();
+        if let Some(senders) = next.get_mut(rule) {
+            senders.retain(|tx| tx.send(Ok(vec![event.clone()])).is_ok());
+        }
+    }
+}
+
+/// the concurrency groups of the rules, a command holds the lock of the group of
+/// its rule while it runs
+#[derive(Clone, Default)]
+struct Groups {
+    locks: Arc<Mutex<HashMap<String, Group>>>,
+}
+
+type Group = Arc<tokio::sync::Mutex<()>>;
+
+impl Groups {
+    fn get(&self, rule: &Rule) -> Option<Group> {
+        let name = rule.group.as_ref()?;
+        let mut locks = self.locks.lock().unwrap();
+        Some(locks.entry(name.clone()).or_default().clone())
+    }
+}
+
+/// the rules a reload touched, by name
+#[derive(Debug, Default)]
+pub struct Changes {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl fmt::Display for Changes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parts = [
+            ("added", &self.added),
+            ("changed", &self.changed),
+            ("removed", &self.removed),
+        ];
+        let parts: Vec<String> = parts
+            .iter()
+            .filter(|(_, names)| !names.is_empty())
+            .map(|(what, names)| format!("{} {}", what, names.join(", ")))
+            .collect();
+        match parts.is_empty() {
+            true => write!(f, "nothing changed"),
+            false => write!(f, "{}", parts.join("; ")),
+        }
+    }
+}
+
+struct Running {
+    rule: Rule,
+    token: CancellationToken,
+    handle: JoinHandle<()>,
+}
+
+impl Supervisor {
+    /// with a state directory, the rules first get the changes
+    /// made since they were stopped, see `shutdown`
+    pub fn start(config: Config, sinks: Sender, state: Option<PathBuf>) -> anyhow::Result<Self> {
+        if let Some(dir) = &state {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("couldn't create `{}`", dir.display()))?;
+        }
+        let mut supervisor = Self {
+            running: HashMap::new(),
+            sinks,
+            state,
+            chains: Chains::default(),
+            groups: Groups::default(),
+        };
+        for rule in config.rules {
+            let running = Running::start(
+                rule,
+                supervisor.sinks.clone(),
+                supervisor.state.as_deref(),
+                &supervisor.chains,
+                &supervisor.groups,
+            )?;
+            supervisor
+                .running
+                .insert(running.rule.name().to_string(), running);
+        }
+        Ok(supervisor)
+    }
+
+    /// applies a new configuration, rules that didn't change keep running
+    /// untouched, changed rules are started again before the old instance is stopped
+    /// so no event is missed in between
+    pub async fn reload(&mut self, config: Config) -> anyhow::Result<Changes> {
+        let names: Vec<String> = config.rules.iter().map(|r| r.name().to_string()).collect();
+
+        let mut started = HashMap::new();
+        for rule in config.rules {
+            if self
+                .running
+                .get(rule.name())
+                .is_some_and(|r| r.rule == rule)
+            {
+                continue;
+            }
+            // the state is from the last shutdown, older than what the rule already saw
+            match Running::start(rule, self.sinks.clone(), None, &self.chains, &self.groups) {
+                Ok(running) => {
+                    started.insert(running.rule.name().to_string(), running);
+                }
+                Err(e) => {
+                    // keep the old configuration running as is
+                    for running in started.into_values() {
+                        running.stop().await;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        let stale: Vec<String> = self
+            .running
+            .keys()
+            .filter(|name| started.contains_key(*name) || !names.contains(name))
+            .cloned()
+            .collect();
+        let mut changes = Changes::default();
+        for name in started.keys() {
+            match self.running.contains_key(name) {
+                true => changes.changed.push(name.clone()),
+                false => changes.added.push(name.clone()),
+            }
+        }
+        for name in stale {
+            if let Some(running) = self.running.remove(&name) {
+                running.stop().await;
+            }
+            if !started.contains_key(&name) {
+                changes.removed.push(name);
+            }
+        }
+        self.running.extend(started);
+        changes.added.sort();
+        changes.changed.sort();
+        changes.removed.sort();
+        Ok(changes)
+    }
+
+    /// stops all the rules, commands that are running are waited for,
+    /// then the files of every rule are saved to the state directory
+    pub async fn shutdown(self) {
+        for running in self.running.into_values() {
+            let rule = running.rule.clone();
+            running.stop().await;
+            // nothing is watched by the rules running after others
+            let Some(dir) = self.state.as_ref().filter(|_| rule.upstream().is_none()) else {
+                continue;
+            };
+            let saved = rule
+                .watch_args()
+                .and_then(|args| Manifest::scan(&args, None))
+                .and_then(|manifest| manifest.save(&state_file(dir, rule.name())));
+            if let Err(e) = saved {
+                tracing::warn!("rule `{}`: couldn't save state: {:#}", rule.name(), e);
+            }
+        }
+    }
+}
+
+impl Running {
+    fn start(
+        rule: Rule,
+        sinks: Sender,
+        state: Option<&Path>,
+        chains: &Chains,
+        groups: &Groups,
+    ) -> anyhow::Result<Self> {
+        let filter = Filter::new(&rule.include, &rule.exclude)?;
+        let group = groups.get(&rule);
+        let token = CancellationToken::new();
+        if let Some(upstream) = rule.upstream() {
+            let events = chains.after(upstream);
+            return Ok(Self::spawn(
+                rule, filter, events, sinks, chains, group, token,
+            ));
+        }
+
+        let args = rule.watch_args()?;
+        // watching before scanning, so nothing changed in between is lost
+        let watcher = Watcher::open(&args)?;
+        let missed = match state {
+            Some(dir) => missed(&rule, &args, dir).unwrap_or_else(|e| {
+                tracing::warn!("rule `{}`: couldn't load state: {:#}", rule.name(), e);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        let events = watcher.spawn_after(missed);
+        Ok(Self::spawn(
+            rule, filter, events, sinks, chains, group, token,
+        ))
+    }
+
+    fn spawn(
+        rule: Rule,
+        filter: Filter,
+        events: Batches,
+        sinks: Sender,
+        chains: &Chains,
+        group: Option<Group>,
+        token: CancellationToken,
+    ) -> Self {
+        let task = run_rule(
+            rule.clone(),
+            filter,
+            events,
+            sinks,
+            chains.clone(),
+            group,
+            token.clone(),
+        );
+        let name = rule.name().to_string();
+        let handle = tokio::spawn(async move {
+            if let Err(e) = task.await {
+                tracing::error!("rule `{}` stopped: {:#}", name, e);
+            }
+        });
+        Self {
+            rule,
+            token,
+            handle,
+        }
+    }
+
+    async fn stop(self) {
+        self.token.cancel();
+        let _ = self.handle.await;
+    }
+}
+
+/// the state file of the rule in the state directory
+fn state_file(dir: &Path, rule: &str) -> PathBuf {
+    dir.join(format!("{}.json", rule.replace('/', "_")))
+}
+
+/// the changes made to the files of the rule since its state was saved, as the
+/// events the rule asked for, nothing if the rule has no state yet
+fn missed(rule: &Rule, args: &WatchArgs, dir: &Path) -> anyhow::Result<Vec<Event>> {
+    let path = state_file(dir, rule.name());
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let old = Manifest::load(&path)?;
+    let mut events = Manifest::scan(args, None)?.changes(&old);
+    events.retain(|event| args.matches(event));
+    if !events.is_empty() {
+        tracing::info!(
+            "rule `{}`: {} files changed while stopped",
+            rule.name(),
+            events.len()
+        );
+    }
+    Ok(events)
+}
+
+/// runs the rules on recorded events instead of watching their paths,
+/// each record is given to every rule that would have seen the event
+pub struct Replay {
+    rules: Vec<Replayed>,
+}
+
+struct Replayed {
+    // `None` for the rules running after others, they get their events from them
+    matcher: Option<Matcher>,
+    events: mpsc::UnboundedSender<anyhow::Result<Vec<Event>>>,
+    handle: JoinHandle<()>,
+}
+
+impl Replay {
+    pub fn start(config: Config) -> anyhow::Result<Self> {
+        let mut rules = Vec::new();
+        let chains = Chains::default();
+        let groups = Groups::default();
+        for rule in config.rules {
+            let filter = Filter::new(&rule.include, &rule.exclude)?;
+            let (events, mut batches) = mpsc::unbounded_channel();
+            let matcher = match rule.upstream() {
+                Some(upstream) => {
+                    batches = chains.after(upstream);
+                    None
+                }
+                None => Some(Matcher::new(&rule.watch_args()?)?),
+            };
+
+            let name = rule.name().to_string();
+            let group = groups.get(&rule);
+            let task = run_rule(
+                rule,
+                filter,
+                batches,
+                Sender::default(),
+                chains.clone(),
+                group,
+                CancellationToken::new(),
+            );
+            let handle = tokio::spawn(async move {
+                if let Err(e) = task.await {
+                    tracing::error!("rule `{}` stopped: {:#}", name, e);
+                }
+            });
+            rules.push(Replayed {
+                matcher,
+                events,
+                handle,
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    pub fn feed(&self, record: &Record) -> anyhow::Result<()> {
+        for rule in &self.rules {
+            let event = record.event()?;
+            if rule.matcher.as_ref().is_some_and(|m| m.matches(&event)) {
+                let _ = rule.events.send(Ok(vec![event]));
+            }
+        }
+        Ok(())
+    }
+
+    /// waits for the rules to handle all the events that were fed
+    pub async fn finish(self) {
+        for rule in self.rules {
+            drop(rule.events);
+            let _ = rule.handle.await;
+        }
+    }
+}
+
+async fn run_rule(
+    rule: Rule,
+    filter: Filter,
+    mut batches: Batches,
+    sinks: Sender,
+    chains: Chains,
+    group: Option<Group>,
+    token: CancellationToken,
+) -> anyhow::Result<()> {
+    let mut limiter = rule.max_rate.map(|rate| Limiter::new(rule.name(), rate));
+    let capture = Capture::new(&rule.capture_args())?;
+    loop {
+        let batch = tokio::select! {
+            batch = debounce::next(&mut batches, rule.debounce, |e| filter.matches(&e.path)) => batch,
+            _ = token.cancelled() => break,
+        };
+        let Some(events) = batch else {
+            break;
+        };
+        let read = Instant::now();
+        let mut events = events?;
+        if let Some(limiter) = &mut limiter {
+            events.retain(|_| limiter.allow());
+            if events.is_empty() {
+                continue;
+            }
+        }
+        let events = Arc::new(events);
+        sinks.send(Some(rule.name()), events.clone());
+
+        // once started, commands are run to completion even if the rule is
+        // stopped, so a shutdown doesn't leave half done work behind
+        for event in events.iter() {
+            if token.is_cancelled() {
+                break;
+            }
+            // held until the command exited, so the other rules of the group wait for it
+            let _running = match &group {
+                Some(group) if rule.on_busy == Some(OnBusy::Drop) => {
+                    match group.clone().try_lock_owned() {
+                        Ok(running) => Some(running),
+                        Err(_) => {
+                            tracing::debug!(
+                                "rule `{}`: group busy, dropped {} {}",
+                                rule.name(),
+                                event.kind,
+                                event.path.display()
+                            );
+                            continue;
+                        }
+                    }
+                }
+                Some(group) => tokio::select! {
+                    running = group.clone().lock_owned() => Some(running),
+                    _ = token.cancelled() => break,
+                },
+                None => None,
+            };
+            tracing::debug!(
+                "rule `{}`: running the command for {} {}",
+                rule.name(),
+                event.kind,
+                event.path.display()
+            );
+            METRICS.rule_triggered(rule.name());
+            METRICS.latency(rule.name(), read.elapsed());
+            let started = Instant::now();
+            let title = format!(
+                "{} ({} {})",
+                rule.command.join(" "),
+                event.kind,
+                event.path.display()
+            );
+            let status = capture.status(&mut rule.command(event), &title).await;
+            METRICS.command(
+                rule.name(),
+                started.elapsed(),
+                status.as_ref().is_ok_and(|s| s.success()),
+            );
+
+            match status {
+                Ok(status) if !status.success() => {
+                    tracing::warn!("rule `{}`: command exited with {}", rule.name(), status);
+                }
+                Ok(_) => chains.succeeded(rule.name(), event),
+                Err(e) => tracing::warn!("rule `{}`: couldn't run command: {}", rule.name(), e),
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use anyhow::Context;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::capture::{Capture, Output};
+use crate::cli::RunArgs;
+use crate::condition::Conditions;
+use crate::exec;
+use crate::sandbox::Sandbox;
+use crate::watcher::Watcher;
+
+/// starts the command and restarts it every time a batch with matching
+/// events arrives, if the command exits on its own it is started again
+/// on the next change
+pub async fn run(args: RunArgs) -> anyhow::Result<()> {
+    let sandbox = Sandbox::new(&args.sandbox)?;
+    let capture = Capture::new(&args.capture)?;
+    let mut events = Watcher::open(&args.watch)?.spawn();
+    let mut conditions = Conditions::new(&args.conditions, &args.watch)?;
+    let (child, output) = spawn(&args.command, &sandbox, &capture, args.clear)?;
+    let (mut child, mut output) = (Some(child), Some(output));
+    let mut terminate = signal(SignalKind::terminate())?;
+
+    loop {
+        tokio::select! {
+            batch = events.recv() => {
+                let Some(batch) = batch else {
+                    break;
+                };
+                // every event goes through the conditions, so their cached copies stay current
+                let mut allowed = batch?.iter().filter(|event| conditions.allows(event)).count();
+                // collapse everything that arrived while we were busy
+                // into the same restart
+                while let Ok(batch) = events.try_recv() {
+                    allowed += batch?.iter().filter(|event| conditions.allows(event)).count();
+                }
+                if allowed == 0 {
+                    continue;
+                }
+
+                if let Some(child) = child.take() {
+                    stop(child, args.signal, args.stop_timeout).await?;
+                }
+                if let Some(output) = output.take() {
+                    output.finish(None).await;
+                }
+                let spawned = spawn(&args.command, &sandbox, &capture, args.clear)?;
+                (child, output) = (Some(spawned.0), Some(spawned.1));
+            }
+            status = wait(&mut child) => {
+                let status = status?;
+                tracing::warn!("`{}` exited with {}", args.command[0], status);
+                if let Some(output) = output.take() {
+                    output.finish(Some(&status)).await;
+                }
+                child = None;
+            }
+            // the child runs in its own process group, so it doesn't get the
+            // terminal signals and has to be stopped by us
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => break,
+        }
+    }
+
+    if let Some(child) = child {
+        stop(child, args.signal, args.stop_timeout).await?;
+    }
+    if let Some(output) = output {
+        output.finish(None).await;
+    }
+    Ok(())
+}
+
+/// spawns the command in its own process group, so the stop signal
+/// reaches the processes it started as well (e.g. `cargo run`)
+fn spawn(
+    command: &[String],
+    sandbox: &Sandbox,
+    capture: &Capture,
+    clear: bool,
+) -> anyhow::Result<(Child, Output)> {
+    if clear {
+        exec::clear_screen();
+    }
+    let mut cmd = Command::new(&command[0]);
+    cmd.args(&command[1..]).process_group(0);
+    sandbox.apply(&mut cmd);
+    capture.prepare(&mut cmd);
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("couldn't run `{}`", command[0]))?;
+    let output = capture.attach(&mut child, &command.join(" "));
+    Ok((child, output))
+}
+
+/// waits for the child to exit, never resolves if there is no child running
+async fn wait(child: &mut Option<Child>) -> std::io::Result<std::process::ExitStatus> {
+    match child {
+        Some(child) => child.wait().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// sends the signal to the child process group and waits for it to exit,
+/// if it didn't exit after `timeout` the process group is killed
+async fn stop(mut child: Child, signal: i32, timeout: Duration) -> anyhow::Result<()> {
+    let Some(pid) = child.id() else {
+        // the child was already reaped
+        return Ok(());
+    };
+
+    unsafe {
+        libc::kill(-(pid as i32), signal);
+    }
+    if tokio::time::timeout(timeout, child.wait()).await.is_err() {
+        tracing::warn!(
+            "process didn't exit after {}, killing it",
+            humantime::format_duration(timeout)
+        );
+        unsafe {
+            libc::kill(-(pid as i32), libc::SIGKILL);
+        }
+        child.wait().await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use anyhow::Context;
+use std::ffi::{CStr, CString, OsString};
+use std::io;
+use tokio::process::Command;
+
+use crate::cli::SandboxArgs;
+
+/// variables `--clean-env` keeps besides the ones given with `--keep-env`
+const KEPT_ENV: &[&str] = &["PATH", "LANG", "TERM", "TZ"];
+
+/// restrictions applied to the commands tube spawns, users and
+/// groups are resolved once when tube starts
+pub struct Sandbox {
+    user: Option<User>,
+    gid: Option<u32>,
+    no_new_privs: bool,
+    clean_env: bool,
+    keep_env: Vec<String>,
+}
+
+struct User {
+    uid: u32,
+    gid: u32,
+    name: String,
+    home: String,
+}
+
+impl Sandbox {
+    pub fn new(args: &SandboxArgs) -> anyhow::Result<Self> {
+        let user = args.user.as_deref().map(user).transpose()?;
+        let gid = match &args.group {
+            Some(name) => Some(group(name)?),
+            None => user.as_ref().map(|user| user.gid),
+        };
+        Ok(Self {
+            user,
+            gid,
+            no_new_privs: args.no_new_privs,
+            clean_env: args.clean_env,
+            keep_env: args.keep_env.clone(),
+        })
+    }
+
+    pub fn apply(&self, cmd: &mut Command) {
+        if self.clean_env {
+            // the variables set on the command so far (e.g. `TUBE_PATH`) are kept
+            let set: Vec<(OsString, OsString)> = cmd
+                .as_std()
+                .get_envs()
+                .filter_map(|(name, value)| Some((name.to_owned(), value?.to_owned())))
+                .collect();
+            cmd.env_clear();
+            let kept = KEPT_ENV
+                .iter()
+                .copied()
+                .chain(self.keep_env.iter().map(String::as_str));
+            for name in kept {
+                if let Some(value) = std::env::var_os(name) {
+                    cmd.env(name, value);
+                }
+            }
+            cmd.envs(set);
+        }
+        // when dropping from root the supplementary groups are cleared as well
+        if let Some(user) = &self.user {
+            cmd.uid(user.uid)
+                .env("HOME", &user.home)
+                .env("USER", &user.name)
+                .env("LOGNAME", &user.name);
+        }
+        if let Some(gid) = self.gid {
+            cmd.gid(gid);
+        }
+        if self.no_new_privs {
+            unsafe {
+                cmd.pre_exec(|| {
+                    if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+        }
+    }
+}
+
+/// looks up the user by name, or by uid if it is a number
+fn user(name: &str) -> anyhow::Result<User> {
+    let passwd = match name.parse::<u32>() {
+        Ok(uid) => unsafe { libc::getpwuid(uid) },
+        Err(_) => {
+            let cname = CString::new(name).context("invalid user name")?;
+            unsafe { libc::getpwnam(cname.as_ptr()) }
+        }
+    };
+    if passwd.is_null() {
+        anyhow::bail!("unknown user `{}`", name);
+    }
+    let passwd = unsafe { &*passwd };
+    let string = |ptr| {
+        unsafe { CStr::from_ptr(ptr) }
+            .to_string_lossy()
+            .into_owned()
+    };
+    Ok(User {
+        uid: passwd.pw_uid,
+        gid: passwd.pw_gid,
+        name: string(passwd.pw_name),
+        home: string(passwd.pw_dir),
+    })
+}
+
+/// looks up the group by name, or by gid if it is a number
+fn group(name: &str) -> anyhow::Result<u32> {
+    if let Ok(gid) = name.parse() {
+        return Ok(gid);
+    }
+    let cname = CString::new(name).context("invalid group name")?;
+    let group = unsafe { libc::getgrnam(cname.as_ptr()) };
+    if group.is_null() {
+        anyhow::bail!("unknown group `{}`", name);
+    }
+    Ok(unsafe { (*group).gr_gid })
+}
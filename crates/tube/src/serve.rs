@@ -0,0 +1,99 @@
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::sse::{self, KeepAlive, Sse};
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use futures::stream::{self, Stream};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::cli::ServeArgs;
+use crate::output::Record;
+use crate::systemd::{self, Watchdog};
+use crate::watcher::{Batches, Watcher};
+
+/// how many events a subscriber can fall behind before it starts missing events
+const SUBSCRIBER_BUFFER: usize = 1024;
+
+type Events = broadcast::Sender<Arc<str>>;
+
+/// serves the live event stream over HTTP, as Server-Sent Events on
+/// `/events` and as a WebSocket on `/ws`, every event is a JSON object
+pub async fn run(args: ServeArgs) -> anyhow::Result<()> {
+    let batches = Watcher::open(&args.watch)?.spawn();
+    let (events, _) = broadcast::channel(SUBSCRIBER_BUFFER);
+
+    let app = Router::new()
+        .route("/events", get(sse))
+        .route("/ws", get(websocket))
+        .with_state(events.clone());
+    let listener = match systemd::listener() {
+        Some(listener) => TcpListener::from_std(listener)?,
+        None => TcpListener::bind(args.listen).await?,
+    };
+    tracing::info!("serving events on http://{}", listener.local_addr()?);
+    systemd::notify("READY=1");
+
+    tokio::select! {
+        result = axum::serve(listener, app) => result?,
+        result = publish(batches, events) => result?,
+        _ = Watchdog::new().run() => {}
+    }
+    Ok(())
+}
+
+/// serializes every event once and broadcasts it to all the subscribers
+async fn publish(mut batches: Batches, events: Events) -> anyhow::Result<()> {
+    while let Some(batch) = batches.recv().await {
+        for event in batch? {
+            let json = serde_json::to_string(&Record::new(&event))?;
+            // no subscribers is not an error, the event is just not delivered
+            let _ = events.send(json.into());
+        }
+    }
+    Ok(())
+}
+
+async fn sse(
+    State(events): State<Events>,
+) -> Sse<impl Stream<Item = Result<sse::Event, Infallible>>> {
+    let stream = stream::unfold(events.subscribe(), |mut rx| async move {
+        let event = match rx.recv().await {
+            Ok(json) => sse::Event::default().data(&*json),
+            // let the client know it missed events instead of failing silently
+            Err(RecvError::Lagged(n)) => sse::Event::default().event("lagged").data(n.to_string()),
+            Err(RecvError::Closed) => return None,
+        };
+        Some((Ok(event), rx))
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+async fn websocket(ws: WebSocketUpgrade, State(events): State<Events>) -> Response {
+    ws.on_upgrade(move |socket| forward(socket, events.subscribe()))
+}
+
+async fn forward(mut socket: WebSocket, mut rx: broadcast::Receiver<Arc<str>>) {
+    loop {
+        tokio::select! {
+            event = rx.recv() => {
+                let message = match event {
+                    Ok(json) => Message::Text(json.to_string()),
+                    Err(RecvError::Lagged(n)) => Message::Text(format!("{{\"lagged\":{}}}", n)),
+                    Err(RecvError::Closed) => break,
+                };
+                if socket.send(message).await.is_err() {
+                    break;
+                }
+            }
+            // the client doesn't send anything, reading only detects it went away
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use anyhow::Context;
+use rusqlite::{params, Connection};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use tube_inotify::Event;
+
+use super::Sink;
+use crate::output::Record;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS events (
+        id INTEGER PRIMARY KEY,
+        ts TEXT NOT NULL,
+        path TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        cookie INTEGER NOT NULL,
+        is_dir INTEGER NOT NULL,
+        rule TEXT
+    );
+    CREATE INDEX IF NOT EXISTS events_ts ON events (ts);
+    CREATE INDEX IF NOT EXISTS events_path ON events (path);
+";
+
+/// the changes made to the schema since the first version, applied in order
+/// to the databases whose `user_version` is behind
+const MIGRATIONS: &[&str] = &["
+    ALTER TABLE events ADD COLUMN pid INTEGER;
+    ALTER TABLE events ADD COLUMN uid INTEGER;
+    ALTER TABLE events ADD COLUMN exe TEXT;
+    ALTER TABLE events ADD COLUMN changes TEXT;
+    ALTER TABLE events ADD COLUMN diff TEXT;
+"];
+
+/// opens the audit database, creating the schema if it doesn't exist yet
+pub fn connect(path: &Path) -> anyhow::Result<Connection> {
+    let conn = Connection::open(path)
+        .with_context(|| format!("couldn't open audit database `{}`", path.display()))?;
+    // lets `tube audit query` read while events are written
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    conn.execute_batch(SCHEMA)?;
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        conn.execute_batch(migration)?;
+        conn.pragma_update(None, "user_version", (i + 1) as i64)?;
+    }
+    Ok(conn)
+}
+
+/// appends the events to an SQLite database, the writes happen on their
+/// own thread since sqlite blocks
+pub struct Audit {
+    tx: mpsc::Sender<Vec<Record<'static>>>,
+    writer: JoinHandle<()>,
+}
+
+impl Audit {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut conn = connect(path)?;
+        let (tx, rx) = mpsc::channel::<Vec<Record>>();
+        let writer = std::thread::spawn(move || {
+            for records in rx {
+                if let Err(e) = insert(&mut conn, &records) {
+                    tracing::warn!("couldn't write audit entries: {}", e);
+                }
+            }
+        });
+        Ok(Self { tx, writer })
+    }
+
+    /// records the events, `rule` is the name of the rule they triggered, if any
+    pub fn log(&self, rule: Option<&str>, events: &[Event]) {
+        let records = events
+            .iter()
+            .map(|event| Record::new(event).with_rule(rule).into_owned())
+            .collect();
+        self.write(records);
+    }
+
+    /// records what the caller added to the records as well, like their diff
+    pub fn write(&self, records: Vec<Record<'static>>) {
+        let _ = self.tx.send(records);
+    }
+
+    /// waits for the records already sent to be written
+    pub fn close(self) {
+        drop(self.tx);
+        let _ = self.writer.join();
+    }
+}
+
+impl Sink for Audit {
+    async fn send(&mut self, rule: Option<&str>, events: &[Event]) {
+        self.log(rule, events);
+    }
+}
+
+fn insert(conn: &mut Connection, records: &[Record]) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare_cached(
+            "INSERT INTO events (ts, path, kind, cookie, is_dir, rule, pid, uid, exe, changes, diff)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        )?;
+        for record in records {
+            let process = record.process.as_ref();
+            let changes = (!record.changes.is_empty()).then(|| record.changes.join(", "));
+            stmt.execute(params![
+                record.ts,
+                record.path,
+                record.kind,
+                record.cookie,
+                record.is_dir,
+                record.rule,
+                process.map(|process| process.pid),
+                process.and_then(|process| process.uid),
+                process.and_then(|process| process.exe.as_deref()),
+                changes,
+                record.diff
+            ])?;
+        }
+    }
+    tx.commit()
+}
@@ -0,0 +1,187 @@
+use anyhow::Context;
+use clap::ValueEnum;
+use serde_json::json;
+use std::borrow::Cow;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tube_inotify::Event;
+
+use super::digest::Digest;
+use super::{hostname, Sink};
+use crate::cli::SinkArgs;
+use crate::exec::substitute;
+use crate::metrics::METRICS;
+use crate::output::Record;
+use crate::rate::Limiter;
+
+/// max number of events listed in a message, the others are only counted
+const MAX_LISTED: usize = 20;
+
+/// chat services with incoming webhooks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ChatService {
+    Slack,
+    Discord,
+    Teams,
+}
+
+impl ChatService {
+    /// recognizes the service from the host of its webhook
+    fn detect(url: &reqwest::Url) -> Option<Self> {
+        let host = url.host_str()?;
+        if host == "hooks.slack.com" {
+            Some(Self::Slack)
+        } else if host.ends_with("discord.com") || host.ends_with("discordapp.com") {
+            Some(Self::Discord)
+        } else if host.ends_with(".webhook.office.com") || host.ends_with(".logic.azure.com") {
+            Some(Self::Teams)
+        } else {
+            None
+        }
+    }
+
+    fn body(self, text: &str) -> serde_json::Value {
+        match self {
+            Self::Slack | Self::Teams => json!({ "text": text }),
+            // a file named `@everyone` doesn't notify anyone
+            Self::Discord => json!({ "content": text, "allowed_mentions": { "parse": [] } }),
+        }
+    }
+
+    /// a value put in the template, Slack reads `<...>` as links and mentions
+    /// (`<!channel>`), so its control characters are escaped
+    fn escape(self, value: &str) -> Cow<'_, str> {
+        match self {
+            Self::Slack if value.contains(['&', '<', '>']) => Cow::Owned(
+                value
+                    .replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;"),
+            ),
+            _ => Cow::Borrowed(value),
+        }
+    }
+}
+
+/// posts the events as a chat message, one per batch or per digest
+pub struct Chat {
+    poster: Arc<Mutex<Poster>>,
+    digest: Option<Digest>,
+}
+
+struct Poster {
+    client: reqwest::Client,
+    url: String,
+    service: ChatService,
+    template: String,
+    limiter: Option<Limiter>,
+}
+
+impl Chat {
+    pub fn new(url: &str, args: &SinkArgs) -> anyhow::Result<Self> {
+        let parsed =
+            reqwest::Url::parse(url).with_context(|| format!("invalid chat url `{}`", url))?;
+        let service = args
+            .chat_service
+            .or_else(|| ChatService::detect(&parsed))
+            .with_context(|| {
+                format!(
+                    "can't tell the service of `{}`, give it with `--chat-service`",
+                    url
+                )
+            })?;
+        let poster = Arc::new(Mutex::new(Poster {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()?,
+            url: url.to_string(),
+            service,
+            template: args.chat_template.clone(),
+            limiter: args.chat_max_rate.map(|rate| Limiter::new("chat", rate)),
+        }));
+
+        let digest = args.chat_digest.map(|duration| {
+            let poster = poster.clone();
+            Digest::spawn(duration, move |records| {
+                let poster = poster.clone();
+                async move { poster.lock().await.post(&records).await }
+            })
+        });
+        Ok(Self { poster, digest })
+    }
+}
+
+impl Sink for Chat {
+    async fn send(&mut self, rule: Option<&str>, events: &[Event]) {
+        let records: Vec<Record> = events
+            .iter()
+            .map(|event| Record::new(event).with_rule(rule).into_owned())
+            .collect();
+        match &self.digest {
+            Some(digest) => digest.push(records),
+            None => self.poster.lock().await.post(&records).await,
+        }
+    }
+
+    async fn close(&mut self) {
+        if let Some(digest) = &mut self.digest {
+            digest.close().await;
+        }
+    }
+}
+
+impl Poster {
+    async fn post(&mut self, records: &[Record<'_>]) {
+        if self
+            .limiter
+            .as_mut()
+            .is_some_and(|limiter| !limiter.allow())
+        {
+            METRICS.undelivered("chat", records.len());
+            return;
+        }
+        let mut lines: Vec<String> = records
+            .iter()
+            .take(MAX_LISTED)
+            .map(|record| self.line(record))
+            .collect();
+        if records.len() > MAX_LISTED {
+            lines.push(format!("and {} more", records.len() - MAX_LISTED));
+        }
+
+        let body = self.service.body(&lines.join("\n"));
+        let posted = self.client.post(&self.url).json(&body).send().await;
+        if let Err(e) = posted.and_then(|response| response.error_for_status()) {
+            METRICS.undelivered("chat", records.len());
+            tracing::warn!(
+                "chat webhook failed ({}), dropping {} events",
+                e,
+                records.len()
+            );
+        }
+    }
+
+    /// the template expanded with the values of the record
+    fn line(&self, record: &Record) -> String {
+        let path = Path::new(record.path.as_ref());
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let dir = path.parent().unwrap_or(Path::new("/")).to_string_lossy();
+        let escape = |value| self.service.escape(value);
+        let values = [
+            ("path", escape(&record.path)),
+            ("name", escape(&name)),
+            ("dir", escape(&dir)),
+            ("kind", escape(&record.kind)),
+            ("hostname", escape(hostname())),
+            ("rule", escape(record.rule.as_deref().unwrap_or_default())),
+        ];
+        substitute(&self.template, |key| {
+            values
+                .iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| value.as_ref())
+        })
+    }
+}
@@ -0,0 +1,78 @@
+use clap::ValueEnum;
+use tube_inotify::Event;
+use zbus::names::BusName;
+use zbus::Connection;
+
+use super::Sink;
+use crate::metrics::METRICS;
+
+const PATH: &str = "/org/tube";
+const INTERFACE: &str = "org.tube";
+const SIGNAL: &str = "FileEvent";
+
+/// the bus `--dbus` emits the signals on
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum Bus {
+    #[default]
+    Session,
+    System,
+}
+
+/// emits an `org.tube.FileEvent` signal per event from `/org/tube`, with
+/// the path, kind, cookie, is_dir and rule (empty outside of rules) as arguments
+pub struct Dbus {
+    bus: Bus,
+    // connected on the first batch
+    conn: Option<Connection>,
+}
+
+impl Dbus {
+    pub fn new(bus: Bus) -> Self {
+        Self { bus, conn: None }
+    }
+
+    async fn connection(&mut self) -> zbus::Result<&Connection> {
+        if self.conn.is_none() {
+            let conn = match self.bus {
+                Bus::Session => Connection::session().await?,
+                Bus::System => Connection::system().await?,
+            };
+            self.conn = Some(conn);
+        }
+        Ok(self.conn.as_ref().unwrap())
+    }
+}
+
+impl Sink for Dbus {
+    async fn send(&mut self, rule: Option<&str>, events: &[Event]) {
+        let conn = match self.connection().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                METRICS.undelivered("dbus", events.len());
+                tracing::warn!(
+                    "couldn't connect to d-bus ({}), dropping {} events",
+                    e,
+                    events.len()
+                );
+                return;
+            }
+        };
+
+        for event in events {
+            let body = (
+                event.path.to_string_lossy(),
+                event.kind.to_string(),
+                event.cookie,
+                event.is_dir,
+                rule.unwrap_or_default(),
+            );
+            let emitted = conn
+                .emit_signal(None::<BusName>, PATH, INTERFACE, SIGNAL, &body)
+                .await;
+            if let Err(e) = emitted {
+                METRICS.undelivered("dbus", 1);
+                tracing::warn!("couldn't emit d-bus signal: {}", e);
+            }
+        }
+    }
+}
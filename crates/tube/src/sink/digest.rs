@@ -0,0 +1,52 @@
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::output::Record;
+
+/// collects the records of the sinks that deliver digests, the records that
+/// follow the first one are collected for the duration and delivered with it
+pub struct Digest {
+    tx: Option<mpsc::UnboundedSender<Vec<Record<'static>>>>,
+    task: JoinHandle<()>,
+}
+
+impl Digest {
+    pub fn spawn<F, D>(duration: Duration, mut deliver: F) -> Self
+    where
+        F: FnMut(Vec<Record<'static>>) -> D + Send + 'static,
+        D: Future<Output = ()> + Send,
+    {
+        let (tx, mut rx) = mpsc::unbounded_channel::<Vec<Record<'static>>>();
+        let task = tokio::spawn(async move {
+            while let Some(mut records) = rx.recv().await {
+                let deadline = Instant::now() + duration;
+                loop {
+                    tokio::select! {
+                        more = rx.recv() => match more {
+                            Some(more) => records.extend(more),
+                            None => break,
+                        },
+                        _ = tokio::time::sleep_until(deadline) => break,
+                    }
+                }
+                deliver(records).await;
+            }
+        });
+        Self { tx: Some(tx), task }
+    }
+
+    pub fn push(&self, records: Vec<Record<'static>>) {
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(records);
+        }
+    }
+
+    /// delivers the digest being collected right away
+    pub async fn close(&mut self) {
+        self.tx = None;
+        let _ = (&mut self.task).await;
+    }
+}
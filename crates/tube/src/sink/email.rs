@@ -0,0 +1,305 @@
+use anyhow::Context;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use percent_encoding::percent_decode_str;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+use tube_inotify::Event;
+
+use super::digest::Digest;
+use super::{hostname, Sink};
+use crate::cli::SinkArgs;
+use crate::metrics::METRICS;
+use crate::output::Record;
+
+/// the whole SMTP session, from connecting to `QUIT`
+const SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+/// max number of events listed in a digest, the others are only counted
+const MAX_LISTED: usize = 1000;
+
+/// emails the events as digests, the events that follow the first one are
+/// collected for the digest duration and sent along in the same email
+pub struct Email {
+    digest: Digest,
+}
+
+/// the SMTP server and the email sent through it
+struct Mailer {
+    host: String,
+    port: u16,
+    // TLS from the start, otherwise STARTTLS when the server offers it
+    tls: bool,
+    // the credentials may be sent without TLS
+    insecure: bool,
+    credentials: Option<(String, String)>,
+    from: String,
+    to: Vec<String>,
+    subject: String,
+}
+
+impl Email {
+    /// `url` is `smtp://[user:password@]host[:port]`, `smtps://` or `smtp+insecure://`
+    pub fn new(url: &str, args: &SinkArgs) -> anyhow::Result<Self> {
+        let parsed =
+            reqwest::Url::parse(url).with_context(|| format!("invalid email url `{}`", url))?;
+        let (tls, insecure) = match parsed.scheme() {
+            "smtp" => (false, false),
+            "smtps" => (true, false),
+            "smtp+insecure" => (false, true),
+            _ => anyhow::bail!(
+                "invalid email url `{}`, expected `smtp://`, `smtps://` or `smtp+insecure://`",
+                url
+            ),
+        };
+        let host = parsed
+            .host_str()
+            .with_context(|| format!("invalid email url `{}`, the host is missing", url))?;
+        let decode = |part: &str| percent_decode_str(part).decode_utf8_lossy().into_owned();
+        let credentials = match parsed.username() {
+            "" => None,
+            user => Some((decode(user), decode(parsed.password().unwrap_or_default()))),
+        };
+        let mailer = Mailer {
+            host: host.to_string(),
+            port: parsed.port().unwrap_or(if tls { 465 } else { 25 }),
+            tls,
+            insecure,
+            credentials,
+            from: args
+                .email_from
+                .clone()
+                .unwrap_or_else(|| format!("tube@{}", hostname())),
+            to: args.email_to.clone(),
+            subject: args.email_subject.clone(),
+        };
+
+        let mailer = Arc::new(mailer);
+        let digest = Digest::spawn(args.email_digest, move |records| {
+            let mailer = mailer.clone();
+            async move {
+                let sent = timeout(SESSION_TIMEOUT, mailer.send(&records))
+                    .await
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("timed out")));
+                if let Err(e) = sent {
+                    METRICS.undelivered("email", records.len());
+                    tracing::warn!("couldn't email {} events: {:#}", records.len(), e);
+                }
+            }
+        });
+        Ok(Self { digest })
+    }
+}
+
+impl Sink for Email {
+    async fn send(&mut self, rule: Option<&str>, events: &[Event]) {
+        let records = events
+            .iter()
+            .map(|event| Record::new(event).with_rule(rule).into_owned())
+            .collect();
+        self.digest.push(records);
+    }
+
+    async fn close(&mut self) {
+        self.digest.close().await;
+    }
+}
+
+trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Io for T {}
+
+/// a session with the SMTP server, over TCP or TLS
+struct Smtp {
+    stream: BufReader<Box<dyn Io>>,
+}
+
+impl Smtp {
+    fn new(stream: Box<dyn Io>) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+        }
+    }
+
+    /// sends the command and reads its reply, see `reply`
+    async fn command(&mut self, command: &str, class: u16) -> anyhow::Result<String> {
+        self.write_line(command).await?;
+        let verb = command.split(' ').next().unwrap_or_default();
+        self.reply(class)
+            .await
+            .with_context(|| format!("{} failed", verb))
+    }
+
+    async fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        let stream = self.stream.get_mut();
+        stream.write_all(line.as_bytes()).await?;
+        stream.write_all(b"\r\n").await
+    }
+
+    /// reads the next reply, the lines of multiline replies are kept, fails
+    /// when the reply code isn't of the class (`2` for `250`)
+    async fn reply(&mut self, class: u16) -> anyhow::Result<String> {
+        let mut reply = String::new();
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await? == 0 {
+                anyhow::bail!("the server closed the connection");
+            }
+            reply.push_str(line.trim_end());
+            reply.push('\n');
+            // `250-` is followed by more lines, `250 ` is the last one
+            if line.as_bytes().get(3) != Some(&b'-') {
+                break;
+            }
+        }
+        let code = reply
+            .get(..3)
+            .and_then(|code| code.parse::<u16>().ok())
+            .with_context(|| format!("invalid reply `{}`", reply.trim_end()))?;
+        anyhow::ensure!(
+            code / 100 == class,
+            "the server replied `{}`",
+            reply.trim_end()
+        );
+        Ok(reply)
+    }
+}
+
+impl Mailer {
+    async fn send(&self, records: &[Record<'_>]) -> anyhow::Result<()> {
+        let tcp = TcpStream::connect((self.host.as_str(), self.port))
+            .await
+            .with_context(|| format!("couldn't connect to `{}:{}`", self.host, self.port))?;
+        let mut stream: Box<dyn Io> = Box::new(tcp);
+        if self.tls {
+            stream = Box::new(self.tls(stream).await?);
+        }
+        let mut smtp = Smtp::new(stream);
+        smtp.reply(2).await?;
+        let ehlo = format!("EHLO {}", hostname());
+        let extensions = smtp.command(&ehlo, 2).await?;
+        let starttls = extensions.lines().any(|line| {
+            line.get(4..)
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("STARTTLS"))
+        });
+        if !self.tls && starttls {
+            smtp.command("STARTTLS", 2).await?;
+            smtp = Smtp::new(Box::new(self.tls(smtp.stream.into_inner()).await?));
+            smtp.command(&ehlo, 2).await?;
+        }
+        if let Some((user, password)) = &self.credentials {
+            // a server not offering STARTTLS may be a connection it was stripped from
+            anyhow::ensure!(
+                self.tls || starttls || self.insecure,
+                "`{}` doesn't offer STARTTLS, the credentials aren't sent without TLS \
+                 unless the url is `smtp+insecure://`",
+                self.host
+            );
+            let token = BASE64_STANDARD.encode(format!("\0{}\0{}", user, password));
+            smtp.command(&format!("AUTH PLAIN {}", token), 2).await?;
+        }
+
+        smtp.command(&format!("MAIL FROM:<{}>", self.from), 2)
+            .await?;
+        for to in &self.to {
+            smtp.command(&format!("RCPT TO:<{}>", to), 2).await?;
+        }
+        smtp.command("DATA", 3).await?;
+        smtp.write_line(&self.message(records)).await?;
+        smtp.reply(2).await.context("the email was refused")?;
+        // the email was accepted already
+        let _ = smtp.command("QUIT", 2).await;
+        Ok(())
+    }
+
+    async fn tls(&self, stream: Box<dyn Io>) -> anyhow::Result<TlsStream<Box<dyn Io>>> {
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let name = ServerName::try_from(self.host.clone())?;
+        let stream = TlsConnector::from(Arc::new(config))
+            .connect(name, stream)
+            .await
+            .with_context(|| format!("TLS with `{}` failed", self.host))?;
+        Ok(stream)
+    }
+
+    /// the email with its headers, ending with the line that ends `DATA`
+    fn message(&self, records: &[Record]) -> String {
+        let mut rules: Vec<&str> = records
+            .iter()
+            .filter_map(|record| record.rule.as_deref())
+            .collect();
+        rules.sort();
+        rules.dedup();
+        let subject = self
+            .subject
+            .replace("{count}", &records.len().to_string())
+            .replace("{hostname}", hostname())
+            .replace("{rule}", &rules.join(", "));
+
+        let mut message = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\n\
+             Content-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n",
+            self.from,
+            self.to.join(", "),
+            encode_header(&subject),
+            date()
+        );
+        for record in records.iter().take(MAX_LISTED) {
+            let mut line = format!("{} {} {}", record.ts, record.kind, record.path);
+            if let Some(rule) = &record.rule {
+                line += &format!(" rule={}", rule);
+            }
+            if let Some(process) = &record.process {
+                line += &format!(" pid={}", process.pid);
+                if let Some(exe) = &process.exe {
+                    line += &format!(" exe={}", exe);
+                }
+            }
+            // the lines start with the timestamp, never with the dot ending `DATA`,
+            // paths can hold line breaks though
+            message += &line.replace(['\r', '\n'], " ");
+            message += "\r\n";
+        }
+        if records.len() > MAX_LISTED {
+            message += &format!("and {} more\r\n", records.len() - MAX_LISTED);
+        }
+        message += ".";
+        message
+    }
+}
+
+/// the header value as is when it's ascii, base64 encoded otherwise (RFC 2047)
+fn encode_header(value: &str) -> String {
+    let value = value.replace(['\r', '\n'], " ");
+    match value.is_ascii() {
+        true => value,
+        false => format!("=?utf-8?B?{}?=", BASE64_STANDARD.encode(value)),
+    }
+}
+
+/// the current time as the `Date` header wants it, in UTC
+fn date() -> String {
+    let mut buf = [0u8; 64];
+    let len = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::gmtime_r(&now, &mut tm);
+        libc::strftime(
+            buf.as_mut_ptr().cast(),
+            buf.len(),
+            c"%a, %d %b %Y %H:%M:%S +0000".as_ptr(),
+            &tm,
+        )
+    };
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
@@ -0,0 +1,139 @@
+use anyhow::Context;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Instant};
+use tube_inotify::Event;
+
+use super::Sink;
+use crate::metrics::METRICS;
+use crate::output::Record;
+
+/// delay before the first reconnect, doubled after every failed attempt
+const BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+/// frames that don't fit in the buffer anymore are dropped
+const MAX_BUFFER_SIZE: u64 = 64 * 1024 * 1024;
+
+/// streams the events to a remote collector, every event is a JSON object
+/// prefixed by its length as a big endian u32, while the collector can't
+/// be reached the frames are appended to a file and sent once it's back,
+/// so events may be delivered twice but not lost
+pub struct Forward {
+    addr: String,
+    buffer: PathBuf,
+    stream: Option<TcpStream>,
+    backoff: Duration,
+    next_attempt: Instant,
+}
+
+impl Forward {
+    /// `url` is `tcp://host:port`, the buffer file defaults to one named
+    /// after the address in the temp directory, so a restarted tube picks it up
+    pub fn new(url: &str, buffer: Option<PathBuf>) -> anyhow::Result<Self> {
+        let addr = url
+            .strip_prefix("tcp://")
+            .with_context(|| format!("invalid forward url `{}`, expected `tcp://host:port`", url))?
+            .to_string();
+        let buffer = buffer.unwrap_or_else(|| {
+            std::env::temp_dir().join(format!("tube-forward-{}.buf", addr.replace(':', "-")))
+        });
+        Ok(Self {
+            addr,
+            buffer,
+            stream: None,
+            backoff: BACKOFF,
+            next_attempt: Instant::now(),
+        })
+    }
+
+    /// connects and sends what was buffered while disconnected
+    async fn connect(&mut self) -> io::Result<TcpStream> {
+        let mut stream = timeout(IO_TIMEOUT, TcpStream::connect(&self.addr)).await??;
+        let buffered = match fs::read(&self.buffer).await {
+            Ok(buffered) => buffered,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        if !buffered.is_empty() {
+            timeout(IO_TIMEOUT, stream.write_all(&buffered)).await??;
+            fs::remove_file(&self.buffer).await?;
+        }
+        Ok(stream)
+    }
+
+    async fn write(&mut self, frames: &[u8]) -> io::Result<()> {
+        if self.stream.is_none() && Instant::now() >= self.next_attempt {
+            match self.connect().await {
+                Ok(stream) => {
+                    self.stream = Some(stream);
+                    self.backoff = BACKOFF;
+                }
+                Err(e) => self.disconnected(e),
+            }
+        }
+        let Some(stream) = &mut self.stream else {
+            return self.buffer(frames).await;
+        };
+        match timeout(IO_TIMEOUT, stream.write_all(frames)).await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(e)) => {
+                self.disconnected(e);
+                self.buffer(frames).await
+            }
+            Err(e) => {
+                self.disconnected(e.into());
+                self.buffer(frames).await
+            }
+        }
+    }
+
+    fn disconnected(&mut self, e: io::Error) {
+        tracing::warn!(
+            "couldn't forward to `{}` ({}), retrying in {:?}",
+            self.addr,
+            e,
+            self.backoff
+        );
+        self.stream = None;
+        self.next_attempt = Instant::now() + self.backoff;
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+    }
+
+    async fn buffer(&self, frames: &[u8]) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.buffer)
+            .await?;
+        if file.metadata().await?.len() + frames.len() as u64 > MAX_BUFFER_SIZE {
+            return Err(io::Error::other("the buffer file is full"));
+        }
+        file.write_all(frames).await
+    }
+}
+
+impl Sink for Forward {
+    async fn send(&mut self, rule: Option<&str>, events: &[Event]) {
+        let mut frames = Vec::new();
+        for event in events {
+            let json = serde_json::to_vec(&Record::new(event).with_rule(rule))
+                .expect("records are serializable");
+            frames.extend_from_slice(&(json.len() as u32).to_be_bytes());
+            frames.extend_from_slice(&json);
+        }
+        if let Err(e) = self.write(&frames).await {
+            METRICS.undelivered("forward", events.len());
+            tracing::warn!(
+                "couldn't buffer events for `{}` ({}), dropping {} events",
+                self.addr,
+                e,
+                events.len()
+            );
+        }
+    }
+}
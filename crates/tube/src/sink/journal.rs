@@ -0,0 +1,154 @@
+use anyhow::Context;
+use clap::ValueEnum;
+use std::io::Write;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use std::time::SystemTime;
+use tube_inotify::{Event, EventKind};
+
+use super::Sink;
+
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+const SYSLOG_SOCKET: &str = "/dev/log";
+
+/// syslog `user` facility
+const FACILITY_USER: u8 = 1;
+/// private enterprise number used for the syslog structured data id
+const SD_ID: &str = "tube@32473";
+
+/// system logs events can be written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogOutput {
+    /// the systemd journal, with `TUBE_*` fields
+    Journald,
+    /// the local syslog daemon, with RFC 5424 structured data
+    Syslog,
+}
+
+/// writes an entry per event to the system log
+pub struct Journal {
+    output: LogOutput,
+    socket: UnixDatagram,
+}
+
+impl Journal {
+    pub fn open(output: LogOutput) -> anyhow::Result<Self> {
+        let path = match output {
+            LogOutput::Journald => JOURNALD_SOCKET,
+            LogOutput::Syslog => SYSLOG_SOCKET,
+        };
+        let socket = UnixDatagram::unbound()?;
+        socket
+            .connect(path)
+            .with_context(|| format!("couldn't connect to `{}`", path))?;
+        Ok(Self { output, socket })
+    }
+
+    /// writes a message of tube itself rather than an event
+    pub fn log(&self, message: &str) -> std::io::Result<()> {
+        let entry = match self.output {
+            LogOutput::Journald => {
+                let mut entry = Vec::new();
+                field(&mut entry, "MESSAGE", message);
+                field(&mut entry, "PRIORITY", "6");
+                field(&mut entry, "SYSLOG_IDENTIFIER", "tube");
+                entry
+            }
+            LogOutput::Syslog => format!(
+                "<{}>1 {} {} tube {} - - {}",
+                FACILITY_USER * 8 + 6,
+                humantime::format_rfc3339_micros(SystemTime::now()),
+                hostname(),
+                std::process::id(),
+                message,
+            )
+            .into_bytes(),
+        };
+        self.socket.send(&entry).map(|_| ())
+    }
+
+    fn entry(&self, event: &Event) -> Vec<u8> {
+        match self.output {
+            LogOutput::Journald => journald_entry(event),
+            LogOutput::Syslog => syslog_entry(event),
+        }
+    }
+}
+
+impl Sink for Journal {
+    async fn send(&mut self, _rule: Option<&str>, events: &[Event]) {
+        for event in events {
+            if let Err(e) = self.socket.send(&self.entry(event)) {
+                tracing::warn!("{:?}: couldn't write entry: {}", self.output, e);
+            }
+        }
+    }
+}
+
+/// removals are more interesting than the rest, so they are logged as notice
+fn severity(event: &Event) -> u8 {
+    match event.kind {
+        EventKind::Delete | EventKind::DeleteSelf | EventKind::MovedFrom => 5,
+        EventKind::Overflow => 4,
+        _ => 6,
+    }
+}
+
+/// formats the event in the journal native protocol
+fn journald_entry(event: &Event) -> Vec<u8> {
+    let path = event.path.to_string_lossy();
+    let mut entry = Vec::new();
+    field(&mut entry, "MESSAGE", &format!("{} {}", event.kind, path));
+    field(&mut entry, "PRIORITY", &severity(event).to_string());
+    field(&mut entry, "SYSLOG_IDENTIFIER", "tube");
+    field(&mut entry, "TUBE_PATH", &path);
+    field(&mut entry, "TUBE_EVENT", &event.kind.to_string());
+    field(&mut entry, "TUBE_COOKIE", &event.cookie.to_string());
+    field(&mut entry, "TUBE_IS_DIR", &event.is_dir.to_string());
+    entry
+}
+
+/// writes a journal field, values with new lines use the length prefixed form
+fn field(entry: &mut Vec<u8>, name: &str, value: &str) {
+    if value.contains('\n') {
+        entry.extend_from_slice(name.as_bytes());
+        entry.push(b'\n');
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    } else {
+        let _ = writeln!(entry, "{}={}", name, value);
+    }
+}
+
+/// formats the event as an RFC 5424 message
+fn syslog_entry(event: &Event) -> Vec<u8> {
+    let path = event.path.to_string_lossy();
+    format!(
+        "<{}>1 {} {} tube {} - [{} path=\"{}\" event=\"{}\"] {} {}",
+        FACILITY_USER * 8 + severity(event),
+        humantime::format_rfc3339_micros(SystemTime::now()),
+        hostname(),
+        std::process::id(),
+        SD_ID,
+        sd_escape(&path),
+        event.kind,
+        event.kind,
+        path,
+    )
+    .into_bytes()
+}
+
+/// escapes a structured data parameter value
+fn sd_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace(']', "\\]")
+}
+
+fn hostname() -> String {
+    std::fs::read_to_string(Path::new("/proc/sys/kernel/hostname"))
+        .map(|name| name.trim().to_string())
+        .unwrap_or_else(|_| "-".to_string())
+}
@@ -0,0 +1,83 @@
+use anyhow::Context;
+use futures::future;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::ClientConfig;
+use std::os::unix::ffi::OsStrExt;
+use tube_inotify::Event;
+
+use super::Sink;
+use crate::metrics::METRICS;
+use crate::output::Record;
+
+/// produces every event as a JSON message to a Kafka topic, keyed by the
+/// event path so the changes of a file stay ordered within a partition
+pub struct Kafka {
+    producer: FutureProducer,
+    topic: String,
+}
+
+impl Kafka {
+    /// `options` are the `KEY=VALUE` pairs given to `--kafka`
+    pub fn new(options: &[String]) -> anyhow::Result<Self> {
+        let mut config = ClientConfig::new();
+        // gives librdkafka a moment to put the events of a batch in one request
+        config.set("linger.ms", "20");
+
+        let mut topic = None;
+        for option in options {
+            let (key, value) = option.split_once('=').with_context(|| {
+                format!("invalid kafka option `{}`, expected `KEY=VALUE`", option)
+            })?;
+            match key {
+                "brokers" => config.set("bootstrap.servers", value),
+                "topic" => {
+                    topic = Some(value.to_string());
+                    continue;
+                }
+                key => config.set(key, value),
+            };
+        }
+        anyhow::ensure!(
+            config.get("bootstrap.servers").is_some(),
+            "kafka needs the `brokers=` option"
+        );
+        let topic = topic.context("kafka needs the `topic=` option")?;
+        let producer = config.create().context("couldn't create kafka producer")?;
+        Ok(Self { producer, topic })
+    }
+}
+
+impl Sink for Kafka {
+    async fn send(&mut self, rule: Option<&str>, events: &[Event]) {
+        // every event is queued before waiting for any delivery, so the
+        // producer can batch them
+        let mut deliveries = Vec::with_capacity(events.len());
+        for event in events {
+            let Ok(payload) = serde_json::to_vec(&Record::new(event).with_rule(rule)) else {
+                continue;
+            };
+            let record = FutureRecord::to(&self.topic)
+                .key(event.path.as_os_str().as_bytes())
+                .payload(&payload);
+            match self.producer.send_result(record) {
+                Ok(delivery) => deliveries.push(delivery),
+                Err((e, _)) => {
+                    METRICS.undelivered("kafka", 1);
+                    tracing::warn!("couldn't queue event for kafka: {}", e);
+                }
+            }
+        }
+
+        for delivery in future::join_all(deliveries).await {
+            match delivery {
+                Ok(Ok(_)) => {}
+                Ok(Err((e, _))) => {
+                    METRICS.undelivered("kafka", 1);
+                    tracing::warn!("kafka delivery failed: {}", e);
+                }
+                // the producer dropped the message, e.g. when it timed out
+                Err(_) => METRICS.undelivered("kafka", 1),
+            }
+        }
+    }
+}
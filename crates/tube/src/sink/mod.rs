@@ -0,0 +1,221 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::task::JoinHandle;
+use tube_inotify::Event;
+
+use crate::cli::SinkArgs;
+use crate::metrics::METRICS;
+
+pub mod audit;
+pub mod chat;
+pub mod dbus;
+pub mod digest;
+pub mod email;
+pub mod forward;
+pub mod journal;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod mqtt;
+pub mod nats;
+pub mod notify;
+pub mod redis;
+pub mod socket;
+pub mod webhook;
+
+/// queue size of sinks that don't have a configurable one
+const QUEUE_SIZE: usize = 64;
+
+/// a destination event batches are delivered to, every sink runs on its own
+/// task so a slow sink doesn't hold back the others or the watcher
+pub trait Sink: Send + 'static {
+    /// `rule` is the name of the rule the events triggered, if they came from one
+    fn send(&mut self, rule: Option<&str>, events: &[Event]) -> impl Future<Output = ()> + Send;
+
+    /// called once no batch is left, to deliver what the sink still holds
+    fn close(&mut self) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+}
+
+/// the sinks requested in the arguments
+pub struct Sinks {
+    sender: Sender,
+    handles: Vec<JoinHandle<()>>,
+}
+
+/// queues batches to the sinks, cheap to clone so every rule can have its own
+#[derive(Clone, Default)]
+pub struct Sender {
+    queues: Vec<Queue>,
+}
+
+/// bounded queue of batches waiting to be delivered to a sink
+#[derive(Clone)]
+struct Queue {
+    name: &'static str,
+    tx: mpsc::Sender<Batch>,
+    dropped: Arc<AtomicU64>,
+}
+
+#[derive(Clone)]
+struct Batch {
+    rule: Option<Arc<str>>,
+    events: Arc<Vec<Event>>,
+}
+
+impl Sinks {
+    pub fn open(args: &SinkArgs) -> anyhow::Result<Self> {
+        let mut spawned = Vec::new();
+        if let Some(url) = &args.webhook {
+            let webhook =
+                webhook::Webhook::new(url.clone(), &args.webhook_header, args.webhook_retries)?;
+            spawned.push(Queue::spawn("webhook", args.webhook_queue, webhook));
+        }
+        if let Some(path) = &args.socket {
+            let socket = socket::Socket::bind(path)?;
+            spawned.push(Queue::spawn("socket", QUEUE_SIZE, socket));
+        }
+        for output in &args.output {
+            let journal = journal::Journal::open(*output)?;
+            let name = match output {
+                journal::LogOutput::Journald => "journald",
+                journal::LogOutput::Syslog => "syslog",
+            };
+            spawned.push(Queue::spawn(name, QUEUE_SIZE, journal));
+        }
+        if let Some(bus) = args.dbus {
+            spawned.push(Queue::spawn("dbus", QUEUE_SIZE, dbus::Dbus::new(bus)));
+        }
+        if args.notify {
+            spawned.push(Queue::spawn("notify", QUEUE_SIZE, notify::Notify));
+        }
+        if let Some(url) = &args.mqtt {
+            let mqtt = mqtt::Mqtt::connect(url, args.topic.clone(), args.qos, args.retain)?;
+            spawned.push(Queue::spawn("mqtt", QUEUE_SIZE, mqtt));
+        }
+        #[cfg(feature = "kafka")]
+        if !args.kafka.is_empty() {
+            let kafka = kafka::Kafka::new(&args.kafka)?;
+            spawned.push(Queue::spawn("kafka", QUEUE_SIZE, kafka));
+        }
+        if let Some(url) = &args.redis {
+            let redis = redis::Redis::new(url, args.channel.clone(), args.redis_stream.clone())?;
+            spawned.push(Queue::spawn("redis", QUEUE_SIZE, redis));
+        }
+        if let Some(url) = &args.nats {
+            let nats = nats::Nats::new(url.clone(), args.subject.clone());
+            spawned.push(Queue::spawn("nats", QUEUE_SIZE, nats));
+        }
+        if let Some(url) = &args.forward {
+            let forward = forward::Forward::new(url, args.forward_buffer.clone())?;
+            spawned.push(Queue::spawn("forward", QUEUE_SIZE, forward));
+        }
+        if let Some(url) = &args.chat {
+            let chat = chat::Chat::new(url, args)?;
+            spawned.push(Queue::spawn("chat", QUEUE_SIZE, chat));
+        }
+        if let Some(url) = &args.email {
+            let email = email::Email::new(url, args)?;
+            spawned.push(Queue::spawn("email", QUEUE_SIZE, email));
+        }
+        if let Some(path) = &args.audit_db {
+            let audit = audit::Audit::open(path)?;
+            spawned.push(Queue::spawn("audit", QUEUE_SIZE, audit));
+        }
+        let (queues, handles) = spawned.into_iter().unzip();
+        Ok(Self {
+            sender: Sender { queues },
+            handles,
+        })
+    }
+
+    /// queues the batch to all the sinks, if the queue of a sink is full
+    /// the batch is dropped for that sink
+    pub fn send(&self, events: Vec<Event>) {
+        self.sender.send(None, Arc::new(events));
+    }
+
+    /// returns a handle the batches can be queued through from other tasks
+    pub fn sender(&self) -> Sender {
+        self.sender.clone()
+    }
+
+    /// waits for the sinks to deliver the batches that are already queued,
+    /// the senders handed out must be dropped by then
+    pub async fn close(self) {
+        drop(self.sender);
+        for handle in self.handles {
+            let _ = handle.await;
+        }
+    }
+}
+
+impl Sender {
+    /// same as `Sinks::send`, for the events that triggered the rule
+    pub fn send(&self, rule: Option<&str>, events: Arc<Vec<Event>>) {
+        let batch = Batch {
+            rule: rule.map(Arc::from),
+            events,
+        };
+        for queue in &self.queues {
+            queue.send(batch.clone());
+        }
+    }
+}
+
+impl Queue {
+    fn spawn<S: Sink>(name: &'static str, size: usize, mut sink: S) -> (Self, JoinHandle<()>) {
+        let (tx, mut rx) = mpsc::channel::<Batch>(size.max(1));
+        let handle = tokio::spawn(async move {
+            while let Some(batch) = rx.recv().await {
+                sink.send(batch.rule.as_deref(), &batch.events).await;
+            }
+            sink.close().await;
+        });
+        let queue = Self {
+            name,
+            tx,
+            dropped: Arc::new(AtomicU64::new(0)),
+        };
+        (queue, handle)
+    }
+
+    fn send(&self, batch: Batch) {
+        match self.tx.try_send(batch) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                METRICS.dropped(self.name);
+                tracing::warn!(
+                    "{} can't keep up, dropped {} batches so far",
+                    self.name,
+                    dropped
+                );
+            }
+            Err(TrySendError::Closed(_)) => {}
+        }
+    }
+}
+
+/// expands `{hostname}`, `{rule}` and `{kind}` in the topic templates of the
+/// broker sinks, `{rule}` is empty for events that didn't come from a rule
+pub fn expand(template: &str, rule: Option<&str>, event: &Event) -> String {
+    template
+        .replace("{hostname}", hostname())
+        .replace("{rule}", rule.unwrap_or_default())
+        .replace("{kind}", &event.kind.to_string())
+}
+
+pub fn hostname() -> &'static str {
+    static HOSTNAME: OnceLock<String> = OnceLock::new();
+    HOSTNAME.get_or_init(|| {
+        let mut buf = [0u8; 256];
+        if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+            return "localhost".to_string();
+        }
+        let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+        String::from_utf8_lossy(&buf[..len]).into_owned()
+    })
+}
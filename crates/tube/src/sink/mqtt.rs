@@ -0,0 +1,87 @@
+use anyhow::Context;
+use rumqttc::{AsyncClient, EventLoop, MqttOptions, QoS};
+use std::time::Duration;
+use tube_inotify::Event;
+
+use super::Sink;
+use crate::metrics::METRICS;
+use crate::output::Record;
+
+const DEFAULT_PORT: u16 = 1883;
+/// delay before reconnecting after the connection to the broker was lost
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// publishes every event as a JSON message to an MQTT broker, the topic
+/// is expanded per event
+pub struct Mqtt {
+    client: AsyncClient,
+    topic: String,
+    qos: QoS,
+    retain: bool,
+}
+
+impl Mqtt {
+    /// `url` is `tcp://host:port` (or `mqtt://`), the port defaults to 1883,
+    /// the connection is made in the background and kept up by its own task
+    pub fn connect(url: &str, topic: String, qos: u8, retain: bool) -> anyhow::Result<Self> {
+        let addr = url
+            .strip_prefix("tcp://")
+            .or_else(|| url.strip_prefix("mqtt://"))
+            .with_context(|| format!("invalid mqtt url `{}`, expected `tcp://host:port`", url))?;
+        let (host, port) = match addr.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().context("invalid mqtt port")?),
+            None => (addr, DEFAULT_PORT),
+        };
+        let qos = rumqttc::qos(qos).map_err(|_| anyhow::anyhow!("invalid qos {}", qos))?;
+
+        let id = format!("tube-{}", std::process::id());
+        let mut options = MqttOptions::new(id, host, port);
+        options.set_keep_alive(Duration::from_secs(30));
+        let (client, eventloop) = AsyncClient::new(options, 64);
+        tokio::spawn(poll(eventloop));
+        Ok(Self {
+            client,
+            topic,
+            qos,
+            retain,
+        })
+    }
+}
+
+/// drives the connection, the event loop reconnects on the next poll after an error
+async fn poll(mut eventloop: EventLoop) {
+    let mut connected = true;
+    loop {
+        match eventloop.poll().await {
+            Ok(_) => connected = true,
+            Err(e) => {
+                // only reported once per outage
+                if connected {
+                    tracing::warn!("mqtt connection lost ({}), reconnecting", e);
+                    connected = false;
+                }
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+impl Sink for Mqtt {
+    async fn send(&mut self, rule: Option<&str>, events: &[Event]) {
+        for event in events {
+            let topic = super::expand(&self.topic, rule, event);
+            let payload = match serde_json::to_vec(&Record::new(event).with_rule(rule)) {
+                Ok(payload) => payload,
+                Err(_) => continue,
+            };
+            if let Err(e) = self
+                .client
+                .publish(topic, self.qos, self.retain, payload)
+                .await
+            {
+                METRICS.undelivered("mqtt", 1);
+                tracing::warn!("couldn't publish to mqtt: {}", e);
+            }
+        }
+    }
+}
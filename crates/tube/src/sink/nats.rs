@@ -0,0 +1,69 @@
+use async_nats::{Client, ConnectOptions};
+use tube_inotify::Event;
+
+use super::Sink;
+use crate::metrics::METRICS;
+use crate::output::Record;
+
+/// publishes every event as a JSON message to a NATS subject, the subject
+/// is expanded per event like the MQTT topic
+pub struct Nats {
+    url: String,
+    // connected on the first batch, the client reconnects by itself after that
+    client: Option<Client>,
+    subject: String,
+}
+
+impl Nats {
+    pub fn new(url: String, subject: String) -> Self {
+        Self {
+            url,
+            client: None,
+            subject,
+        }
+    }
+
+    async fn client(&mut self) -> Result<&Client, async_nats::ConnectError> {
+        if self.client.is_none() {
+            let client = ConnectOptions::new()
+                .name("tube")
+                .connect(self.url.as_str())
+                .await?;
+            self.client = Some(client);
+        }
+        Ok(self.client.as_ref().unwrap())
+    }
+}
+
+impl Sink for Nats {
+    async fn send(&mut self, rule: Option<&str>, events: &[Event]) {
+        let subject = self.subject.clone();
+        let client = match self.client().await {
+            Ok(client) => client,
+            Err(e) => {
+                METRICS.undelivered("nats", events.len());
+                tracing::warn!(
+                    "couldn't connect to nats ({}), dropping {} events",
+                    e,
+                    events.len()
+                );
+                return;
+            }
+        };
+
+        for event in events {
+            let Ok(payload) = serde_json::to_vec(&Record::new(event).with_rule(rule)) else {
+                continue;
+            };
+            let subject = super::expand(&subject, rule, event);
+            if let Err(e) = client.publish(subject, payload.into()).await {
+                METRICS.undelivered("nats", 1);
+                tracing::warn!("couldn't publish to nats: {}", e);
+            }
+        }
+        // publishes are buffered by the client, make sure the batch went out
+        if let Err(e) = client.flush().await {
+            tracing::warn!("couldn't flush nats messages: {}", e);
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use notify_rust::Notification;
+use tube_inotify::Event;
+
+use super::Sink;
+
+/// max number of events listed in a notification body
+const MAX_LISTED: usize = 5;
+
+/// shows a desktop notification per batch of events
+pub struct Notify;
+
+impl Sink for Notify {
+    async fn send(&mut self, _rule: Option<&str>, events: &[Event]) {
+        let summary = match events {
+            [event] => format!("{} {}", event.kind, name(event)),
+            _ => format!("{} changes", events.len()),
+        };
+        let mut body: Vec<String> = events
+            .iter()
+            .take(MAX_LISTED)
+            .map(|event| format!("{} {}", event.kind, event.path.display()))
+            .collect();
+        if events.len() > MAX_LISTED {
+            body.push(format!("and {} more", events.len() - MAX_LISTED));
+        }
+
+        // showing a notification blocks on the bus round trip
+        let shown = tokio::task::spawn_blocking(move || {
+            Notification::new()
+                .appname("tube")
+                .summary(&summary)
+                .body(&body.join("\n"))
+                .show()
+                .map(|_| ())
+        })
+        .await;
+        if let Ok(Err(e)) = shown {
+            tracing::warn!("couldn't show notification: {}", e);
+        }
+    }
+}
+
+fn name(event: &Event) -> String {
+    event
+        .path
+        .file_name()
+        .unwrap_or(event.path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
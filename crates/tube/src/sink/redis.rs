@@ -0,0 +1,81 @@
+use anyhow::Context;
+use redis::aio::ConnectionManager;
+use redis::Client;
+use tube_inotify::Event;
+
+use super::Sink;
+use crate::metrics::METRICS;
+use crate::output::Record;
+
+/// publishes every event to a Redis pub/sub channel and/or appends it to a
+/// stream, the names are expanded per event like the MQTT topic
+pub struct Redis {
+    client: Client,
+    // connected on the first batch, reconnects by itself after that
+    conn: Option<ConnectionManager>,
+    channel: Option<String>,
+    stream: Option<String>,
+}
+
+impl Redis {
+    pub fn new(url: &str, channel: Option<String>, stream: Option<String>) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            channel.is_some() || stream.is_some(),
+            "redis needs `--channel` or `--redis-stream`"
+        );
+        let client = Client::open(url).with_context(|| format!("invalid redis url `{}`", url))?;
+        Ok(Self {
+            client,
+            conn: None,
+            channel,
+            stream,
+        })
+    }
+
+    async fn connection(&mut self) -> redis::RedisResult<&mut ConnectionManager> {
+        if self.conn.is_none() {
+            self.conn = Some(ConnectionManager::new(self.client.clone()).await?);
+        }
+        Ok(self.conn.as_mut().unwrap())
+    }
+}
+
+impl Sink for Redis {
+    async fn send(&mut self, rule: Option<&str>, events: &[Event]) {
+        // the whole batch goes in a single round trip
+        let mut pipe = redis::pipe();
+        for event in events {
+            let record = Record::new(event).with_rule(rule);
+            if let Some(channel) = &self.channel {
+                let Ok(payload) = serde_json::to_string(&record) else {
+                    continue;
+                };
+                pipe.publish(super::expand(channel, rule, event), payload)
+                    .ignore();
+            }
+            if let Some(stream) = &self.stream {
+                let mut fields = vec![
+                    ("path", record.path.to_string()),
+                    ("kind", record.kind.clone()),
+                    ("cookie", record.cookie.to_string()),
+                    ("is_dir", record.is_dir.to_string()),
+                    ("ts", record.ts.clone()),
+                ];
+                if let Some(rule) = &record.rule {
+                    fields.push(("rule", rule.clone()));
+                }
+                pipe.xadd(super::expand(stream, rule, event), "*", &fields)
+                    .ignore();
+            }
+        }
+
+        let result = match self.connection().await {
+            Ok(conn) => pipe.query_async::<()>(conn).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            METRICS.undelivered("redis", events.len());
+            tracing::warn!("redis failed ({}), dropping {} events", e, events.len());
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use anyhow::Context;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncWriteExt;
+use tokio::net::UnixListener;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tube_inotify::Event;
+
+use super::Sink;
+use crate::output::Record;
+
+/// how many lines a client can fall behind before it is disconnected
+const CLIENT_BUFFER: usize = 1024;
+
+/// broadcasts newline delimited JSON events to every client
+/// connected to a unix domain socket
+pub struct Socket {
+    path: PathBuf,
+    clients: Arc<Mutex<Vec<mpsc::Sender<Arc<str>>>>>,
+}
+
+impl Socket {
+    pub fn bind(path: &Path) -> anyhow::Result<Self> {
+        // a socket left behind by a previous run that didn't exit cleanly, anything
+        // else at the path, or a socket still in use, is left alone
+        if let Ok(metadata) = std::fs::symlink_metadata(path) {
+            anyhow::ensure!(
+                metadata.file_type().is_socket(),
+                "`{}` exists and isn't a socket",
+                path.display()
+            );
+            anyhow::ensure!(
+                std::os::unix::net::UnixStream::connect(path).is_err(),
+                "`{}` is in use by another process",
+                path.display()
+            );
+            std::fs::remove_file(path)
+                .with_context(|| format!("couldn't remove stale socket `{}`", path.display()))?;
+        }
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("couldn't bind socket `{}`", path.display()))?;
+
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        tokio::spawn(accept(listener, clients.clone()));
+        Ok(Self {
+            path: path.to_path_buf(),
+            clients,
+        })
+    }
+}
+
+/// accepts clients, every client gets a writer task with its own buffer
+async fn accept(listener: UnixListener, clients: Arc<Mutex<Vec<mpsc::Sender<Arc<str>>>>>) {
+    loop {
+        let mut stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                tracing::warn!("socket: couldn't accept client: {}", e);
+                continue;
+            }
+        };
+
+        let (tx, mut rx) = mpsc::channel::<Arc<str>>(CLIENT_BUFFER);
+        clients.lock().unwrap().push(tx);
+        tokio::spawn(async move {
+            while let Some(line) = rx.recv().await {
+                if stream.write_all(line.as_bytes()).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+impl Sink for Socket {
+    async fn send(&mut self, rule: Option<&str>, events: &[Event]) {
+        let mut clients = self.clients.lock().unwrap();
+        for event in events {
+            let mut line = match serde_json::to_string(&Record::new(event).with_rule(rule)) {
+                Ok(line) => line,
+                Err(_) => continue,
+            };
+            line.push('\n');
+            let line: Arc<str> = line.into();
+
+            // clients that went away or can't keep up are dropped, which
+            // closes their connection once the writer task exits
+            clients.retain(|client| match client.try_send(line.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    tracing::warn!("socket: disconnecting slow client");
+                    false
+                }
+                Err(TrySendError::Closed(_)) => false,
+            });
+        }
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,87 @@
+use anyhow::Context;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::Serialize;
+use std::time::Duration;
+use tube_inotify::Event;
+
+use super::Sink;
+use crate::metrics::METRICS;
+use crate::output::Record;
+
+/// delay before the first retry, doubled after every failed attempt
+const BACKOFF: Duration = Duration::from_millis(500);
+
+/// posts every batch as a JSON object to an HTTP endpoint
+pub struct Webhook {
+    client: reqwest::Client,
+    url: String,
+    retries: u32,
+}
+
+#[derive(Serialize)]
+struct Body<'a> {
+    events: Vec<Record<'a>>,
+}
+
+impl Webhook {
+    /// creates the webhook, headers are given as `Name: value`
+    pub fn new(url: String, headers: &[String], retries: u32) -> anyhow::Result<Self> {
+        let mut map = HeaderMap::new();
+        for header in headers {
+            let (name, value) = header
+                .split_once(':')
+                .with_context(|| format!("invalid header `{}`, expected `Name: value`", header))?;
+            map.insert(
+                HeaderName::try_from(name.trim())?,
+                HeaderValue::try_from(value.trim())?,
+            );
+        }
+
+        let client = reqwest::Client::builder()
+            .default_headers(map)
+            .timeout(Duration::from_secs(30))
+            .build()?;
+        Ok(Self {
+            client,
+            url,
+            retries,
+        })
+    }
+
+    async fn post(&self, body: &Body<'_>) -> anyhow::Result<()> {
+        self.client
+            .post(&self.url)
+            .json(body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+impl Sink for Webhook {
+    async fn send(&mut self, rule: Option<&str>, events: &[Event]) {
+        let body = Body {
+            events: events
+                .iter()
+                .map(|event| Record::new(event).with_rule(rule))
+                .collect(),
+        };
+
+        let mut backoff = BACKOFF;
+        for attempt in 0..=self.retries {
+            match self.post(&body).await {
+                Ok(()) => return,
+                Err(e) if attempt < self.retries => {
+                    tracing::warn!("webhook failed ({:#}), retrying in {:?}", e, backoff);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(e) => {
+                    METRICS.undelivered("webhook", events.len());
+                    tracing::warn!("webhook failed ({:#}), dropping {} events", e, events.len())
+                }
+            }
+        }
+    }
+}
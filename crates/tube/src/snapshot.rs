@@ -0,0 +1,216 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tube_inotify::{Event, EventKind};
+
+use crate::cli::{DiffArgs, SnapshotArgs, WatchArgs};
+use crate::hash::Algorithm;
+use crate::logging;
+use crate::output::Printer;
+use crate::watcher::Matcher;
+
+/// exit code of `tube diff` when files changed, like diff(1)
+pub const CHANGED_EXIT_CODE: i32 = 1;
+
+/// the files under the watched paths at some point in time
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(with = "humantime_serde")]
+    created: SystemTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<Algorithm>,
+    files: BTreeMap<PathBuf, Entry>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+struct Entry {
+    size: u64,
+    #[serde(with = "humantime_serde")]
+    mtime: SystemTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+}
+
+impl Manifest {
+    /// walks the paths the same way the watcher does
+    pub fn scan(args: &WatchArgs, hash: Option<Algorithm>) -> anyhow::Result<Self> {
+        let matcher = Matcher::new(args)?;
+
+        let mut files = BTreeMap::new();
+        for path in matcher.files() {
+            // gone since it was listed
+            let Ok(metadata) = path.metadata() else {
+                continue;
+            };
+            let hash = match hash {
+                Some(algorithm) => match algorithm.digest(&path) {
+                    Ok(digest) => Some(digest),
+                    Err(_) => continue,
+                },
+                None => None,
+            };
+            let entry = Entry {
+                size: metadata.len(),
+                mtime: metadata.modified()?,
+                hash,
+            };
+            files.insert(path, entry);
+        }
+        Ok(Self {
+            created: SystemTime::now(),
+            hash,
+            files,
+        })
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("couldn't open `{}`", path.display()))?;
+        serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("`{}` is not a snapshot", path.display()))
+    }
+
+    /// writes the manifest to a temporary file first, so a crash
+    /// never leaves a partial manifest behind
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let tmp = path.with_extension("tmp");
+        let file =
+            File::create(&tmp).with_context(|| format!("couldn't create `{}`", tmp.display()))?;
+        let mut out = BufWriter::new(file);
+        serde_json::to_writer(&mut out, self)?;
+        out.flush()?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("couldn't write `{}`", path.display()))?;
+        Ok(())
+    }
+
+    /// the files created, modified and deleted since `old` as
+    /// `CREATE`, `MODIFY` and `DELETE` events, sorted by path
+    pub fn changes(&self, old: &Manifest) -> Vec<Event> {
+        let mut events = Vec::new();
+        for (path, entry) in &self.files {
+            match old.files.get(path) {
+                None => events.push((path, EventKind::Create)),
+                Some(old_entry) if self.is_modified(old_entry, entry) => {
+                    events.push((path, EventKind::Modify));
+                }
+                Some(_) => {}
+            }
+        }
+        for path in old.files.keys() {
+            if !self.files.contains_key(path) {
+                events.push((path, EventKind::Delete));
+            }
+        }
+        events.sort_by(|a, b| a.0.cmp(b.0));
+        events
+            .into_iter()
+            .map(|(path, kind)| Event {
+                path: path.to_path_buf(),
+                kind,
+                cookie: 0,
+                is_dir: false,
+                process: None,
+                changes: Vec::new(),
+                inode: None,
+                renamed_from: None,
+            })
+            .collect()
+    }
+
+    /// updates the file to what it is now, a path that is gone takes
+    /// the files under it along, in case it was a directory
+    pub fn refresh(&mut self, path: &Path) {
+        if path.is_dir() {
+            return;
+        }
+        match stat(path) {
+            Some(entry) => {
+                self.files.insert(path.to_path_buf(), entry);
+            }
+            None => self.files.retain(|file, _| !file.starts_with(path)),
+        }
+    }
+
+    /// brings the manifest up to date with a newer scan and returns what changed,
+    /// the files are checked again first, so the ones the manifest was refreshed
+    /// with since the scan are left out
+    pub fn reconcile(&mut self, scanned: &Manifest) -> Vec<Event> {
+        let mut events = scanned.changes(self);
+        events.retain_mut(|event| {
+            let now = stat(&event.path);
+            event.kind = match (self.files.get(&event.path), &now) {
+                (None, None) => return false,
+                (Some(known), Some(now)) if known == now => return false,
+                (None, Some(_)) => EventKind::Create,
+                (Some(_), None) => EventKind::Delete,
+                (Some(_), Some(_)) => EventKind::Modify,
+            };
+            match now {
+                Some(entry) => self.files.insert(event.path.clone(), entry),
+                None => self.files.remove(&event.path),
+            };
+            true
+        });
+        events
+    }
+
+    /// with hashes, files count as modified only when their content changed
+    fn is_modified(&self, old: &Entry, new: &Entry) -> bool {
+        match self.hash {
+            Some(_) => old.size != new.size || old.hash != new.hash,
+            None => old != new,
+        }
+    }
+}
+
+/// the entry of the file without a hash, `None` if it isn't a file anymore
+fn stat(path: &Path) -> Option<Entry> {
+    let metadata = path.metadata().ok().filter(|metadata| metadata.is_file())?;
+    Some(Entry {
+        size: metadata.len(),
+        mtime: metadata.modified().ok()?,
+        hash: None,
+    })
+}
+
+/// writes the manifest of the paths to the output file, or stdout
+pub fn snapshot(mut args: SnapshotArgs) -> anyhow::Result<()> {
+    args.watch.recursive = true;
+    let manifest = Manifest::scan(&args.watch, args.watch.hash)?;
+    match &args.output {
+        Some(path) => manifest.save(path)?,
+        None => {
+            let mut out = std::io::stdout().lock();
+            serde_json::to_writer(&mut out, &manifest)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+/// prints the files created, modified and deleted since the snapshot as
+/// `CREATE`, `MODIFY` and `DELETE` events, exits with 1 if anything changed
+pub fn diff(mut args: DiffArgs) -> anyhow::Result<()> {
+    args.watch.recursive = true;
+    let old = Manifest::load(&args.snapshot)?;
+    // hashed again only if the snapshot has hashes to compare with
+    let changes = Manifest::scan(&args.watch, old.hash)?.changes(&old);
+
+    let mut printer = Printer::new(std::io::stdout(), args.format);
+    if logging::stdout_color() {
+        printer = printer.with_color();
+    }
+    for event in &changes {
+        printer.print(event)?;
+    }
+    printer.flush()?;
+    if !changes.is_empty() {
+        std::process::exit(CHANGED_EXIT_CODE);
+    }
+    Ok(())
+}
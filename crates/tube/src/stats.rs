@@ -0,0 +1,69 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::Instant;
+
+use crate::cli::StatsArgs;
+use crate::metrics::METRICS;
+use crate::sink::journal::Journal;
+
+/// writes the metrics summary to stderr or the system log, on SIGUSR1,
+/// every `--stats-interval` and once tube stops with `--stats`
+pub struct Reporter {
+    started: Instant,
+    journal: Option<Journal>,
+}
+
+impl Reporter {
+    pub fn new(args: &StatsArgs) -> anyhow::Result<Arc<Self>> {
+        let journal = args.stats_output.map(Journal::open).transpose()?;
+        let reporter = Arc::new(Self {
+            started: Instant::now(),
+            journal,
+        });
+
+        let mut user1 = signal(SignalKind::user_defined1())?;
+        let mut interval = args.stats_interval.map(tokio::time::interval);
+        let task = reporter.clone();
+        tokio::spawn(async move {
+            // the first tick of an interval completes right away
+            if let Some(interval) = &mut interval {
+                interval.tick().await;
+            }
+            loop {
+                tokio::select! {
+                    _ = user1.recv() => task.report(),
+                    _ = tick(interval.as_mut()) => task.report(),
+                }
+            }
+        });
+        Ok(reporter)
+    }
+
+    pub fn report(&self) {
+        let uptime = Duration::from_secs(self.started.elapsed().as_secs());
+        let summary = format!(
+            "stats after {}\n{}",
+            humantime::format_duration(uptime),
+            METRICS.summary()
+        );
+        let Some(journal) = &self.journal else {
+            for line in summary.lines() {
+                eprintln!("tube: {}", line);
+            }
+            return;
+        };
+        if let Err(e) = journal.log(&summary) {
+            tracing::warn!("couldn't write stats: {}", e);
+        }
+    }
+}
+
+async fn tick(interval: Option<&mut tokio::time::Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
@@ -0,0 +1,205 @@
+use anyhow::Context;
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tube_inotify::{Depth, Event, EventKind, Flag, Inotify, Mask};
+
+use crate::cli::SyncArgs;
+
+/// mirrors the source directory into the destination, first with a full pass
+/// over the tree and then by applying every change as it happens
+pub async fn run(args: SyncArgs) -> anyhow::Result<()> {
+    let src = args
+        .src
+        .canonicalize()
+        .with_context(|| format!("couldn't resolve `{}`", args.src.display()))?;
+    if !src.is_dir() {
+        anyhow::bail!("`{}` is not a directory", src.display());
+    }
+    let mirror = Mirror {
+        src,
+        dst: std::path::absolute(&args.dst)?,
+        dry_run: args.dry_run,
+    };
+
+    // the watch is added before the full pass, so changes made
+    // during the pass are not missed
+    let mask = Mask::CLOSE_WRITE | Mask::CREATE | Mask::DELETE | Mask::MOVE;
+    let mut inotify = Inotify::with_flags(Flag::NONBLOCKING)?
+        .include_hidden(true)
+        .watch_recursive(mirror.src.clone(), mask, Depth::any())?;
+
+    mirror.sync_tree(Path::new(""))?;
+
+    while let Some(events) = inotify.next().await {
+        let events: Vec<Event> = events?.filter_map(|e| inotify.resolve(&e)).collect();
+        if let Err(e) = mirror.apply(events) {
+            tracing::warn!("sync: {:#}", e);
+        }
+    }
+    Ok(())
+}
+
+struct Mirror {
+    src: PathBuf,
+    dst: PathBuf,
+    dry_run: bool,
+}
+
+impl Mirror {
+    /// applies a batch of events to the destination, `MOVED_FROM` and `MOVED_TO`
+    /// pairs (by cookie) become renames, unpaired moves are treated as a delete
+    /// (moved out of the tree) or a copy (moved into the tree)
+    fn apply(&self, events: Vec<Event>) -> anyhow::Result<()> {
+        let mut moved: HashMap<u32, PathBuf> = HashMap::new();
+
+        for event in events {
+            let Ok(rel) = event.path.strip_prefix(&self.src) else {
+                continue;
+            };
+            let rel = rel.to_path_buf();
+
+            match event.kind {
+                EventKind::Create if event.is_dir => self.sync_tree(&rel)?,
+                EventKind::Create | EventKind::CloseWrite => self.copy(&rel)?,
+                EventKind::Delete => self.remove(&rel)?,
+                EventKind::MovedFrom => {
+                    moved.insert(event.cookie, rel);
+                }
+                EventKind::MovedTo => match moved.remove(&event.cookie) {
+                    Some(from) => self.rename(&from, &rel)?,
+                    None if event.is_dir => self.sync_tree(&rel)?,
+                    None => self.copy(&rel)?,
+                },
+                _ => {}
+            }
+        }
+
+        for rel in moved.into_values() {
+            self.remove(&rel)?;
+        }
+        Ok(())
+    }
+
+    /// makes the destination subtree identical to the source subtree, files
+    /// are copied if their size or modification time differ
+    fn sync_tree(&self, rel: &Path) -> anyhow::Result<()> {
+        let src = self.src.join(rel);
+        let dst = self.dst_path(rel);
+        if !src.is_dir() {
+            return Ok(());
+        }
+        if !dst.is_dir() {
+            self.act(format!("mkdir {}", dst.display()), || {
+                fs::create_dir_all(&dst)
+            })?;
+        }
+
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&src)? {
+            let entry = entry?;
+            let child = rel.join(entry.file_name());
+            names.push(entry.file_name());
+
+            if entry.file_type()?.is_dir() {
+                self.sync_tree(&child)?;
+            } else if !same_file(&entry.path(), &self.dst_path(&child)) {
+                self.copy(&child)?;
+            }
+        }
+
+        // the destination is a mirror, anything the source doesn't have is removed
+        if let Ok(entries) = fs::read_dir(&dst) {
+            for entry in entries.flatten() {
+                if !names.contains(&entry.file_name()) {
+                    self.remove(&rel.join(entry.file_name()))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn copy(&self, rel: &Path) -> anyhow::Result<()> {
+        let src = self.src.join(rel);
+        let dst = self.dst_path(rel);
+        if src.is_dir() {
+            return self.sync_tree(rel);
+        }
+
+        self.act(
+            format!("copy {} -> {}", src.display(), dst.display()),
+            || {
+                if let Some(parent) = dst.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                match fs::copy(&src, &dst) {
+                    // the file was removed before we got to it, the
+                    // delete event that follows takes care of it
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+                    result => result?,
+                };
+                let modified = fs::metadata(&src)?.modified()?;
+                fs::File::options()
+                    .write(true)
+                    .open(&dst)?
+                    .set_modified(modified)
+            },
+        )
+    }
+
+    fn remove(&self, rel: &Path) -> anyhow::Result<()> {
+        let dst = self.dst_path(rel);
+        let Ok(metadata) = fs::symlink_metadata(&dst) else {
+            return Ok(());
+        };
+
+        self.act(format!("remove {}", dst.display()), || {
+            match metadata.is_dir() {
+                true => fs::remove_dir_all(&dst),
+                false => fs::remove_file(&dst),
+            }
+        })
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> anyhow::Result<()> {
+        let from_dst = self.dst_path(from);
+        let to_dst = self.dst_path(to);
+        if !from_dst.exists() {
+            return self.copy(to);
+        }
+
+        self.act(
+            format!("rename {} -> {}", from_dst.display(), to_dst.display()),
+            || fs::rename(&from_dst, &to_dst),
+        )
+    }
+
+    fn dst_path(&self, rel: &Path) -> PathBuf {
+        match rel.as_os_str().is_empty() {
+            true => self.dst.clone(),
+            false => self.dst.join(rel),
+        }
+    }
+
+    /// runs the action, or only prints it in dry run mode
+    fn act<F>(&self, description: String, action: F) -> anyhow::Result<()>
+    where
+        F: FnOnce() -> io::Result<()>,
+    {
+        if self.dry_run {
+            println!("{}", description);
+            return Ok(());
+        }
+        action().with_context(|| format!("couldn't {}", description))
+    }
+}
+
+/// checks if the destination looks like a copy of the source
+fn same_file(src: &Path, dst: &Path) -> bool {
+    match (fs::metadata(src), fs::metadata(dst)) {
+        (Ok(src), Ok(dst)) => src.len() == dst.len() && src.modified().ok() == dst.modified().ok(),
+        _ => false,
+    }
+}
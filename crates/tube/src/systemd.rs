@@ -0,0 +1,93 @@
+use std::env;
+use std::ffi::OsStr;
+use std::net::TcpListener;
+use std::os::fd::FromRawFd;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::path::Path;
+use std::time::Duration;
+
+/// the first file descriptor passed by socket activation
+const LISTEN_FDS_START: i32 = 3;
+
+/// sends a state change (e.g. `READY=1`) to the service manager, does
+/// nothing when not running as a `Type=notify` unit
+pub fn notify(state: &str) {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let path = path.as_encoded_bytes();
+    // a leading `@` stands for an abstract socket
+    let addr = match path.strip_prefix(b"@") {
+        Some(name) => SocketAddr::from_abstract_name(name),
+        None => SocketAddr::from_pathname(Path::new(OsStr::from_bytes(path))),
+    };
+    let sent = addr.and_then(|addr| {
+        let socket = UnixDatagram::unbound()?;
+        socket.send_to_addr(state.as_bytes(), &addr)
+    });
+    if let Err(e) = sent {
+        tracing::warn!("couldn't notify systemd: {}", e);
+    }
+}
+
+/// the listener passed by socket activation, if tube was started through a `.socket` unit
+pub fn listener() -> Option<TcpListener> {
+    let pid = env::var("LISTEN_PID").ok()?.parse::<u32>().ok()?;
+    let fds = env::var("LISTEN_FDS").ok()?.parse::<i32>().ok()?;
+    if pid != std::process::id() || fds < 1 {
+        return None;
+    }
+
+    let listener = unsafe { TcpListener::from_raw_fd(LISTEN_FDS_START) };
+    listener.set_nonblocking(true).ok()?;
+    Some(listener)
+}
+
+/// how often the watchdog has to be petted, half the interval the unit
+/// asked for with `WatchdogSec=`, `None` if the watchdog is off
+fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok()? != std::process::id() {
+            return None;
+        }
+    }
+    let usec = env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    Some(Duration::from_micros(usec) / 2)
+}
+
+/// ticks when the watchdog should be petted, pending forever if it is off,
+/// awaited from the event loop so a stuck loop stops petting it
+pub struct Watchdog {
+    interval: Option<tokio::time::Interval>,
+}
+
+impl Watchdog {
+    pub fn new() -> Self {
+        Self {
+            interval: watchdog_interval().map(tokio::time::interval),
+        }
+    }
+
+    pub async fn tick(&mut self) {
+        match &mut self.interval {
+            Some(interval) => {
+                interval.tick().await;
+            }
+            None => std::future::pending().await,
+        }
+    }
+
+    pub fn pet(&self) {
+        notify("WATCHDOG=1");
+    }
+
+    /// pets the watchdog forever, for loops that are already a `select`
+    pub async fn run(mut self) {
+        loop {
+            self.tick().await;
+            self.pet();
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use anyhow::Context;
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tube_inotify::{EventKind, Flag, Inotify, Mask};
+
+use crate::cli::TailArgs;
+
+/// prints the content appended to the files, the files are followed by name: the
+/// parent directories are watched so a file that is truncated, removed, or
+/// rotated (renamed and created again) is picked up again from its start
+pub async fn run(args: TailArgs) -> anyhow::Result<()> {
+    let mut files = HashMap::new();
+    let mut inotify = Inotify::with_flags(Flag::NONBLOCKING)?;
+
+    for path in &args.paths {
+        let path = std::path::absolute(path)?;
+        let parent = path
+            .parent()
+            .context("path has no parent to watch")?
+            .to_path_buf();
+        let mask = Mask::MODIFY | Mask::CREATE | Mask::DELETE | Mask::MOVE;
+        inotify = inotify.watch(parent.clone(), mask)?;
+
+        let tailed = match File::open(&path) {
+            Ok(mut file) => {
+                let offset = file.seek(SeekFrom::End(0))?;
+                Tailed {
+                    file: Some(file),
+                    offset,
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Tailed::default(),
+            Err(e) => return Err(e).context(format!("couldn't open `{}`", path.display())),
+        };
+        files.insert(path, tailed);
+    }
+
+    let mut out = Output::new(files.len() > 1);
+    while let Some(events) = inotify.next().await {
+        for event in events? {
+            let Some(event) = inotify.resolve(&event) else {
+                continue;
+            };
+            let Some(tailed) = files.get_mut(&event.path) else {
+                continue;
+            };
+
+            match event.kind {
+                EventKind::Modify => tailed.read(&event.path, &mut out)?,
+                EventKind::Create | EventKind::MovedTo => {
+                    // whatever is left in the previous file is printed before switching
+                    tailed.read(&event.path, &mut out)?;
+                    tailed.reopen(&event.path)?;
+                    tailed.read(&event.path, &mut out)?;
+                }
+                EventKind::Delete | EventKind::MovedFrom => {
+                    tailed.read(&event.path, &mut out)?;
+                    *tailed = Tailed::default();
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// a followed file and the offset up to which it was printed
+#[derive(Default)]
+struct Tailed {
+    file: Option<File>,
+    offset: u64,
+}
+
+impl Tailed {
+    fn reopen(&mut self, path: &Path) -> io::Result<()> {
+        *self = match File::open(path) {
+            Ok(file) => Self {
+                file: Some(file),
+                offset: 0,
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(e),
+        };
+        Ok(())
+    }
+
+    /// prints everything written since the last read
+    fn read(&mut self, path: &Path, out: &mut Output) -> io::Result<()> {
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+
+        let len = file.metadata()?.len();
+        if len < self.offset {
+            tracing::info!("{}: file truncated", path.display());
+            self.offset = 0;
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+
+        let mut buffer = Vec::new();
+        self.offset += file.read_to_end(&mut buffer)? as u64;
+        if !buffer.is_empty() {
+            out.write(path, &buffer)?;
+        }
+        Ok(())
+    }
+}
+
+/// writes the content to stdout, with a header line every time
+/// the printed file changes when following multiple files
+struct Output {
+    headers: bool,
+    last: Option<PathBuf>,
+}
+
+impl Output {
+    fn new(headers: bool) -> Self {
+        Self {
+            headers,
+            last: None,
+        }
+    }
+
+    fn write(&mut self, path: &Path, content: &[u8]) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        if self.headers && self.last.as_deref() != Some(path) {
+            if self.last.is_some() {
+                writeln!(stdout)?;
+            }
+            writeln!(stdout, "==> {} <==", path.display())?;
+            self.last = Some(path.to_path_buf());
+        }
+        stdout.write_all(content)?;
+        stdout.flush()
+    }
+}
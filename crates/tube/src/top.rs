@@ -0,0 +1,379 @@
+use crossterm::event::{Event as TermEvent, EventStream, KeyCode, KeyEventKind, KeyModifiers};
+use futures::StreamExt;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::Stylize;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tube_inotify::{Event, EventKind};
+
+use crate::cli::TopArgs;
+use crate::config::Config;
+use crate::watcher::Watcher;
+
+/// the events are counted in buckets of this length, the window slides by it
+const BUCKET: Duration = Duration::from_secs(1);
+
+const HELP: &str = "q quit  p pause  c clear";
+
+pub async fn run(args: TopArgs) -> anyhow::Result<()> {
+    let mut rules = Vec::new();
+    let mut sources = Vec::new();
+    if args.watch.paths().next().is_some() {
+        sources.push(Watcher::open(&args.watch)?);
+    }
+    if let Some(path) = &args.config {
+        let config = Config::load(path)?;
+        // the rules running after others don't watch anything
+        for rule in config.rules.iter().filter(|rule| rule.upstream().is_none()) {
+            sources.push(Watcher::open(&rule.watch_args()?)?);
+            rules.push(rule.name().to_string());
+        }
+    }
+    anyhow::ensure!(!sources.is_empty(), "no paths to watch");
+
+    // the events of every watcher, with the rule they were watched for, the
+    // paths given on the command line come before the rules
+    let (tx, events) = mpsc::unbounded_channel();
+    let offset = sources.len() - rules.len();
+    for (i, watcher) in sources.into_iter().enumerate() {
+        let tx = tx.clone();
+        let rule = i.checked_sub(offset);
+        let mut batches = watcher.spawn();
+        tokio::spawn(async move {
+            while let Some(batch) = batches.recv().await {
+                if tx.send((rule, batch)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(tx);
+
+    let mut top = Top::new(rules, args.window);
+    let mut terminal = ratatui::init();
+    let result = top.run(&mut terminal, events).await;
+    ratatui::restore();
+    result
+}
+
+/// the events of the last window, counted per directory, rule and process
+struct Top {
+    rules: Vec<String>,
+    window: Duration,
+    // the latest last
+    buckets: VecDeque<Bucket>,
+    started: Instant,
+    paused: bool,
+    // what is drawn while paused
+    shown: Option<Totals>,
+}
+
+#[derive(Default)]
+struct Bucket {
+    dirs: HashMap<PathBuf, Count>,
+    rules: HashMap<usize, u64>,
+    writers: HashMap<String, u64>,
+    events: u64,
+    overflows: u64,
+}
+
+/// the events on a directory and how many of them were of each kind
+#[derive(Default, Clone)]
+struct Count {
+    events: u64,
+    kinds: HashMap<EventKind, u64>,
+}
+
+/// the buckets of the window added together
+#[derive(Default, Clone)]
+struct Totals {
+    dirs: Vec<(PathBuf, Count)>,
+    rules: Vec<(usize, u64)>,
+    writers: Vec<(String, u64)>,
+    events: u64,
+    overflows: u64,
+    // the length of the window the events were counted in, shorter than the
+    // window right after starting
+    span: Duration,
+}
+
+impl Top {
+    fn new(rules: Vec<String>, window: Duration) -> Self {
+        Self {
+            rules,
+            window: window.max(BUCKET),
+            buckets: VecDeque::from([Bucket::default()]),
+            started: Instant::now(),
+            paused: false,
+            shown: None,
+        }
+    }
+
+    async fn run(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        mut events: mpsc::UnboundedReceiver<(Option<usize>, anyhow::Result<Vec<Event>>)>,
+    ) -> anyhow::Result<()> {
+        let mut keys = EventStream::new();
+        let mut tick = tokio::time::interval(BUCKET);
+        // every watcher stopped, what was counted is still shown until quitting
+        let mut stopped = false;
+
+        loop {
+            tokio::select! {
+                batch = events.recv(), if !stopped => match batch {
+                    Some((rule, batch)) => self.count(rule, batch?),
+                    None => stopped = true,
+                },
+                key = keys.next() => match key {
+                    Some(Ok(TermEvent::Key(key))) if key.kind == KeyEventKind::Press => {
+                        let ctrl_c = key.modifiers.contains(KeyModifiers::CONTROL)
+                            && key.code == KeyCode::Char('c');
+                        match key.code {
+                            _ if ctrl_c => break,
+                            KeyCode::Char('q') | KeyCode::Esc => break,
+                            KeyCode::Char('p') | KeyCode::Char(' ') => {
+                                self.paused = !self.paused;
+                                self.shown = self.paused.then(|| self.totals());
+                            }
+                            KeyCode::Char('c') => {
+                                self.buckets = VecDeque::from([Bucket::default()]);
+                                self.started = Instant::now();
+                            }
+                            _ => {}
+                        }
+                        terminal.draw(|frame| self.draw(frame))?;
+                    }
+                    Some(Err(e)) => return Err(e.into()),
+                    None => break,
+                    _ => {}
+                },
+                // redrawn once a second instead of on every batch, a busy tree
+                // would redraw the screen continuously
+                _ = tick.tick() => {
+                    self.slide();
+                    terminal.draw(|frame| self.draw(frame))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn count(&mut self, rule: Option<usize>, events: Vec<Event>) {
+        let bucket = self.buckets.back_mut().unwrap();
+        for event in events {
+            if event.kind == EventKind::Overflow {
+                bucket.overflows += 1;
+                continue;
+            }
+            bucket.events += 1;
+            // the events on a watched directory itself count for it
+            let dir = match event.kind {
+                EventKind::DeleteSelf | EventKind::MoveSelf => event.path.as_path(),
+                _ => event.path.parent().unwrap_or(Path::new("/")),
+            };
+            let count = bucket.dirs.entry(dir.to_path_buf()).or_default();
+            count.events += 1;
+            *count.kinds.entry(event.kind).or_default() += 1;
+            if let Some(rule) = rule {
+                *bucket.rules.entry(rule).or_default() += 1;
+            }
+            if let Some(process) = &event.process {
+                let writer = match &process.exe {
+                    Some(exe) => format!("{} ({})", exe.display(), process.pid),
+                    None => process.pid.to_string(),
+                };
+                *bucket.writers.entry(writer).or_default() += 1;
+            }
+        }
+    }
+
+    /// starts a new bucket and drops the ones that left the window
+    fn slide(&mut self) {
+        self.buckets.push_back(Bucket::default());
+        let max = self.window.div_duration_f64(BUCKET).ceil() as usize;
+        while self.buckets.len() > max {
+            self.buckets.pop_front();
+        }
+    }
+
+    fn totals(&self) -> Totals {
+        let mut dirs: HashMap<&Path, Count> = HashMap::new();
+        let mut rules: HashMap<usize, u64> = HashMap::new();
+        let mut writers: HashMap<&str, u64> = HashMap::new();
+        let mut totals = Totals::default();
+        for bucket in &self.buckets {
+            for (dir, count) in &bucket.dirs {
+                let total = dirs.entry(dir).or_default();
+                total.events += count.events;
+                for (kind, n) in &count.kinds {
+                    *total.kinds.entry(*kind).or_default() += n;
+                }
+            }
+            for (rule, n) in &bucket.rules {
+                *rules.entry(*rule).or_default() += n;
+            }
+            for (writer, n) in &bucket.writers {
+                *writers.entry(writer).or_default() += n;
+            }
+            totals.events += bucket.events;
+            totals.overflows += bucket.overflows;
+        }
+
+        totals.dirs = dirs
+            .into_iter()
+            .map(|(dir, count)| (dir.to_path_buf(), count))
+            .collect();
+        totals
+            .dirs
+            .sort_by(|a, b| b.1.events.cmp(&a.1.events).then_with(|| a.0.cmp(&b.0)));
+        totals.rules = rules.into_iter().collect();
+        totals
+            .rules
+            .sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        totals.writers = writers
+            .into_iter()
+            .map(|(writer, n)| (writer.to_string(), n))
+            .collect();
+        totals
+            .writers
+            .sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        totals.span = self.started.elapsed().clamp(BUCKET, self.window);
+        totals
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let totals = match &self.shown {
+            Some(shown) => shown.clone(),
+            None => self.totals(),
+        };
+        let [header, body, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let mut title = vec![
+            Span::from("tube top").bold(),
+            Span::from(format!(
+                "  last {}  {} events  {:.1}/s",
+                humantime::format_duration(self.window),
+                totals.events,
+                rate(totals.events, totals.span)
+            )),
+        ];
+        if totals.overflows > 0 {
+            title.push(
+                Span::from(format!("  {} overflows", totals.overflows))
+                    .bold()
+                    .red(),
+            );
+        }
+        if self.paused {
+            title.push(Span::from("  PAUSED").bold().yellow());
+        }
+        frame.render_widget(Line::from(title), header);
+
+        // the side panels are only shown when there is something to put in them
+        let mut side = Vec::new();
+        if !self.rules.is_empty() {
+            side.push(Constraint::Length(self.rules.len() as u16 + 3));
+        }
+        if !totals.writers.is_empty() {
+            side.push(Constraint::Min(5));
+        }
+        let [dirs, side_area] = match side.is_empty() {
+            true => [body, Rect::default()],
+            false => Layout::horizontal([Constraint::Percentage(65), Constraint::Percentage(35)])
+                .areas(body),
+        };
+        self.draw_dirs(frame, dirs, &totals);
+
+        let mut areas = Layout::vertical(side).split(side_area).to_vec().into_iter();
+        if !self.rules.is_empty() {
+            let rows = totals.rules.iter().map(|(rule, n)| {
+                Row::new([
+                    self.rules[*rule].clone(),
+                    n.to_string(),
+                    format!("{:.1}", rate(*n, totals.span)),
+                ])
+            });
+            frame.render_widget(
+                table(rows, ["rule", "events", "/s"], Constraint::Min(10))
+                    .block(Block::bordered().title("rules")),
+                areas.next().unwrap(),
+            );
+        }
+        if !totals.writers.is_empty() {
+            let rows = totals.writers.iter().map(|(writer, n)| {
+                Row::new([
+                    writer.clone(),
+                    n.to_string(),
+                    format!("{:.1}", rate(*n, totals.span)),
+                ])
+            });
+            frame.render_widget(
+                table(rows, ["process", "events", "/s"], Constraint::Min(10))
+                    .block(Block::bordered().title("writers")),
+                areas.next().unwrap(),
+            );
+        }
+
+        frame.render_widget(Line::from(HELP).dim(), footer);
+    }
+
+    fn draw_dirs(&self, frame: &mut Frame, area: Rect, totals: &Totals) {
+        let height = area.height.saturating_sub(3) as usize;
+        let rows = totals.dirs.iter().take(height).map(|(dir, count)| {
+            // the most frequent kinds first
+            let mut kinds: Vec<(&EventKind, &u64)> = count.kinds.iter().collect();
+            kinds.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.mask().cmp(&b.0.mask())));
+            let kinds: Vec<String> = kinds
+                .iter()
+                .take(3)
+                .map(|(kind, n)| format!("{} {}", kind, n))
+                .collect();
+            Row::new([
+                dir.display().to_string(),
+                count.events.to_string(),
+                format!("{:.1}", rate(count.events, totals.span)),
+                kinds.join("  "),
+            ])
+        });
+        let title = format!("directories ({})", totals.dirs.len());
+        frame.render_widget(
+            Table::new(
+                rows,
+                [
+                    Constraint::Fill(2),
+                    Constraint::Length(8),
+                    Constraint::Length(8),
+                    Constraint::Fill(1),
+                ],
+            )
+            .header(Row::new(["directory", "events", "/s", "kinds"]).bold())
+            .block(Block::bordered().title(title)),
+            area,
+        );
+    }
+}
+
+/// a table with a name column followed by the event count and rate
+fn table<'a>(
+    rows: impl IntoIterator<Item = Row<'a>>,
+    header: [&'a str; 3],
+    name: Constraint,
+) -> Table<'a> {
+    Table::new(rows, [name, Constraint::Length(8), Constraint::Length(8)])
+        .header(Row::new(header).bold())
+}
+
+fn rate(events: u64, span: Duration) -> f64 {
+    events as f64 / span.as_secs_f64()
+}
@@ -0,0 +1,445 @@
+use anyhow::Context;
+use crossterm::event::{
+    Event as TermEvent, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers,
+};
+use futures::StreamExt;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use std::os::fd::RawFd;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::io::unix::AsyncFd;
+use tube_inotify::{Event, EventKind, Flag, Inotify};
+
+use crate::cli::{TuiArgs, WatchArgs};
+use crate::limits::Limits;
+use crate::watcher::Matcher;
+
+/// max number of events kept for display
+const HISTORY: usize = 1000;
+
+/// max number of reads done before the screen is redrawn
+const READS_PER_DRAW: usize = 64;
+
+const HELP: &str = "q quit  p pause  / filter  a add watch  d remove watch  c clear  ↑↓ select";
+
+pub async fn run(args: TuiArgs) -> anyhow::Result<()> {
+    let mut app = App::new(args.watch)?;
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal).await;
+    ratatui::restore();
+    result
+}
+
+/// the dashboard state, the inotify instance is owned directly instead of
+/// running on a `Watcher` thread so watches can be added and removed while running
+struct App {
+    inotify: AsyncFd<Inotify>,
+    args: WatchArgs,
+    roots: Vec<Root>,
+    // shared with the directory filter of the inotify instance
+    matchers: Arc<Mutex<Vec<Arc<Matcher>>>>,
+    events: VecDeque<Entry>,
+    // events received while paused, shown on resume
+    held: Vec<Entry>,
+    paused: bool,
+    filter: String,
+    input: Option<Input>,
+    selected: ListState,
+    limits: Limits,
+    status: Option<String>,
+}
+
+/// a path given on the command line or added at runtime
+struct Root {
+    path: PathBuf,
+    matcher: Arc<Matcher>,
+    events: u64,
+}
+
+struct Entry {
+    time: String,
+    event: Event,
+}
+
+/// the line being edited in the footer
+struct Input {
+    mode: Mode,
+    text: String,
+}
+
+#[derive(PartialEq)]
+enum Mode {
+    Filter,
+    Add,
+}
+
+impl App {
+    fn new(args: WatchArgs) -> anyhow::Result<Self> {
+        let matchers: Arc<Mutex<Vec<Arc<Matcher>>>> = Arc::default();
+        let dir_matchers = matchers.clone();
+        let inotify = Inotify::with_flags(Flag::NONBLOCKING)?
+            .include_hidden(args.hidden)
+            .filter_dirs(move |dir| {
+                !dir_matchers
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .any(|matcher| matcher.ignores_dir(dir))
+            });
+
+        let paths: Vec<PathBuf> = args.paths().cloned().collect();
+        let mut app = Self {
+            inotify: AsyncFd::new(inotify)?,
+            args,
+            roots: Vec::new(),
+            matchers,
+            events: VecDeque::new(),
+            held: Vec::new(),
+            paused: false,
+            filter: String::new(),
+            input: None,
+            selected: ListState::default().with_selected(Some(0)),
+            limits: Limits::read(),
+            status: None,
+        };
+        for path in paths {
+            app.add(&path)?;
+        }
+        Ok(app)
+    }
+
+    async fn run(&mut self, terminal: &mut DefaultTerminal) -> anyhow::Result<()> {
+        let mut keys = EventStream::new();
+        // the limits can be changed with sysctl while running
+        let mut refresh = tokio::time::interval(Duration::from_secs(1));
+
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            tokio::select! {
+                ready = self.inotify.readable_mut() => {
+                    let mut guard = ready?;
+                    let mut events = Vec::new();
+                    for _ in 0..READS_PER_DRAW {
+                        let inotify = guard.get_inner_mut();
+                        match inotify.try_read()? {
+                            Some(batch) => events.extend(batch.filter_map(|e| inotify.resolve(&e))),
+                            None => {
+                                guard.clear_ready();
+                                break;
+                            }
+                        }
+                    }
+                    self.push(events);
+                }
+                key = keys.next() => match key {
+                    Some(Ok(TermEvent::Key(key)))
+                        if key.kind == KeyEventKind::Press && self.on_key(key) => break,
+                    Some(Err(e)) => return Err(e.into()),
+                    None => break,
+                    _ => {}
+                },
+                _ = refresh.tick() => self.limits = Limits::read(),
+            }
+        }
+        Ok(())
+    }
+
+    /// watches the path the same way the paths from the arguments are watched
+    fn add(&mut self, path: &Path) -> anyhow::Result<()> {
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("couldn't resolve `{}`", path.display()))?;
+        if self.roots.iter().any(|root| root.path == path) {
+            anyhow::bail!("`{}` is already watched", path.display());
+        }
+        let args = WatchArgs {
+            paths: vec![path.clone()],
+            watch: Vec::new(),
+            paths_from: None,
+            paths_from0: None,
+            ..self.args.clone()
+        };
+        let matcher = Arc::new(Matcher::new(&args)?);
+        self.matchers.lock().unwrap().push(matcher.clone());
+
+        let inotify = self.inotify.get_mut();
+        let added = if args.is_recursive() && path.is_dir() {
+            inotify.add_recursive(path.clone(), args.mask(), args.depth())
+        } else {
+            inotify.add_watch(path.clone(), args.mask()).map(|_| ())
+        };
+        if let Err(e) = added {
+            self.matchers
+                .lock()
+                .unwrap()
+                .retain(|m| !Arc::ptr_eq(m, &matcher));
+            self.unwatch(&path);
+            return Err(e.into());
+        }
+
+        self.roots.push(Root {
+            path,
+            matcher,
+            events: 0,
+        });
+        Ok(())
+    }
+
+    fn remove(&mut self, index: usize) {
+        let root = self.roots.remove(index);
+        self.matchers
+            .lock()
+            .unwrap()
+            .retain(|m| !Arc::ptr_eq(m, &root.matcher));
+        self.unwatch(&root.path);
+        if index >= self.roots.len() {
+            self.selected.select(self.roots.len().checked_sub(1));
+        }
+    }
+
+    /// removes the watches of the path and the directories under it, watches
+    /// that are also under another root are kept, the kernel shares them
+    fn unwatch(&mut self, path: &Path) {
+        let inotify = self.inotify.get_mut();
+        let wds: Vec<RawFd> = inotify
+            .watches()
+            .filter(|(_, watched)| watched.starts_with(path))
+            .filter(|(_, watched)| !self.roots.iter().any(|r| watched.starts_with(&r.path)))
+            .map(|(wd, _)| wd)
+            .collect();
+        for wd in wds {
+            // the directory may already be gone, the kernel removed its watch then
+            let _ = inotify.unwatch(wd);
+        }
+    }
+
+    fn push(&mut self, events: Vec<Event>) {
+        let time = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+        for event in events {
+            match self.roots.iter_mut().find(|r| r.matcher.matches(&event)) {
+                Some(root) => root.events += 1,
+                None if event.kind == EventKind::Overflow => {}
+                None => continue,
+            }
+            let entry = Entry {
+                time: time[11..19].to_string(),
+                event,
+            };
+            if self.paused {
+                self.held.push(entry);
+            } else {
+                self.events.push_back(entry);
+            }
+        }
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.events.len() > HISTORY {
+            self.events.pop_front();
+        }
+        if self.held.len() > HISTORY {
+            self.held.drain(..self.held.len() - HISTORY);
+        }
+    }
+
+    /// handles a key press, returns `true` when the dashboard should exit
+    fn on_key(&mut self, key: KeyEvent) -> bool {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return true;
+        }
+        if let Some(input) = &mut self.input {
+            match key.code {
+                KeyCode::Esc => {
+                    if input.mode == Mode::Filter {
+                        self.filter.clear();
+                    }
+                    self.input = None;
+                }
+                KeyCode::Enter => {
+                    let input = self.input.take().unwrap();
+                    if input.mode == Mode::Add && !input.text.is_empty() {
+                        self.status = match self.add(Path::new(&input.text)) {
+                            Ok(()) => Some(format!("watching `{}`", input.text)),
+                            Err(e) => Some(format!("{:#}", e)),
+                        };
+                    }
+                }
+                KeyCode::Backspace => {
+                    input.text.pop();
+                }
+                KeyCode::Char(c) => input.text.push(c),
+                _ => {}
+            }
+            // the filter is applied while it is typed
+            if let Some(Input {
+                mode: Mode::Filter,
+                text,
+            }) = &self.input
+            {
+                self.filter = text.clone();
+            }
+            return false;
+        }
+
+        self.status = None;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Char('p') | KeyCode::Char(' ') => {
+                self.paused = !self.paused;
+                if !self.paused {
+                    self.events.extend(self.held.drain(..));
+                    self.trim();
+                }
+            }
+            KeyCode::Char('/') => {
+                self.input = Some(Input {
+                    mode: Mode::Filter,
+                    text: self.filter.clone(),
+                })
+            }
+            KeyCode::Char('a') => {
+                self.input = Some(Input {
+                    mode: Mode::Add,
+                    text: String::new(),
+                })
+            }
+            KeyCode::Char('d') | KeyCode::Delete => {
+                if let Some(index) = self.selected.selected().filter(|i| *i < self.roots.len()) {
+                    self.remove(index);
+                }
+            }
+            KeyCode::Char('c') => self.events.clear(),
+            KeyCode::Up | KeyCode::Char('k') => self.selected.select_previous(),
+            KeyCode::Down | KeyCode::Char('j') => self.selected.select_next(),
+            _ => {}
+        }
+        false
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, body, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [tree, events] =
+            Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)])
+                .areas(body);
+        let inotify = self.inotify.get_ref();
+
+        let used = inotify.watches().count();
+        let mut title = vec![
+            Span::from("tube").bold(),
+            Span::from(format!("  watches {}", used)),
+        ];
+        if let Some(max) = self.limits.max_user_watches {
+            title.push(Span::from(format!(
+                " / {} ({:.1}%)",
+                max,
+                used as f64 * 100.0 / max as f64
+            )));
+        }
+        if self.paused {
+            title.push(Span::from("  PAUSED").bold().yellow());
+        }
+        if !self.filter.is_empty() {
+            title.push(Span::from(format!("  filter: {}", self.filter)));
+        }
+        frame.render_widget(Line::from(title), header);
+
+        let items: Vec<ListItem> = self
+            .roots
+            .iter()
+            .map(|root| {
+                let mut dirs: Vec<&Path> = inotify
+                    .watches()
+                    .map(|(_, path)| path)
+                    .filter(|path| path.starts_with(&root.path) && *path != root.path)
+                    .collect();
+                dirs.sort();
+                let mut lines = vec![Line::from(format!(
+                    "{}  {} events  {} watches",
+                    root.path.display(),
+                    root.events,
+                    dirs.len() + 1
+                ))];
+                lines.extend(dirs.iter().map(|dir| {
+                    let relative = dir.strip_prefix(&root.path).unwrap_or(dir);
+                    let name = relative.file_name().unwrap_or_default().to_string_lossy();
+                    Line::from(format!(
+                        "{}{}/",
+                        "  ".repeat(relative.components().count()),
+                        name
+                    ))
+                    .dim()
+                }));
+                ListItem::new(lines)
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::bordered().title("watches"))
+            .highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(list, tree, &mut self.selected);
+
+        let height = events.height.saturating_sub(2) as usize;
+        let shown: Vec<&Entry> = self
+            .events
+            .iter()
+            .filter(|entry| {
+                self.filter.is_empty() || entry.event.path.to_string_lossy().contains(&self.filter)
+            })
+            .collect();
+        let lines: Vec<Line> = shown[shown.len().saturating_sub(height)..]
+            .iter()
+            .map(|entry| {
+                Line::from(vec![
+                    Span::from(entry.time.as_str()).dim(),
+                    Span::from(" "),
+                    Span::styled(
+                        format!("{:<13}", entry.event.kind.to_string()),
+                        Style::new().fg(color(entry.event.kind)),
+                    ),
+                    Span::from(" "),
+                    Span::from(entry.event.path.to_string_lossy()),
+                ])
+            })
+            .collect();
+        let title = format!("events ({})", shown.len());
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(title)),
+            events,
+        );
+
+        let line = match (&self.input, &self.status) {
+            (Some(input), _) => {
+                let prompt = match input.mode {
+                    Mode::Filter => "/",
+                    Mode::Add => "add: ",
+                };
+                Line::from(format!("{}{}▏", prompt, input.text))
+            }
+            (None, Some(status)) => Line::from(status.as_str()),
+            (None, None) => Line::from(HELP).dim(),
+        };
+        frame.render_widget(line, footer);
+    }
+}
+
+fn color(kind: EventKind) -> Color {
+    match kind {
+        EventKind::Create => Color::Green,
+        EventKind::Delete | EventKind::DeleteSelf => Color::Red,
+        EventKind::Modify | EventKind::CloseWrite => Color::Yellow,
+        EventKind::MovedFrom | EventKind::MovedTo | EventKind::MoveSelf => Color::Cyan,
+        EventKind::Overflow => Color::Magenta,
+        _ => Color::Reset,
+    }
+}
@@ -0,0 +1,72 @@
+use anyhow::Context;
+use std::fmt;
+use tube_inotify::{EventKind, Flag, Inotify};
+
+use crate::cli::WaitArgs;
+
+/// exit code used when the timeout passed, same as `timeout(1)`
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// the error of a command that stopped because its timeout passed, tube
+/// exits with `TIMEOUT_EXIT_CODE` once it cleaned up
+#[derive(Debug)]
+pub struct TimedOut;
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("timed out")
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+pub async fn run(args: WaitArgs) -> anyhow::Result<()> {
+    let mask = args.events.iter().fold(0, |mask, event| mask | event);
+    let path = std::path::absolute(&args.path)
+        .with_context(|| format!("couldn't resolve `{}`", args.path.display()))?;
+
+    // directories are watched directly and any event inside them counts, for
+    // other paths (that may not exist yet) the parent directory is watched and
+    // only events on the path itself are waited for
+    let is_dir = path.is_dir();
+    let (target, watched) = match is_dir {
+        true => (path.canonicalize()?, None),
+        false => {
+            let parent = path
+                .parent()
+                .context("path has no parent to watch")?
+                .canonicalize()
+                .with_context(|| format!("couldn't resolve the parent of `{}`", path.display()))?;
+            let watched = parent.join(path.file_name().context("path has no file name")?);
+            (parent, Some(watched))
+        }
+    };
+
+    let mut inotify = Inotify::with_flags(Flag::NONBLOCKING)?.watch(target.clone(), mask)?;
+
+    // waiting for a file to be created that already exists (or was created
+    // right before the watch was added) is done right away
+    if let Some(watched) = watched.as_ref().filter(|p| p.exists()) {
+        if mask & EventKind::Create.mask() != 0 {
+            println!("{}", watched.display());
+            return Ok(());
+        }
+    }
+
+    let waiting = tokio::task::spawn_blocking(move || {
+        futures::executor::block_on(inotify.wait_for(|event| {
+            event.kind.mask() & mask != 0 && watched.as_ref().is_none_or(|p| &event.path == p)
+        }))
+    });
+
+    let event = match args.timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, waiting).await {
+            Ok(event) => event,
+            Err(_) => std::process::exit(TIMEOUT_EXIT_CODE),
+        },
+        None => waiting.await,
+    };
+    let event = event??.context("stopped waiting before the event")?;
+    println!("{}", event.path.display());
+    Ok(())
+}
@@ -0,0 +1,384 @@
+use anyhow::Context;
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tube_inotify::{
+    Attributor, Echoes, Event, EventKind, EventSource, Flag, InodeTracker, Inotify, Mask,
+    MetaCache, Rescan, Uploads,
+};
+
+use crate::cli::WatchArgs;
+use crate::filter::{self, Filter};
+use crate::hash::Hasher;
+use crate::ignore::Ignore;
+use crate::metrics::METRICS;
+use crate::reconcile::Reconciler;
+
+/// how symlinks under the watched paths are watched
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum Symlinks {
+    #[default]
+    Follow,
+    DontFollow,
+    Resolve,
+}
+
+impl From<Symlinks> for tube_inotify::Symlinks {
+    fn from(symlinks: Symlinks) -> Self {
+        match symlinks {
+            Symlinks::Follow => Self::Follow,
+            Symlinks::DontFollow => Self::DontFollow,
+            Symlinks::Resolve => Self::ResolveTarget,
+        }
+    }
+}
+
+/// sequences of events reported as one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pattern {
+    /// `CREATE`, `CLOSE_WRITE` and a rename to the final name, as `FILE_READY`
+    Upload,
+}
+
+/// how long a file written in place is left alone before it is ready
+const SETTLE: Duration = Duration::from_secs(1);
+
+/// receiving side of a spawned `Watcher`
+pub type Batches = mpsc::UnboundedReceiver<anyhow::Result<Vec<Event>>>;
+
+/// wraps the `Inotify` stream, resolving the events and filtering
+/// out the ones that were not requested in the arguments
+pub struct Watcher {
+    inotify: Inotify,
+    matcher: Matcher,
+    attributor: Option<Attributor>,
+    meta: Option<MetaCache>,
+    inodes: Option<InodeTracker>,
+    rescan: Option<Rescan>,
+    uploads: Option<Uploads>,
+    reconciler: Option<Reconciler>,
+    hasher: Option<Hasher>,
+}
+
+impl Watcher {
+    /// creates an `Inotify` instance watching all the paths given
+    /// in the arguments, paths are canonicalized so events are reported
+    /// with absolute paths
+    pub fn open(args: &WatchArgs) -> anyhow::Result<Self> {
+        let matcher = Matcher::new(args)?;
+        let dir_ignore = matcher.ignore.clone();
+        let mut inotify = Inotify::with_flags(Flag::NONBLOCKING)?
+            .include_hidden(args.hidden)
+            .symlinks(args.symlinks.into())
+            .scan_new_directories(args.scan_new_dirs)
+            .filter_dirs(move |dir| !dir_ignore.is_ignored(dir, true));
+
+        // the events the pattern is made of, whatever is reported
+        let mask = match args.pattern {
+            Some(Pattern::Upload) => {
+                args.mask()
+                    | Mask::CREATE
+                    | Mask::MODIFY
+                    | Mask::CLOSE_WRITE
+                    | Mask::MOVE
+                    | Mask::DELETE
+            }
+            None => args.mask(),
+        };
+        for path in &matcher.roots {
+            tracing::debug!("watching `{}`", path.display());
+            inotify = if args.is_recursive() && path.is_dir() {
+                inotify.watch_recursive(path.clone(), mask, args.depth())
+            } else {
+                inotify.watch(path.clone(), mask)
+            }?;
+        }
+        let attributor = args.attribute.then(|| {
+            let attributor = Attributor::new(matcher.roots.iter().map(PathBuf::as_path));
+            if !attributor.is_privileged() {
+                tracing::warn!("fanotify needs root, looking for the processes in /proc instead");
+            }
+            attributor
+        });
+        let meta = args.meta_changes.then(|| {
+            let mut meta = MetaCache::new();
+            for file in matcher.files() {
+                meta.remember(&file);
+            }
+            meta
+        });
+        let inodes = args.inodes.then(|| {
+            let mut inodes = InodeTracker::new();
+            for (_, dir) in inotify.watches() {
+                inodes.remember(dir);
+            }
+            for file in matcher.files() {
+                inodes.remember(&file);
+            }
+            inodes
+        });
+        let rescan = args.recover_overflow.then(|| Rescan::new(&inotify));
+        let uploads = args
+            .pattern
+            .map(|_| Uploads::new(args.settle.unwrap_or(SETTLE)));
+        // scanned once watching, a change made meanwhile is reported twice at worst
+        let reconciler = args
+            .rescan_interval
+            .map(|interval| Reconciler::new(args, interval))
+            .transpose()?;
+        let hasher = args
+            .hash
+            .filter(|_| args.skip_unchanged)
+            .map(|algorithm| Hasher::new(algorithm, true));
+        Ok(Self {
+            inotify,
+            matcher,
+            attributor,
+            meta,
+            inodes,
+            rescan,
+            uploads,
+            reconciler,
+            hasher,
+        })
+    }
+
+    /// leaves out the events caused by the writes expected in `echoes`
+    pub fn suppress_echoes(mut self, echoes: Echoes) -> Self {
+        self.inotify = self.inotify.suppress_echoes(echoes);
+        self
+    }
+
+    /// returns the matching events of the next batch read from inotify,
+    /// the returned list may be empty if no event in the batch matched
+    pub async fn next(&mut self) -> Option<anyhow::Result<Vec<Event>>> {
+        // the uploads that settle before the next event are reported on their own
+        if let Some(uploads) = &mut self.uploads {
+            if let Some(deadline) = uploads.deadline() {
+                match readable(&self.inotify, deadline) {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        let settled = uploads.expire();
+                        return Some(Ok(self.filter(settled)));
+                    }
+                    Err(e) => return Some(Err(e.into())),
+                }
+            }
+        }
+        let events = match self.inotify.next_events().await? {
+            Ok(events) => events,
+            Err(e) => return Some(Err(e.into())),
+        };
+        let mut resolved = Vec::new();
+        for event in events {
+            if let Some(rescan) = &mut self.rescan {
+                match event.kind {
+                    EventKind::Overflow => {
+                        tracing::warn!("events were lost, scanning the watched paths again");
+                        resolved.push(event);
+                        resolved.extend(rescan.recover(&mut self.inotify));
+                        continue;
+                    }
+                    _ => rescan.update(&event),
+                }
+            }
+            resolved.push(event);
+        }
+        if let Some(uploads) = &mut self.uploads {
+            resolved = resolved
+                .into_iter()
+                .flat_map(|event| uploads.push(event))
+                .collect();
+            resolved.extend(uploads.expire());
+        }
+        Some(Ok(self.filter(resolved)))
+    }
+
+    /// keeps the events that were asked for and adds what the arguments asked to them
+    fn filter(&mut self, events: Vec<Event>) -> Vec<Event> {
+        events
+            .into_iter()
+            .filter(|event| self.matcher.matches(event))
+            .filter(|event| {
+                self.hasher
+                    .as_mut()
+                    .is_none_or(|hasher| hasher.update(event))
+            })
+            .map(|mut event| {
+                if let Some(meta) = &mut self.meta {
+                    meta.update(&mut event);
+                }
+                if let Some(inodes) = &mut self.inodes {
+                    inodes.update(&mut event);
+                }
+                if let Some(attributor) = &mut self.attributor {
+                    attributor.attribute(&mut event);
+                }
+                event
+            })
+            .inspect(|event| {
+                tracing::trace!("{} {}", event.kind, event.path.display());
+                METRICS.event(&event.kind.to_string());
+            })
+            .collect()
+    }
+
+    /// moves the watcher to its own thread and returns a channel receiving its batches,
+    /// waiting for uploads to settle blocks, so modes that need to wait on other things at the
+    /// same time (child processes, timers) should consume the events through the channel
+    pub fn spawn(self) -> Batches {
+        self.spawn_after(Vec::new())
+    }
+
+    /// same as `spawn`, the given events are received first, as their own batch
+    pub fn spawn_after(mut self, events: Vec<Event>) -> Batches {
+        let (tx, rx) = mpsc::unbounded_channel();
+        if !events.is_empty() {
+            let _ = tx.send(Ok(events));
+        }
+        if let Some(reconciler) = &self.reconciler {
+            reconciler.start(tx.clone());
+        }
+        std::thread::spawn(move || {
+            futures::executor::block_on(async {
+                while let Some(events) = self.next().await {
+                    if let (Ok(events), Some(reconciler)) = (&events, &self.reconciler) {
+                        reconciler.seen(events);
+                    }
+                    let failed = events.is_err();
+                    if tx.send(events).is_err() || failed {
+                        break;
+                    }
+                }
+            })
+        });
+        rx
+    }
+}
+
+/// waits for the instance to have events until the deadline, `false` if it passed first
+fn readable(inotify: &Inotify, deadline: Instant) -> std::io::Result<bool> {
+    let timeout = deadline.saturating_duration_since(Instant::now());
+    let mut fd = libc::pollfd {
+        fd: inotify.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // rounded up, so the deadline has passed when nothing came
+    let timeout = timeout.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32;
+    match unsafe { libc::poll(&mut fd, 1, timeout) } {
+        -1 => Err(std::io::Error::last_os_error()),
+        ready => Ok(ready > 0),
+    }
+}
+
+/// decides if an event is one the arguments asked for, without watching
+/// anything, so events that didn't come from inotify (replays) can be filtered the same way
+pub struct Matcher {
+    args: WatchArgs,
+    roots: Vec<PathBuf>,
+    ignore: Arc<Ignore>,
+    filter: Filter,
+}
+
+impl Matcher {
+    /// paths are canonicalized so they compare to the absolute paths of the events
+    pub fn new(args: &WatchArgs) -> anyhow::Result<Self> {
+        let roots = args
+            .paths()
+            .map(|path| {
+                path.canonicalize()
+                    .with_context(|| format!("couldn't resolve `{}`", path.display()))
+            })
+            .collect::<anyhow::Result<Vec<PathBuf>>>()?;
+        // `--paths-from` can come up empty
+        anyhow::ensure!(!roots.is_empty(), "no paths to watch");
+
+        let ignore = Arc::new(Ignore::new(
+            args.preset,
+            !args.no_gitignore,
+            roots.iter().map(PathBuf::as_path),
+        )?);
+        let mut exclude = args.exclude.clone();
+        if let Some(path) = &args.ignore_file {
+            exclude.extend(filter::read_ignore_file(path)?);
+        }
+        Ok(Self {
+            args: args.clone(),
+            roots,
+            ignore,
+            filter: Filter::new(&args.include, &exclude)?,
+        })
+    }
+
+    pub fn matches(&self, event: &Event) -> bool {
+        self.args.matches(event) && self.includes(&event.path, event.is_dir)
+    }
+
+    /// checks if events of the path would be reported, whatever their kind
+    pub fn includes(&self, path: &Path, is_dir: bool) -> bool {
+        self.is_watched(path) && !self.ignore.is_ignored(path, is_dir) && self.filter.matches(path)
+    }
+
+    /// lists the files that currently exist under the roots and would be reported,
+    /// walking the same directories the watcher does
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for root in &self.roots {
+            if root.is_dir() {
+                self.walk(root, 1, &mut files);
+            } else if self.includes(root, false) {
+                files.push(root.clone());
+            }
+        }
+        files
+    }
+
+    /// `level` is the depth of the entries of the directory
+    fn walk(&self, dir: &Path, level: usize, files: &mut Vec<PathBuf>) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_hidden = entry.file_name().as_encoded_bytes().first() == Some(&b'.');
+            if is_hidden && !self.args.hidden {
+                continue;
+            }
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => {
+                    let depth = self.args.depth();
+                    if depth.max.is_none_or(|max| level < max) && !self.ignores_dir(&path) {
+                        self.walk(&path, level + 1, files);
+                    }
+                }
+                Ok(t) if t.is_file() && self.includes(&path, false) => files.push(path),
+                _ => {}
+            }
+        }
+    }
+
+    /// checks if directories found under the roots should be left unwatched
+    pub fn ignores_dir(&self, dir: &Path) -> bool {
+        self.ignore.is_ignored(dir, true)
+    }
+
+    /// checks the path is one of the roots or under one, as deep as the depth of the
+    /// arguments allows, only direct children count for roots not watched recursively
+    fn is_watched(&self, path: &Path) -> bool {
+        // resolved links are reported under their target, which may be anywhere
+        if self.args.is_recursive() && matches!(self.args.symlinks, Symlinks::Resolve) {
+            return true;
+        }
+        let depth = self.args.depth();
+        self.roots.iter().any(|root| {
+            path.strip_prefix(root)
+                .is_ok_and(|rel| depth.contains(rel.components().count()))
+        })
+    }
+}
@@ -0,0 +1,488 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// how long the tests wait for tube to start watching, and for the files it writes
+const STARTUP: Duration = Duration::from_millis(500);
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// tells the directories of the same process apart
+static DIRS: AtomicUsize = AtomicUsize::new(0);
+
+/// a temporary directory, removed on drop
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        let name = format!(
+            "tube-commands-{}-{}",
+            std::process::id(),
+            DIRS.fetch_add(1, Ordering::Relaxed)
+        );
+        let dir = std::env::temp_dir().join(name);
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir.canonicalize().unwrap())
+    }
+
+    fn join(&self, path: &str) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// tube running in `cwd`, killed on drop
+struct Tube(Child);
+
+impl Tube {
+    fn spawn(cwd: &Path, args: &[&str]) -> Self {
+        let child = Command::new(env!("CARGO_BIN_EXE_tube"))
+            .args(args)
+            .current_dir(cwd)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        std::thread::sleep(STARTUP);
+        Self(child)
+    }
+}
+
+impl Drop for Tube {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// the content of the file once it was written
+fn wait_for(path: &Path) -> String {
+    let started = Instant::now();
+    while started.elapsed() < TIMEOUT {
+        match fs::read_to_string(path) {
+            Ok(content) if !content.is_empty() => return content,
+            _ => std::thread::sleep(Duration::from_millis(20)),
+        }
+    }
+    panic!("`{}` wasn't written", path.display());
+}
+
+/// names that run code when they are substituted into a shell script
+const HOSTILE_NAMES: &[&str] = &["$(touch pwned)", "a;touch pwned", "`touch pwned`"];
+
+#[test]
+fn failure_hook_gets_the_event_through_the_environment() {
+    for name in HOSTILE_NAMES {
+        let cwd = TempDir::new();
+        let watched = TempDir::new();
+        let out = cwd.join("out");
+        // placeholders in the script are left as they are
+        let hook = format!("printf %s \"$TUBE_NAME\" > {}; : {{name}}", out.display());
+        let _tube = Tube::spawn(
+            &cwd.0,
+            &[
+                "exec",
+                watched.0.to_str().unwrap(),
+                "--on-failure-cmd",
+                &hook,
+                "--",
+                "false",
+            ],
+        );
+        fs::write(watched.join(name), "").unwrap();
+        assert_eq!(wait_for(&out), *name);
+        assert!(!cwd.join("pwned").exists(), "`{}` ran as code", name);
+        assert!(!watched.join("pwned").exists(), "`{}` ran as code", name);
+    }
+}
+
+/// runs tube with a configuration holding the rule, watching `watched`
+fn rules(cwd: &TempDir, watched: &TempDir, rule: &str) -> Tube {
+    let config = cwd.join("tube.toml");
+    let rule = format!(
+        "[[rule]]\npaths = [\"{}\"]\nevents = [\"create\"]\n{}",
+        watched.0.display(),
+        rule
+    );
+    fs::write(&config, rule).unwrap();
+    Tube::spawn(&cwd.0, &["--config", config.to_str().unwrap()])
+}
+
+/// the error tube exits with for the configuration
+fn config_error(cwd: &TempDir, config: &str) -> String {
+    let path = cwd.join("tube.toml");
+    fs::write(&path, config).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_tube"))
+        .args(["--config", path.to_str().unwrap()])
+        .current_dir(&cwd.0)
+        .output()
+        .unwrap();
+    assert!(!output.status.success(), "the configuration was accepted");
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn rule_names_are_unique() {
+    let cwd = TempDir::new();
+    let watched = TempDir::new();
+    let rule = |name: &str| {
+        format!(
+            "[[rule]]\n{}paths = [\"{}\"]\ncommand = [\"true\"]\n",
+            name,
+            watched.0.display()
+        )
+    };
+    let explicit = rule("name = \"build\"\n").repeat(2);
+    assert!(config_error(&cwd, &explicit).contains("rule `build` is defined more than once"));
+    // the second rule is named after its index
+    let index = format!("{}{}", rule("name = \"1\"\n"), rule(""));
+    assert!(config_error(&cwd, &index).contains("rule `1` is defined more than once"));
+}
+
+#[test]
+fn rule_arguments_are_substituted_one_by_one() {
+    for name in HOSTILE_NAMES {
+        let cwd = TempDir::new();
+        let watched = TempDir::new();
+        let out = cwd.join("out");
+        let script = format!("printf '%s|%s' \"$1\" \"$2\" > {}", out.display());
+        let rule = format!(
+            "command = [\"sh\", \"-c\", \"{}\", \"sh\", \"a b\", \"{{name}}\"]\n",
+            script.replace('"', "\\\"")
+        );
+        let _tube = rules(&cwd, &watched, &rule);
+        fs::write(watched.join(name), "").unwrap();
+        assert_eq!(wait_for(&out), format!("a b|{}", name));
+        assert!(!cwd.join("pwned").exists(), "`{}` ran as code", name);
+        assert!(!watched.join("pwned").exists(), "`{}` ran as code", name);
+    }
+}
+
+#[test]
+fn rule_shell_gets_the_event_through_the_environment() {
+    for name in HOSTILE_NAMES {
+        let cwd = TempDir::new();
+        let watched = TempDir::new();
+        let out = cwd.join("out");
+        // placeholders in the script are left as they are
+        let script = format!("printf %s \"$TUBE_NAME\" > {}; : {{name}}", out.display());
+        let rule = format!(
+            "shell = \"sh -c\"\ncommand = [\"{}\"]\n",
+            script.replace('"', "\\\"")
+        );
+        let _tube = rules(&cwd, &watched, &rule);
+        fs::write(watched.join(name), "").unwrap();
+        assert_eq!(wait_for(&out), *name);
+        assert!(!cwd.join("pwned").exists(), "`{}` ran as code", name);
+        assert!(!watched.join("pwned").exists(), "`{}` ran as code", name);
+    }
+}
+
+#[test]
+fn rule_logs_keep_the_other_files_of_the_directory() {
+    let cwd = TempDir::new();
+    let watched = TempDir::new();
+    let logs = cwd.join("logs");
+    fs::create_dir(&logs).unwrap();
+    let old = logs.join("2020-01-02T03-04-05.000000Z-1.log");
+    fs::write(&old, "").unwrap();
+    fs::write(logs.join("app.log"), "").unwrap();
+    let rule = format!(
+        "command = [\"echo\", \"{{name}}\"]\nlog_dir = \"{}\"\nlog_keep = 1\n",
+        logs.display()
+    );
+    let _tube = rules(&cwd, &watched, &rule);
+    fs::write(watched.join("a"), "").unwrap();
+
+    let started = Instant::now();
+    while old.exists() && started.elapsed() < TIMEOUT {
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert!(!old.exists(), "the oldest log wasn't removed");
+    assert!(logs.join("app.log").exists());
+    let run: Vec<PathBuf> = fs::read_dir(&logs)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| !path.ends_with("app.log"))
+        .collect();
+    assert_eq!(run.len(), 1);
+    // the title is written first, the output of the command after it
+    let echoed = || {
+        fs::read_to_string(&run[0])
+            .unwrap()
+            .lines()
+            .any(|line| line == "a")
+    };
+    while !echoed() && started.elapsed() < TIMEOUT {
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert!(echoed(), "the output wasn't logged");
+}
+
+#[test]
+fn daemon_reports_errors_before_detaching() {
+    let cwd = TempDir::new();
+    let daemon = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_tube"))
+            .arg("daemon")
+            .args(args)
+            .current_dir(&cwd.0)
+            .output()
+            .unwrap();
+        assert!(!output.status.success(), "the daemon detached");
+        String::from_utf8(output.stderr).unwrap()
+    };
+    assert!(daemon(&["--config", "missing.toml"]).contains("couldn't read config"));
+
+    let config = cwd.join("tube.toml");
+    fs::write(&config, "[[rule]]\ncommand = [\"true\"]\n").unwrap();
+    let error = daemon(&["--config", config.to_str().unwrap()]);
+    assert!(error.contains("doesn't define any path"), "{}", error);
+
+    // the pid of the test itself, which is running
+    let pidfile = cwd.join("tube.pid");
+    fs::write(&pidfile, std::process::id().to_string()).unwrap();
+    fs::write(
+        &config,
+        format!(
+            "[[rule]]\npaths = [\"{}\"]\ncommand = [\"true\"]\n",
+            cwd.0.display()
+        ),
+    )
+    .unwrap();
+    let error = daemon(&[
+        "--config",
+        config.to_str().unwrap(),
+        "--pidfile",
+        pidfile.to_str().unwrap(),
+    ]);
+    assert!(error.contains("already running"), "{}", error);
+}
+
+#[test]
+fn stats_are_reported_on_timeout() {
+    let watched = TempDir::new();
+    let output = Command::new(env!("CARGO_BIN_EXE_tube"))
+        .args([watched.0.to_str().unwrap(), "--timeout", "200ms", "--stats"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(124));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("tube: stats after"), "{}", stderr);
+}
+
+/// waits until the file holds the line, returns whether it did
+fn wait_for_line(path: &Path, line: &str) -> bool {
+    let started = Instant::now();
+    while started.elapsed() < TIMEOUT {
+        let content = fs::read_to_string(path).unwrap_or_default();
+        if content.lines().any(|l| l == line) {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    false
+}
+
+#[test]
+fn removed_rules_stop_on_reload() {
+    let cwd = TempDir::new();
+    let watched = TempDir::new();
+    let config = cwd.join("tube.toml");
+    // appends the name of the created file to `out-<rule>`
+    let rule = |name: &str| {
+        format!(
+            "[[rule]]\nname = \"{0}\"\npaths = [\"{1}\"]\nevents = [\"create\"]\n\
+             shell = \"sh -c\"\ncommand = [\"echo $TUBE_NAME >> {2}/out-{0}\"]\n",
+            name,
+            watched.0.display(),
+            cwd.0.display()
+        )
+    };
+    fs::write(&config, format!("{}{}", rule("a"), rule("b"))).unwrap();
+    let _tube = Tube::spawn(
+        &cwd.0,
+        &[
+            "daemon",
+            "--foreground",
+            "--config",
+            config.to_str().unwrap(),
+        ],
+    );
+    fs::write(watched.join("1"), "").unwrap();
+    assert!(wait_for_line(&cwd.join("out-a"), "1"));
+    assert!(wait_for_line(&cwd.join("out-b"), "1"));
+
+    fs::write(&config, rule("b")).unwrap();
+    thread::sleep(STARTUP);
+    fs::write(watched.join("2"), "").unwrap();
+    assert!(wait_for_line(&cwd.join("out-b"), "2"));
+    // the commands of both rules would have run by now
+    thread::sleep(STARTUP);
+    let ran = fs::read_to_string(cwd.join("out-a")).unwrap();
+    assert!(!ran.lines().any(|line| line == "2"), "the removed rule ran");
+}
+
+/// an SMTP server without STARTTLS on a local port, it accepts every command
+/// and sends the ones of the first session through the channel once it ended
+fn smtp_server() -> (u16, mpsc::Receiver<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut stream = stream;
+        let mut commands = Vec::new();
+        stream.write_all(b"220 localhost\r\n").unwrap();
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap_or(0) > 0 {
+            let command = line.trim_end().to_string();
+            line.clear();
+            let reply: &[u8] = match command.split(' ').next().unwrap() {
+                "EHLO" => b"250-localhost\r\n250 AUTH PLAIN\r\n",
+                "DATA" => {
+                    stream.write_all(b"354 go on\r\n").unwrap();
+                    // the message ends with a line holding a dot
+                    while reader.read_line(&mut line).unwrap_or(0) > 0 && line.trim_end() != "." {
+                        line.clear();
+                    }
+                    line.clear();
+                    b"250 sent\r\n"
+                }
+                "QUIT" => b"221 bye\r\n",
+                _ => b"250 ok\r\n",
+            };
+            let quit = command == "QUIT";
+            commands.push(command);
+            if stream.write_all(reply).is_err() || quit {
+                break;
+            }
+        }
+        let _ = tx.send(commands);
+    });
+    (port, rx)
+}
+
+/// runs tube emailing the events of `watched` through the url, with the credentials
+/// `user:secret`, and returns the commands the server received for the first event
+fn email(cwd: &TempDir, watched: &TempDir, scheme: &str) -> Vec<String> {
+    let (port, commands) = smtp_server();
+    let url = format!("{}://user:secret@127.0.0.1:{}", scheme, port);
+    let _tube = Tube::spawn(
+        &cwd.0,
+        &[
+            watched.0.to_str().unwrap(),
+            "--email",
+            &url,
+            "--email-to",
+            "ops@example.com",
+            "--email-digest",
+            "0s",
+        ],
+    );
+    fs::write(watched.join("a"), "").unwrap();
+    commands.recv_timeout(TIMEOUT).unwrap()
+}
+
+#[test]
+fn email_credentials_need_tls() {
+    let cwd = TempDir::new();
+    let watched = TempDir::new();
+    let commands = email(&cwd, &watched, "smtp");
+    assert!(
+        !commands.iter().any(|command| command.starts_with("AUTH")),
+        "the credentials were sent without TLS: {:?}",
+        commands
+    );
+    assert!(!commands.iter().any(|command| command == "DATA"));
+}
+
+#[test]
+fn email_credentials_without_tls_when_insecure() {
+    let cwd = TempDir::new();
+    let watched = TempDir::new();
+    let commands = email(&cwd, &watched, "smtp+insecure");
+    assert!(commands
+        .iter()
+        .any(|command| command.starts_with("AUTH PLAIN")));
+    assert!(commands.iter().any(|command| command == "DATA"));
+}
+
+/// a webhook on a local port, it sends the body of the first request through the channel
+fn webhook() -> (u16, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut stream = stream;
+        let mut length = 0;
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap_or(0) > 0 && line != "\r\n" {
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            line.clear();
+        }
+        let mut body = vec![0; length];
+        std::io::Read::read_exact(&mut reader, &mut body).unwrap();
+        let _ = stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n");
+        let _ = tx.send(String::from_utf8(body).unwrap());
+    });
+    (port, rx)
+}
+
+/// runs tube posting the events of `watched` to the service, and returns the
+/// body of the message for a file created with the name
+fn chat(service: &str, name: &str) -> serde_json::Value {
+    let cwd = TempDir::new();
+    let watched = TempDir::new();
+    let (port, bodies) = webhook();
+    let url = format!("http://127.0.0.1:{}", port);
+    let _tube = Tube::spawn(
+        &cwd.0,
+        &[
+            watched.0.to_str().unwrap(),
+            "--chat",
+            &url,
+            "--chat-service",
+            service,
+            "--chat-template",
+            "{name} {kind}",
+        ],
+    );
+    fs::write(watched.join(name), "").unwrap();
+    serde_json::from_str(&bodies.recv_timeout(TIMEOUT).unwrap()).unwrap()
+}
+
+#[test]
+fn chat_names_are_escaped_for_slack() {
+    let body = chat("slack", "<!channel> & {kind}");
+    let text = body["text"].as_str().unwrap();
+    assert!(
+        text.starts_with("&lt;!channel&gt; &amp; {kind} "),
+        "the name wasn't escaped: {}",
+        text
+    );
+}
+
+#[test]
+fn chat_names_dont_mention_on_discord() {
+    let body = chat("discord", "@everyone");
+    assert!(body["content"].as_str().unwrap().starts_with("@everyone "));
+    assert_eq!(body["allowed_mentions"]["parse"], serde_json::json!([]));
+}